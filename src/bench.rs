@@ -0,0 +1,136 @@
+//! Measure compression levels on the current machine.
+//!
+//! Speed depends on the hardware and on the data, so the best level for an
+//! application is best picked at runtime, from a representative sample.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use ll;
+use bulk::{Compressor, Decompressor};
+
+// Each measure is repeated until it took at least this long.
+const MIN_DURATION_MS: u64 = 20;
+
+/// Results for a compression level, from [`evaluate`](fn.evaluate.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    /// Compression level.
+    pub level: i32,
+    /// Size of the sample, in bytes.
+    pub size: usize,
+    /// Size of the compressed sample, in bytes.
+    pub compressed_size: usize,
+    /// Compression speed, in MB/s of uncompressed data.
+    pub compression_speed: f64,
+    /// Decompression speed, in MB/s of uncompressed data.
+    pub decompression_speed: f64,
+}
+
+impl LevelStats {
+    /// Compression ratio: the size divided by the compressed size.
+    pub fn ratio(&self) -> f64 {
+        self.size as f64 / self.compressed_size as f64
+    }
+}
+
+/// Compresses and decompresses `sample` with each of the given levels.
+///
+/// Each measure is repeated until it took a few milliseconds, so this
+/// takes a while with many levels or a large sample. A sample of a few
+/// hundred kilobytes is usually enough.
+pub fn evaluate(sample: &[u8], levels: &[i32]) -> io::Result<Vec<LevelStats>> {
+    let mut compressor = Compressor::new();
+    let mut decompressor = Decompressor::new();
+
+    levels.iter()
+        .map(|&level| {
+            let compressed = try!(compressor.compress(sample, level));
+            let compression_speed = try!(speed(sample.len(), || {
+                compressor.compress(sample, level).map(|_| ())
+            }));
+            let decompression_speed = try!(speed(sample.len(), || {
+                decompressor.decompress(&compressed, sample.len())
+                    .map(|_| ())
+            }));
+
+            Ok(LevelStats {
+                level: level,
+                size: sample.len(),
+                compressed_size: compressed.len(),
+                compression_speed: compression_speed,
+                decompression_speed: decompression_speed,
+            })
+        })
+        .collect()
+}
+
+/// Returns the highest level compressing `sample` at `min_mbps` or more.
+///
+/// This suits pipelines which should compress as hard as possible,
+/// without compression becoming the bottleneck. Speed is measured on
+/// this machine, in MB/s of uncompressed data, assuming it decreases
+/// as the level increases: only a few levels are actually measured.
+///
+/// If even level 1 is too slow, returns 1.
+pub fn choose_level_for_throughput(sample: &[u8], min_mbps: f64)
+                                   -> io::Result<i32> {
+    let mut compressor = Compressor::new();
+
+    // Binary search for the last level fast enough.
+    let mut low = 1;
+    let mut high = unsafe { ll::ZSTD_maxCLevel() };
+    while low < high {
+        let level = low + (high - low + 1) / 2;
+        let mbps = try!(speed(sample.len(), || {
+            compressor.compress(sample, level).map(|_| ())
+        }));
+        if mbps >= min_mbps {
+            low = level;
+        } else {
+            high = level - 1;
+        }
+    }
+    Ok(low)
+}
+
+// Runs `f` repeatedly, and returns the speed in MB/s for `size` bytes per
+// run.
+fn speed<F: FnMut() -> io::Result<()>>(size: usize, mut f: F)
+                                       -> io::Result<f64> {
+    let min_duration = Duration::from_millis(MIN_DURATION_MS);
+    let start = Instant::now();
+    let mut runs = 0u64;
+    while runs == 0 || start.elapsed() < min_duration {
+        try!(f());
+        runs += 1;
+    }
+
+    let seconds = start.elapsed().as_secs_f64();
+    Ok((size as u64 * runs) as f64 / 1_000_000.0 / seconds)
+}
+
+#[test]
+fn test_evaluate() {
+    let sample: Vec<u8> = (0..100000u32).map(|i| (i % 251) as u8).collect();
+    let stats = evaluate(&sample, &[1, 19]).unwrap();
+
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].level, 1);
+    assert_eq!(stats[1].level, 19);
+    for level in &stats {
+        assert!(level.ratio() > 1.0);
+        assert!(level.compression_speed > 0.0);
+        assert!(level.decompression_speed > 0.0);
+    }
+}
+
+#[test]
+fn test_choose_level_for_throughput() {
+    let sample: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+    let max_level = unsafe { ll::ZSTD_maxCLevel() };
+
+    assert_eq!(choose_level_for_throughput(&sample, 0.0).unwrap(),
+               max_level);
+    assert_eq!(choose_level_for_throughput(&sample, 1e12).unwrap(), 1);
+}
@@ -4,13 +4,15 @@ extern crate glob;
 fn main() {
     let mut config = gcc::Config::new();
 
-    let globs = &["zstd/lib/common/*.c",
-                  "zstd/lib/compress/*.c",
-                  "zstd/lib/decompress/*.c",
-                  "zstd/lib/legacy/*.c",
-                  "zstd/lib/dictBuilder/*.c"];
+    let mut globs = vec!["zstd/lib/common/*.c",
+                         "zstd/lib/compress/*.c",
+                         "zstd/lib/decompress/*.c",
+                         "zstd/lib/dictBuilder/*.c"];
+    if cfg!(feature = "legacy") {
+        globs.push("zstd/lib/legacy/*.c");
+    }
 
-    for pattern in globs {
+    for pattern in &globs {
         for path in glob::glob(pattern).unwrap() {
             let path = path.unwrap();
             config.file(path);
@@ -23,7 +25,21 @@ fn main() {
     config.include("zstd/lib/common");
     config.include("zstd/lib/legacy");
 
-    config.define("ZSTD_LEGACY_SUPPORT", Some("1"));
+    if cfg!(feature = "legacy") {
+        // Decode frames from v0.1 onward.
+        config.define("ZSTD_LEGACY_SUPPORT", Some("1"));
+    } else {
+        config.define("ZSTD_LEGACY_SUPPORT", Some("0"));
+    }
+    // We only compile the C sources, not the x86-64 assembly ones.
+    config.define("ZSTD_DISABLE_ASM", Some("1"));
+    // Prefix the bundled xxhash symbols, like the zstd makefile does.
+    config.define("XXH_NAMESPACE", Some("ZSTD_"));
+
+    if cfg!(feature = "zstdmt") {
+        config.define("ZSTD_MULTITHREAD", None);
+        config.flag("-pthread");
+    }
 
     // Compile!
     config.compile("libzstd.a");
@@ -0,0 +1,695 @@
+use ll;
+use context::EncoderContext;
+use dict::EncoderDictionary;
+use params::{CParameter, CompressionParams, ResetDirective};
+use bulk::DictContent;
+use bulk::page::{self, PAGE_HEADER_SIZE, PageResult};
+
+use std::cmp;
+use std::io::{self, IoSlice, Write};
+use std::mem::MaybeUninit;
+
+// Returns the context, creating it if needed.
+fn get_context(context: &mut Option<EncoderContext>)
+               -> io::Result<&mut EncoderContext> {
+    if context.is_none() {
+        *context = Some(try!(EncoderContext::new()));
+    }
+    Ok(context.as_mut().unwrap())
+}
+
+// Compresses some input, appending the output to `buffer`.
+//
+// Grows `buffer` when it's full. Returns zstd's result code.
+fn compress_stream(context: &EncoderContext, buffer: &mut Vec<u8>,
+                   input: &mut ll::ZSTD_inBuffer,
+                   directive: ll::ZSTD_EndDirective)
+                   -> io::Result<usize> {
+    if buffer.len() == buffer.capacity() {
+        let len = buffer.len();
+        buffer.reserve(cmp::max(len / 2, 64));
+    }
+    let len = buffer.len();
+    let (remaining, written) = {
+        let mut output =
+            ll::ZSTD_outBuffer::uninit(buffer.spare_capacity_mut());
+        let remaining = try!(ll::parse_code(unsafe {
+            ll::ZSTD_compressStream2(context.c, &mut output, input, directive)
+        }));
+        (remaining, output.pos)
+    };
+    unsafe {
+        buffer.set_len(len + written);
+    }
+    Ok(remaining)
+}
+
+/// A sequence of literals followed by a match, as zstd encodes data.
+///
+/// See [`Compressor::generate_sequences`](struct.Compressor.html#method.generate_sequences).
+#[cfg(feature = "experimental")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sequence {
+    /// Distance back to the match, or 0 for a block delimiter.
+    pub offset: u32,
+    /// Number of literals copied before the match.
+    pub lit_length: u32,
+    /// Length of the match, or 0 for the literals ending a block.
+    pub match_length: u32,
+    /// Which repeat offset the match uses (1 to 3), or 0 if none.
+    pub rep: u32,
+}
+
+// Largest block zstd can encode, `ZSTD_BLOCKSIZE_MAX`.
+#[cfg(feature = "experimental")]
+const MAX_BLOCK_SIZE: u64 = 128 * 1024;
+// Shortest match zstd can encode.
+#[cfg(feature = "experimental")]
+const MIN_MATCH: u32 = 3;
+
+/// An external match-finder, like a hardware accelerator.
+///
+/// zstd hands each block to the producer, which finds the sequences to
+/// encode it with; zstd then does the entropy coding. Register one with
+/// `set_sequence_producer` on a compressor or an encoder.
+///
+/// Producers are not compatible with multithreading, nor with long
+/// distance matching.
+#[cfg(feature = "experimental")]
+pub trait SequenceProducer: Send {
+    /// Writes the sequences encoding `src` to `sequences`.
+    ///
+    /// The sequences must cover the whole block, and end with a block
+    /// delimiter: a sequence with offset and match length 0, holding the
+    /// last literals. Offsets can reach back at most `window_size` bytes,
+    /// into earlier blocks or `dict`.
+    ///
+    /// Returns the number of sequences written, or `None` if the block
+    /// can't be handled: zstd then finds the sequences itself if fallback
+    /// was enabled, or fails otherwise.
+    fn produce(&mut self, sequences: &mut [Sequence], src: &[u8],
+               dict: &[u8], level: i32, window_size: usize)
+               -> Option<usize>;
+}
+
+/// Allows to compress multiple blocks of data, re-using the context.
+///
+/// The context is only created on the first compression, so an unused
+/// compressor is cheap to create and to keep around.
+///
+/// The dictionary can be owned (like a `Vec<u8>` or an `Arc<[u8]>`), or
+/// borrowed for the lifetime `'a`, so it isn't copied for each compressor.
+#[derive(Default)]
+pub struct Compressor<'a> {
+    // created on first use
+    context: Option<EncoderContext>,
+    dict: DictContent<'a>,
+    // whether parameters were set on the context
+    advanced: bool,
+}
+
+impl<'a> Compressor<'a> {
+    /// Creates a new zstd compressor
+    pub fn new() -> Self {
+        Compressor::default()
+    }
+
+    /// Creates a new zstd compressor, using the given dictionary.
+    pub fn with_dict<D: AsRef<[u8]> + Send + 'a>(dict: D) -> Self {
+        Compressor {
+            context: None,
+            dict: DictContent::new(dict),
+            advanced: false,
+        }
+    }
+
+    /// Replaces the dictionary used for the following compressions.
+    ///
+    /// This keeps the context, and the parameters set on it.
+    pub fn set_dictionary<D: AsRef<[u8]> + Send + 'a>(&mut self, dict: D)
+                                                      -> io::Result<()> {
+        self.replace_dictionary(DictContent::new(dict))
+    }
+
+    /// Stops using a dictionary for the following compressions.
+    pub fn clear_dictionary(&mut self) -> io::Result<()> {
+        self.replace_dictionary(DictContent::default())
+    }
+
+    fn replace_dictionary(&mut self, dict: DictContent<'a>)
+                          -> io::Result<()> {
+        self.dict = dict;
+        if self.advanced {
+            let context = try!(get_context(&mut self.context));
+            try!(context.load_dictionary(self.dict.as_bytes()));
+        }
+        Ok(())
+    }
+
+    /// Sets a single compression parameter.
+    ///
+    /// Parameters apply to all following compressions, along with the
+    /// level given to each call. They don't apply when using a prepared
+    /// dictionary, which has its own parameters.
+    pub fn set_parameter(&mut self, parameter: CParameter) -> io::Result<()> {
+        try!(self.use_context_parameters());
+        try!(get_context(&mut self.context)).set_cparameter(parameter)
+    }
+
+    /// Sets all the compression parameters at once.
+    ///
+    /// These take precedence over the compression level.
+    /// See [`CompressionParams`](../params/struct.CompressionParams.html).
+    pub fn set_compression_params(&mut self, params: &CompressionParams)
+                                  -> io::Result<()> {
+        try!(self.use_context_parameters());
+        try!(get_context(&mut self.context)).set_compression_params(params)
+    }
+
+    // Switches to compressing with the parameters set on the context.
+    fn use_context_parameters(&mut self) -> io::Result<()> {
+        if !self.advanced {
+            let context = try!(get_context(&mut self.context));
+            let dict = self.dict.as_bytes();
+            if !dict.is_empty() {
+                try!(context.load_dictionary(dict));
+            }
+            self.advanced = true;
+        }
+        Ok(())
+    }
+
+    // Compresses `source` to the `capacity` bytes at `destination`.
+    //
+    // Returns zstd's result code, or an error if the context could not be
+    // created.
+    unsafe fn compress_raw(&mut self, destination: *mut u8,
+                           capacity: usize, source: &[u8], level: i32)
+                           -> io::Result<ll::ErrorCode> {
+        let c = try!(get_context(&mut self.context)).c;
+        if !self.advanced {
+            let dict = self.dict.as_bytes();
+            return Ok(ll::ZSTD_compress_usingDict(c,
+                                                  destination,
+                                                  capacity,
+                                                  source.as_ptr(),
+                                                  source.len(),
+                                                  dict.as_ptr(),
+                                                  dict.len(),
+                                                  level));
+        }
+
+        let code = ll::ZSTD_CCtx_setParameter(c,
+                                              ll::ZSTD_c_compressionLevel,
+                                              level);
+        if ll::ZSTD_isError(code) != 0 {
+            return Ok(code);
+        }
+        Ok(ll::ZSTD_compress2(c,
+                              destination,
+                              capacity,
+                              source.as_ptr(),
+                              source.len()))
+    }
+
+    /// Compress a single block of data to the given destination buffer.
+    ///
+    /// Returns the number of bytes written, or an error if something happened
+    /// (for instance if the destination buffer was too small).
+    pub fn compress_to_buffer(&mut self, destination: &mut [u8],
+                              source: &[u8], level: i32)
+                              -> io::Result<usize> {
+        let code = try!(unsafe {
+            self.compress_raw(destination.as_mut_ptr(),
+                              destination.len(),
+                              source,
+                              level)
+        });
+        ll::parse_code(code)
+    }
+
+    /// Compress a single block of data to the given uninitialized buffer.
+    ///
+    /// This avoids initializing the destination beforehand, for instance
+    /// when using the spare capacity of a `Vec`.
+    ///
+    /// Returns the number of bytes written; these bytes, at the start of
+    /// `destination`, are now initialized.
+    pub fn compress_to_uninit_buffer(&mut self,
+                                     destination: &mut [MaybeUninit<u8>],
+                                     source: &[u8], level: i32)
+                                     -> io::Result<usize> {
+        let code = try!(unsafe {
+            self.compress_raw(destination.as_mut_ptr() as *mut u8,
+                              destination.len(),
+                              source,
+                              level)
+        });
+        ll::parse_code(code)
+    }
+
+    /// Compress a single block of data to the given destination buffer,
+    /// using a prepared dictionary.
+    ///
+    /// The dictionary given to `with_dict` is ignored, and the compression
+    /// level is the one the dictionary was prepared with.
+    pub fn compress_to_buffer_using_dict(&mut self, destination: &mut [u8],
+                                         source: &[u8],
+                                         dictionary: &EncoderDictionary)
+                                         -> io::Result<usize> {
+        unsafe {
+            self.compress_raw_using_dict(destination.as_mut_ptr(),
+                                         destination.len(),
+                                         source,
+                                         dictionary)
+        }
+    }
+
+    // Compresses `source` to the `capacity` bytes at `destination`, using
+    // a prepared dictionary.
+    unsafe fn compress_raw_using_dict(&mut self, destination: *mut u8,
+                                      capacity: usize, source: &[u8],
+                                      dictionary: &EncoderDictionary)
+                                      -> io::Result<usize> {
+        let c = try!(get_context(&mut self.context)).c;
+        ll::parse_code(ll::ZSTD_compress_usingCDict(c,
+                                                    destination,
+                                                    capacity,
+                                                    source.as_ptr(),
+                                                    source.len(),
+                                                    dictionary.as_raw()))
+    }
+
+    /// Compresses a block of data using a prepared dictionary,
+    /// and returns the compressed result.
+    pub fn compress_using_dict(&mut self, data: &[u8],
+                               dictionary: &EncoderDictionary)
+                               -> io::Result<Vec<u8>> {
+        let buffer_len = unsafe { ll::ZSTD_compressBound(data.len()) };
+        let mut buffer = Vec::with_capacity(buffer_len);
+        let len = {
+            let destination = &mut buffer.spare_capacity_mut()[..buffer_len];
+            try!(unsafe {
+                self.compress_raw_using_dict(destination.as_mut_ptr() as
+                                             *mut u8,
+                                             buffer_len,
+                                             data,
+                                             dictionary)
+            })
+        };
+        unsafe {
+            buffer.set_len(len);
+        }
+        Ok(buffer)
+    }
+
+    /// Compresses a block of data into a fixed-size page.
+    ///
+    /// The page receives a small header followed by the compressed data,
+    /// so it can later be decompressed with
+    /// [`Decompressor::decompress_page`].
+    ///
+    /// If the compressed data doesn't fit in the page,
+    /// returns `PageResult::Raw`: the data should then be stored as-is.
+    ///
+    /// [`Decompressor::decompress_page`]: struct.Decompressor.html#method.decompress_page
+    pub fn compress_page(&mut self, source: &[u8], page: &mut [u8],
+                         level: i32)
+                         -> io::Result<PageResult> {
+        if page.len() <= PAGE_HEADER_SIZE {
+            return Ok(PageResult::Raw);
+        }
+
+        let code = try!(unsafe {
+            let destination = &mut page[PAGE_HEADER_SIZE..];
+            self.compress_raw(destination.as_mut_ptr(),
+                              destination.len(),
+                              source,
+                              level)
+        });
+        if unsafe { ll::ZSTD_getErrorCode(code) } ==
+           ll::ZSTD_error_dstSize_tooSmall {
+            return Ok(PageResult::Raw);
+        }
+        let len = try!(ll::parse_code(code));
+
+        page::write_header(page, len);
+        Ok(PageResult::Compressed(PAGE_HEADER_SIZE + len))
+    }
+
+    /// Returns the sequences zstd would encode `data` with, without
+    /// compressing it further.
+    ///
+    /// Each sequence copies some literals, then a match from earlier
+    /// data. Blocks end with a sequence whose offset and match length are
+    /// both 0, holding the last literals of the block. The literal and
+    /// match lengths add up to the size of `data`.
+    ///
+    /// Dictionaries and parameters set on this compressor are used.
+    #[cfg(feature = "experimental")]
+    pub fn generate_sequences(&mut self, data: &[u8], level: i32)
+                              -> io::Result<Vec<Sequence>> {
+        try!(self.use_context_parameters());
+        let context = try!(get_context(&mut self.context));
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+
+        // Same as `ZSTD_sequenceBound`: a sequence is at least 3 bytes
+        // long, plus a delimiter for each block (at least 1KB).
+        let bound = data.len() / 3 + data.len() / 1024 + 2;
+        let mut sequences: Vec<Sequence> = Vec::with_capacity(bound);
+        let count = try!(ll::parse_code(unsafe {
+            ll::ZSTD_generateSequences(context.c,
+                                       sequences.as_mut_ptr() as
+                                       *mut ll::ZSTD_Sequence,
+                                       bound,
+                                       data.as_ptr(),
+                                       data.len())
+        }));
+        unsafe {
+            sequences.set_len(count);
+        }
+        Ok(sequences)
+    }
+
+    /// Compresses `data` into a frame, using the given sequences instead of
+    /// searching for matches.
+    ///
+    /// The sequences follow the format returned by
+    /// [`generate_sequences`](#method.generate_sequences): each block,
+    /// up to 128KB, ends with a delimiter holding its last literals, and
+    /// the literal and match lengths add up to the size of `data`. Matches
+    /// are at least 3 bytes long, and can reach back into the dictionary
+    /// given to [`with_dict`](#method.with_dict). Repeat offsets are
+    /// found by zstd, so the `rep` field is ignored.
+    ///
+    /// Sequences are checked before compression, and invalid ones are
+    /// reported as `InvalidInput` errors.
+    #[cfg(feature = "experimental")]
+    pub fn compress_sequences(&mut self, sequences: &[Sequence],
+                              data: &[u8], level: i32)
+                              -> io::Result<Vec<u8>> {
+        try!(validate_sequences(sequences,
+                                data.len() as u64,
+                                self.dict.as_bytes().len() as u64));
+        try!(self.use_context_parameters());
+        let context = try!(get_context(&mut self.context));
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        try!(context.set_parameter(ll::ZSTD_c_blockDelimiters,
+                                   ll::ZSTD_sf_explicitBlockDelimiters));
+        try!(context.set_parameter(ll::ZSTD_c_validateSequences, 1));
+
+        let buffer_len = unsafe { ll::ZSTD_compressBound(data.len()) };
+        let mut buffer: Vec<u8> = Vec::with_capacity(buffer_len);
+        let len = try!(ll::parse_code(unsafe {
+            ll::ZSTD_compressSequences(context.c,
+                                       buffer.as_mut_ptr(),
+                                       buffer_len,
+                                       sequences.as_ptr() as
+                                       *const ll::ZSTD_Sequence,
+                                       sequences.len(),
+                                       data.as_ptr(),
+                                       data.len())
+        }));
+        unsafe {
+            buffer.set_len(len);
+        }
+        Ok(buffer)
+    }
+
+    /// Uses an external match-finder for the following compressions.
+    ///
+    /// With `fallback`, blocks the producer can't handle are compressed
+    /// by zstd alone; otherwise, the compression fails.
+    #[cfg(feature = "experimental")]
+    pub fn set_sequence_producer<P>(&mut self, producer: P, fallback: bool)
+                                    -> io::Result<()>
+        where P: SequenceProducer + 'static
+    {
+        try!(self.use_context_parameters());
+        try!(get_context(&mut self.context))
+            .set_sequence_producer(Box::new(producer), fallback)
+    }
+
+    /// Compresses a block of data and returns the compressed result.
+    pub fn compress(&mut self, data: &[u8], lvl: i32) -> io::Result<Vec<u8>> {
+        // We allocate a big buffer, slightly larger than the input data.
+        let buffer_len = unsafe { ll::ZSTD_compressBound(data.len()) };
+        let mut buffer = Vec::with_capacity(buffer_len);
+        let len = {
+            let destination = &mut buffer.spare_capacity_mut()[..buffer_len];
+            try!(self.compress_to_uninit_buffer(destination, data, lvl))
+        };
+        unsafe {
+            buffer.set_len(len);
+        }
+
+        // Should we shrink the vec? Meh, let the user do it if he wants.
+        Ok(buffer)
+    }
+
+    /// Compresses a block of data as a single frame, sent to `writer`.
+    ///
+    /// The output goes through a buffer of `ZSTD_CStreamOutSize()` bytes
+    /// (about 128kB), rather than being built whole in memory first.
+    /// The frame header records the size of `data`.
+    ///
+    /// Returns the number of compressed bytes written. On error, the
+    /// writer may have received part of the frame.
+    pub fn compress_to_writer<W: Write>(&mut self, data: &[u8],
+                                        mut writer: W, level: i32)
+                                        -> io::Result<u64> {
+        try!(self.use_context_parameters());
+        let context = try!(get_context(&mut self.context));
+        try!(context.reset(ResetDirective::SessionOnly));
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        try!(context.set_pledged_src_size(data.len() as u64));
+
+        let out_size = unsafe { ll::ZSTD_CStreamOutSize() };
+        let mut buffer = Vec::with_capacity(out_size);
+        let mut input = ll::ZSTD_inBuffer::new(data);
+        let mut written = 0;
+        loop {
+            buffer.clear();
+            let remaining = try!(compress_stream(context,
+                                                 &mut buffer,
+                                                 &mut input,
+                                                 ll::ZSTD_e_end));
+            try!(writer.write_all(&buffer));
+            written += buffer.len() as u64;
+            if remaining == 0 {
+                return Ok(written);
+            }
+        }
+    }
+
+    /// Compresses data split across several slices, as a single frame.
+    ///
+    /// The slices are handed to zstd in turn, without copying them
+    /// together first. The frame decompresses to their concatenation,
+    /// and its header records the total size.
+    pub fn compress_vectored(&mut self, slices: &[IoSlice], level: i32)
+                             -> io::Result<Vec<u8>> {
+        try!(self.use_context_parameters());
+        let context = try!(get_context(&mut self.context));
+        // A previous call may have failed in the middle of a frame.
+        try!(context.reset(ResetDirective::SessionOnly));
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        let total = slices.iter().map(|slice| slice.len()).sum::<usize>();
+        try!(context.set_pledged_src_size(total as u64));
+
+        let mut buffer =
+            Vec::with_capacity(unsafe { ll::ZSTD_compressBound(total) });
+        for slice in slices {
+            let mut input = ll::ZSTD_inBuffer::new(slice);
+            while input.pos < input.size {
+                try!(compress_stream(context,
+                                     &mut buffer,
+                                     &mut input,
+                                     ll::ZSTD_e_continue));
+            }
+        }
+        let mut input = ll::ZSTD_inBuffer::new(&[]);
+        while try!(compress_stream(context,
+                                   &mut buffer,
+                                   &mut input,
+                                   ll::ZSTD_e_end)) != 0 {}
+        Ok(buffer)
+    }
+}
+
+// Checks sequences describe `size` bytes, cut in valid blocks.
+#[cfg(feature = "experimental")]
+fn validate_sequences(sequences: &[Sequence], size: u64, dict_size: u64)
+                      -> io::Result<()> {
+    let mut position = 0u64;
+    let mut block = 0u64;
+    for sequence in sequences {
+        let length = sequence.lit_length as u64 +
+                     sequence.match_length as u64;
+        if sequence.match_length == 0 {
+            if sequence.offset != 0 {
+                return Err(invalid_sequence("match of length 0", position));
+            }
+        } else {
+            if sequence.match_length < MIN_MATCH {
+                return Err(invalid_sequence("match shorter than 3 bytes",
+                                            position));
+            }
+            let reach = position + sequence.lit_length as u64 + dict_size;
+            if sequence.offset == 0 || sequence.offset as u64 > reach {
+                return Err(invalid_sequence("offset out of range",
+                                            position));
+            }
+        }
+        block += length;
+        position += length;
+        if block > MAX_BLOCK_SIZE {
+            return Err(invalid_sequence("block larger than 128KB",
+                                        position));
+        }
+        if position > size {
+            return Err(invalid_sequence("sequences longer than the data",
+                                        position));
+        }
+        if sequence.offset == 0 && sequence.match_length == 0 {
+            block = 0;
+        }
+    }
+    if position < size {
+        return Err(invalid_sequence("sequences shorter than the data",
+                                    position));
+    }
+    match sequences.last() {
+        Some(last) if last.offset == 0 && last.match_length == 0 => Ok(()),
+        _ => Err(invalid_sequence("missing final block delimiter", size)),
+    }
+}
+
+#[cfg(feature = "experimental")]
+fn invalid_sequence(what: &str, position: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   format!("invalid sequences at {}: {}", position, what))
+}
+
+#[test]
+fn test_compress_vectored() {
+    let parts = [&b"scattered "[..], &b""[..], &b"across "[..],
+                 &b"slices"[..]];
+    let slices: Vec<IoSlice> =
+        parts.iter().map(|part| IoSlice::new(part)).collect();
+    let mut compressor = Compressor::with_dict(&b"scattered slices"[..]);
+    let compressed = compressor.compress_vectored(&slices, 3).unwrap();
+    assert_eq!(::safe::get_frame_content_size(&compressed).unwrap(), Some(23));
+    let decompressed =
+        ::bulk::Decompressor::with_dict(&b"scattered slices"[..])
+            .decompress(&compressed, 23)
+            .unwrap();
+    assert_eq!(&decompressed[..], &b"scattered across slices"[..]);
+
+    // The context is reused for the next frames.
+    let compressed = compressor.compress_vectored(&[], 3).unwrap();
+    assert_eq!(::safe::get_frame_content_size(&compressed).unwrap(), Some(0));
+}
+
+#[test]
+fn test_compress_to_writer() {
+    let data: Vec<u8> = (0..500000u32).map(|i| (i % 251) as u8).collect();
+    let mut compressor = Compressor::new();
+    let mut compressed = Vec::new();
+    let written = compressor.compress_to_writer(&data, &mut compressed, 3)
+        .unwrap();
+    assert_eq!(written, compressed.len() as u64);
+    assert_eq!(::safe::get_frame_content_size(&compressed).unwrap(),
+               Some(500000));
+    assert_eq!(::bulk::decompress(&compressed, 500000).unwrap(), data);
+}
+
+#[test]
+fn test_lazy_context() {
+    let mut compressor = Compressor::new();
+    assert!(compressor.context.is_none());
+
+    let compressed = compressor.compress(b"lazy", 1).unwrap();
+    assert!(compressor.context.is_some());
+    assert_eq!(&::bulk::decompress(&compressed, 4).unwrap()[..], b"lazy");
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_generate_sequences() {
+    let data = b"sequences, sequences, and more sequences";
+    let sequences =
+        Compressor::new().generate_sequences(&data[..], 3).unwrap();
+    assert!(sequences.iter().any(|s| s.match_length > 0));
+    let total: u32 = sequences.iter()
+        .map(|s| s.lit_length + s.match_length)
+        .sum();
+    assert_eq!(total as usize, data.len());
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_sequence_producer() {
+    // Only literals, in a single sequence.
+    struct Literals;
+
+    impl SequenceProducer for Literals {
+        fn produce(&mut self, sequences: &mut [Sequence], src: &[u8],
+                   _: &[u8], _: i32, _: usize)
+                   -> Option<usize> {
+            sequences[0] = Sequence {
+                offset: 0,
+                lit_length: src.len() as u32,
+                match_length: 0,
+                rep: 0,
+            };
+            Some(1)
+        }
+    }
+
+    let data: Vec<u8> = (0..1000u32).map(|i| (i % 7 * 37) as u8).collect();
+    let mut compressor = Compressor::new();
+    let normal = compressor.compress(&data, 3).unwrap();
+    compressor.set_sequence_producer(Literals, false).unwrap();
+    let literals = compressor.compress(&data, 3).unwrap();
+    assert!(literals.len() > normal.len());
+    assert_eq!(::bulk::decompress(&literals, 1000).unwrap(), data);
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_compress_sequences() {
+    let data = b"abcdabcdabcdabcd, abcdabcdabcdabcd";
+    let sequences = [Sequence {
+                         offset: 4,
+                         lit_length: 4,
+                         match_length: 12,
+                         rep: 0,
+                     },
+                     Sequence {
+                         offset: 18,
+                         lit_length: 2,
+                         match_length: 16,
+                         rep: 0,
+                     },
+                     Sequence::default()];
+    let mut compressor = Compressor::new();
+    let compressed = compressor.compress_sequences(&sequences, data, 3)
+        .unwrap();
+    assert_eq!(::bulk::decompress(&compressed, data.len()).unwrap(),
+               &data[..]);
+
+    // Sequences found by zstd can be fed back.
+    let generated = compressor.generate_sequences(data, 3).unwrap();
+    let compressed = compressor.compress_sequences(&generated, data, 3)
+        .unwrap();
+    assert_eq!(::bulk::decompress(&compressed, data.len()).unwrap(),
+               &data[..]);
+
+    let mut bad = sequences;
+    bad[1].offset = 40;
+    assert!(compressor.compress_sequences(&bad, data, 3).is_err());
+    assert!(compressor.compress_sequences(&sequences[..2], data, 3)
+        .is_err());
+}
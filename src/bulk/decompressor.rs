@@ -0,0 +1,309 @@
+use ll;
+use context::DecoderContext;
+use dict::DecoderDictionary;
+use bulk::DictContent;
+use bulk::page::{self, PAGE_HEADER_SIZE};
+use stream::{BLOCK_HEADER_SIZE, parse_block_header};
+
+use std::{cmp, io, usize};
+use std::io::Read;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+
+/// Allows to decompress multiple blocks of data, re-using the context.
+///
+/// As for the compressor, the context is only created on first use.
+///
+/// Like with [`Compressor`](struct.Compressor.html), the dictionary can
+/// be owned or borrowed.
+#[derive(Default)]
+pub struct Decompressor<'a> {
+    // created on first use
+    context: Option<DecoderContext>,
+    dict: DictContent<'a>,
+    prepared: Option<Arc<DecoderDictionary>>,
+}
+
+impl<'a> Decompressor<'a> {
+    /// Creates a new zstd decompressor.
+    pub fn new() -> Self {
+        Decompressor::default()
+    }
+
+    /// Creates a new zstd decompressor, using the given dictionary.
+    pub fn with_dict<D: AsRef<[u8]> + Send + 'a>(dict: D) -> Self {
+        Decompressor {
+            context: None,
+            dict: DictContent::new(dict),
+            prepared: None,
+        }
+    }
+
+    /// Creates a new zstd decompressor, using a prepared dictionary.
+    ///
+    /// The dictionary can be shared by many decompressors,
+    /// and isn't loaded again for each block.
+    pub fn with_prepared_dictionary(dictionary: Arc<DecoderDictionary>)
+                                    -> Self {
+        Decompressor {
+            context: None,
+            dict: DictContent::default(),
+            prepared: Some(dictionary),
+        }
+    }
+
+    /// Replaces the dictionary used for the following decompressions.
+    ///
+    /// This keeps the context.
+    pub fn set_dictionary<D: AsRef<[u8]> + Send + 'a>(&mut self, dict: D) {
+        self.dict = DictContent::new(dict);
+        self.prepared = None;
+    }
+
+    /// Replaces the dictionary used for the following decompressions
+    /// with a prepared one.
+    pub fn set_prepared_dictionary(&mut self,
+                                   dictionary: Arc<DecoderDictionary>) {
+        self.dict = DictContent::default();
+        self.prepared = Some(dictionary);
+    }
+
+    /// Stops using a dictionary for the following decompressions.
+    pub fn clear_dictionary(&mut self) {
+        self.dict = DictContent::default();
+        self.prepared = None;
+    }
+
+    /// Deompress a single block of data to the given destination buffer.
+    ///
+    /// Returns the number of bytes written, or an error if something happened
+    /// (for instance if the destination buffer was too small).
+    pub fn decompress_to_buffer(&mut self, destination: &mut [u8],
+                                source: &[u8])
+                                -> io::Result<usize> {
+        unsafe {
+            self.decompress_raw(destination.as_mut_ptr(),
+                                destination.len(),
+                                source)
+        }
+    }
+
+    /// Decompress a single block of data to the given uninitialized buffer.
+    ///
+    /// This avoids initializing the destination beforehand, for instance
+    /// when using the spare capacity of a `Vec`.
+    ///
+    /// Returns the number of bytes written; these bytes, at the start of
+    /// `destination`, are now initialized.
+    pub fn decompress_to_uninit_buffer(&mut self,
+                                       destination: &mut [MaybeUninit<u8>],
+                                       source: &[u8])
+                                       -> io::Result<usize> {
+        unsafe {
+            self.decompress_raw(destination.as_mut_ptr() as *mut u8,
+                                destination.len(),
+                                source)
+        }
+    }
+
+    // Decompresses `source` to the `capacity` bytes at `destination`,
+    // using the dictionary, if any.
+    unsafe fn decompress_raw(&mut self, destination: *mut u8,
+                             capacity: usize, source: &[u8])
+                             -> io::Result<usize> {
+        if self.context.is_none() {
+            self.context = Some(try!(DecoderContext::new()));
+        }
+        let c = self.context.as_ref().unwrap().c;
+        let code = match self.prepared {
+            Some(ref dict) => {
+                ll::ZSTD_decompress_usingDDict(c,
+                                               destination,
+                                               capacity,
+                                               source.as_ptr(),
+                                               source.len(),
+                                               dict.as_raw())
+            }
+            None => {
+                let dict = self.dict.as_bytes();
+                ll::ZSTD_decompress_usingDict(c,
+                                              destination,
+                                              capacity,
+                                              source.as_ptr(),
+                                              source.len(),
+                                              dict.as_ptr(),
+                                              dict.len())
+            }
+        };
+        ll::parse_code(code)
+    }
+
+    /// Decompresses a page written by [`Compressor::compress_page`].
+    ///
+    /// Returns the number of bytes written to `destination`.
+    ///
+    /// [`Compressor::compress_page`]: struct.Compressor.html#method.compress_page
+    pub fn decompress_page(&mut self, page: &[u8], destination: &mut [u8])
+                           -> io::Result<usize> {
+        let len = try!(page::read_header(page));
+        let source = &page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + len];
+        self.decompress_to_buffer(destination, source)
+    }
+
+    /// Decompresses all the frames in `data`, one after the other.
+    ///
+    /// `decompress` stops after the first frame; this behaves like the
+    /// `zstd` command-line tool instead. Skippable frames are skipped.
+    pub fn decompress_multi(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        self.decompress_multi_with_limit(data, usize::MAX)
+    }
+
+    /// Decompresses all the frames in `data`, up to `limit` bytes in total.
+    ///
+    /// Returns an error if the decompressed data would exceed `limit`.
+    pub fn decompress_multi_with_limit(&mut self, mut data: &[u8],
+                                       limit: usize)
+                                       -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        while !data.is_empty() {
+            let (frame_size, bound) = unsafe {
+                let frame_size = try!(ll::parse_code(
+                    ll::ZSTD_findFrameCompressedSize(data.as_ptr(),
+                                                     data.len())));
+                (frame_size,
+                 ll::ZSTD_decompressBound(data.as_ptr(), frame_size))
+            };
+            if bound == ll::ZSTD_CONTENTSIZE_ERROR {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "invalid frame"));
+            }
+
+            let capacity = cmp::min(bound, (limit - buffer.len()) as u64);
+            let capacity = capacity as usize;
+            buffer.reserve(capacity);
+            let len = {
+                let destination =
+                    &mut buffer.spare_capacity_mut()[..capacity];
+                try!(self.decompress_to_uninit_buffer(destination,
+                                                      &data[..frame_size]))
+            };
+            unsafe {
+                let total = buffer.len() + len;
+                buffer.set_len(total);
+            }
+            data = &data[frame_size..];
+        }
+        Ok(buffer)
+    }
+
+    /// Reads a single frame from `reader`, and decompresses it.
+    ///
+    /// Only the bytes of the frame are read, so the reader can hold more
+    /// records after it. Skippable frames before it are skipped.
+    ///
+    /// The decompressed data should be less than `capacity` bytes, or an
+    /// error will be returned; a frame larger than
+    /// `ZSTD_compressBound(capacity)` is rejected before it's read whole.
+    ///
+    /// Returns the decompressed data, and the number of bytes read.
+    pub fn decompress_from_reader<R: Read>(&mut self, mut reader: R,
+                                           capacity: usize)
+                                           -> io::Result<(Vec<u8>, u64)> {
+        let mut frame = Vec::new();
+        let mut consumed = 0;
+        let header = loop {
+            frame.clear();
+            let header = try!(read_frame_header(&mut reader, &mut frame));
+            if header.frameType != ll::ZSTD_skippableFrame {
+                break header;
+            }
+            let size = header.frameContentSize;
+            let skipped = try!(io::copy(&mut (&mut reader).take(size),
+                                        &mut io::sink()));
+            if skipped < size {
+                return Err(incomplete_frame());
+            }
+            consumed += frame.len() as u64 + size;
+        };
+        if header.frameContentSize != ll::ZSTD_CONTENTSIZE_UNKNOWN &&
+           header.frameContentSize > capacity as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "frame content exceeds capacity"));
+        }
+
+        let bound = unsafe { ll::ZSTD_compressBound(capacity) } as u64;
+        loop {
+            try!(read_more(&mut reader, &mut frame, BLOCK_HEADER_SIZE));
+            let start = frame.len() - BLOCK_HEADER_SIZE;
+            let (last, _, size) = try!(parse_block_header(&frame[start..]));
+            if frame.len() as u64 + size > bound {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "frame exceeds capacity"));
+            }
+            try!(read_more(&mut reader, &mut frame, size as usize));
+            if last {
+                break;
+            }
+        }
+        if header.checksumFlag != 0 {
+            try!(read_more(&mut reader, &mut frame, 4));
+        }
+        consumed += frame.len() as u64;
+
+        let data = try!(self.decompress(&frame, capacity));
+        Ok((data, consumed))
+    }
+
+    /// Decompress a block of data, and return the decompressed result in a `Vec<u8>`.
+    ///
+    /// The decompressed data should be less than `capacity` bytes,
+    /// or an error will be returned.
+    pub fn decompress(&mut self, data: &[u8], capacity: usize)
+                      -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(capacity);
+        let len = {
+            let destination = &mut buffer.spare_capacity_mut()[..capacity];
+            try!(self.decompress_to_uninit_buffer(destination, data))
+        };
+        unsafe {
+            buffer.set_len(len);
+        }
+        Ok(buffer)
+    }
+}
+
+// Reads a frame header from `reader`, appending it to `frame`.
+fn read_frame_header<R: Read>(reader: &mut R, frame: &mut Vec<u8>)
+                              -> io::Result<ll::ZSTD_frameHeader> {
+    // Magic number and frame header descriptor.
+    try!(read_more(reader, frame, 5));
+    let mut header = ll::ZSTD_frameHeader::default();
+    loop {
+        let needed = try!(ll::parse_code(unsafe {
+            ll::ZSTD_getFrameHeader(&mut header, frame.as_ptr(), frame.len())
+        }));
+        if needed == 0 {
+            return Ok(header);
+        }
+        let len = frame.len();
+        try!(read_more(reader, frame, needed - len));
+    }
+}
+
+// Reads exactly `n` more bytes from `reader`, appending them to `frame`.
+fn read_more<R: Read>(reader: &mut R, frame: &mut Vec<u8>, n: usize)
+                      -> io::Result<()> {
+    let start = frame.len();
+    frame.resize(start + n, 0);
+    reader.read_exact(&mut frame[start..]).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            incomplete_frame()
+        } else {
+            e
+        }
+    })
+}
+
+fn incomplete_frame() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete frame")
+}
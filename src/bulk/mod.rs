@@ -0,0 +1,342 @@
+//! Methods to compress and decompress individual blocks.
+//!
+//! These methods process all the input data at once.
+//! It is therefore best used with relatively small blocks
+//! (like small network packets).
+//!
+//! The free functions in this module re-use a compression or
+//! decompression context kept for each thread, so calling them in a loop
+//! is about as fast as re-using a [`Compressor`](struct.Compressor.html)
+//! or a [`Decompressor`](struct.Decompressor.html).
+//!
+//! The [`shard`](shard/index.html) module packs many small compressed
+//! records in a single buffer, with random access to each record.
+
+mod compressor;
+mod decompressor;
+mod page;
+pub mod shard;
+#[cfg(feature = "experimental")]
+pub mod workspace;
+
+pub use self::compressor::Compressor;
+#[cfg(feature = "experimental")]
+pub use self::compressor::{Sequence, SequenceProducer};
+pub use self::decompressor::Decompressor;
+pub use self::page::{PAGE_HEADER_SIZE, PageResult};
+
+use std::cell::RefCell;
+use std::io;
+
+thread_local! {
+    // Contexts re-used by the free functions below.
+    static COMPRESSOR: RefCell<Compressor<'static>> =
+        RefCell::new(Compressor::new());
+    static DECOMPRESSOR: RefCell<Decompressor<'static>> =
+        RefCell::new(Decompressor::new());
+}
+
+// Runs `f` with this thread's compressor.
+//
+// Falls back to a new compressor if the thread's one is unavailable
+// (while the thread is being destroyed).
+fn with_compressor<T, F>(f: F) -> io::Result<T>
+    where F: FnOnce(&mut Compressor) -> io::Result<T>
+{
+    let mut f = Some(f);
+    let result = COMPRESSOR.try_with(|compressor| {
+        compressor.try_borrow_mut()
+            .ok()
+            .map(|mut compressor| (f.take().unwrap())(&mut compressor))
+    });
+    match result {
+        Ok(Some(result)) => result,
+        _ => (f.take().unwrap())(&mut Compressor::new()),
+    }
+}
+
+// Same as `with_compressor`, for this thread's decompressor.
+fn with_decompressor<T, F>(f: F) -> io::Result<T>
+    where F: FnOnce(&mut Decompressor) -> io::Result<T>
+{
+    let mut f = Some(f);
+    let result = DECOMPRESSOR.try_with(|decompressor| {
+        decompressor.try_borrow_mut()
+            .ok()
+            .map(|mut decompressor| (f.take().unwrap())(&mut decompressor))
+    });
+    match result {
+        Ok(Some(result)) => result,
+        _ => (f.take().unwrap())(&mut Decompressor::new()),
+    }
+}
+
+// Dictionary content given to a compressor or decompressor.
+//
+// It can be owned (a `Vec<u8>`, an `Arc<[u8]>`, a memory map...)
+// or borrowed, to avoid copying the dictionary for each of them.
+#[derive(Default)]
+struct DictContent<'a> {
+    content: Option<Box<AsRef<[u8]> + Send + 'a>>,
+}
+
+impl<'a> DictContent<'a> {
+    fn new<D: AsRef<[u8]> + Send + 'a>(content: D) -> Self {
+        DictContent { content: Some(Box::new(content)) }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self.content {
+            Some(ref content) => AsRef::<[u8]>::as_ref(&**content),
+            None => &[],
+        }
+    }
+}
+
+/// Compresses a single block of data to the given destination buffer.
+///
+/// Returns the number of bytes written, or an error if something happened
+/// (for instance if the destination buffer was too small).
+pub fn compress_to_buffer(destination: &mut [u8], source: &[u8], level: i32)
+                          -> io::Result<usize> {
+    with_compressor(|c| c.compress_to_buffer(destination, source, level))
+}
+
+/// Compresses a block of data and returns the compressed result.
+pub fn compress(data: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    with_compressor(|c| c.compress(data, level))
+}
+
+/// Compresses a block of data into a fixed-size page.
+///
+/// See [`Compressor::compress_page`](struct.Compressor.html#method.compress_page).
+pub fn compress_page(source: &[u8], page: &mut [u8], level: i32)
+                     -> io::Result<PageResult> {
+    with_compressor(|c| c.compress_page(source, page, level))
+}
+
+/// Decompresses a page written by `compress_page`.
+///
+/// Returns the number of bytes written to `destination`.
+pub fn decompress_page(page: &[u8], destination: &mut [u8])
+                       -> io::Result<usize> {
+    with_decompressor(|d| d.decompress_page(page, destination))
+}
+
+/// Deompress a single block of data to the given destination buffer.
+///
+/// Returns the number of bytes written, or an error if something happened
+/// (for instance if the destination buffer was too small).
+pub fn decompress_to_buffer(destination: &mut [u8], source: &[u8])
+                            -> io::Result<usize> {
+    with_decompressor(|d| d.decompress_to_buffer(destination, source))
+}
+
+/// Decompresses a block of data and returns the decompressed result.
+///
+/// The decompressed data should be less than `capacity` bytes,
+/// or an error will be returned.
+pub fn decompress(data: &[u8], capacity: usize) -> io::Result<Vec<u8>> {
+    with_decompressor(|d| d.decompress(data, capacity))
+}
+
+#[test]
+fn test_direct() {
+    // hipsum.co
+    let text =
+        "Pork belly art party wolf XOXO, neutra scenester ugh thundercats \
+         tattooed squid skateboard beard readymade kogi. VHS cardigan \
+         schlitz, meditation chartreuse kogi tilde church-key. Actually \
+         direct trade hammock, aesthetic VHS semiotics organic narwhal lo-fi \
+         heirloom flexitarian master cleanse polaroid man bun. Flannel \
+         helvetica mustache, bicycle rights small batch slow-carb neutra \
+         tilde williamsburg meh poutine humblebrag. Four dollar toast \
+         butcher actually franzen, gastropub mustache tofu cardigan. 90's \
+         fingerstache forage brooklyn meditation single-origin coffee tofu \
+         actually, ramps pabst farm-to-table art party kombucha artisan \
+         fanny pack. Flannel salvia ennui viral leggings selfies.";
+
+    let compressed = compress(text.as_bytes(), 1).unwrap();
+
+    let uncompressed = decompress(&compressed, text.len()).unwrap();
+
+    assert_eq!(text.as_bytes(), &uncompressed[..]);
+}
+
+#[test]
+fn test_prepared_dictionary() {
+    use dict::{DecoderDictionary, EncoderDictionary};
+    use std::sync::Arc;
+
+    let dict = b"GET /api/v1/users HTTP/1.1\r\nHost: example.com\r\n".to_vec();
+    let text = b"GET /api/v1/users/42 HTTP/1.1\r\nHost: example.com\r\n";
+
+    let prepared = EncoderDictionary::with_dedicated_search(&dict, 5)
+        .unwrap();
+    let compressed = Compressor::new()
+        .compress_using_dict(text, &prepared)
+        .unwrap();
+
+    let prepared = Arc::new(DecoderDictionary::new(&dict).unwrap());
+    let uncompressed = Decompressor::with_prepared_dictionary(prepared)
+        .decompress(&compressed, text.len())
+        .unwrap();
+    assert_eq!(&text[..], &uncompressed[..]);
+
+    let uncompressed = Decompressor::with_dict(dict)
+        .decompress(&compressed, text.len())
+        .unwrap();
+
+    assert_eq!(&text[..], &uncompressed[..]);
+}
+
+#[test]
+fn test_page() {
+    let text = [b'a'; 4096];
+    let mut page = [0u8; 512];
+
+    let used = match compress_page(&text, &mut page, 1).unwrap() {
+        PageResult::Compressed(used) => used,
+        PageResult::Raw => panic!("repetitive data should fit"),
+    };
+    assert!(used <= page.len());
+
+    let mut output = [0u8; 4096];
+    let len = decompress_page(&page, &mut output).unwrap();
+    assert_eq!(&output[..len], &text[..]);
+
+    // Pseudo-random data doesn't compress.
+    let mut seed = 1u32;
+    let noise: Vec<u8> = (0..512)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    assert_eq!(compress_page(&noise, &mut page, 1).unwrap(), PageResult::Raw);
+}
+
+#[test]
+fn test_parameters() {
+    use params::CParameter;
+
+    let text = b"Some text to compress, with a checksum.";
+    let plain = compress(text, 3).unwrap();
+
+    let mut compressor = Compressor::new();
+    compressor.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    let compressed = compressor.compress(text, 3).unwrap();
+    assert_eq!(compressed.len(), plain.len() + 4);
+
+    let uncompressed = decompress(&compressed, text.len()).unwrap();
+    assert_eq!(&text[..], &uncompressed[..]);
+}
+
+#[test]
+fn test_dictionary_swap() {
+    let dict_a = b"first tenant, first tenant, first tenant".to_vec();
+    let dict_b = b"second tenant, second tenant, second tenant".to_vec();
+    let text = b"first tenant data";
+
+    let mut compressor = Compressor::with_dict(dict_a.clone());
+    let compressed_a = compressor.compress(text, 3).unwrap();
+    compressor.set_dictionary(dict_b.clone()).unwrap();
+    let compressed_b = compressor.compress(text, 3).unwrap();
+    compressor.clear_dictionary().unwrap();
+    let compressed = compressor.compress(text, 3).unwrap();
+
+    let mut decompressor = Decompressor::with_dict(dict_a);
+    assert_eq!(&decompressor.decompress(&compressed_a, 100).unwrap()[..],
+               &text[..]);
+    decompressor.set_dictionary(dict_b);
+    assert_eq!(&decompressor.decompress(&compressed_b, 100).unwrap()[..],
+               &text[..]);
+    decompressor.clear_dictionary();
+    assert_eq!(&decompressor.decompress(&compressed, 100).unwrap()[..],
+               &text[..]);
+}
+
+#[test]
+fn test_borrowed_dictionary() {
+    use std::sync::Arc;
+
+    let dict = b"shared tenant, shared tenant, shared tenant".to_vec();
+    let text = b"shared tenant data";
+
+    // Borrowed: the compressor can't outlive `dict`.
+    let compressed = Compressor::with_dict(&dict[..]).compress(text, 3)
+        .unwrap();
+
+    // Shared between several decompressors without copies.
+    let shared: Arc<[u8]> = dict.clone().into();
+    let mut first = Decompressor::with_dict(shared.clone());
+    let mut second = Decompressor::with_dict(shared);
+    assert_eq!(&first.decompress(&compressed, 100).unwrap()[..], &text[..]);
+    assert_eq!(&second.decompress(&compressed, 100).unwrap()[..],
+               &text[..]);
+}
+
+#[test]
+fn test_multi() {
+    let mut data = compress(b"first frame, ", 1).unwrap();
+    data.extend_from_slice(&::encode_all(b"second frame", 1).unwrap());
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(&decompressor.decompress_multi(&data).unwrap()[..],
+               &b"first frame, second frame"[..]);
+    assert!(decompressor.decompress_multi_with_limit(&data, 16).is_err());
+}
+
+#[test]
+fn test_thread_contexts() {
+    let text = b"compressed again and again, compressed again and again";
+
+    for level in 1..5 {
+        let compressed = compress(text, level).unwrap();
+        // The thread's compressor is available again after each call.
+        assert!(COMPRESSOR.with(|c| c.try_borrow_mut().is_ok()));
+        assert_eq!(&decompress(&compressed, 100).unwrap()[..], &text[..]);
+    }
+
+    // An error leaves the contexts usable.
+    assert!(decompress(b"not zstd data", 100).is_err());
+    let compressed = compress(text, 3).unwrap();
+    assert_eq!(&decompress(&compressed, 100).unwrap()[..], &text[..]);
+}
+
+#[test]
+fn test_decompress_from_reader() {
+    use params::CParameter;
+    use std::io::Cursor;
+
+    let mut compressor = Compressor::new();
+    compressor.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    let first = compressor.compress(b"first record", 3).unwrap();
+    let second = compress(&[b'r'; 1000], 3).unwrap();
+
+    // A skippable frame, then both records, then something else.
+    let mut stream = vec![0x50, 0x2a, 0x4d, 0x18, 3, 0, 0, 0];
+    stream.extend_from_slice(b"abc");
+    stream.extend_from_slice(&first);
+    stream.extend_from_slice(&second);
+    stream.extend_from_slice(b"trailing");
+    let mut reader = Cursor::new(stream);
+
+    let mut decompressor = Decompressor::new();
+    let (data, consumed) =
+        decompressor.decompress_from_reader(&mut reader, 100).unwrap();
+    assert_eq!(&data[..], b"first record");
+    assert_eq!(consumed, 11 + first.len() as u64);
+    let (data, consumed) =
+        decompressor.decompress_from_reader(&mut reader, 1000).unwrap();
+    assert_eq!(&data[..], &[b'r'; 1000][..]);
+    assert_eq!(consumed, second.len() as u64);
+    assert_eq!(reader.position(), 11 + (first.len() + second.len()) as u64);
+
+    // Too large for the capacity.
+    let mut reader = Cursor::new(&second[..]);
+    assert!(decompressor.decompress_from_reader(&mut reader, 100).is_err());
+    // Cut short.
+    let mut reader = Cursor::new(&second[..second.len() - 1]);
+    assert!(decompressor.decompress_from_reader(&mut reader, 1000).is_err());
+}
@@ -0,0 +1,50 @@
+//! Fixed-size page layout.
+//!
+//! A compressed page starts with the size of the compressed data,
+//! as a 4-bytes little-endian integer, followed by a single zstd frame.
+//! Anything after the frame is padding, and is ignored.
+
+use std::io;
+
+/// Size of the header written at the start of each compressed page.
+pub const PAGE_HEADER_SIZE: usize = 4;
+
+/// Outcome of compressing a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageResult {
+    /// The data was compressed into the page.
+    ///
+    /// Contains the number of bytes used, including the header.
+    /// The rest of the page is left untouched.
+    Compressed(usize),
+    /// The compressed data doesn't fit in the page.
+    ///
+    /// The page content is unspecified;
+    /// the data should be stored uncompressed instead.
+    Raw,
+}
+
+pub fn write_header(page: &mut [u8], len: usize) {
+    let len = len as u32;
+    for i in 0..PAGE_HEADER_SIZE {
+        page[i] = (len >> (8 * i)) as u8;
+    }
+}
+
+pub fn read_header(page: &[u8]) -> io::Result<usize> {
+    if page.len() < PAGE_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "page too small for its header"));
+    }
+
+    let mut len = 0usize;
+    for i in 0..PAGE_HEADER_SIZE {
+        len |= (page[i] as usize) << (8 * i);
+    }
+
+    if len > page.len() - PAGE_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "invalid page header"));
+    }
+    Ok(len)
+}
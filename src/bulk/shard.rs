@@ -0,0 +1,225 @@
+//! Packed shards of small records.
+//!
+//! A shard holds many small records, each compressed as its own frame
+//! (usually with a dictionary), followed by an index giving random access
+//! to each record. All integers are 4-bytes little-endian:
+//!
+//! * the compressed records, one after the other;
+//! * the end offset of each record;
+//! * a footer: the dictionary ID (0 for none), the number of records,
+//!   and a magic number.
+
+use bulk::{Compressor, Decompressor};
+use dict::Dictionary;
+
+use std::io;
+
+// Last bytes of a shard.
+const SHARD_MAGIC: u32 = 0x4448535A;
+// Dictionary ID, record count, magic number.
+const FOOTER_SIZE: usize = 12;
+
+/// Builds a shard of compressed records.
+pub struct ShardWriter {
+    compressor: Compressor<'static>,
+    level: i32,
+    dict_id: u32,
+    // compressed records
+    data: Vec<u8>,
+    // end offset of each record
+    offsets: Vec<u32>,
+}
+
+impl ShardWriter {
+    /// Creates a shard compressing records without dictionary.
+    pub fn new(level: i32) -> Self {
+        ShardWriter::with_compressor(Compressor::new(), 0, level)
+    }
+
+    /// Creates a shard compressing records with the given dictionary.
+    ///
+    /// The dictionary ID is recorded in the shard.
+    pub fn with_dictionary(dictionary: &Dictionary, level: i32) -> Self {
+        let compressor = Compressor::with_dict(dictionary.to_vec());
+        ShardWriter::with_compressor(compressor, dictionary.id(), level)
+    }
+
+    fn with_compressor(compressor: Compressor<'static>, dict_id: u32,
+                       level: i32)
+                       -> Self {
+        ShardWriter {
+            compressor: compressor,
+            level: level,
+            dict_id: dict_id,
+            data: Vec::new(),
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Compresses and adds a record.
+    ///
+    /// Returns the index of the record in the shard.
+    pub fn push(&mut self, record: &[u8]) -> io::Result<usize> {
+        let compressed = try!(self.compressor.compress(record, self.level));
+        let end = self.data.len() + compressed.len();
+        if end > u32::max_value() as usize {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "shard is too large"));
+        }
+
+        self.data.extend_from_slice(&compressed);
+        self.offsets.push(end as u32);
+        Ok(self.offsets.len() - 1)
+    }
+
+    /// Returns the number of records added so far.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns `true` if no record was added.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Appends the index, and returns the complete shard.
+    pub fn finish(self) -> Vec<u8> {
+        let mut shard = self.data;
+        for &offset in &self.offsets {
+            write_u32(&mut shard, offset);
+        }
+        write_u32(&mut shard, self.dict_id);
+        write_u32(&mut shard, self.offsets.len() as u32);
+        write_u32(&mut shard, SHARD_MAGIC);
+        shard
+    }
+}
+
+/// Gives random access to the records of a shard.
+pub struct ShardReader<'a> {
+    // compressed records
+    data: &'a [u8],
+    // end offset of each record
+    index: &'a [u8],
+    dict_id: u32,
+    decompressor: Decompressor<'static>,
+}
+
+impl<'a> ShardReader<'a> {
+    /// Opens a shard whose records were compressed without dictionary.
+    pub fn new(shard: &'a [u8]) -> io::Result<Self> {
+        ShardReader::with_decompressor(shard, Decompressor::new())
+    }
+
+    /// Opens a shard whose records were compressed with `dictionary`.
+    ///
+    /// Returns an error if the shard was built with another dictionary.
+    pub fn with_dictionary(shard: &'a [u8], dictionary: &Dictionary)
+                           -> io::Result<Self> {
+        let decompressor = Decompressor::with_dict(dictionary.to_vec());
+        let reader = try!(ShardReader::with_decompressor(shard,
+                                                         decompressor));
+        if reader.dict_id != dictionary.id() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "wrong dictionary for this shard"));
+        }
+        Ok(reader)
+    }
+
+    fn with_decompressor(shard: &'a [u8],
+                         decompressor: Decompressor<'static>)
+                         -> io::Result<Self> {
+        if shard.len() < FOOTER_SIZE {
+            return Err(invalid_shard());
+        }
+        let footer = &shard[shard.len() - FOOTER_SIZE..];
+        let dict_id = read_u32(&footer[0..4]);
+        let count = read_u32(&footer[4..8]) as usize;
+        if read_u32(&footer[8..12]) != SHARD_MAGIC ||
+           count > (shard.len() - FOOTER_SIZE) / 4 {
+            return Err(invalid_shard());
+        }
+
+        let index_start = shard.len() - FOOTER_SIZE - 4 * count;
+        Ok(ShardReader {
+            data: &shard[..index_start],
+            index: &shard[index_start..shard.len() - FOOTER_SIZE],
+            dict_id: dict_id,
+            decompressor: decompressor,
+        })
+    }
+
+    /// Returns the ID of the dictionary used by this shard, or 0.
+    pub fn dict_id(&self) -> u32 {
+        self.dict_id
+    }
+
+    /// Returns the number of records in the shard.
+    pub fn len(&self) -> usize {
+        self.index.len() / 4
+    }
+
+    /// Returns `true` if the shard has no record.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Decompresses the record at the given index.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn get(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        assert!(index < self.len(), "record index out of bounds");
+
+        let start = if index == 0 {
+            0
+        } else {
+            read_u32(&self.index[4 * (index - 1)..]) as usize
+        };
+        let end = read_u32(&self.index[4 * index..]) as usize;
+        if start > end || end > self.data.len() {
+            return Err(invalid_shard());
+        }
+
+        self.decompressor.decompress_multi(&self.data[start..end])
+    }
+}
+
+fn invalid_shard() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid shard")
+}
+
+fn write_u32(buffer: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        buffer.push((value >> (8 * i)) as u8);
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = 0;
+    for i in 0..4 {
+        value |= (bytes[i] as u32) << (8 * i);
+    }
+    value
+}
+
+#[test]
+fn test_shard() {
+    let records: Vec<String> =
+        (0..100).map(|i| format!("record number {}", i)).collect();
+
+    let mut writer = ShardWriter::new(3);
+    for record in &records {
+        writer.push(record.as_bytes()).unwrap();
+    }
+    let shard = writer.finish();
+
+    let mut reader = ShardReader::new(&shard).unwrap();
+    assert_eq!(reader.len(), 100);
+    assert_eq!(reader.dict_id(), 0);
+    assert_eq!(reader.get(42).unwrap(), records[42].as_bytes());
+    assert_eq!(reader.get(0).unwrap(), records[0].as_bytes());
+
+    assert!(ShardReader::new(&shard[1..shard.len() - 1]).is_err());
+}
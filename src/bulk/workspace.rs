@@ -0,0 +1,256 @@
+//! Contexts living in caller-provided memory.
+//!
+//! These contexts never allocate: all their memory comes from a workspace
+//! given at creation. This suits environments without a heap, or with
+//! strict memory limits.
+
+use ll;
+
+use std::{cmp, fmt, io, mem};
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+/// A compressor living in a caller-provided workspace.
+pub struct StaticCompressor<'a> {
+    pub(crate) c: ll::ZSTDCompressionContext,
+    // The context lives in the workspace.
+    workspace: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> StaticCompressor<'a> {
+    /// Returns the workspace size needed to compress at the given level.
+    pub fn workspace_size(level: i32) -> usize {
+        unsafe { ll::ZSTD_estimateCCtxSize(level) }
+    }
+
+    /// Creates a compressor in the given workspace.
+    ///
+    /// The workspace must be 8-bytes aligned, and large enough for the
+    /// levels used later (see `workspace_size()`).
+    pub fn new(workspace: &'a mut [u8]) -> io::Result<Self> {
+        try!(check_alignment(workspace));
+        let c = unsafe {
+            ll::ZSTD_initStaticCCtx(workspace.as_mut_ptr() as *mut _,
+                                    workspace.len())
+        };
+        if c.is_null() {
+            return Err(workspace_too_small());
+        }
+        Ok(StaticCompressor {
+            c: c,
+            workspace: PhantomData,
+        })
+    }
+
+    /// Compresses a single block of data to the given destination buffer.
+    ///
+    /// Returns the number of bytes written. Fails if the workspace
+    /// is too small for this level.
+    pub fn compress_to_buffer(&mut self, destination: &mut [u8],
+                              source: &[u8], level: i32)
+                              -> io::Result<usize> {
+        let code = unsafe {
+            ll::ZSTD_compressCCtx(self.c,
+                                  destination.as_mut_ptr(),
+                                  destination.len(),
+                                  source.as_ptr(),
+                                  source.len(),
+                                  level)
+        };
+        ll::parse_code(code)
+    }
+}
+
+// Like other contexts, it can be used from any thread, one at a time.
+unsafe impl<'a> Send for StaticCompressor<'a> {}
+
+/// A decompressor living in a caller-provided workspace.
+pub struct StaticDecompressor<'a> {
+    c: ll::ZSTDDecompressionContext,
+    // The context lives in the workspace.
+    workspace: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> StaticDecompressor<'a> {
+    /// Returns the workspace size needed to decompress.
+    pub fn workspace_size() -> usize {
+        unsafe { ll::ZSTD_estimateDCtxSize() }
+    }
+
+    /// Creates a decompressor in the given workspace.
+    ///
+    /// The workspace must be 8-bytes aligned, and at least
+    /// `workspace_size()` bytes.
+    pub fn new(workspace: &'a mut [u8]) -> io::Result<Self> {
+        try!(check_alignment(workspace));
+        let c = unsafe {
+            ll::ZSTD_initStaticDCtx(workspace.as_mut_ptr() as *mut _,
+                                    workspace.len())
+        };
+        if c.is_null() {
+            return Err(workspace_too_small());
+        }
+        Ok(StaticDecompressor {
+            c: c,
+            workspace: PhantomData,
+        })
+    }
+
+    /// Decompresses a single block of data to the given destination buffer.
+    ///
+    /// Returns the number of bytes written.
+    pub fn decompress_to_buffer(&mut self, destination: &mut [u8],
+                                source: &[u8])
+                                -> io::Result<usize> {
+        let code = unsafe {
+            ll::ZSTD_decompressDCtx(self.c,
+                                    destination.as_mut_ptr(),
+                                    destination.len(),
+                                    source.as_ptr(),
+                                    source.len())
+        };
+        ll::parse_code(code)
+    }
+}
+
+unsafe impl<'a> Send for StaticDecompressor<'a> {}
+
+/// Error from [`decompress_to_buffer`](fn.decompress_to_buffer.html).
+///
+/// Unlike `io::Error`, creating it never allocates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceError {
+    /// The workspace is too small.
+    TooSmall,
+    /// zstd failed; contains zstd's error message.
+    Zstd(&'static str),
+}
+
+impl fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WorkspaceError::TooSmall => write!(f, "workspace is too small"),
+            WorkspaceError::Zstd(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<WorkspaceError> for io::Error {
+    fn from(error: WorkspaceError) -> io::Error {
+        let kind = match error {
+            WorkspaceError::TooSmall => io::ErrorKind::InvalidInput,
+            WorkspaceError::Zstd(_) => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error.to_string())
+    }
+}
+
+/// Decompresses a single block of data without any allocation.
+///
+/// The decompression context is created in `workspace`, past its first
+/// 8-bytes boundary. It needs `StaticDecompressor::workspace_size()`
+/// bytes from there, so a buffer with no particular alignment must hold
+/// `StaticDecompressor::workspace_size() + 7` bytes. This suits
+/// bootloaders and firmware updaters unpacking a payload into a known
+/// buffer.
+///
+/// Returns the number of bytes written to `destination`.
+pub fn decompress_to_buffer(workspace: &mut [u8], destination: &mut [u8],
+                            source: &[u8])
+                            -> Result<usize, WorkspaceError> {
+    let workspace = align(workspace);
+    unsafe {
+        let c = ll::ZSTD_initStaticDCtx(workspace.as_mut_ptr() as *mut _,
+                                        workspace.len());
+        if c.is_null() {
+            return Err(WorkspaceError::TooSmall);
+        }
+        let code = ll::ZSTD_decompressDCtx(c,
+                                           destination.as_mut_ptr(),
+                                           destination.len(),
+                                           source.as_ptr(),
+                                           source.len());
+        if ll::ZSTD_isError(code) != 0 {
+            let name = CStr::from_ptr(ll::ZSTD_getErrorName(code));
+            return Err(WorkspaceError::Zstd(name.to_str()
+                .unwrap_or("unknown error")));
+        }
+        Ok(code)
+    }
+}
+
+// Skips the first bytes of the workspace, up to an 8-bytes boundary.
+fn align(workspace: &mut [u8]) -> &mut [u8] {
+    let alignment = mem::align_of::<u64>();
+    let misalignment = workspace.as_ptr() as usize % alignment;
+    let skip = (alignment - misalignment) % alignment;
+    let skip = cmp::min(skip, workspace.len());
+    &mut workspace[skip..]
+}
+
+fn check_alignment(workspace: &[u8]) -> io::Result<()> {
+    if workspace.as_ptr() as usize % mem::align_of::<u64>() != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "workspace must be 8-bytes aligned"));
+    }
+    Ok(())
+}
+
+fn workspace_too_small() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "workspace is too small")
+}
+
+#[test]
+fn test_workspace() {
+    use std::slice;
+
+    // Use a `u64` buffer to get an aligned workspace.
+    fn aligned(size: usize) -> Vec<u64> {
+        vec![0u64; (size + 7) / 8]
+    }
+    fn as_bytes(buffer: &mut Vec<u64>) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8,
+                                      buffer.len() * 8)
+        }
+    }
+
+    let text = b"Some text compressed without allocating.";
+
+    let mut cworkspace = aligned(StaticCompressor::workspace_size(3));
+    let mut compressor = StaticCompressor::new(as_bytes(&mut cworkspace))
+        .unwrap();
+    let mut compressed = [0u8; 128];
+    let len = compressor.compress_to_buffer(&mut compressed, text, 3)
+        .unwrap();
+
+    let mut dworkspace = aligned(StaticDecompressor::workspace_size());
+    let mut decompressor =
+        StaticDecompressor::new(as_bytes(&mut dworkspace)).unwrap();
+    let mut decompressed = [0u8; 128];
+    let len = decompressor
+        .decompress_to_buffer(&mut decompressed, &compressed[..len])
+        .unwrap();
+    assert_eq!(&decompressed[..len], &text[..]);
+
+    assert!(StaticDecompressor::new(&mut [0u8; 8]).is_err());
+}
+
+#[test]
+fn test_heapless() {
+    let text = b"Some payload to unpack.";
+    let compressed = ::bulk::compress(text, 3).unwrap();
+
+    // Skipping a byte leaves `workspace_size() + 7` bytes, misaligned.
+    let mut workspace = vec![0u8; StaticDecompressor::workspace_size() + 8];
+    let mut decompressed = [0u8; 64];
+    let len = decompress_to_buffer(&mut workspace[1..],
+                                   &mut decompressed,
+                                   &compressed)
+        .unwrap();
+    assert_eq!(&decompressed[..len], &text[..]);
+
+    assert_eq!(decompress_to_buffer(&mut [0u8; 16], &mut decompressed,
+                                    &compressed),
+               Err(WorkspaceError::TooSmall));
+}
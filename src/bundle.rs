@@ -0,0 +1,546 @@
+//! Bundles of named assets, with random access.
+//!
+//! A bundle packs many assets (textures, levels, sounds...) in a single
+//! file. Each asset is split in chunks of a fixed size, each compressed
+//! as its own frame, optionally with a shared dictionary. An index at
+//! the end of the bundle finds any asset in constant time, and any part
+//! of an asset only requires decompressing the chunks holding it.
+//!
+//! The index is stored in a skippable frame, so the whole bundle is also
+//! a valid zstd stream. All integers are little-endian. The index holds:
+//!
+//! * the dictionary ID (0 for none) and the number of assets, on 4 bytes;
+//! * for each asset: the length of its name (4 bytes), its name in UTF-8,
+//!   its size (8 bytes), its chunk size and its number of chunks
+//!   (4 bytes), and the compressed size of each chunk (4 bytes);
+//! * the size of the whole index frame, and a magic number (4 bytes).
+//!
+//! Assets are stored in the order of the index, from the start of the
+//! bundle.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use bulk::{Compressor, Decompressor};
+use dict::Dictionary;
+use stream::SKIPPABLE_MAGIC_START;
+
+// Skippable frame variant holding the index.
+const INDEX_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xB;
+// Last bytes of a bundle.
+const BUNDLE_MAGIC: u32 = 0x4C444E42;
+// Index frame size, and magic number.
+const FOOTER_SIZE: usize = 8;
+// Skippable frame magic number and size.
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// Chunk size used by default: 64KB.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 16;
+
+// Location of an asset in the bundle.
+struct Asset {
+    // offset of the first chunk in the bundle
+    offset: u64,
+    size: u64,
+    chunk_size: u32,
+    // end of each compressed chunk, from `offset`
+    ends: Vec<u64>,
+}
+
+/// Writes a bundle of assets.
+///
+/// Don't forget to call `finish()` to write the index!
+pub struct BundleWriter<W: Write> {
+    writer: W,
+    compressor: Compressor<'static>,
+    level: i32,
+    dict_id: u32,
+    chunk_size: usize,
+    // assets written so far, in order
+    assets: Vec<(String, Asset)>,
+    names: HashMap<String, usize>,
+    // bytes written so far
+    position: u64,
+    // whether a write failed, leaving the bundle in an unknown state
+    failed: bool,
+}
+
+impl<W: Write> BundleWriter<W> {
+    /// Creates a bundle compressing assets without dictionary.
+    pub fn new(writer: W, level: i32) -> Self {
+        BundleWriter::with_compressor(writer, Compressor::new(), 0, level)
+    }
+
+    /// Creates a bundle compressing assets with the given dictionary.
+    ///
+    /// The dictionary ID is recorded in the bundle.
+    pub fn with_dictionary(writer: W, dictionary: &Dictionary, level: i32)
+                           -> Self {
+        let compressor = Compressor::with_dict(dictionary.to_vec());
+        BundleWriter::with_compressor(writer,
+                                      compressor,
+                                      dictionary.id(),
+                                      level)
+    }
+
+    fn with_compressor(writer: W, compressor: Compressor<'static>,
+                       dict_id: u32, level: i32)
+                       -> Self {
+        BundleWriter {
+            writer: writer,
+            compressor: compressor,
+            level: level,
+            dict_id: dict_id,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            assets: Vec::new(),
+            names: HashMap::new(),
+            position: 0,
+            failed: false,
+        }
+    }
+
+    /// Sets the chunk size for the following assets.
+    ///
+    /// Smaller chunks make partial reads cheaper, while larger chunks
+    /// compress better. Defaults to
+    /// [`DEFAULT_CHUNK_SIZE`](constant.DEFAULT_CHUNK_SIZE.html).
+    ///
+    /// # Panics
+    ///
+    /// If `size` is 0 or doesn't fit in 32 bits.
+    pub fn set_chunk_size(&mut self, size: usize) {
+        assert!(size > 0 && size as u64 <= u32::max_value() as u64,
+                "invalid chunk size");
+        self.chunk_size = size;
+    }
+
+    /// Compresses and adds an asset.
+    ///
+    /// Returns an `InvalidInput` error if an asset already has this name.
+    /// Once the writer failed, the bundle is broken: this and `finish()`
+    /// return an error.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        try!(self.check_failed());
+        if self.names.contains_key(name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "duplicate asset name"));
+        }
+
+        let mut ends = Vec::new();
+        let mut end = 0;
+        for chunk in data.chunks(self.chunk_size) {
+            let compressed = try!(self.compressor.compress(chunk,
+                                                           self.level));
+            if compressed.len() as u64 > u32::max_value() as u64 {
+                return Err(io::Error::new(io::ErrorKind::Other,
+                                          "chunk is too large"));
+            }
+            if let Err(e) = self.writer.write_all(&compressed) {
+                // Part of the chunk may be written: later offsets would
+                // be wrong.
+                self.failed = true;
+                return Err(e);
+            }
+            end += compressed.len() as u64;
+            ends.push(end);
+        }
+
+        let asset = Asset {
+            offset: self.position,
+            size: data.len() as u64,
+            chunk_size: self.chunk_size as u32,
+            ends: ends,
+        };
+        self.position += end;
+        self.names.insert(name.to_owned(), self.assets.len());
+        self.assets.push((name.to_owned(), asset));
+        Ok(())
+    }
+
+    /// Returns the number of assets added so far.
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    /// Returns `true` if no asset was added.
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Writes the index, and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.check_failed());
+        let mut index = Vec::new();
+        write_u32(&mut index, self.dict_id);
+        write_u32(&mut index, self.assets.len() as u32);
+        for &(ref name, ref asset) in &self.assets {
+            write_u32(&mut index, name.len() as u32);
+            index.extend_from_slice(name.as_bytes());
+            write_u64(&mut index, asset.size);
+            write_u32(&mut index, asset.chunk_size);
+            write_u32(&mut index, asset.ends.len() as u32);
+            let mut start = 0;
+            for &end in &asset.ends {
+                write_u32(&mut index, (end - start) as u32);
+                start = end;
+            }
+        }
+
+        let content_size = index.len() + FOOTER_SIZE;
+        let frame_size = FRAME_HEADER_SIZE + content_size;
+        if frame_size as u64 > u32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "index is too large"));
+        }
+        let mut frame = Vec::with_capacity(frame_size);
+        write_u32(&mut frame, INDEX_MAGIC);
+        write_u32(&mut frame, content_size as u32);
+        frame.extend_from_slice(&index);
+        write_u32(&mut frame, frame_size as u32);
+        write_u32(&mut frame, BUNDLE_MAGIC);
+
+        try!(self.writer.write_all(&frame));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    fn check_failed(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "a previous write failed"));
+        }
+        Ok(())
+    }
+}
+
+/// Reads assets from a bundle.
+pub struct BundleReader<R: Read + Seek> {
+    reader: R,
+    decompressor: Decompressor<'static>,
+    dict_id: u32,
+    assets: HashMap<String, Asset>,
+}
+
+impl<R: Read + Seek> BundleReader<R> {
+    /// Opens a bundle whose assets were compressed without dictionary.
+    ///
+    /// The bundle must start at the beginning of `reader`.
+    pub fn new(reader: R) -> io::Result<Self> {
+        BundleReader::with_decompressor(reader, Decompressor::new())
+    }
+
+    /// Opens a bundle whose assets were compressed with `dictionary`.
+    ///
+    /// Returns an error if the bundle was built with another dictionary.
+    pub fn with_dictionary(reader: R, dictionary: &Dictionary)
+                           -> io::Result<Self> {
+        let decompressor = Decompressor::with_dict(dictionary.to_vec());
+        let bundle = try!(BundleReader::with_decompressor(reader,
+                                                          decompressor));
+        if bundle.dict_id != dictionary.id() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "wrong dictionary for this bundle"));
+        }
+        Ok(bundle)
+    }
+
+    fn with_decompressor(mut reader: R,
+                         decompressor: Decompressor<'static>)
+                         -> io::Result<Self> {
+        let mut footer = [0u8; FOOTER_SIZE];
+        let end = try!(reader.seek(SeekFrom::End(0)));
+        if end < (FRAME_HEADER_SIZE + FOOTER_SIZE) as u64 {
+            return Err(invalid_bundle());
+        }
+        try!(reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64))));
+        try!(reader.read_exact(&mut footer));
+        let frame_size = read_u32(&footer) as u64;
+        if read_u32(&footer[4..]) != BUNDLE_MAGIC || frame_size > end ||
+           frame_size < (FRAME_HEADER_SIZE + FOOTER_SIZE) as u64 {
+            return Err(invalid_bundle());
+        }
+
+        let index_start = end - frame_size;
+        try!(reader.seek(SeekFrom::Start(index_start)));
+        let mut frame = vec![0u8; frame_size as usize];
+        try!(reader.read_exact(&mut frame));
+        if read_u32(&frame) != INDEX_MAGIC {
+            return Err(invalid_bundle());
+        }
+        let index = &frame[FRAME_HEADER_SIZE..frame.len() - FOOTER_SIZE];
+        let (dict_id, assets) = try!(parse_index(index, index_start));
+
+        Ok(BundleReader {
+            reader: reader,
+            decompressor: decompressor,
+            dict_id: dict_id,
+            assets: assets,
+        })
+    }
+
+    /// Returns the ID of the dictionary used by this bundle, or 0.
+    pub fn dict_id(&self) -> u32 {
+        self.dict_id
+    }
+
+    /// Returns the number of assets in the bundle.
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    /// Returns `true` if the bundle has no asset.
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Returns the names of the assets, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> =
+            self.assets.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the size of an asset, or `None` if there is no such asset.
+    pub fn size(&self, name: &str) -> Option<u64> {
+        self.assets.get(name).map(|asset| asset.size)
+    }
+
+    /// Decompresses a whole asset.
+    ///
+    /// Returns a `NotFound` error if there is no such asset.
+    pub fn get(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let asset = try!(self.assets.get(name).ok_or_else(not_found));
+        let chunk_size = asset.chunk_size as u64;
+
+        // The size comes from the bundle: only trust what actually
+        // decompresses, a chunk at a time.
+        let mut data = Vec::new();
+        let mut start = 0;
+        for &end in &asset.ends {
+            let remaining = asset.size - data.len() as u64;
+            if remaining == 0 {
+                break;
+            }
+            try!(self.reader.seek(SeekFrom::Start(asset.offset + start)));
+            let mut compressed = vec![0u8; (end - start) as usize];
+            try!(self.reader.read_exact(&mut compressed));
+            let expected = cmp::min(chunk_size, remaining) as usize;
+            let chunk = try!(self.decompressor
+                .decompress(&compressed, expected));
+            if chunk.len() != expected {
+                return Err(invalid_bundle());
+            }
+            data.extend_from_slice(&chunk);
+            start = end;
+        }
+        Ok(data)
+    }
+
+    /// Reads part of an asset, starting at `offset`.
+    ///
+    /// Only the chunks holding the requested part are decompressed.
+    /// Returns the number of bytes read: less than `buf.len()` only at
+    /// the end of the asset.
+    pub fn read_at(&mut self, name: &str, offset: u64, buf: &mut [u8])
+                   -> io::Result<usize> {
+        let asset = try!(self.assets.get(name).ok_or_else(not_found));
+        let end = cmp::min(asset.size,
+                           offset.saturating_add(buf.len() as u64));
+        let chunk_size = asset.chunk_size as u64;
+
+        let mut position = offset;
+        while position < end {
+            let chunk = (position / chunk_size) as usize;
+            let start = if chunk == 0 { 0 } else { asset.ends[chunk - 1] };
+            let compressed_size = (asset.ends[chunk] - start) as usize;
+            try!(self.reader.seek(SeekFrom::Start(asset.offset + start)));
+            let mut compressed = vec![0u8; compressed_size];
+            try!(self.reader.read_exact(&mut compressed));
+            let data = try!(self.decompressor
+                .decompress(&compressed, chunk_size as usize));
+
+            let chunk_start = chunk as u64 * chunk_size;
+            let from = (position - chunk_start) as usize;
+            let to = cmp::min(end - chunk_start, data.len() as u64) as usize;
+            if from >= to {
+                return Err(invalid_bundle());
+            }
+            let written = (position - offset) as usize;
+            buf[written..written + to - from]
+                .copy_from_slice(&data[from..to]);
+            position += (to - from) as u64;
+        }
+        Ok((position - offset) as usize)
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+// Parses the index; `limit` is where the assets must end.
+fn parse_index(mut index: &[u8], limit: u64)
+               -> io::Result<(u32, HashMap<String, Asset>)> {
+    let dict_id = try!(take_u32(&mut index));
+    let count = try!(take_u32(&mut index));
+
+    let mut assets = HashMap::new();
+    let mut offset = 0;
+    for _ in 0..count {
+        let name_len = try!(take_u32(&mut index)) as usize;
+        if index.len() < name_len {
+            return Err(invalid_bundle());
+        }
+        let name = try!(String::from_utf8(index[..name_len].to_vec())
+            .map_err(|_| invalid_bundle()));
+        index = &index[name_len..];
+        let size = try!(take_u64(&mut index));
+        let chunk_size = try!(take_u32(&mut index));
+        let chunks = try!(take_u32(&mut index));
+        if chunk_size == 0 {
+            return Err(invalid_bundle());
+        }
+        let chunk_size_64 = chunk_size as u64;
+        let needed = size / chunk_size_64 +
+                     (size % chunk_size_64 != 0) as u64;
+        if (chunks as u64) < needed {
+            return Err(invalid_bundle());
+        }
+
+        let mut ends = Vec::new();
+        let mut end = 0;
+        for _ in 0..chunks {
+            end += try!(take_u32(&mut index)) as u64;
+            ends.push(end);
+        }
+        if offset + end > limit {
+            return Err(invalid_bundle());
+        }
+        assets.insert(name,
+                      Asset {
+                          offset: offset,
+                          size: size,
+                          chunk_size: chunk_size,
+                          ends: ends,
+                      });
+        offset += end;
+    }
+    Ok((dict_id, assets))
+}
+
+fn take_u32(data: &mut &[u8]) -> io::Result<u32> {
+    if data.len() < 4 {
+        return Err(invalid_bundle());
+    }
+    let value = read_u32(data);
+    *data = &data[4..];
+    Ok(value)
+}
+
+fn take_u64(data: &mut &[u8]) -> io::Result<u64> {
+    let low = try!(take_u32(data)) as u64;
+    let high = try!(take_u32(data)) as u64;
+    Ok(low | high << 32)
+}
+
+fn invalid_bundle() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid bundle")
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such asset")
+}
+
+fn write_u32(buffer: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        buffer.push((value >> (8 * i)) as u8);
+    }
+}
+
+fn write_u64(buffer: &mut Vec<u8>, value: u64) {
+    write_u32(buffer, value as u32);
+    write_u32(buffer, (value >> 32) as u32);
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = 0;
+    for i in 0..4 {
+        value |= (bytes[i] as u32) << (8 * i);
+    }
+    value
+}
+
+#[test]
+fn test_bundle() {
+    use std::io::Cursor;
+
+    let level: Vec<u8> = (0..100000u32).map(|i| (i % 249) as u8).collect();
+    let mut writer = BundleWriter::new(Cursor::new(Vec::new()), 3);
+    writer.set_chunk_size(4096);
+    writer.add("levels/1.map", &level).unwrap();
+    writer.add("empty", b"").unwrap();
+    writer.add("title.txt", b"A Game").unwrap();
+    assert!(writer.add("empty", b"again").is_err());
+    let bundle = writer.finish().unwrap().into_inner();
+
+    let mut reader = BundleReader::new(Cursor::new(&bundle)).unwrap();
+    assert_eq!(reader.names(), vec!["empty", "levels/1.map", "title.txt"]);
+    assert_eq!(reader.size("levels/1.map"), Some(level.len() as u64));
+    assert_eq!(reader.get("title.txt").unwrap(), b"A Game");
+    assert_eq!(reader.get("empty").unwrap(), b"");
+    assert_eq!(reader.get("levels/1.map").unwrap(), level);
+    assert_eq!(reader.get("missing").unwrap_err().kind(),
+               io::ErrorKind::NotFound);
+
+    // Across a chunk boundary, and past the end.
+    let mut part = [0u8; 100];
+    assert_eq!(reader.read_at("levels/1.map", 4050, &mut part).unwrap(),
+               100);
+    assert_eq!(&part[..], &level[4050..4150]);
+    assert_eq!(reader.read_at("levels/1.map", 99950, &mut part).unwrap(),
+               50);
+    assert_eq!(&part[..50], &level[99950..]);
+}
+
+#[test]
+fn test_corrupt_bundle() {
+    use std::io::Cursor;
+
+    let mut writer = BundleWriter::new(Cursor::new(Vec::new()), 3);
+    writer.add("a", b"abc").unwrap();
+    let bundle = writer.finish().unwrap().into_inner();
+    let frame_size = read_u32(&bundle[bundle.len() - FOOTER_SIZE..]);
+    // Skip the dictionary ID, the number of assets and the name.
+    let size_at = bundle.len() - frame_size as usize + FRAME_HEADER_SIZE +
+                  13;
+
+    // The number of chunks needed for this size would overflow.
+    let mut corrupt = bundle.clone();
+    for byte in &mut corrupt[size_at..size_at + 8] {
+        *byte = 0xff;
+    }
+    assert!(BundleReader::new(Cursor::new(&corrupt)).is_err());
+
+    // A whole chunk, while it only holds 3 bytes.
+    let mut corrupt = bundle.clone();
+    corrupt[size_at..size_at + 8].copy_from_slice(&[0, 0, 1, 0, 0, 0, 0, 0]);
+    let mut reader = BundleReader::new(Cursor::new(&corrupt)).unwrap();
+    assert_eq!(reader.get("a").unwrap_err().kind(),
+               io::ErrorKind::InvalidData);
+    let mut part = [0u8; 2];
+    assert_eq!(reader.read_at("a", u64::max_value(), &mut part).unwrap(),
+               0);
+}
+
+#[test]
+fn test_bundle_writer_failure() {
+    let mut output = [0u8; 4];
+    let mut writer = BundleWriter::new(&mut output[..], 3);
+    assert!(writer.add("a", b"does not fit").is_err());
+    // Offsets would be wrong from now on.
+    assert!(writer.add("b", b"").is_err());
+    assert!(writer.finish().is_err());
+}
@@ -0,0 +1,404 @@
+use ll;
+use memory;
+#[cfg(feature = "debug-leak-check")]
+use leak;
+use params::{CParameter, CompressionParams, DEFAULT_WINDOW_LOG_LIMIT,
+             DParameter, LiteralCompressionMode, ResetDirective,
+             ZstdVersion};
+use dict::{DecoderDictionary, EncoderDictionary};
+use stream::FrameProgression;
+#[cfg(feature = "experimental")]
+use bulk::{Sequence, SequenceProducer};
+
+#[cfg(feature = "experimental")]
+use libc::{c_int, c_void, size_t};
+
+use std::io;
+#[cfg(feature = "experimental")]
+use std::{panic, ptr, slice};
+use std::sync::Arc;
+
+pub struct EncoderContext {
+    pub c: ll::ZSTDCompressionContext,
+    // refuse settings that could make the output vary between runs
+    pub deterministic: bool,
+    // oldest decoder the frames must stay readable by
+    compatibility: Option<ZstdVersion>,
+    // prepared dictionary referenced by the context: kept alive until the
+    // context is freed, since zstd doesn't copy it
+    prepared: Option<Arc<EncoderDictionary>>,
+    // external match-finder, called by zstd: kept alive, and at the same
+    // address, until the context is freed
+    #[cfg(feature = "experimental")]
+    producer: Option<Box<Box<SequenceProducer>>>,
+}
+
+impl Default for EncoderContext {
+    fn default() -> Self {
+        EncoderContext::new().unwrap()
+    }
+}
+
+impl EncoderContext {
+    /// Creates a new context, failing if it cannot be allocated.
+    pub fn new() -> io::Result<Self> {
+        let c = unsafe { ll::ZSTD_createCCtx_advanced(memory::custom_mem()) };
+        if c.is_null() {
+            return Err(memory::allocation_error("compression context"));
+        }
+        #[cfg(feature = "debug-leak-check")]
+        leak::register("compression context", c);
+        Ok(EncoderContext {
+            c: c,
+            deterministic: false,
+            compatibility: None,
+            prepared: None,
+            #[cfg(feature = "experimental")]
+            producer: None,
+        })
+    }
+
+    /// Sets a single compression parameter.
+    pub fn set_parameter(&self, param: ll::ZSTD_cParameter, value: i32)
+                         -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_setParameter(self.c, param, value)
+        }));
+        Ok(())
+    }
+
+    /// Sets a typed compression parameter.
+    pub fn set_cparameter(&self, param: CParameter) -> io::Result<()> {
+        if let CParameter::NbWorkers(n_workers) = param {
+            if n_workers > 0 {
+                try!(self.check_not_deterministic("multithreading"));
+            }
+        }
+        match param {
+            CParameter::WindowLog(window_log) => {
+                try!(self.check_window_log(window_log))
+            }
+            CParameter::JobSize(size) => try!(check_job_size(size)),
+            #[cfg(feature = "experimental")]
+            CParameter::Raw(..) if self.compatibility.is_some() => {
+                return Err(incompatible("raw parameters"));
+            }
+            _ => (),
+        }
+        let (param, value) = param.as_raw();
+        self.set_parameter(param, value)
+    }
+
+    /// Sets the expected size of the input.
+    pub fn set_source_size_hint(&self, size: usize) -> io::Result<()> {
+        if size > ll::ZSTD_SRCSIZEHINT_MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "source size hint is too large"));
+        }
+        self.set_parameter(ll::ZSTD_c_srcSizeHint, size as i32)
+    }
+
+    /// Sets how literals are compressed.
+    pub fn set_literal_compression_mode(&self,
+                                        mode: LiteralCompressionMode)
+                                        -> io::Result<()> {
+        let value = match mode {
+            LiteralCompressionMode::Auto => ll::ZSTD_ps_auto,
+            LiteralCompressionMode::Huffman => ll::ZSTD_ps_enable,
+            LiteralCompressionMode::Uncompressed => ll::ZSTD_ps_disable,
+        };
+        self.set_parameter(ll::ZSTD_c_literalCompressionMode, value)
+    }
+
+    /// Applies all the given compression parameters.
+    pub fn set_compression_params(&self, params: &CompressionParams)
+                                  -> io::Result<()> {
+        let params = params.as_raw();
+        try!(self.check_window_log(params.windowLog));
+        try!(self.set_parameter(ll::ZSTD_c_windowLog,
+                                params.windowLog as i32));
+        try!(self.set_parameter(ll::ZSTD_c_chainLog, params.chainLog as i32));
+        try!(self.set_parameter(ll::ZSTD_c_hashLog, params.hashLog as i32));
+        try!(self.set_parameter(ll::ZSTD_c_searchLog,
+                                params.searchLog as i32));
+        try!(self.set_parameter(ll::ZSTD_c_minMatch, params.minMatch as i32));
+        try!(self.set_parameter(ll::ZSTD_c_targetLength,
+                                params.targetLength as i32));
+        try!(self.set_parameter(ll::ZSTD_c_strategy, params.strategy));
+        Ok(())
+    }
+
+    /// Pins the parameters that could make the output vary between runs.
+    pub fn set_deterministic(&mut self) -> io::Result<()> {
+        try!(self.set_parameter(ll::ZSTD_c_nbWorkers, 0));
+        try!(self.set_parameter(ll::ZSTD_c_deterministicRefPrefix, 1));
+        self.deterministic = true;
+        Ok(())
+    }
+
+    /// Returns an error if deterministic output was requested.
+    pub fn check_not_deterministic(&self, what: &str) -> io::Result<()> {
+        if self.deterministic {
+            let msg = format!("{} is not allowed in deterministic mode", what);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        Ok(())
+    }
+
+    /// Refuses settings producing frames `version` couldn't decode.
+    pub fn set_max_format_compatibility(&mut self, version: ZstdVersion)
+                                        -> io::Result<()> {
+        if version < ZstdVersion::FRAME_FORMAT {
+            return Err(incompatible("the current frame format"));
+        }
+        let window_log = try!(self.get_parameter(ll::ZSTD_c_windowLog));
+        self.compatibility = Some(version);
+        if let Err(e) = self.check_window_log(window_log as u32) {
+            self.compatibility = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Checks a window is small enough for the oldest decoder, if any.
+    fn check_window_log(&self, window_log: u32) -> io::Result<()> {
+        if self.compatibility.is_some() &&
+           window_log > DEFAULT_WINDOW_LOG_LIMIT {
+            return Err(incompatible("windows larger than 128MB"));
+        }
+        Ok(())
+    }
+
+    /// Announces the exact size of the next frame.
+    ///
+    /// It will be written in the frame header.
+    pub fn set_pledged_src_size(&self, size: u64) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_setPledgedSrcSize(self.c, size)
+        }));
+        Ok(())
+    }
+
+    /// Returns the current value of a compression parameter.
+    pub fn get_parameter(&self, param: ll::ZSTD_cParameter)
+                         -> io::Result<i32> {
+        let mut value = 0;
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_getParameter(self.c, param, &mut value)
+        }));
+        Ok(value)
+    }
+
+    /// Loads a dictionary, used for all following frames.
+    pub fn load_dictionary(&self, dictionary: &[u8]) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_loadDictionary(self.c,
+                                         dictionary.as_ptr(),
+                                         dictionary.len())
+        }));
+        Ok(())
+    }
+
+    /// References a prepared dictionary, used for all following frames.
+    ///
+    /// The dictionary is shared, not copied: this is cheap even for
+    /// many contexts at once.
+    pub fn ref_dictionary(&mut self, dictionary: Arc<EncoderDictionary>)
+                          -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_refCDict(self.c, dictionary.as_raw())
+        }));
+        self.prepared = Some(dictionary);
+        Ok(())
+    }
+
+    /// Resets the session, the parameters, or both.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_reset(self.c, directive.as_raw())
+        }));
+        if directive != ResetDirective::SessionOnly {
+            self.deterministic = false;
+            self.compatibility = None;
+            self.prepared = None;
+            #[cfg(feature = "experimental")]
+            {
+                self.producer = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers an external match-finder, used for all following frames.
+    ///
+    /// With `fallback`, blocks the producer can't handle are compressed by
+    /// zstd alone; otherwise, the compression fails.
+    #[cfg(feature = "experimental")]
+    pub fn set_sequence_producer(&mut self,
+                                 producer: Box<SequenceProducer>,
+                                 fallback: bool)
+                                 -> io::Result<()> {
+        try!(self.set_parameter(ll::ZSTD_c_enableSeqProducerFallback,
+                                fallback as i32));
+        let mut producer = Box::new(producer);
+        unsafe {
+            let state = &mut *producer as *mut Box<SequenceProducer>;
+            ll::ZSTD_registerSequenceProducer(self.c,
+                                              state as *mut c_void,
+                                              Some(produce_sequences));
+        }
+        self.producer = Some(producer);
+        Ok(())
+    }
+
+    /// Returns the progress of the current frame.
+    pub fn progression(&self) -> FrameProgression {
+        unsafe { ll::ZSTD_getFrameProgression(self.c) }.into()
+    }
+}
+
+// Refuses job sizes zstd doesn't accept, rather than letting them wrap.
+fn check_job_size(size: u32) -> io::Result<()> {
+    if size > ll::ZSTDMT_JOBSIZE_MAX as u32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "job size is too large"));
+    }
+    Ok(())
+}
+
+fn incompatible(what: &str) -> io::Error {
+    let msg = format!("{} would break format compatibility", what);
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// Calls the producer registered with `set_sequence_producer`.
+//
+// Panics must not unwind into C: they are reported as errors, like
+// invalid results.
+#[cfg(feature = "experimental")]
+unsafe extern "C" fn produce_sequences(state: *mut c_void,
+                                       out: *mut ll::ZSTD_Sequence,
+                                       capacity: size_t,
+                                       src: *const c_void,
+                                       src_size: size_t,
+                                       dict: *const c_void,
+                                       dict_size: size_t, level: c_int,
+                                       window_size: size_t)
+                                       -> size_t {
+    let producer = &mut *(state as *mut Box<SequenceProducer>);
+    // zstd doesn't initialize the output before handing it over.
+    ptr::write_bytes(out, 0, capacity);
+    let sequences = slice::from_raw_parts_mut(out as *mut Sequence,
+                                              capacity);
+    let src = as_slice(src, src_size);
+    let dict = as_slice(dict, dict_size);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        producer.produce(sequences, src, dict, level, window_size)
+    }));
+    match result {
+        Ok(Some(n)) if n <= capacity => n,
+        _ => ll::ZSTD_SEQUENCE_PRODUCER_ERROR,
+    }
+}
+
+// zstd passes NULL for empty buffers.
+#[cfg(feature = "experimental")]
+unsafe fn as_slice<'a>(data: *const c_void, size: size_t) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data as *const u8, size)
+    }
+}
+
+// A context can be moved to another thread, as long as it's used by one
+// thread at a time.
+unsafe impl Send for EncoderContext {}
+
+impl Drop for EncoderContext {
+    fn drop(&mut self) {
+        #[cfg(feature = "debug-leak-check")]
+        leak::unregister(self.c);
+        let code = unsafe { ll::ZSTD_freeCCtx(self.c) };
+        ll::parse_code(code).unwrap();
+    }
+}
+
+pub struct DecoderContext {
+    pub c: ll::ZSTDDecompressionContext,
+    // same as for `EncoderContext`
+    prepared: Option<Arc<DecoderDictionary>>,
+}
+
+impl Default for DecoderContext {
+    fn default() -> Self {
+        DecoderContext::new().unwrap()
+    }
+}
+
+impl DecoderContext {
+    /// Creates a new context, failing if it cannot be allocated.
+    pub fn new() -> io::Result<Self> {
+        let c = unsafe { ll::ZSTD_createDCtx_advanced(memory::custom_mem()) };
+        if c.is_null() {
+            return Err(memory::allocation_error("decompression context"));
+        }
+        #[cfg(feature = "debug-leak-check")]
+        leak::register("decompression context", c);
+        Ok(DecoderContext {
+            c: c,
+            prepared: None,
+        })
+    }
+
+    /// Sets a typed decompression parameter.
+    pub fn set_dparameter(&self, param: DParameter) -> io::Result<()> {
+        let (param, value) = param.as_raw();
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_DCtx_setParameter(self.c, param, value)
+        }));
+        Ok(())
+    }
+
+    /// Loads a dictionary, used for all following frames.
+    pub fn load_dictionary(&self, dictionary: &[u8]) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_DCtx_loadDictionary(self.c,
+                                         dictionary.as_ptr(),
+                                         dictionary.len())
+        }));
+        Ok(())
+    }
+
+    /// References a prepared dictionary, used for all following frames.
+    pub fn ref_dictionary(&mut self, dictionary: Arc<DecoderDictionary>)
+                          -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_DCtx_refDDict(self.c, dictionary.as_raw())
+        }));
+        self.prepared = Some(dictionary);
+        Ok(())
+    }
+
+    /// Resets the session, the parameters, or both.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_DCtx_reset(self.c, directive.as_raw())
+        }));
+        if directive != ResetDirective::SessionOnly {
+            self.prepared = None;
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Send for DecoderContext {}
+
+impl Drop for DecoderContext {
+    fn drop(&mut self) {
+        #[cfg(feature = "debug-leak-check")]
+        leak::unregister(self.c);
+        let code = unsafe { ll::ZSTD_freeDCtx(self.c) };
+        ll::parse_code(code).unwrap();
+    }
+}
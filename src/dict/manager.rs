@@ -0,0 +1,224 @@
+use dict::{DecoderDictionary, EncoderDictionary};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Caches prepared dictionaries, for services using many dictionaries.
+///
+/// Dictionaries are registered with a key (an application key, or simply
+/// the dictionary ID), and prepared on demand for compression or
+/// decompression. Prepared dictionaries are kept within a memory budget,
+/// evicting the least recently used ones first.
+///
+/// The manager can be shared between threads, for instance in an `Arc`.
+pub struct DictManager<K> {
+    inner: Mutex<Inner<K>>,
+}
+
+struct Inner<K> {
+    // dictionary content, by key
+    contents: HashMap<K, Arc<Vec<u8>>>,
+    // prepared dictionaries
+    cache: HashMap<(K, Usage), Cached>,
+    // memory used by the prepared dictionaries
+    memory: usize,
+    max_memory: usize,
+    // incremented on each access, to find the least recently used entry
+    tick: u64,
+}
+
+// What a dictionary was prepared for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Usage {
+    // compression, at the given level
+    Encoder(i32),
+    Decoder,
+}
+
+#[derive(Clone)]
+enum Prepared {
+    Encoder(Arc<EncoderDictionary>),
+    Decoder(Arc<DecoderDictionary>),
+}
+
+struct Cached {
+    prepared: Prepared,
+    size: usize,
+    last_used: u64,
+}
+
+impl<K: Hash + Eq + Clone> DictManager<K> {
+    /// Creates a manager keeping at most `max_memory` bytes of prepared
+    /// dictionaries.
+    ///
+    /// Dictionaries still in use when evicted stay alive until dropped,
+    /// but are no longer counted.
+    pub fn new(max_memory: usize) -> Self {
+        DictManager {
+            inner: Mutex::new(Inner {
+                contents: HashMap::new(),
+                cache: HashMap::new(),
+                memory: 0,
+                max_memory: max_memory,
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Registers a dictionary under the given key.
+    ///
+    /// Replaces any dictionary previously registered with this key.
+    pub fn insert(&self, key: K, dictionary: Vec<u8>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_prepared(&key);
+        inner.contents.insert(key, Arc::new(dictionary));
+    }
+
+    /// Forgets the dictionary registered under the given key.
+    pub fn remove(&self, key: &K) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_prepared(key);
+        inner.contents.remove(key);
+    }
+
+    /// Returns the memory used by the cached prepared dictionaries.
+    pub fn memory_usage(&self) -> usize {
+        self.inner.lock().unwrap().memory
+    }
+
+    /// Returns the dictionary registered under `key`, prepared for
+    /// compression at the given level.
+    ///
+    /// Returns `None` if no dictionary is registered under this key.
+    pub fn encoder_dictionary(&self, key: &K, level: i32)
+                              -> io::Result<Option<Arc<EncoderDictionary>>> {
+        let prepared = try!(self.get(key, Usage::Encoder(level), |content| {
+            let dict = try!(EncoderDictionary::new(content, level));
+            let size = dict.size();
+            Ok((Prepared::Encoder(Arc::new(dict)), size))
+        }));
+        Ok(prepared.map(|prepared| match prepared {
+            Prepared::Encoder(dict) => dict,
+            Prepared::Decoder(_) => unreachable!(),
+        }))
+    }
+
+    /// Returns the dictionary registered under `key`, prepared for
+    /// decompression.
+    ///
+    /// Returns `None` if no dictionary is registered under this key.
+    pub fn decoder_dictionary(&self, key: &K)
+                              -> io::Result<Option<Arc<DecoderDictionary>>> {
+        let prepared = try!(self.get(key, Usage::Decoder, |content| {
+            let dict = try!(DecoderDictionary::new(content));
+            let size = dict.size();
+            Ok((Prepared::Decoder(Arc::new(dict)), size))
+        }));
+        Ok(prepared.map(|prepared| match prepared {
+            Prepared::Decoder(dict) => dict,
+            Prepared::Encoder(_) => unreachable!(),
+        }))
+    }
+
+    // Returns the cached dictionary, or prepares it.
+    fn get<F>(&self, key: &K, usage: Usage, prepare: F)
+              -> io::Result<Option<Prepared>>
+        where F: FnOnce(&[u8]) -> io::Result<(Prepared, usize)>
+    {
+        let cache_key = (key.clone(), usage);
+        let content = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some(cached) = inner.cache.get_mut(&cache_key) {
+                cached.last_used = tick;
+                return Ok(Some(cached.prepared.clone()));
+            }
+            match inner.contents.get(key) {
+                Some(content) => content.clone(),
+                None => return Ok(None),
+            }
+        };
+
+        // Don't block other threads while preparing the dictionary.
+        let (prepared, size) = try!(prepare(&content));
+
+        let mut inner = self.inner.lock().unwrap();
+        // The dictionary may have been replaced in the meantime.
+        let current = inner.contents
+            .get(key)
+            .map_or(false, |c| Arc::ptr_eq(c, &content));
+        if current && !inner.cache.contains_key(&cache_key) {
+            let tick = inner.tick;
+            inner.cache.insert(cache_key, Cached {
+                prepared: prepared.clone(),
+                size: size,
+                last_used: tick,
+            });
+            inner.memory += size;
+            inner.evict();
+        }
+        Ok(Some(prepared))
+    }
+}
+
+impl<K: Hash + Eq + Clone> Inner<K> {
+    // Evicts the least recently used dictionaries, until within budget.
+    fn evict(&mut self) {
+        while self.memory > self.max_memory {
+            let oldest = self.cache
+                .iter()
+                .min_by_key(|&(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => {
+                    let cached = self.cache.remove(&key).unwrap();
+                    self.memory -= cached.size;
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Drops all the prepared versions of a dictionary.
+    fn remove_prepared(&mut self, key: &K) {
+        let keys: Vec<_> = self.cache
+            .keys()
+            .filter(|&&(ref k, _)| k == key)
+            .cloned()
+            .collect();
+        for key in keys {
+            let cached = self.cache.remove(&key).unwrap();
+            self.memory -= cached.size;
+        }
+    }
+}
+
+#[test]
+fn test_manager() {
+    let manager = DictManager::new(usize::max_value());
+    manager.insert(1, b"some dictionary content".to_vec());
+
+    assert!(manager.encoder_dictionary(&2, 3).unwrap().is_none());
+    let first = manager.encoder_dictionary(&1, 3).unwrap().unwrap();
+    let second = manager.encoder_dictionary(&1, 3).unwrap().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(manager.decoder_dictionary(&1).unwrap().is_some());
+    assert!(manager.memory_usage() > 0);
+
+    manager.remove(&1);
+    assert_eq!(manager.memory_usage(), 0);
+    assert!(manager.decoder_dictionary(&1).unwrap().is_none());
+}
+
+#[test]
+fn test_manager_eviction() {
+    let manager = DictManager::new(0);
+    manager.insert("tenant", b"some dictionary content".to_vec());
+
+    // Still returned, but not kept.
+    assert!(manager.encoder_dictionary(&"tenant", 3).unwrap().is_some());
+    assert_eq!(manager.memory_usage(), 0);
+}
@@ -0,0 +1,931 @@
+//! Train a dictionary from various sources.
+//!
+//! A dictionary can help improve the compression of small files.
+//! The dictionary must be present during decompression,
+//! but can be shared accross multiple "similar" files.
+//!
+//! Creating a dictionary using the `zstd` C library,
+//! using the `zstd` command-line interface, using this library,
+//! or using the `train` binary provided, should give the same result,
+//! and are therefore completely compatible.
+//!
+//! To use, see [`Encoder::with_dictionary`] or [`Decoder::with_dictionary`].
+//!
+//! A [`Dictionary`](struct.Dictionary.html) holds a dictionary with its ID,
+//! and can be passed wherever a dictionary is expected.
+//!
+//! When the same dictionary is used many times, it can be prepared once
+//! as an [`EncoderDictionary`](struct.EncoderDictionary.html)
+//! or a [`DecoderDictionary`](struct.DecoderDictionary.html).
+//! A [`DictManager`](struct.DictManager.html) caches prepared dictionaries
+//! for services using many of them.
+//!
+//! [`Encoder::with_dictionary`]: ../struct.Encoder.html#method.with_dictionary
+//! [`Decoder::with_dictionary`]: ../struct.Decoder.html#method.with_dictionary
+
+use ll;
+use memory;
+use bulk::Compressor;
+use xxhash::xxh64;
+
+mod manager;
+
+pub use self::manager::DictManager;
+
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::io::{self, Read, Write};
+use std::ops::{Deref, Range};
+use std::path;
+use std::fs;
+
+/// A dictionary prepared for compression.
+///
+/// Loading a dictionary has a cost; preparing it once lets
+/// multiple compressions re-use it for free.
+///
+/// The compression level is fixed when the dictionary is prepared.
+///
+/// Wrapped in an `Arc`, it can be referenced by any number of encoders at
+/// once (see `Encoder::with_prepared_dictionary`), across threads: the
+/// dictionary is never modified, and outlives every encoder using it.
+pub struct EncoderDictionary {
+    cdict: ll::ZSTDCompressionDictionary,
+}
+
+impl EncoderDictionary {
+    /// Prepares the given dictionary for compression at the given level.
+    ///
+    /// The dictionary content is copied, and can be dropped afterward.
+    pub fn new(dictionary: &[u8], level: i32) -> io::Result<Self> {
+        EncoderDictionary::create(dictionary, level, false)
+    }
+
+    /// Prepares the given dictionary, using a dedicated search structure.
+    ///
+    /// This speeds up compression of small inputs with this dictionary,
+    /// at the cost of a larger dictionary in memory and a slower
+    /// preparation. It only has an effect for levels using the
+    /// greedy, lazy and lazy2 strategies (roughly levels 5 to 12);
+    /// other levels silently fall back to the regular search.
+    pub fn with_dedicated_search(dictionary: &[u8], level: i32)
+                                 -> io::Result<Self> {
+        EncoderDictionary::create(dictionary, level, true)
+    }
+
+    fn create(dictionary: &[u8], level: i32, dedicated_search: bool)
+              -> io::Result<Self> {
+        let cdict = unsafe {
+            let params = ll::ZSTD_createCCtxParams();
+            let result = ll::parse_code(ll::ZSTD_CCtxParams_init(params,
+                                                                 level))
+                .and_then(|_| {
+                    ll::parse_code(ll::ZSTD_CCtxParams_setParameter(
+                        params,
+                        ll::ZSTD_c_enableDedicatedDictSearch,
+                        dedicated_search as i32))
+                })
+                .map(|_| {
+                    ll::ZSTD_createCDict_advanced2(dictionary.as_ptr(),
+                                                   dictionary.len(),
+                                                   ll::ZSTD_dlm_byCopy,
+                                                   ll::ZSTD_dct_auto,
+                                                   params,
+                                                   memory::custom_mem())
+                });
+            ll::ZSTD_freeCCtxParams(params);
+            try!(result)
+        };
+        if cdict.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "could not prepare dictionary"));
+        }
+        Ok(EncoderDictionary { cdict: cdict })
+    }
+
+    /// Returns the memory used by this prepared dictionary, in bytes.
+    pub fn size(&self) -> usize {
+        unsafe { ll::ZSTD_sizeof_CDict(self.cdict) }
+    }
+
+    pub(crate) fn as_raw(&self) -> ll::ZSTDCompressionDictionary {
+        self.cdict
+    }
+}
+
+// A prepared dictionary is never modified after its creation,
+// and zstd allows using it from several threads at once.
+unsafe impl Send for EncoderDictionary {}
+unsafe impl Sync for EncoderDictionary {}
+
+impl Drop for EncoderDictionary {
+    fn drop(&mut self) {
+        let code = unsafe { ll::ZSTD_freeCDict(self.cdict) };
+        ll::parse_code(code).unwrap();
+    }
+}
+
+/// A dictionary prepared for decompression.
+///
+/// Like [`EncoderDictionary`](struct.EncoderDictionary.html), this avoids
+/// loading the dictionary again for each decompression.
+pub struct DecoderDictionary {
+    ddict: ll::ZSTDDecompressionDictionary,
+}
+
+impl DecoderDictionary {
+    /// Prepares the given dictionary for decompression.
+    ///
+    /// The dictionary content is copied, and can be dropped afterward.
+    pub fn new(dictionary: &[u8]) -> io::Result<Self> {
+        let ddict = unsafe {
+            ll::ZSTD_createDDict_advanced(dictionary.as_ptr(),
+                                          dictionary.len(),
+                                          ll::ZSTD_dlm_byCopy,
+                                          ll::ZSTD_dct_auto,
+                                          memory::custom_mem())
+        };
+        if ddict.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "could not prepare dictionary"));
+        }
+        Ok(DecoderDictionary { ddict: ddict })
+    }
+
+    /// Returns the memory used by this prepared dictionary, in bytes.
+    pub fn size(&self) -> usize {
+        unsafe { ll::ZSTD_sizeof_DDict(self.ddict) }
+    }
+
+    pub(crate) fn as_raw(&self) -> ll::ZSTDDecompressionDictionary {
+        self.ddict
+    }
+}
+
+// Same as `EncoderDictionary`.
+unsafe impl Send for DecoderDictionary {}
+unsafe impl Sync for DecoderDictionary {}
+
+impl Drop for DecoderDictionary {
+    fn drop(&mut self) {
+        let code = unsafe { ll::ZSTD_freeDDict(self.ddict) };
+        ll::parse_code(code).unwrap();
+    }
+}
+
+/// A dictionary, with its ID.
+///
+/// A `Dictionary` dereferences to its content, so it can be given
+/// to any function expecting a dictionary as `&[u8]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    content: Vec<u8>,
+}
+
+// First bytes of a dictionary in the zstd format.
+const DICT_MAGIC: u32 = 0xEC30A437;
+
+impl Dictionary {
+    /// Loads a dictionary in the zstd format.
+    ///
+    /// Returns an `InvalidData` error if the data doesn't start
+    /// with a valid dictionary header.
+    pub fn from_bytes(content: Vec<u8>) -> io::Result<Self> {
+        if content.len() < 8 || read_u32_le(&content[..4]) != DICT_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "not a zstd dictionary"));
+        }
+        Ok(Dictionary {
+            id: read_u32_le(&content[4..8]),
+            content: content,
+        })
+    }
+
+    /// Uses arbitrary data as a dictionary.
+    ///
+    /// Without the zstd dictionary header, the content is only used to
+    /// find matches, and the dictionary has no ID (`0`).
+    pub fn from_raw_content(content: Vec<u8>) -> Self {
+        Dictionary {
+            id: 0,
+            content: content,
+        }
+    }
+
+    /// Loads a dictionary in the zstd format from a file.
+    pub fn from_file<P: AsRef<path::Path>>(path: P) -> io::Result<Self> {
+        let mut content = Vec::new();
+        let mut file = try!(fs::File::open(path));
+        try!(file.read_to_end(&mut content));
+        Dictionary::from_bytes(content)
+    }
+
+    /// Writes the content of this dictionary, for instance to a file.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.content)
+    }
+
+    /// Returns the ID of this dictionary.
+    ///
+    /// Frames compressed with this dictionary record this ID.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the content of this dictionary.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Returns the content of this dictionary, consuming it.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.content
+    }
+}
+
+impl Deref for Dictionary {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.content
+    }
+}
+
+impl AsRef<[u8]> for Dictionary {
+    fn as_ref(&self) -> &[u8] {
+        &self.content
+    }
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+// Segment sizes tried by `ZDICT_trainFromBuffer`: 4 steps from 50 to
+// 2000.
+const TRAIN_SEGMENT_SIZES: [u32; 5] = [50, 537, 1024, 1511, 1998];
+
+/// Samples laid out for training.
+///
+/// Training works best on many small samples. `zstd` expects them
+/// concatenated in a single buffer, along with the size of each sample:
+/// these helpers build this layout from a corpus.
+#[derive(Debug, Clone, Default)]
+pub struct Samples {
+    data: Vec<u8>,
+    sizes: Vec<usize>,
+}
+
+impl Samples {
+    /// Splits `corpus` after each occurrence of `delimiter`.
+    ///
+    /// The delimiter is kept at the end of each sample.
+    /// For instance, use `b'\n'` for log files.
+    pub fn split_by_delimiter(corpus: &[u8], delimiter: u8) -> Self {
+        let sizes = corpus.split(|&b| b == delimiter)
+            .map(|sample| sample.len() + 1)
+            .collect::<Vec<_>>();
+        let mut samples = Samples {
+            data: corpus.to_vec(),
+            sizes: sizes,
+        };
+        // The last piece has no delimiter after it.
+        let last = samples.sizes.pop().unwrap() - 1;
+        if last > 0 {
+            samples.sizes.push(last);
+        }
+        samples
+    }
+
+    /// Splits `corpus` in records of `record_size` bytes.
+    ///
+    /// The last sample may be shorter.
+    ///
+    /// # Panics
+    ///
+    /// If `record_size` is 0.
+    pub fn split_by_size(corpus: &[u8], record_size: usize) -> Self {
+        Samples {
+            data: corpus.to_vec(),
+            sizes: corpus.chunks(record_size).map(|c| c.len()).collect(),
+        }
+    }
+
+    /// Takes each range of `corpus` as a sample.
+    ///
+    /// Ranges may overlap, or leave parts of the corpus out.
+    ///
+    /// # Panics
+    ///
+    /// If a range is out of the corpus bounds.
+    pub fn from_ranges<I>(corpus: &[u8], ranges: I) -> Self
+        where I: IntoIterator<Item = Range<usize>>
+    {
+        let mut samples = Samples::default();
+        for range in ranges {
+            samples.push(&corpus[range]);
+        }
+        samples
+    }
+
+    /// Adds a sample.
+    pub fn push(&mut self, sample: &[u8]) {
+        self.data.extend_from_slice(sample);
+        self.sizes.push(sample.len());
+    }
+
+    /// Returns the concatenated samples.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the size of each sample.
+    pub fn sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    /// Trains a dictionary from these samples.
+    ///
+    /// See [`from_continuous`](fn.from_continuous.html).
+    pub fn train(&self, max_size: usize) -> io::Result<Vec<u8>> {
+        from_continuous(&self.data, &self.sizes, max_size)
+    }
+
+    /// Trains a dictionary from these samples, reporting progress.
+    ///
+    /// This gives the same dictionary as [`train`](#method.train), which
+    /// tries several segment sizes and keeps the best dictionary; here,
+    /// they are tried one at a time. After each of them, `progress` is
+    /// called with the number of sizes tried and the total number of
+    /// sizes. If it returns `false`, training is cancelled and this
+    /// returns an error.
+    ///
+    /// zstd reports nothing while it tries a size, so progress comes in a
+    /// few coarse steps, which may take a while each on large corpora.
+    pub fn train_with_progress<F>(&self, max_size: usize, mut progress: F)
+                                  -> io::Result<Vec<u8>>
+        where F: FnMut(usize, usize) -> bool
+    {
+        try!(check_sample_sizes(&self.data, &self.sizes));
+
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        let mut error = None;
+        for (i, &k) in TRAIN_SEGMENT_SIZES.iter().enumerate() {
+            match self.train_segment(max_size, k) {
+                Ok(dict) => {
+                    let score = try!(self.score(&dict));
+                    if best.as_ref()
+                        .map_or(true, |&(best_score, _)| score < best_score) {
+                        best = Some((score, dict));
+                    }
+                }
+                // zstd skips the sizes that fail, too.
+                Err(e) => error = Some(e),
+            }
+
+            if !progress(i + 1, TRAIN_SEGMENT_SIZES.len()) {
+                return Err(io::Error::new(io::ErrorKind::Other,
+                                          "training cancelled"));
+            }
+        }
+
+        match best {
+            Some((_, dict)) => Ok(dict),
+            None => Err(error.unwrap()),
+        }
+    }
+
+    // Trains a dictionary with the given segment size, and the other
+    // parameters `ZDICT_trainFromBuffer` uses.
+    fn train_segment(&self, max_size: usize, k: u32) -> io::Result<Vec<u8>> {
+        let mut params = ll::ZDICT_fastCover_params_t::default();
+        params.k = k;
+        params.d = 8;
+        params.zParams.compressionLevel = 3;
+
+        let mut result = Vec::with_capacity(max_size);
+        let nb_samples = self.sizes.len() as u32;
+        unsafe {
+            let code = ll::ZDICT_optimizeTrainFromBuffer_fastCover(
+                result.as_mut_ptr(), result.capacity(),
+                self.data.as_ptr(), self.sizes.as_ptr(), nb_samples,
+                &mut params);
+            let written = try!(ll::parse_code(code));
+            result.set_len(written);
+        }
+        Ok(result)
+    }
+
+    // Scores a dictionary like zstd does: its size, plus the compressed
+    // size of the samples left out of training (the last 25%).
+    fn score(&self, dict: &[u8]) -> io::Result<usize> {
+        let nb_train = (self.sizes.len() as f64 * 0.75) as usize;
+        let dict_size = dict.len();
+        let dict = try!(EncoderDictionary::new(dict, 3));
+        let mut compressor = Compressor::new();
+        let mut offset = self.sizes[..nb_train].iter().sum();
+        let mut total = dict_size;
+        for &size in &self.sizes[nb_train..] {
+            let sample = &self.data[offset..offset + size];
+            total += try!(compressor.compress_using_dict(sample, &dict)).len();
+            offset += size;
+        }
+        Ok(total)
+    }
+}
+
+/// Collects samples from a stream, keeping a bounded amount in memory.
+///
+/// When more data than `max_memory` is added, a uniform random selection
+/// of the samples is kept. This allows training from very large corpora.
+/// The selection is deterministic: the same samples added in the same
+/// order give the same dictionary.
+///
+/// Degenerate samples, which slow training down and skew the dictionary,
+/// can be dropped as they are added: see
+/// [`set_min_sample_size`](#method.set_min_sample_size) and
+/// [`set_deduplicate`](#method.set_deduplicate). The
+/// [`discarded`](#method.discarded) method reports what was dropped.
+pub struct DictTrainer {
+    // kept samples, by random priority: the lowest ones are evicted first
+    samples: BinaryHeap<Reverse<(u64, Vec<u8>)>>,
+    // total size of the kept samples
+    size: usize,
+    max_memory: usize,
+    // state of the random generator (xorshift)
+    rng: u64,
+    // samples shorter than this are dropped
+    min_sample_size: usize,
+    // hashes of the samples seen so far, if dropping duplicates
+    seen: Option<HashSet<u64>>,
+    discarded: Discarded,
+}
+
+/// Samples a [`DictTrainer`](struct.DictTrainer.html) didn't keep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Discarded {
+    /// Samples shorter than the minimum size.
+    pub too_small: usize,
+    /// Samples identical to one added before.
+    pub duplicates: usize,
+    /// Samples evicted to respect the memory limit.
+    pub evicted: usize,
+    /// Total size of the samples above, in bytes.
+    pub bytes: usize,
+}
+
+impl DictTrainer {
+    /// Creates a trainer keeping at most `max_memory` bytes of samples.
+    pub fn new(max_memory: usize) -> Self {
+        DictTrainer {
+            samples: BinaryHeap::new(),
+            size: 0,
+            max_memory: max_memory,
+            rng: 0x2545F4914F6CDD1D,
+            min_sample_size: 0,
+            seen: None,
+            discarded: Discarded::default(),
+        }
+    }
+
+    /// Drops the samples added from now on that are shorter than `size`.
+    ///
+    /// Near-empty samples carry nothing to learn from.
+    pub fn set_min_sample_size(&mut self, size: usize) {
+        self.min_sample_size = size;
+    }
+
+    /// Drops the samples added from now on that are identical to one
+    /// added before.
+    ///
+    /// Samples are told apart by a 64-bit hash, kept for each sample
+    /// seen: this takes 8 bytes per distinct sample, on top of
+    /// `max_memory`.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        if !deduplicate {
+            self.seen = None;
+        } else if self.seen.is_none() {
+            self.seen = Some(HashSet::new());
+        }
+    }
+
+    /// Adds a sample.
+    ///
+    /// The sample is copied; it may be dropped later to respect the
+    /// memory limit.
+    pub fn add_sample(&mut self, sample: &[u8]) {
+        if sample.len() < self.min_sample_size {
+            self.discarded.too_small += 1;
+            self.discarded.bytes += sample.len();
+            return;
+        }
+        if let Some(ref mut seen) = self.seen {
+            if !seen.insert(xxh64(sample, 0)) {
+                self.discarded.duplicates += 1;
+                self.discarded.bytes += sample.len();
+                return;
+            }
+        }
+
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        self.samples.push(Reverse((self.rng, sample.to_vec())));
+        self.size += sample.len();
+        while self.size > self.max_memory {
+            let Reverse((_, evicted)) = self.samples.pop().unwrap();
+            self.size -= evicted.len();
+            self.discarded.evicted += 1;
+            self.discarded.bytes += evicted.len();
+        }
+    }
+
+    /// Returns what was dropped so far.
+    pub fn discarded(&self) -> Discarded {
+        self.discarded
+    }
+
+    /// Returns the number of samples currently kept.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if no sample is kept.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the kept samples, laid out for training.
+    pub fn into_samples(self) -> Samples {
+        let mut samples = Samples::default();
+        for Reverse((_, sample)) in self.samples.into_vec() {
+            samples.push(&sample);
+        }
+        samples
+    }
+
+    /// Trains a dictionary from the kept samples.
+    pub fn train(self, max_size: usize) -> io::Result<Vec<u8>> {
+        self.into_samples().train(max_size)
+    }
+}
+
+/// Compression results for a single sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleStats {
+    /// Size of the sample.
+    pub size: usize,
+    /// Compressed size, without dictionary.
+    pub compressed: usize,
+    /// Compressed size, with the dictionary.
+    pub compressed_with_dict: usize,
+}
+
+/// How well a dictionary compresses a set of samples.
+///
+/// See [`evaluate`](fn.evaluate.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    /// Results for each sample, in order.
+    pub samples: Vec<SampleStats>,
+}
+
+impl Evaluation {
+    /// Compression ratio over all the samples, without dictionary.
+    ///
+    /// This is the total size divided by the total compressed size.
+    pub fn ratio(&self) -> f64 {
+        self.ratio_by(|s| s.compressed)
+    }
+
+    /// Compression ratio over all the samples, with the dictionary.
+    pub fn ratio_with_dict(&self) -> f64 {
+        self.ratio_by(|s| s.compressed_with_dict)
+    }
+
+    fn ratio_by<F: Fn(&SampleStats) -> usize>(&self, compressed: F) -> f64 {
+        let size: usize = self.samples.iter().map(|s| s.size).sum();
+        let compressed: usize = self.samples.iter().map(compressed).sum();
+        size as f64 / compressed as f64
+    }
+}
+
+/// Compresses each sample with and without `dictionary`, at the given level.
+///
+/// This helps deciding whether a dictionary, or a newly trained one,
+/// is worth using. Samples should be representative of the data to
+/// compress, and not part of the training set.
+pub fn evaluate<S: AsRef<[u8]>>(dictionary: &[u8], samples: &[S], level: i32)
+                                -> io::Result<Evaluation> {
+    let dict = try!(EncoderDictionary::new(dictionary, level));
+    let mut compressor = Compressor::new();
+
+    let mut stats = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let sample = sample.as_ref();
+        let compressed = try!(compressor.compress(sample, level)).len();
+        let compressed_with_dict =
+            try!(compressor.compress_using_dict(sample, &dict)).len();
+        stats.push(SampleStats {
+            size: sample.len(),
+            compressed: compressed,
+            compressed_with_dict: compressed_with_dict,
+        });
+    }
+
+    Ok(Evaluation { samples: stats })
+}
+
+/// Train a dictionary from a big continuous chunk of data,
+/// with the given ID.
+///
+/// Works like [`from_continuous`](fn.from_continuous.html);
+/// `max_size` is the maximum size of the dictionary.
+/// A `dict_id` of `0` picks a random ID.
+pub fn train_with_id(sample_data: &[u8], sample_sizes: &[usize],
+                     max_size: usize, dict_id: u32)
+                     -> io::Result<Dictionary> {
+    try!(check_sample_sizes(sample_data, sample_sizes));
+
+    // Same parameters as `ZDICT_trainFromBuffer`.
+    let mut params = ll::ZDICT_fastCover_params_t::default();
+    params.d = 8;
+    params.steps = 4;
+    params.zParams.compressionLevel = 3;
+    params.zParams.dictID = dict_id;
+
+    let mut result = Vec::with_capacity(max_size);
+    let nb_samples = sample_sizes.len() as u32;
+    let id = unsafe {
+        let code = ll::ZDICT_optimizeTrainFromBuffer_fastCover(
+            result.as_mut_ptr(), result.capacity(),
+            sample_data.as_ptr(), sample_sizes.as_ptr(), nb_samples,
+            &mut params);
+        let written = try!(ll::parse_code(code));
+        result.set_len(written);
+        ll::ZDICT_getDictID(result.as_ptr(), result.len())
+    };
+
+    Ok(Dictionary {
+        id: id,
+        content: result,
+    })
+}
+
+// Complain if the lengths don't add up to the entire data.
+fn check_sample_sizes(sample_data: &[u8], sample_sizes: &[usize])
+                      -> io::Result<()> {
+    if sample_sizes.iter().fold(0, |a, b| a + b) != sample_data.len() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  "sample sizes don't add up".to_string()));
+    }
+    Ok(())
+}
+
+/// Parameters for the COVER training algorithm.
+///
+/// Parameters left to 0 are optimized by
+/// [`optimize_cover`](fn.optimize_cover.html).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CoverParams {
+    /// Segment size. Reasonable values range from 16 to 2048 and more.
+    pub k: u32,
+    /// Size of the matches looked for (dmer size), at most `k`.
+    /// Reasonable values range from 6 to 16.
+    pub d: u32,
+    /// Number of `k` values tried when optimizing (0 means 40).
+    ///
+    /// More steps find better parameters, but take longer.
+    pub steps: u32,
+    /// Fraction of the samples used for training, the rest being used
+    /// to evaluate the dictionaries (0 means 1.0: all samples are used
+    /// for both).
+    pub split_point: f64,
+    /// Number of threads trying parameters in parallel (0 means 1).
+    ///
+    /// Each thread needs its own working memory, about the size of the
+    /// samples. Without the `zstdmt` feature, training is
+    /// single-threaded.
+    pub threads: u32,
+}
+
+/// Train a dictionary with the COVER algorithm, searching for the best
+/// parameters.
+///
+/// `params` fixes some parameters, leaving those at 0 to be optimized.
+/// Returns the dictionary and the parameters that produced it.
+pub fn optimize_cover(sample_data: &[u8], sample_sizes: &[usize],
+                      max_size: usize, params: CoverParams)
+                      -> io::Result<(Vec<u8>, CoverParams)> {
+    try!(check_sample_sizes(sample_data, sample_sizes));
+
+    let mut raw = ll::ZDICT_cover_params_t::default();
+    raw.k = params.k;
+    raw.d = params.d;
+    raw.steps = params.steps;
+    raw.nbThreads = cmp::max(params.threads, 1);
+    raw.splitPoint = params.split_point;
+
+    let mut result = Vec::with_capacity(max_size);
+    let nb_samples = sample_sizes.len() as u32;
+    unsafe {
+        let code = ll::ZDICT_optimizeTrainFromBuffer_cover(
+            result.as_mut_ptr(), result.capacity(),
+            sample_data.as_ptr(), sample_sizes.as_ptr(), nb_samples,
+            &mut raw);
+        let written = try!(ll::parse_code(code));
+        result.set_len(written);
+    }
+
+    let chosen = CoverParams {
+        k: raw.k,
+        d: raw.d,
+        steps: raw.steps,
+        split_point: raw.splitPoint,
+        threads: params.threads,
+    };
+    Ok((result, chosen))
+}
+
+/// Train a dictionary from a big continuous chunk of data.
+///
+/// This is the most efficient way to train a dictionary,
+/// since this is directly fed into `zstd`.
+pub fn from_continuous(sample_data: &[u8], sample_sizes: &[usize],
+                       max_size: usize)
+                       -> io::Result<Vec<u8>> {
+    try!(check_sample_sizes(sample_data, sample_sizes));
+
+    let mut result = Vec::with_capacity(max_size);
+    unsafe {
+        let code = ll::ZDICT_trainFromBuffer(result.as_mut_ptr(),
+                                             result.capacity(),
+                                             sample_data.as_ptr(),
+                                             sample_sizes.as_ptr(),
+                                             sample_sizes.len() as u32);
+        let written = try!(ll::parse_code(code));
+        result.set_len(written);
+    }
+    Ok(result)
+}
+
+/// Train a dictionary from multiple samples.
+///
+/// The samples will internaly be copied to a single continuous buffer,
+/// so make sure you have enough memory available.
+pub fn from_samples<S: AsRef<[u8]>>(samples: &[S], max_size: usize)
+                                    -> io::Result<Vec<u8>> {
+    // Copy every sample to a big chunk of memory
+    let data: Vec<_> = samples.iter()
+        .flat_map(|s| s.as_ref())
+        .cloned()
+        .collect();
+    let sizes: Vec<_> = samples.iter().map(|s| s.as_ref().len()).collect();
+
+    from_continuous(&data, &sizes, max_size)
+}
+
+/// Train a dict from a list of files.
+pub fn from_files<P: AsRef<path::Path>>(filenames: &[P], max_size: usize)
+                                        -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut sizes = Vec::new();
+
+    for filename in filenames {
+        let mut file = try!(fs::File::open(filename));
+        let len = try!(file.read_to_end(&mut buffer));
+        sizes.push(len);
+    }
+
+    from_continuous(&buffer, &sizes, max_size)
+}
+
+#[test]
+fn test_dictionary_header() {
+    assert!(Dictionary::from_bytes(b"not a dictionary".to_vec()).is_err());
+
+    let mut content = vec![0x37, 0xA4, 0x30, 0xEC, 42, 0, 0, 0];
+    content.extend_from_slice(b"some entropy tables");
+    let dict = Dictionary::from_bytes(content.clone()).unwrap();
+    assert_eq!(dict.id(), 42);
+
+    let mut written = Vec::new();
+    dict.write_to(&mut written).unwrap();
+    assert_eq!(written, content);
+}
+
+#[test]
+fn test_samples() {
+    let samples = Samples::split_by_delimiter(b"a\nbc\n\ndef", b'\n');
+    assert_eq!(samples.sizes(), &[2, 3, 1, 3]);
+
+    let samples = Samples::split_by_delimiter(b"a\nbc\n", b'\n');
+    assert_eq!(samples.sizes(), &[2, 3]);
+
+    let samples = Samples::split_by_size(b"abcdefg", 3);
+    assert_eq!(samples.sizes(), &[3, 3, 1]);
+
+    let samples = Samples::from_ranges(b"abcdefg", vec![4..6, 0..2]);
+    assert_eq!(samples.data(), b"efab");
+    assert_eq!(samples.sizes(), &[2, 2]);
+}
+
+#[test]
+fn test_evaluate() {
+    let samples: Vec<Vec<u8>> = (0..10)
+        .map(|i| format!("{{\"id\": {}, \"kind\": \"event\"}}", i)
+            .into_bytes())
+        .collect();
+    let dict = b"{\"id\": , \"kind\": \"event\"}";
+
+    let evaluation = evaluate(dict, &samples, 3).unwrap();
+    assert_eq!(evaluation.samples.len(), 10);
+    assert!(evaluation.ratio_with_dict() > evaluation.ratio());
+}
+
+#[test]
+fn test_train_with_progress() {
+    let mut samples = Samples::default();
+    for i in 0..1000 {
+        let sample = format!("{{\"id\": {}, \"kind\": \"event\", \
+                              \"score\": {}}}",
+                             i,
+                             i * 7 % 13);
+        samples.push(sample.as_bytes());
+    }
+
+    let mut steps = Vec::new();
+    let dict = samples.train_with_progress(4096, |done, total| {
+            steps.push((done, total));
+            true
+        })
+        .unwrap();
+    assert_eq!(steps, vec![(1, 5), (2, 5), (3, 5), (4, 5), (5, 5)]);
+    assert_eq!(dict, samples.train(4096).unwrap());
+
+    assert!(samples.train_with_progress(4096, |_, _| false).is_err());
+}
+
+#[test]
+fn test_trainer_memory() {
+    let mut trainer = DictTrainer::new(1000);
+    for i in 0..1000 {
+        trainer.add_sample(format!("sample {:05}", i).as_bytes());
+    }
+    assert_eq!(trainer.len(), 1000 / 12);
+
+    let samples = trainer.into_samples();
+    assert!(samples.data().len() <= 1000);
+}
+
+#[test]
+fn test_trainer_discards() {
+    let mut trainer = DictTrainer::new(1000);
+    trainer.set_min_sample_size(4);
+    trainer.set_deduplicate(true);
+    for i in 0..100 {
+        trainer.add_sample(format!("sample {:02}", i % 10).as_bytes());
+    }
+    trainer.add_sample(b"");
+    trainer.add_sample(b"abc");
+    assert_eq!(trainer.len(), 10);
+    assert_eq!(trainer.discarded(),
+               Discarded {
+                   too_small: 2,
+                   duplicates: 90,
+                   evicted: 0,
+                   bytes: 90 * 9 + 3,
+               });
+
+    let mut trainer = DictTrainer::new(100);
+    for i in 0..20 {
+        trainer.add_sample(format!("sample {:02}", i).as_bytes());
+    }
+    assert_eq!(trainer.discarded().evicted, 20 - 100 / 9);
+}
+
+#[test]
+fn test_optimize_cover_threads() {
+    let mut samples = Samples::default();
+    for i in 0..2000 {
+        let sample = format!("{{\"id\": {}, \"kind\": \"event\", \
+                              \"score\": {}}}",
+                             i,
+                             i * 7 % 13);
+        samples.push(sample.as_bytes());
+    }
+    let params = CoverParams {
+        d: 8,
+        steps: 4,
+        threads: 2,
+        ..CoverParams::default()
+    };
+    let (dict, chosen) =
+        optimize_cover(samples.data(), samples.sizes(), 4096, params)
+            .unwrap();
+    assert!(!dict.is_empty());
+    assert!(chosen.k > 0);
+    assert_eq!(chosen.threads, 2);
+}
@@ -0,0 +1,284 @@
+//! Compress files on disk, and read them back.
+
+use std::cmp;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use ll;
+use stream::raw::{Encoder, InBuffer, OutBuffer, Status};
+use stream::seekable::{SeekTable, SeekableReader};
+
+/// A file compressed by [`compress_paths`](fn.compress_paths.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedFile {
+    /// Path of the compressed file.
+    pub path: PathBuf,
+    /// Size of the original file, in bytes.
+    pub uncompressed_size: u64,
+    /// Size of the compressed file, in bytes.
+    pub compressed_size: u64,
+}
+
+/// Compresses many files concurrently.
+///
+/// Each file is compressed next to the original, with a `.zst` extension
+/// appended, overwriting any existing file. At most `threads` files are
+/// compressed at once; `0` uses the parallelism available on this
+/// machine. Each thread reuses a single compression context.
+///
+/// Returns the result for each file, in the order of `paths`.
+/// When a file fails, its incomplete output is removed,
+/// and the other files are still compressed.
+pub fn compress_paths<P>(paths: &[P], level: i32, threads: usize)
+                         -> Vec<io::Result<CompressedFile>>
+    where P: AsRef<Path> + Sync
+{
+    let threads = if threads == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        threads
+    };
+    let threads = cmp::min(threads, paths.len());
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<CompressedFile>>>> =
+        Mutex::new(paths.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mut encoder = None;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= paths.len() {
+                        break;
+                    }
+                    let result = reuse_encoder(&mut encoder, level)
+                        .and_then(|e| compress_path(e, paths[i].as_ref()));
+                    if result.is_err() {
+                        // The context may be in the middle of a frame.
+                        encoder = None;
+                    }
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+
+    results.into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect()
+}
+
+// Returns the thread's encoder, creating it if needed.
+fn reuse_encoder(encoder: &mut Option<Encoder>, level: i32)
+                 -> io::Result<&mut Encoder> {
+    if encoder.is_none() {
+        *encoder = Some(try!(Encoder::new(level)));
+    }
+    Ok(encoder.as_mut().unwrap())
+}
+
+// Compresses `source` to `source.zst`.
+fn compress_path(encoder: &mut Encoder, source: &Path)
+                 -> io::Result<CompressedFile> {
+    let mut destination = OsString::from(source.as_os_str());
+    destination.push(".zst");
+    let destination = PathBuf::from(destination);
+
+    let input = try!(File::open(source));
+    let output = try!(File::create(&destination));
+    match compress_file(encoder, input, output) {
+        Ok((uncompressed_size, compressed_size)) => {
+            Ok(CompressedFile {
+                path: destination,
+                uncompressed_size: uncompressed_size,
+                compressed_size: compressed_size,
+            })
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&destination);
+            Err(e)
+        }
+    }
+}
+
+// Compresses `input` to `output` as a single frame.
+//
+// Returns the uncompressed and compressed sizes.
+fn compress_file(encoder: &mut Encoder, mut input: File, mut output: File)
+                 -> io::Result<(u64, u64)> {
+    let mut in_buffer = vec![0u8; unsafe { ll::ZSTD_CStreamInSize() }];
+    let mut out_buffer = vec![0u8; unsafe { ll::ZSTD_CStreamOutSize() }];
+    let mut uncompressed_size = 0;
+    let mut compressed_size = 0;
+
+    loop {
+        let read = try!(input.read(&mut in_buffer));
+        if read == 0 {
+            break;
+        }
+        uncompressed_size += read as u64;
+
+        let mut src = InBuffer::new(&in_buffer[..read]);
+        loop {
+            let mut dst = OutBuffer::new(&mut out_buffer);
+            let status = try!(encoder.poll_compress(&mut src, &mut dst));
+            try!(output.write_all(dst.as_slice()));
+            compressed_size += dst.pos as u64;
+            if status == Status::NeedInput {
+                break;
+            }
+        }
+    }
+
+    loop {
+        let mut dst = OutBuffer::new(&mut out_buffer);
+        let status = try!(encoder.poll_finish(&mut dst));
+        try!(output.write_all(dst.as_slice()));
+        compressed_size += dst.pos as u64;
+        if status == Status::Done {
+            break;
+        }
+    }
+
+    try!(output.flush());
+    Ok((uncompressed_size, compressed_size))
+}
+
+/// A compressed file, read like the original one.
+///
+/// The file must be a seekable archive: independent frames, with a seek
+/// table appended (see [`stream::seekable`](../stream/seekable/index.html))
+/// or kept in a separate index file. Reading and seeking work in
+/// decompressed coordinates; only the frame holding the current position
+/// is decompressed, and kept until another one is needed.
+pub struct ZstdFile {
+    reader: SeekableReader<File>,
+}
+
+impl ZstdFile {
+    /// Opens an archive with its seek table appended.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = try!(File::open(path));
+        Ok(ZstdFile { reader: try!(SeekableReader::open(file)) })
+    }
+
+    /// Opens an archive, with its seek table in a separate index file.
+    pub fn open_with_index<P, Q>(path: P, index: Q) -> io::Result<Self>
+        where P: AsRef<Path>,
+              Q: AsRef<Path>
+    {
+        let table = try!(SeekTable::read_from(try!(File::open(index))));
+        let file = try!(File::open(path));
+        Ok(ZstdFile { reader: SeekableReader::new(file, table) })
+    }
+
+    /// Returns the size of the decompressed content, in bytes.
+    pub fn len(&self) -> u64 {
+        self.reader.table().decompressed_size()
+    }
+
+    /// Returns `true` if the decompressed content is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the seek table of the archive.
+    pub fn table(&self) -> &SeekTable {
+        self.reader.table()
+    }
+
+    /// Returns the underlying file.
+    pub fn into_inner(self) -> File {
+        self.reader.into_inner()
+    }
+}
+
+impl Read for ZstdFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Seek for ZstdFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+#[test]
+fn test_compress_paths() {
+    let dir = ::std::env::temp_dir()
+        .join(format!("zstd-rs-fs-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut paths = Vec::new();
+    for i in 0..5 {
+        let path = dir.join(format!("file{}", i));
+        fs::write(&path, vec![i as u8; 10000 * i]).unwrap();
+        paths.push(path);
+    }
+    paths.push(dir.join("missing"));
+
+    let results = compress_paths(&paths, 3, 2);
+    assert_eq!(results.len(), 6);
+    for (i, result) in results.iter().take(5).enumerate() {
+        let file = result.as_ref().unwrap();
+        assert_eq!(file.uncompressed_size, 10000 * i as u64);
+        let compressed = fs::read(&file.path).unwrap();
+        assert_eq!(compressed.len() as u64, file.compressed_size);
+        assert_eq!(::decode_all(&compressed).unwrap(),
+                   vec![i as u8; 10000 * i]);
+    }
+    assert!(results[5].is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_zstd_file() {
+    let dir = ::std::env::temp_dir()
+        .join(format!("zstd-rs-file-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let text: Vec<u8> = (0..20000u32).map(|i| (i % 241) as u8).collect();
+    let mut archive = Vec::new();
+    for chunk in text.chunks(3000) {
+        archive.extend_from_slice(&::encode_all(chunk, 1).unwrap());
+    }
+    let table = SeekTable::build(&archive[..]).unwrap();
+    let mut index = Vec::new();
+    table.write_to(&mut index).unwrap();
+    fs::write(dir.join("index"), &index).unwrap();
+    fs::write(dir.join("data"), &archive).unwrap();
+    table.write_to(&mut archive).unwrap();
+    fs::write(dir.join("data.zst"), &archive).unwrap();
+
+    let mut file = ZstdFile::open(dir.join("data.zst")).unwrap();
+    assert_eq!(file.len(), text.len() as u64);
+    let mut buffer = [0u8; 100];
+    file.seek(SeekFrom::End(-150)).unwrap();
+    file.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer[..], &text[text.len() - 150..text.len() - 50]);
+    file.seek(SeekFrom::Current(-1000)).unwrap();
+    file.read_exact(&mut buffer).unwrap();
+    let start = text.len() - 1050;
+    assert_eq!(&buffer[..], &text[start..start + 100]);
+
+    let mut file = ZstdFile::open_with_index(dir.join("data"),
+                                             dir.join("index"))
+        .unwrap();
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).unwrap();
+    assert_eq!(content, text);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,81 @@
+//! Registry of live contexts, to find the ones never dropped.
+
+use std::backtrace::Backtrace;
+use std::sync::Mutex;
+
+use libc::c_void;
+
+// Each live context, with where it was created.
+static LIVE: Mutex<Vec<Live>> = Mutex::new(Vec::new());
+
+struct Live {
+    address: usize,
+    kind: &'static str,
+    backtrace: Backtrace,
+}
+
+// Records a new context.
+pub(crate) fn register(kind: &'static str, context: *mut c_void) {
+    LIVE.lock().unwrap().push(Live {
+        address: context as usize,
+        kind: kind,
+        backtrace: Backtrace::force_capture(),
+    });
+}
+
+// Forgets a context, which is being freed.
+pub(crate) fn unregister(context: *mut c_void) {
+    let mut live = LIVE.lock().unwrap();
+    if let Some(i) = live.iter()
+        .position(|l| l.address == context as usize) {
+        live.swap_remove(i);
+    }
+}
+
+/// Returns the number of zstd contexts currently alive.
+///
+/// Requires the `debug-leak-check` feature.
+pub fn live_contexts() -> usize {
+    LIVE.lock().unwrap().len()
+}
+
+/// Panics if any zstd context is still alive.
+///
+/// The panic message tells where each context was created. Call it at
+/// the end of a test to catch encoders or decoders that were never
+/// dropped, for instance in a task that never completed.
+///
+/// The free functions of the `bulk` module keep a context per thread,
+/// freed only when the thread exits: call it from another thread.
+///
+/// Requires the `debug-leak-check` feature.
+pub fn assert_no_leaks() {
+    let live = LIVE.lock().unwrap();
+    if live.is_empty() {
+        return;
+    }
+
+    let mut message = format!("{} zstd context(s) still alive", live.len());
+    for l in live.iter() {
+        message.push_str(&format!("\n\n{} created at:\n{}",
+                                  l.kind,
+                                  l.backtrace));
+    }
+    panic!("{}", message);
+}
+
+#[test]
+fn test_registry() {
+    use context::EncoderContext;
+
+    let context = EncoderContext::default();
+    let address = context.c as usize;
+    let registered = |address| {
+        LIVE.lock().unwrap().iter().any(|l| l.address == address)
+    };
+
+    assert!(registered(address));
+    assert!(live_contexts() > 0);
+    drop(context);
+    assert!(!registered(address));
+}
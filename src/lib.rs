@@ -6,6 +6,24 @@
 //!   and send the output to another write.
 //! * A [decoder](struct.Decoder.html) to read input data from a `Read`
 //!   and decompress it.
+//! * Pull-based and push-based variants of both, in the
+//!   [`stream::read`](stream/read/index.html) and
+//!   [`stream::write`](stream/write/index.html) modules, and async
+//!   variants in `stream::futures` with the `futures` feature.
+//! * A [`bulk`](bulk/index.html) module to compress and decompress
+//!   whole buffers at once.
+//! * A [`bundle`](bundle/index.html) module to pack many named assets in
+//!   a single file, with random access.
+//! * An [`fs`](fs/index.html) module to compress files on disk.
+//! * An [`xxhash`](xxhash/index.html) module, exposing the hash function
+//!   zstd uses for its checksums.
+//! * A [`sys`](sys/index.html) module with the raw C bindings, for what
+//!   the rest of the API doesn't cover, and a [`safe`](safe/index.html)
+//!   module mirroring them with owned contexts and slices.
+//!
+//! Encoders, decoders and compressors can be moved to another thread
+//! (they are `Send`), so callbacks given to them must be `Send` too.
+//! Prepared dictionaries are also `Sync`, and can be shared in an `Arc`.
 //!
 //! # Example
 //!
@@ -24,26 +42,62 @@
 //! [zstd]: https://github.com/Cyan4973/zstd
 #![deny(missing_docs)]
 extern crate libc;
+#[cfg(feature = "futures")]
+extern crate futures_io;
 
+mod context;
+#[cfg(feature = "debug-leak-check")]
+mod leak;
 mod ll;
-mod stream;
+mod memory;
+mod verify;
 
-pub mod block;
+pub mod bench;
+pub mod bulk;
+pub mod bundle;
 pub mod dict;
+pub mod fs;
+pub mod params;
+pub mod safe;
+pub mod stream;
+pub mod sys;
+pub mod xxhash;
+
+pub use bulk as block;
+#[cfg(feature = "debug-leak-check")]
+pub use leak::{assert_no_leaks, live_contexts};
+pub use memory::{memory_in_use, set_memory_limit, track_memory};
+pub use stream::ChunkReader;
+pub use stream::read::Decoder;
+pub use stream::write::{AutoFinishEncoder, Encoder};
+
+use std::io::{self, Read};
 
-pub use stream::encoder::{AutoFinishEncoder, Encoder};
-pub use stream::decoder::Decoder;
 
-use std::io;
+/// Returns `true` if support for legacy frames was built.
+///
+/// Legacy frames, from zstd versions prior to v0.8, can then be
+/// decompressed like the current ones. This depends on the `legacy`
+/// feature, enabled by default.
+pub fn legacy_support() -> bool {
+    cfg!(feature = "legacy")
+}
 
+/// Returns `true` if `data` starts with a frame in a legacy format.
+///
+/// See [`stream::legacy_version`](stream/fn.legacy_version.html).
+pub fn is_legacy_frame(data: &[u8]) -> bool {
+    stream::legacy_version(data).is_some()
+}
 
 /// Decompress the given data as if using a `Decoder`.
 ///
-/// The input data must be in the zstd frame format.
+/// The input data must be in the zstd frame format. When the frame header
+/// records the content size, the output is allocated once, to that size.
 pub fn decode_all(data: &[u8]) -> io::Result<Vec<u8>> {
     let mut result = Vec::new();
     let mut decoder = try!(Decoder::new(data));
-    try!(io::copy(&mut decoder, &mut result));
+    try!(decoder.read_to_end(&mut result));
     Ok(result)
 }
 
@@ -58,6 +112,136 @@ pub fn encode_all(data: &[u8], level: i32) -> io::Result<Vec<u8>> {
     encoder.finish()
 }
 
+/// Compress all the given data, appending the frame to `destination`.
+///
+/// The frame is written straight into the spare capacity of the vector,
+/// without going through an intermediate buffer; room for the worst case
+/// is reserved first, so this is usually a single pass. Unlike
+/// `encode_all`, the frame header records the content size.
+///
+/// Returns the size of the frame.
+pub fn encode_all_to_vec(data: &[u8], level: i32, destination: &mut Vec<u8>)
+                         -> io::Result<usize> {
+    let context = try!(context::EncoderContext::new());
+    try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+    let start = destination.len();
+    destination.reserve(unsafe { ll::ZSTD_compressBound(data.len()) });
+    let mut input = ll::ZSTD_inBuffer::new(data);
+    loop {
+        let len = destination.len();
+        let (remaining, written) = {
+            let mut output =
+                ll::ZSTD_outBuffer::uninit(destination.spare_capacity_mut());
+            let remaining = try!(ll::parse_code(unsafe {
+                ll::ZSTD_compressStream2(context.c,
+                                         &mut output,
+                                         &mut input,
+                                         ll::ZSTD_e_end)
+            }));
+            (remaining, output.pos)
+        };
+        unsafe {
+            destination.set_len(len + written);
+        }
+        if remaining == 0 {
+            return Ok(destination.len() - start);
+        }
+        destination.reserve(remaining);
+    }
+}
+
+/// Compress all data from `source` into `destination`.
+pub fn copy_encode<R: io::Read, W: io::Write>(source: R, destination: W,
+                                              level: i32)
+                                              -> io::Result<()> {
+    copy_encode_cancellable(source, destination, level, || false)
+}
+
+/// Decompress all data from `source` into `destination`.
+pub fn copy_decode<R: io::Read, W: io::Write>(source: R, destination: W)
+                                              -> io::Result<()> {
+    copy_decode_cancellable(source, destination, || false)
+}
+
+/// Like `copy_encode`, but can be aborted.
+///
+/// `cancelled` is called between chunks; once it returns `true`,
+/// this stops and returns an error. The output is then incomplete:
+/// it's up to the caller to clean it up.
+///
+/// With an `AtomicBool` shared with another thread, use something like
+/// `|| flag.load(Ordering::Relaxed)`.
+pub fn copy_encode_cancellable<R, W, F>(mut source: R, destination: W,
+                                        level: i32, cancelled: F)
+                                        -> io::Result<()>
+    where R: io::Read,
+          W: io::Write,
+          F: FnMut() -> bool
+{
+    let mut encoder = try!(Encoder::new(destination, level));
+    try!(copy_cancellable(&mut source, &mut encoder, cancelled));
+    try!(encoder.finish());
+    Ok(())
+}
+
+/// Like `copy_decode`, but can be aborted.
+///
+/// See [`copy_encode_cancellable`](fn.copy_encode_cancellable.html).
+pub fn copy_decode_cancellable<R, W, F>(source: R, mut destination: W,
+                                        cancelled: F)
+                                        -> io::Result<()>
+    where R: io::Read,
+          W: io::Write,
+          F: FnMut() -> bool
+{
+    let mut decoder = try!(Decoder::new(source));
+    copy_cancellable(&mut decoder, &mut destination, cancelled)
+}
+
+// Copies everything from `reader` to `writer`,
+// unless `cancelled` says otherwise between two chunks.
+fn copy_cancellable<R, W, F>(reader: &mut R, writer: &mut W,
+                             mut cancelled: F)
+                             -> io::Result<()>
+    where R: io::Read,
+          W: io::Write,
+          F: FnMut() -> bool
+{
+    let mut buffer = [0u8; 32 * 1024];
+    loop {
+        if cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "operation cancelled"));
+        }
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        try!(writer.write_all(&buffer[..read]));
+    }
+}
+
+/// Compress all data from `source` into `destination`, and check the result.
+///
+/// The compressed output is decompressed as it is produced; its size and
+/// content checksum are compared with the input's once compression is
+/// finished. A mismatch results in an `InvalidData` error.
+///
+/// Returns the `destination` writer on success.
+pub fn copy_encode_verified<R: io::Read, W: io::Write>(source: R,
+                                                       destination: W,
+                                                       level: i32)
+                                                       -> io::Result<W> {
+    let mut source = verify::HashingReader::new(source);
+    let verifier = try!(verify::Verifier::new(destination));
+    let mut encoder = try!(Encoder::new(verifier, level));
+    try!(io::copy(&mut source, &mut encoder));
+    let verifier = try!(encoder.finish());
+    verifier.check(source.size(), source.checksum())
+}
+
 
 #[test]
 fn test_cycle() {
@@ -72,3 +256,64 @@ fn test_cycle() {
 
     assert_eq!(text, &decompressed);
 }
+
+#[test]
+fn test_encode_all_to_vec() {
+    let data = b"straight into the vector, straight into the vector";
+    let mut output = b"prefix".to_vec();
+    let size = encode_all_to_vec(data, 3, &mut output).unwrap();
+    assert_eq!(output.len(), 6 + size);
+    assert_eq!(&output[..6], b"prefix");
+    assert_eq!(&decode_all(&output[6..]).unwrap()[..], &data[..]);
+}
+
+#[test]
+fn test_legacy_detection() {
+    assert!(is_legacy_frame(&[0x25, 0xB5, 0x2F, 0xFD, 0x00]));
+    assert_eq!(stream::legacy_version(&[0x27, 0xB5, 0x2F, 0xFD]), Some(7));
+    assert!(!is_legacy_frame(&encode_all(b"", 1).unwrap()));
+    assert!(!is_legacy_frame(&[0x25]));
+}
+
+#[test]
+fn test_cancel() {
+    let text = b"This won't be compressed.";
+
+    let mut output = Vec::new();
+    assert!(copy_encode_cancellable(&text[..], &mut output, 1, || true)
+        .is_err());
+
+    let mut output = Vec::new();
+    copy_encode(&text[..], &mut output, 1).unwrap();
+    let mut decoded = Vec::new();
+    copy_decode(&output[..], &mut decoded).unwrap();
+    assert_eq!(&decoded[..], &text[..]);
+}
+
+#[test]
+fn test_copy_encode_verified() {
+    let text = b"Some text, compressed then checked.";
+
+    let compressed = copy_encode_verified(&text[..], Vec::new(), 1).unwrap();
+
+    assert_eq!(&decode_all(&compressed).unwrap()[..], &text[..]);
+}
+
+#[test]
+fn test_send_sync() {
+    fn assert_send<T: Send>() {}
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send::<Encoder<Vec<u8>>>();
+    assert_send::<AutoFinishEncoder<Vec<u8>>>();
+    assert_send::<Decoder<&[u8]>>();
+    assert_send::<stream::read::Encoder<&[u8]>>();
+    assert_send::<stream::write::Decoder<Vec<u8>>>();
+    assert_send::<stream::raw::Encoder>();
+    assert_send::<stream::raw::Decoder>();
+    assert_send::<bulk::Compressor>();
+    assert_send::<bulk::Decompressor>();
+    assert_send::<xxhash::Xxh64>();
+    assert_send_sync::<dict::EncoderDictionary>();
+    assert_send_sync::<dict::DecoderDictionary>();
+}
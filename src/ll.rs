@@ -2,86 +2,400 @@
 
 //! # Streaming compression
 //!
-//! A `ZBUFFCompressionContext` object is required to track streaming operation.
-//! Use `ZBUFF_createCCtx()` and `ZBUFF_freeCCtx()` to create/release resources.
-//! `ZBUFFCompressionContext` objects can be reused multiple times.
+//! A `ZSTD_CStream` object is required to track streaming operation.
+//! Since v1.3.0, it is the same object as a `ZSTD_CCtx`:
+//! use `ZSTD_createCCtx()` and `ZSTD_freeCCtx()` to create/release resources.
+//! `ZSTD_CStream` objects can be reused multiple times.
 //!
-//! Start by initializing `ZBUFFCompressionContext`.
-//! Use `ZBUFF_compressInit()` to start a new compression operation.
-//! Use `ZBUFF_compressInitDictionary()` for a compression which requires a dictionary.
+//! Parameters are set using `ZSTD_CCtx_setParameter()`,
+//! and a dictionary can be loaded with `ZSTD_CCtx_loadDictionary()`.
+//! They are sticky: they apply to all following frames.
 //!
-//! Use `ZBUFF_compressContinue()` repetitively to consume input stream.
-//! `*srcSizePtr` and `*dstCapacityPtr` can be any size.
-//! The function will report how many bytes were read or written within `*srcSizePtr` and `*dstCapacityPtr`.
-//! Note that it may not consume the entire input, in which case it's up to the caller to present again remaining data.
-//! The content of @dst will be overwritten (up to `*dstCapacityPtr`) at each call, so save its content if it matters or change @dst .
-//! @return : a hint to preferred nb of bytes to use as input for next function call (it's just a hint, to improve latency)
-//!           or an error code, which can be tested using `ZBUFF_isError()`.
+//! Use `ZSTD_compressStream2()` with `ZSTD_e_continue` repetitively to
+//! consume input stream. The function will automatically update both `pos`
+//! fields within `input` and `output`.
+//! Note that it may not consume the entire input, in which case `pos < size`,
+//! and it's up to the caller to present again remaining data.
+//! @return : a size hint, preferred nb of bytes to use as input for next
+//!           function call, or an error code, which can be tested using
+//!           `ZSTD_isError()`.
 //!
-//! At any moment, it's possible to flush whatever data remains within buffer, using `ZBUFF_compressFlush()`.
-//! The nb of bytes written into @dst will be reported into `*dstCapacityPtr`.
-//! Note that the function cannot output more than `*dstCapacityPtr`,
-//! therefore, some content might still be left into internal buffer if `*dstCapacityPtr` is too small.
-//! @return : nb of bytes still present into internal buffer (0 if it's empty)
-//!           or an error code, which can be tested using `ZBUFF_isError()`.
+//! At any moment, it's possible to flush whatever data might remain stuck
+//! within internal buffer, using `ZSTD_compressStream2()` with
+//! `ZSTD_e_flush`. `output->pos` will be updated.
+//! Note that, if `output->size` is too small, a single invocation with
+//! `ZSTD_e_flush` might not be enough (return code > 0).
+//! In which case, make some room to receive more compressed data,
+//! and call again `ZSTD_compressStream2()` with `ZSTD_e_flush`.
+//! @return : 0 if internal buffers are entirely flushed,
+//!           >0 if some data still present within internal buffer,
+//!           or an error code, which can be tested using `ZSTD_isError()`.
 //!
-//! `ZBUFF_compressEnd()` instructs to finish a frame.
-//! It will perform a flush and write frame epilogue.
+//! Calling `ZSTD_compressStream2()` with `ZSTD_e_end` instructs to finish a
+//! frame. It will perform a flush and write frame epilogue.
 //! The epilogue is required for decoders to consider a frame completed.
-//! Similar to `ZBUFF_compressFlush()`, it may not be able to output the entire internal buffer content if `*dstCapacityPtr` is too small.
-//! In which case, call again `ZBUFF_compressFlush()` to complete the flush.
-//! @return : nb of bytes still present into internal buffer (0 if it's empty)
-//!           or an error code, which can be tested using `ZBUFF_isError()`.
+//! Similar to flush, it may not be able to output the entire content
+//! if `output->size` is too small. In which case, call again with
+//! `ZSTD_e_end` to complete the flush.
+//! @return : 0 if frame fully completed and fully flushed,
+//!           >0 if some data still present within internal buffer,
+//!           or an error code, which can be tested using `ZSTD_isError()`.
 //!
-//! Hint : recommended buffer sizes (not compulsory) : `ZBUFF_recommendedCInSize` / `ZBUFF_recommendedCOutSize`
-//! input : `ZBUFF_recommendedCInSize == 128 KB` block size is the internal unit, it improves latency to use this value (skipped buffering).
-//! output : `ZBUFF_recommendedCOutSize == ZSTD_compressBound(128 KB) + 3 + 3` : ensures it's always possible to write/flush/end a full block. Skip some buffering.
-//! By using both, it ensures that input will be entirely consumed, and output will always contain the result, reducing intermediate buffering.
+//! Hint : recommended buffer sizes (not compulsory) :
+//! `ZSTD_CStreamInSize()` / `ZSTD_CStreamOutSize()`.
 //!
 //!
 //! # Streaming decompression
 //!
-//! A `ZBUFFDecompressionContext` object is required to track streaming operations.
-//! Use `ZBUFF_createDCtx()` and `ZBUFF_freeDCtx()` to create/release resources.
-//! Use `ZBUFF_decompressInit()` to start a new decompression operation,
-//!  or `ZBUFF_decompressInitDictionary()` if decompression requires a dictionary.
-//! Note that `ZBUFFDecompressionContext` objects can be reused multiple times.
+//! A `ZSTD_DStream` object is required to track streaming operations.
+//! Since v1.3.0, it is the same object as a `ZSTD_DCtx`:
+//! use `ZSTD_createDCtx()` and `ZSTD_freeDCtx()` to create/release resources.
+//! `ZSTD_DStream` objects can be reused multiple times.
 //!
-//! Use `ZBUFF_decompressContinue()` repetitively to consume your input.
-//! `*srcSizePtr` and `*dstCapacityPtr` can be any size.
-//! The function will report how many bytes were read or written by modifying `*srcSizePtr` and `*dstCapacityPtr`.
-//! Note that it may not consume the entire input, in which case it's up to the caller to present remaining input again.
-//! The content of `@dst` will be overwritten (up to `*dstCapacityPtr`) at each function call, so save its content if it matters or change @dst.
-//! @return : a hint to preferred nb of bytes to use as input for next function call (it's only a hint, to help latency)
-//!           or 0 when a frame is completely decoded
-//!           or an error code, which can be tested using `ZBUFF_isError()`.
+//! Use `ZSTD_decompressStream()` repetitively to consume your input.
+//! The function will update both `pos` fields.
+//! If `input.pos < input.size`, some input has not been consumed.
+//! It's up to the caller to present again remaining data.
+//! If `output.pos < output.size`, decoder has flushed everything it could.
+//! @return : 0 when a frame is completely decoded and fully flushed,
+//!           or an error code, which can be tested using `ZSTD_isError()`,
+//!           or any other value > 0, which means there is still some
+//!           decoding or flushing to do to complete current frame.
 //!
-//! Hint : recommended buffer sizes (not compulsory) : `ZBUFF_recommendedDInSize()` / `ZBUFF_recommendedDOutSize()`
-//! output : `ZBUFF_recommendedDOutSize`==128 KB block size is the internal unit, it ensures it's always possible to write a full block when decoded.
-//! input  : `ZBUFF_recommendedDInSize`==128Kb+3; just follow indications from `ZBUFF_decompressContinue()` to minimize latency. It should always be <= 128 KB + 3 .
+//! Hint : recommended buffer sizes (not compulsory) :
+//! `ZSTD_DStreamInSize()` / `ZSTD_DStreamOutSize()`.
+
+#![allow(dead_code, missing_docs, non_camel_case_types, non_snake_case,
+         non_upper_case_globals)]
 
 use std::io;
 use std::ffi::CStr;
-use libc::{c_char, c_int, c_uint, c_void, size_t};
-
-pub type ZBUFFCompressionContext = *mut c_void;
-pub type ZBUFFDecompressionContext = *mut c_void;
+use std::mem::MaybeUninit;
+use libc::{c_char, c_int, c_uint, c_ulonglong, c_void, size_t};
 
 pub type ZSTDCompressionContext = *mut c_void;
 pub type ZSTDDecompressionContext = *mut c_void;
 
+pub type ZSTDCompressionDictionary = *mut c_void;
+pub type ZSTDDecompressionDictionary = *mut c_void;
+pub type ZSTDCompressionParameters = *mut c_void;
+
 pub type ErrorCode = size_t;
 
+#[repr(C)]
+pub struct ZSTD_inBuffer {
+    /// start of input buffer
+    pub src: *const c_void,
+    /// size of input buffer
+    pub size: size_t,
+    /// position where reading stopped. Will be updated.
+    pub pos: size_t,
+}
+
+#[repr(C)]
+pub struct ZSTD_outBuffer {
+    /// start of output buffer
+    pub dst: *mut c_void,
+    /// size of output buffer
+    pub size: size_t,
+    /// position where writing stopped. Will be updated.
+    pub pos: size_t,
+}
+
+impl ZSTD_inBuffer {
+    /// Wraps the given slice, starting at the beginning.
+    pub fn new(src: &[u8]) -> Self {
+        ZSTD_inBuffer {
+            src: src.as_ptr() as *const c_void,
+            size: src.len(),
+            pos: 0,
+        }
+    }
+}
+
+impl ZSTD_outBuffer {
+    /// Wraps the given slice, starting at the beginning.
+    pub fn new(dst: &mut [u8]) -> Self {
+        ZSTD_outBuffer {
+            dst: dst.as_mut_ptr() as *mut c_void,
+            size: dst.len(),
+            pos: 0,
+        }
+    }
+
+    /// Wraps the given uninitialized slice, like the spare capacity of a
+    /// `Vec`: zstd only writes to it.
+    pub fn uninit(dst: &mut [MaybeUninit<u8>]) -> Self {
+        ZSTD_outBuffer {
+            dst: dst.as_mut_ptr() as *mut c_void,
+            size: dst.len(),
+            pos: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ZSTD_frameProgression {
+    /// nb input bytes read and buffered
+    pub ingested: c_ulonglong,
+    /// nb input bytes actually compressed
+    pub consumed: c_ulonglong,
+    /// nb of compressed bytes generated and buffered
+    pub produced: c_ulonglong,
+    /// nb of compressed bytes flushed : not provided; can be tracked from
+    /// caller side
+    pub flushed: c_ulonglong,
+    /// MT only : latest started job nb
+    pub currentJobID: c_uint,
+    /// MT only : nb of workers actively compressing at probe time
+    pub nbActiveWorkers: c_uint,
+}
+
+pub type ZSTD_ResetDirective = c_int;
+pub const ZSTD_reset_session_only: ZSTD_ResetDirective = 1;
+pub const ZSTD_reset_parameters: ZSTD_ResetDirective = 2;
+pub const ZSTD_reset_session_and_parameters: ZSTD_ResetDirective = 3;
+
+pub type ZSTD_frameType_e = c_int;
+pub const ZSTD_frame: ZSTD_frameType_e = 0;
+pub const ZSTD_skippableFrame: ZSTD_frameType_e = 1;
+
+/// Value of `frameContentSize` when the size isn't in the header.
+pub const ZSTD_CONTENTSIZE_UNKNOWN: c_ulonglong = !0;
+/// Returned instead of a size when the input is invalid.
+pub const ZSTD_CONTENTSIZE_ERROR: c_ulonglong = !0 - 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ZSTD_frameHeader {
+    /// if == ZSTD_CONTENTSIZE_UNKNOWN, it means this field is not
+    /// available. 0 means "empty"
+    pub frameContentSize: c_ulonglong,
+    /// can be very large, up to <= frameContentSize
+    pub windowSize: c_ulonglong,
+    pub blockSizeMax: c_uint,
+    /// if == ZSTD_skippableFrame, frameContentSize is the size of
+    /// skippable content
+    pub frameType: ZSTD_frameType_e,
+    pub headerSize: c_uint,
+    pub dictID: c_uint,
+    pub checksumFlag: c_uint,
+    pub _reserved1: c_uint,
+    pub _reserved2: c_uint,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZSTD_compressionParameters {
+    /// largest match distance : larger == more compression, more memory
+    /// needed during decompression
+    pub windowLog: c_uint,
+    /// fully searched segment : larger == more compression, slower, more
+    /// memory (useless for fast)
+    pub chainLog: c_uint,
+    /// dispatch table : larger == faster, more memory
+    pub hashLog: c_uint,
+    /// nb of searches : larger == more compression, slower
+    pub searchLog: c_uint,
+    /// match length searched : larger == faster decompression, sometimes
+    /// less compression
+    pub minMatch: c_uint,
+    /// acceptable match size for optimal parser (only) : larger == more
+    /// compression, slower
+    pub targetLength: c_uint,
+    /// see ZSTD_strategy definition above
+    pub strategy: ZSTD_strategy,
+}
+
+pub type ZSTD_strategy = c_int;
+pub const ZSTD_fast: ZSTD_strategy = 1;
+pub const ZSTD_dfast: ZSTD_strategy = 2;
+pub const ZSTD_greedy: ZSTD_strategy = 3;
+pub const ZSTD_lazy: ZSTD_strategy = 4;
+pub const ZSTD_lazy2: ZSTD_strategy = 5;
+pub const ZSTD_btlazy2: ZSTD_strategy = 6;
+pub const ZSTD_btopt: ZSTD_strategy = 7;
+pub const ZSTD_btultra: ZSTD_strategy = 8;
+pub const ZSTD_btultra2: ZSTD_strategy = 9;
+
+pub type ZSTD_EndDirective = c_int;
+/// collect more data, encoder decides when to output compressed result
+pub const ZSTD_e_continue: ZSTD_EndDirective = 0;
+/// flush any data provided so far
+pub const ZSTD_e_flush: ZSTD_EndDirective = 1;
+/// flush any remaining data _and_ close current frame
+pub const ZSTD_e_end: ZSTD_EndDirective = 2;
+
+pub type ZSTD_cParameter = c_int;
+pub const ZSTD_c_compressionLevel: ZSTD_cParameter = 100;
+pub const ZSTD_c_windowLog: ZSTD_cParameter = 101;
+pub const ZSTD_c_hashLog: ZSTD_cParameter = 102;
+pub const ZSTD_c_chainLog: ZSTD_cParameter = 103;
+pub const ZSTD_c_searchLog: ZSTD_cParameter = 104;
+pub const ZSTD_c_minMatch: ZSTD_cParameter = 105;
+pub const ZSTD_c_targetLength: ZSTD_cParameter = 106;
+pub const ZSTD_c_strategy: ZSTD_cParameter = 107;
+pub const ZSTD_c_enableLongDistanceMatching: ZSTD_cParameter = 160;
+pub const ZSTD_c_ldmHashLog: ZSTD_cParameter = 161;
+pub const ZSTD_c_ldmMinMatch: ZSTD_cParameter = 162;
+pub const ZSTD_c_ldmBucketSizeLog: ZSTD_cParameter = 163;
+pub const ZSTD_c_ldmHashRateLog: ZSTD_cParameter = 164;
+pub const ZSTD_c_contentSizeFlag: ZSTD_cParameter = 200;
+pub const ZSTD_c_checksumFlag: ZSTD_cParameter = 201;
+pub const ZSTD_c_dictIDFlag: ZSTD_cParameter = 202;
+pub const ZSTD_c_nbWorkers: ZSTD_cParameter = 400;
+pub const ZSTD_c_jobSize: ZSTD_cParameter = 401;
+pub const ZSTD_c_overlapLog: ZSTD_cParameter = 402;
+pub const ZSTD_c_rsyncable: ZSTD_cParameter = 500;
+pub const ZSTD_c_literalCompressionMode: ZSTD_cParameter = 1002;
+pub const ZSTD_c_srcSizeHint: ZSTD_cParameter = 1004;
+pub const ZSTD_c_enableDedicatedDictSearch: ZSTD_cParameter = 1005;
+pub const ZSTD_c_blockDelimiters: ZSTD_cParameter = 1008;
+pub const ZSTD_c_validateSequences: ZSTD_cParameter = 1009;
+pub const ZSTD_c_deterministicRefPrefix: ZSTD_cParameter = 1012;
+pub const ZSTD_c_enableSeqProducerFallback: ZSTD_cParameter = 1014;
+
+/// Largest value of `ZSTD_c_srcSizeHint`.
+pub const ZSTD_SRCSIZEHINT_MAX: c_int = c_int::max_value();
+/// Largest value of `ZSTD_c_jobSize`.
+#[cfg(target_pointer_width = "32")]
+pub const ZSTDMT_JOBSIZE_MAX: c_int = 512 << 20;
+/// Largest value of `ZSTD_c_jobSize`.
+#[cfg(not(target_pointer_width = "32"))]
+pub const ZSTDMT_JOBSIZE_MAX: c_int = 1024 << 20;
+
+pub type ZSTD_dParameter = c_int;
+pub const ZSTD_d_windowLogMax: ZSTD_dParameter = 100;
+
+pub type ZSTD_paramSwitch_e = c_int;
+/// Let the library automatically determine whether the feature shall be
+/// enabled
+pub const ZSTD_ps_auto: ZSTD_paramSwitch_e = 0;
+/// Force-enable the feature
+pub const ZSTD_ps_enable: ZSTD_paramSwitch_e = 1;
+/// Do not use the feature
+pub const ZSTD_ps_disable: ZSTD_paramSwitch_e = 2;
+
+pub type ZSTD_sequenceFormat_e = c_int;
+/// Blocks are cut by zstd; sequences don't contain delimiters
+pub const ZSTD_sf_noBlockDelimiters: ZSTD_sequenceFormat_e = 0;
+/// Each block ends with a sequence of offset and match length 0
+pub const ZSTD_sf_explicitBlockDelimiters: ZSTD_sequenceFormat_e = 1;
+
+pub type ZSTD_dictLoadMethod_e = c_int;
+/// Copy dictionary content internally
+pub const ZSTD_dlm_byCopy: ZSTD_dictLoadMethod_e = 0;
+/// Reference dictionary content -- the dictionary buffer must outlive its
+/// users.
+pub const ZSTD_dlm_byRef: ZSTD_dictLoadMethod_e = 1;
+
+pub type ZSTD_dictContentType_e = c_int;
+/// dictionary is "full" when starting with ZSTD_MAGIC_DICTIONARY, otherwise
+/// it is "rawContent"
+pub const ZSTD_dct_auto: ZSTD_dictContentType_e = 0;
+/// ensures dictionary is always loaded as rawContent, even if it starts with
+/// ZSTD_MAGIC_DICTIONARY
+pub const ZSTD_dct_rawContent: ZSTD_dictContentType_e = 1;
+/// refuses to load a dictionary if it does not respect Zstandard's
+/// specification, starting with ZSTD_MAGIC_DICTIONARY
+pub const ZSTD_dct_fullDict: ZSTD_dictContentType_e = 2;
+
+/// `opaque` is the custom memory state, `size` the requested size
+pub type ZSTD_allocFunction =
+    Option<unsafe extern "C" fn(opaque: *mut c_void, size: size_t)
+                                -> *mut c_void>;
+/// `opaque` is the custom memory state, `address` the memory to release
+pub type ZSTD_freeFunction =
+    Option<unsafe extern "C" fn(opaque: *mut c_void, address: *mut c_void)>;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ZSTD_customMem {
+    pub customAlloc: ZSTD_allocFunction,
+    pub customFree: ZSTD_freeFunction,
+    pub opaque: *mut c_void,
+}
+
+/// Use the default allocator
+pub const ZSTD_defaultCMem: ZSTD_customMem = ZSTD_customMem {
+    customAlloc: None,
+    customFree: None,
+    opaque: 0 as *mut c_void,
+};
+
+pub type ZSTD_ErrorCode = c_int;
+pub const ZSTD_error_no_error: ZSTD_ErrorCode = 0;
+pub const ZSTD_error_GENERIC: ZSTD_ErrorCode = 1;
+pub const ZSTD_error_prefix_unknown: ZSTD_ErrorCode = 10;
+pub const ZSTD_error_version_unsupported: ZSTD_ErrorCode = 12;
+pub const ZSTD_error_frameParameter_unsupported: ZSTD_ErrorCode = 14;
+pub const ZSTD_error_frameParameter_windowTooLarge: ZSTD_ErrorCode = 16;
+pub const ZSTD_error_corruption_detected: ZSTD_ErrorCode = 20;
+pub const ZSTD_error_checksum_wrong: ZSTD_ErrorCode = 22;
+pub const ZSTD_error_dictionary_corrupted: ZSTD_ErrorCode = 30;
+pub const ZSTD_error_dictionary_wrong: ZSTD_ErrorCode = 32;
+pub const ZSTD_error_dictionaryCreation_failed: ZSTD_ErrorCode = 34;
+pub const ZSTD_error_parameter_unsupported: ZSTD_ErrorCode = 40;
+pub const ZSTD_error_parameter_outOfBound: ZSTD_ErrorCode = 42;
+pub const ZSTD_error_stage_wrong: ZSTD_ErrorCode = 60;
+pub const ZSTD_error_init_missing: ZSTD_ErrorCode = 62;
+pub const ZSTD_error_memory_allocation: ZSTD_ErrorCode = 64;
+pub const ZSTD_error_workSpace_tooSmall: ZSTD_ErrorCode = 66;
+pub const ZSTD_error_dstSize_tooSmall: ZSTD_ErrorCode = 70;
+pub const ZSTD_error_srcSize_wrong: ZSTD_ErrorCode = 72;
+pub const ZSTD_error_dstBuffer_null: ZSTD_ErrorCode = 74;
+
+/// A sequence found by `ZSTD_generateSequences()`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ZSTD_Sequence {
+    /// distance of the match, or 0 for a block delimiter
+    pub offset: c_uint,
+    /// number of literals before the match
+    pub litLength: c_uint,
+    /// length of the match
+    pub matchLength: c_uint,
+    /// repeat offset used, if any (1 to 3), or 0
+    pub rep: c_uint,
+}
+
+/// Finds the sequences of a block, for `ZSTD_registerSequenceProducer()`.
+///
+/// Returns the number of sequences written to `outSeqs`, ending with a
+/// block delimiter, or `ZSTD_SEQUENCE_PRODUCER_ERROR`.
+pub type ZSTD_sequenceProducer_F =
+    Option<unsafe extern "C" fn(sequenceProducerState: *mut c_void,
+                                outSeqs: *mut ZSTD_Sequence,
+                                outSeqsCapacity: size_t,
+                                src: *const c_void, srcSize: size_t,
+                                dict: *const c_void, dictSize: size_t,
+                                compressionLevel: c_int,
+                                windowSize: size_t)
+                                -> size_t>;
+/// Returned by a sequence producer that can't handle a block.
+pub const ZSTD_SEQUENCE_PRODUCER_ERROR: size_t = !0;
+
+/// Valid range of a parameter, from `ZSTD_cParam_getBounds()`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ZSTD_bounds {
+    /// an error code, to test with `ZSTD_isError()`
+    pub error: size_t,
+    pub lowerBound: c_int,
+    pub upperBound: c_int,
+}
+
 /// Parse the result code
 ///
 /// Returns the number of bytes written if the code represents success,
 /// or the error message otherwise.
 pub fn parse_code(code: ErrorCode) -> Result<usize, io::Error> {
     unsafe {
-        if ZBUFF_isError(code) == 0 {
+        if ZSTD_isError(code) == 0 {
             Ok(code as usize)
         } else {
-            let msg = CStr::from_ptr(ZBUFF_getErrorName(code));
+            let msg = CStr::from_ptr(ZSTD_getErrorName(code));
             let error = io::Error::new(io::ErrorKind::Other,
                                        msg.to_str().unwrap().to_string());
             Err(error)
@@ -89,57 +403,117 @@ pub fn parse_code(code: ErrorCode) -> Result<usize, io::Error> {
     }
 }
 
-extern "C" {
-    // zbuff.h
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ZDICT_params_t {
+    /// optimize for a specific zstd compression level; 0 means default
+    pub compressionLevel: c_int,
+    /// Write log to stderr; 0 = none (default); 1 = errors; 2 = progression;
+    /// 3 = details; 4 = debug;
+    pub notificationLevel: c_uint,
+    /// force dictID value; 0 means auto mode (32-bits random value)
+    pub dictID: c_uint,
+}
 
-    pub fn ZBUFF_createCCtx() -> ZBUFFCompressionContext;
-    pub fn ZBUFF_freeCCtx(cctx: ZBUFFCompressionContext) -> ErrorCode;
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ZDICT_cover_params_t {
+    /// Segment size : constraint: 0 < k : Reasonable range [16, 2048+]
+    pub k: c_uint,
+    /// dmer size : constraint: 0 < d <= k : Reasonable range [6, 16]
+    pub d: c_uint,
+    /// Number of steps : Only used for optimization : 0 means default (40)
+    pub steps: c_uint,
+    /// Number of threads : constraint: 0 < nbThreads : 1 means
+    /// single-threaded
+    pub nbThreads: c_uint,
+    /// Percentage of samples used for training: 0 means default (1.0)
+    pub splitPoint: f64,
+    /// Train dictionaries to shrink in size starting from the minimum size
+    pub shrinkDict: c_uint,
+    /// Sets shrinkDictMaxRegression so that a smaller dictionary can be at
+    /// worse this percentage larger than the largest dictionary tried
+    pub shrinkDictMaxRegression: c_uint,
+    pub zParams: ZDICT_params_t,
+}
 
-    pub fn ZBUFF_compressInit(cctx: ZBUFFCompressionContext,
-                              compressionLevel: c_int)
-                              -> ErrorCode;
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ZDICT_fastCover_params_t {
+    /// Segment size : constraint: 0 < k : Reasonable range [16, 2048+]
+    pub k: c_uint,
+    /// dmer size : constraint: 0 < d <= k : Reasonable range [6, 16]
+    pub d: c_uint,
+    /// log of size of frequency array : constraint: 0 < f <= 31 : 1 means
+    /// default(20)
+    pub f: c_uint,
+    /// Number of steps : Only used for optimization : 0 means default (40)
+    pub steps: c_uint,
+    /// Number of threads : constraint: 0 < nbThreads : 1 means
+    /// single-threaded
+    pub nbThreads: c_uint,
+    /// Percentage of samples used for training: 0 means default (0.75)
+    pub splitPoint: f64,
+    /// Acceleration level: constraint: 0 < accel <= 10, 0 means default (1)
+    pub accel: c_uint,
+    /// Train dictionaries to shrink in size starting from the minimum size
+    pub shrinkDict: c_uint,
+    /// Sets shrinkDictMaxRegression so that a smaller dictionary can be at
+    /// worse this percentage larger than the largest dictionary tried
+    pub shrinkDictMaxRegression: c_uint,
+    pub zParams: ZDICT_params_t,
+}
 
-    pub fn ZBUFF_compressInitDictionary(cctx: ZBUFFCompressionContext,
-                                        dict: *const u8, dictSize: size_t,
-                                        compressionLevel: c_int)
-                                        -> ErrorCode;
+pub type XXH64_state = *mut c_void;
+pub type XXH64_hash_t = u64;
+pub type XXH_errorcode = c_int;
+pub const XXH_OK: XXH_errorcode = 0;
 
-    pub fn ZBUFF_compressContinue(cctx: ZBUFFCompressionContext, dst: *mut u8,
-                                  dstCapacityPtr: *mut size_t, src: *const u8,
-                                  srcSizePtr: *mut size_t)
-                                  -> ErrorCode;
-    pub fn ZBUFF_compressFlush(cctx: ZBUFFCompressionContext, dst: *mut u8,
-                               dstCapacityPtr: *mut size_t)
-                               -> ErrorCode;
-    pub fn ZBUFF_compressEnd(cctx: ZBUFFCompressionContext, dst: *mut u8,
-                             dstCapacityPtr: *mut size_t)
-                             -> ErrorCode;
+extern "C" {
+    // zstd.h
 
+    pub fn ZSTD_versionNumber() -> c_uint;
+    pub fn ZSTD_versionString() -> *const c_char;
 
-    // -***************************************************************************
-    // ******************************************************************************
-    pub fn ZBUFF_createDCtx() -> ZBUFFDecompressionContext;
-    pub fn ZBUFF_freeDCtx(dctx: ZBUFFDecompressionContext) -> ErrorCode;
+    pub fn ZSTD_isError(code: size_t) -> c_uint;
+    pub fn ZSTD_getErrorName(code: size_t) -> *const c_char;
+    pub fn ZSTD_getErrorCode(functionResult: size_t) -> ZSTD_ErrorCode;
+    pub fn ZSTD_getErrorString(code: ZSTD_ErrorCode) -> *const c_char;
 
-    pub fn ZBUFF_decompressInit(dctx: ZBUFFDecompressionContext) -> size_t;
-    pub fn ZBUFF_decompressInitDictionary(dctx: ZBUFFDecompressionContext,
-                                          dict: *const u8, dictSize: size_t)
-                                          -> ErrorCode;
-    pub fn ZBUFF_decompressContinue(dctx: ZBUFFDecompressionContext,
-                                    dst: *mut u8, dstCapacityPtr: *mut size_t,
-                                    src: *const u8, srcSizePtr: *mut size_t)
-                                    -> ErrorCode;
+    // Simple API
 
-    pub fn ZBUFF_isError(errorCode: size_t) -> c_uint;
-    pub fn ZBUFF_getErrorName(errorCode: size_t) -> *const c_char;
+    pub fn ZSTD_compress(dst: *mut u8, dstCapacity: size_t, src: *const u8,
+                         srcSize: size_t, compressionLevel: c_int)
+                         -> ErrorCode;
+    /// `compressedSize` must be the exact size of some number of frames.
+    pub fn ZSTD_decompress(dst: *mut u8, dstCapacity: size_t,
+                           src: *const u8, compressedSize: size_t)
+                           -> ErrorCode;
 
-    pub fn ZBUFF_recommendedCInSize() -> size_t;
-    pub fn ZBUFF_recommendedCOutSize() -> size_t;
+    /// @return : the decompressed size of the frame starting at `src`,
+    ///           `ZSTD_CONTENTSIZE_UNKNOWN` if it isn't in the header, or
+    ///           `ZSTD_CONTENTSIZE_ERROR` for invalid input
+    pub fn ZSTD_getFrameContentSize(src: *const u8, srcSize: size_t)
+                                    -> c_ulonglong;
 
-    pub fn ZBUFF_recommendedDInSize() -> size_t;
-    pub fn ZBUFF_recommendedDOutSize() -> size_t;
+    pub fn ZSTD_defaultCLevel() -> c_int;
+    pub fn ZSTD_sizeof_CCtx(cctx: ZSTDCompressionContext) -> size_t;
+    pub fn ZSTD_sizeof_DCtx(dctx: ZSTDDecompressionContext) -> size_t;
 
-    // zstd.h
+    /// Returns the valid range of a compression parameter.
+    pub fn ZSTD_cParam_getBounds(cParam: ZSTD_cParameter) -> ZSTD_bounds;
+    /// Returns the valid range of a decompression parameter.
+    pub fn ZSTD_dParam_getBounds(dParam: ZSTD_dParameter) -> ZSTD_bounds;
+
+    /// Returns the ID of a dictionary, or 0 for raw content.
+    pub fn ZSTD_getDictID_fromDict(dict: *const u8, dictSize: size_t)
+                                   -> c_uint;
+    pub fn ZSTD_getDictID_fromDDict(ddict: ZSTDDecompressionDictionary)
+                                    -> c_uint;
+    /// Returns the dictionary ID needed by a frame, or 0 if none or
+    /// unknown.
+    pub fn ZSTD_getDictID_fromFrame(src: *const u8, srcSize: size_t)
+                                    -> c_uint;
 
     // Compression context memory management
     pub fn ZSTD_createCCtx() -> ZSTDCompressionContext;
@@ -149,6 +523,17 @@ extern "C" {
     pub fn ZSTD_createDCtx() -> ZSTDDecompressionContext;
     pub fn ZSTD_freeDCtx(cctx: ZSTDDecompressionContext) -> ErrorCode;
 
+    // Allocation through a custom allocator
+    pub fn ZSTD_createCCtx_advanced(customMem: ZSTD_customMem)
+                                    -> ZSTDCompressionContext;
+    pub fn ZSTD_createDCtx_advanced(customMem: ZSTD_customMem)
+                                    -> ZSTDDecompressionContext;
+    pub fn ZSTD_createDDict_advanced(dict: *const u8, dictSize: size_t,
+                                     dictLoadMethod: ZSTD_dictLoadMethod_e,
+                                     dictContentType: ZSTD_dictContentType_e,
+                                     customMem: ZSTD_customMem)
+                                     -> ZSTDDecompressionDictionary;
+
     /// Compression using a pre-defined Dictionary content (see dictBuilder).
     ///
     /// Note : dict can be NULL, in which case, it's equivalent to ZSTD_compressCCtx() */
@@ -169,14 +554,282 @@ extern "C" {
                                      dict: *const u8, dictSize: size_t)
                                      -> ErrorCode;
 
+    /// @return : the compressed size of the first frame starting at `src`,
+    ///           suitable to pass as `srcSize` to `ZSTD_decompress` or
+    ///           similar, or an error code if input is invalid
+    pub fn ZSTD_findFrameCompressedSize(src: *const u8, srcSize: size_t)
+                                        -> ErrorCode;
+
+    /// @return : an upper-bound for the decompressed size of all the frames
+    ///           in `src`, or ZSTD_CONTENTSIZE_ERROR if an error occurred
+    pub fn ZSTD_decompressBound(src: *const u8, srcSize: size_t)
+                                -> c_ulonglong;
+
     /// maximum compressed size (worst case scenario)
     pub fn ZSTD_compressBound(srcSize: size_t) -> size_t;
 
+    pub fn ZSTD_minCLevel() -> c_int;
+    pub fn ZSTD_maxCLevel() -> c_int;
+
+    // Bulk processing dictionary API
+
+    pub fn ZSTD_createCDict(dictBuffer: *const u8, dictSize: size_t,
+                            compressionLevel: c_int)
+                            -> ZSTDCompressionDictionary;
+    pub fn ZSTD_freeCDict(cdict: ZSTDCompressionDictionary) -> ErrorCode;
+    pub fn ZSTD_sizeof_CDict(cdict: ZSTDCompressionDictionary) -> size_t;
+
+    pub fn ZSTD_createDDict(dictBuffer: *const u8, dictSize: size_t)
+                            -> ZSTDDecompressionDictionary;
+    pub fn ZSTD_freeDDict(ddict: ZSTDDecompressionDictionary) -> ErrorCode;
+    pub fn ZSTD_sizeof_DDict(ddict: ZSTDDecompressionDictionary) -> size_t;
+
+    pub fn ZSTD_decompress_usingDDict(dctx: ZSTDDecompressionContext,
+                                      dst: *mut u8, dstCapacity: size_t,
+                                      src: *const u8, srcSize: size_t,
+                                      ddict: ZSTDDecompressionDictionary)
+                                      -> ErrorCode;
+
+    pub fn ZSTD_compress_usingCDict(cctx: ZSTDCompressionContext,
+                                    dst: *mut u8, dstCapacity: size_t,
+                                    src: *const u8, srcSize: size_t,
+                                    cdict: ZSTDCompressionDictionary)
+                                    -> ErrorCode;
+
+    // Advanced compression API
+
+    pub fn ZSTD_CCtx_setParameter(cctx: ZSTDCompressionContext,
+                                  param: ZSTD_cParameter, value: c_int)
+                                  -> ErrorCode;
+    pub fn ZSTD_CCtx_getParameter(cctx: ZSTDCompressionContext,
+                                  param: ZSTD_cParameter, value: *mut c_int)
+                                  -> ErrorCode;
+    pub fn ZSTD_CCtx_setPledgedSrcSize(cctx: ZSTDCompressionContext,
+                                       pledgedSrcSize: c_ulonglong)
+                                       -> ErrorCode;
+    pub fn ZSTD_CCtx_loadDictionary(cctx: ZSTDCompressionContext,
+                                    dict: *const u8, dictSize: size_t)
+                                    -> ErrorCode;
+    pub fn ZSTD_CCtx_refCDict(cctx: ZSTDCompressionContext,
+                              cdict: ZSTDCompressionDictionary)
+                              -> ErrorCode;
+    pub fn ZSTD_CCtx_reset(cctx: ZSTDCompressionContext,
+                           reset: ZSTD_ResetDirective)
+                           -> ErrorCode;
+    /// Uses `prefix` as a dictionary for the next frame only.
+    ///
+    /// The prefix is referenced, not copied: it must outlive the frame.
+    pub fn ZSTD_CCtx_refPrefix(cctx: ZSTDCompressionContext,
+                               prefix: *const u8, prefixSize: size_t)
+                               -> ErrorCode;
+
+    pub fn ZSTD_DCtx_setParameter(dctx: ZSTDDecompressionContext,
+                                  param: ZSTD_dParameter, value: c_int)
+                                  -> ErrorCode;
+    pub fn ZSTD_DCtx_loadDictionary(dctx: ZSTDDecompressionContext,
+                                    dict: *const u8, dictSize: size_t)
+                                    -> ErrorCode;
+    pub fn ZSTD_DCtx_refDDict(dctx: ZSTDDecompressionContext,
+                              ddict: ZSTDDecompressionDictionary)
+                              -> ErrorCode;
+    pub fn ZSTD_DCtx_reset(dctx: ZSTDDecompressionContext,
+                           reset: ZSTD_ResetDirective)
+                           -> ErrorCode;
+    pub fn ZSTD_DCtx_refPrefix(dctx: ZSTDDecompressionContext,
+                               prefix: *const u8, prefixSize: size_t)
+                               -> ErrorCode;
+
+    pub fn ZSTD_createCCtxParams() -> ZSTDCompressionParameters;
+    pub fn ZSTD_freeCCtxParams(params: ZSTDCompressionParameters)
+                               -> ErrorCode;
+    pub fn ZSTD_CCtxParams_init(cctxParams: ZSTDCompressionParameters,
+                                compressionLevel: c_int)
+                                -> ErrorCode;
+    pub fn ZSTD_CCtxParams_setParameter(params: ZSTDCompressionParameters,
+                                        param: ZSTD_cParameter, value: c_int)
+                                        -> ErrorCode;
+
+    pub fn ZSTD_createCDict_advanced2(dict: *const u8, dictSize: size_t,
+                                      dictLoadMethod: ZSTD_dictLoadMethod_e,
+                                      dictContentType: ZSTD_dictContentType_e,
+                                      cctxParams: ZSTDCompressionParameters,
+                                      customMem: ZSTD_customMem)
+                                      -> ZSTDCompressionDictionary;
+
+    /// Returns `ZSTD_compressionParameters` structure for a selected
+    /// compression level and estimated srcSize.
+    ///
+    /// `estimatedSrcSize` value is optional, select 0 if not known
+    pub fn ZSTD_getCParams(compressionLevel: c_int,
+                           estimatedSrcSize: c_ulonglong, dictSize: size_t)
+                           -> ZSTD_compressionParameters;
+
+    /// Ensure param values remain within authorized range.
+    ///
+    /// @return 0 on success, or an error code (can be checked with
+    /// ZSTD_isError())
+    pub fn ZSTD_checkCParams(params: ZSTD_compressionParameters)
+                             -> ErrorCode;
+
+    // Memory management
+
+    pub fn ZSTD_estimateCCtxSize(compressionLevel: c_int) -> size_t;
+    pub fn ZSTD_estimateDCtxSize() -> size_t;
+
+    /// Initialize an object using a pre-allocated fixed-size buffer.
+    ///
+    /// `workspace`: The memory area to emplace the object into. Provided
+    /// pointer *must be 8-bytes aligned*. Buffer must outlive object.
+    /// @return : pointer to object (same address as workspace, just
+    /// different type), or NULL if error (size too small, incorrect
+    /// alignment, etc.)
+    pub fn ZSTD_initStaticCCtx(workspace: *mut c_void, workspaceSize: size_t)
+                               -> ZSTDCompressionContext;
+    pub fn ZSTD_initStaticDCtx(workspace: *mut c_void, workspaceSize: size_t)
+                               -> ZSTDDecompressionContext;
+
+    pub fn ZSTD_compressCCtx(ctx: ZSTDCompressionContext, dst: *mut u8,
+                             dstCapacity: size_t, src: *const u8,
+                             srcSize: size_t, compressionLevel: c_int)
+                             -> ErrorCode;
+    pub fn ZSTD_decompressDCtx(ctx: ZSTDDecompressionContext, dst: *mut u8,
+                               dstCapacity: size_t, src: *const u8,
+                               srcSize: size_t)
+                               -> ErrorCode;
+
+    pub fn ZSTD_estimateCStreamSize(compressionLevel: c_int) -> size_t;
+    pub fn ZSTD_estimateCStreamSize_usingCParams(cParams:
+                                                 ZSTD_compressionParameters)
+                                                 -> size_t;
+    pub fn ZSTD_estimateDStreamSize(windowSize: size_t) -> size_t;
+
+    /// Generates the sequences zstd would compress `src` to, with the
+    /// parameters set on the context.
+    ///
+    /// @return : the number of sequences written to `outSeqs`, or an
+    ///           error code
+    pub fn ZSTD_generateSequences(zc: ZSTDCompressionContext,
+                                  outSeqs: *mut ZSTD_Sequence,
+                                  outSeqsSize: size_t, src: *const u8,
+                                  srcSize: size_t)
+                                  -> ErrorCode;
+
+    /// Compresses `src` to a frame, using the given sequences.
+    ///
+    /// The sequences are formatted according to `ZSTD_c_blockDelimiters`,
+    /// and checked first if `ZSTD_c_validateSequences` is set.
+    ///
+    /// @return : the compressed size, or an error code
+    pub fn ZSTD_compressSequences(cctx: ZSTDCompressionContext,
+                                  dst: *mut u8, dstCapacity: size_t,
+                                  inSeqs: *const ZSTD_Sequence,
+                                  inSeqsSize: size_t, src: *const u8,
+                                  srcSize: size_t)
+                                  -> ErrorCode;
+
+    /// Uses an external match-finder; zstd only does the entropy coding.
+    ///
+    /// Pass a `None` producer to go back to zstd's own match-finder.
+    pub fn ZSTD_registerSequenceProducer(cctx: ZSTDCompressionContext,
+                                         sequenceProducerState: *mut c_void,
+                                         sequenceProducer:
+                                         ZSTD_sequenceProducer_F);
+
+    /// Compresses using the parameters and dictionary set on the context.
+    pub fn ZSTD_compress2(cctx: ZSTDCompressionContext, dst: *mut u8,
+                          dstCapacity: size_t, src: *const u8,
+                          srcSize: size_t)
+                          -> ErrorCode;
+
+    // Streaming
+
+    pub fn ZSTD_compressStream2(cctx: ZSTDCompressionContext,
+                                output: *mut ZSTD_outBuffer,
+                                input: *mut ZSTD_inBuffer,
+                                endOp: ZSTD_EndDirective)
+                                -> ErrorCode;
+    pub fn ZSTD_decompressStream(dctx: ZSTDDecompressionContext,
+                                 output: *mut ZSTD_outBuffer,
+                                 input: *mut ZSTD_inBuffer)
+                                 -> ErrorCode;
+
+    /// Decode Frame Header, or requires larger `srcSize`.
+    ///
+    /// @return : 0, `zfhPtr` is correctly filled,
+    ///          >0, `srcSize` is too small, value is wanted `srcSize` amount,
+    ///           or an error code, which can be tested using ZSTD_isError()
+    pub fn ZSTD_getFrameHeader(zfhPtr: *mut ZSTD_frameHeader,
+                               src: *const u8, srcSize: size_t)
+                               -> ErrorCode;
+
+    pub fn ZSTD_CStreamInSize() -> size_t;
+    pub fn ZSTD_CStreamOutSize() -> size_t;
+
+    pub fn ZSTD_DStreamInSize() -> size_t;
+    pub fn ZSTD_DStreamOutSize() -> size_t;
+
+    /// Tells how much data has been ingested (read from input),
+    /// consumed (input actually compressed) and produced (output) for
+    /// current frame.
+    ///
+    /// Note : (ingested - consumed) is amount of input data buffered
+    /// internally, not yet compressed.
+    pub fn ZSTD_getFrameProgression(cctx: ZSTDCompressionContext)
+                                    -> ZSTD_frameProgression;
+
+    // xxhash.h (namespaced with `ZSTD_`)
+
+    pub fn ZSTD_XXH64(input: *const u8, length: size_t,
+                      seed: XXH64_hash_t)
+                      -> XXH64_hash_t;
+
+    pub fn ZSTD_XXH64_createState() -> XXH64_state;
+    pub fn ZSTD_XXH64_freeState(statePtr: XXH64_state) -> XXH_errorcode;
+    pub fn ZSTD_XXH64_reset(statePtr: XXH64_state, seed: XXH64_hash_t)
+                            -> XXH_errorcode;
+    pub fn ZSTD_XXH64_update(statePtr: XXH64_state, input: *const u8,
+                             length: size_t)
+                             -> XXH_errorcode;
+    pub fn ZSTD_XXH64_digest(statePtr: XXH64_state) -> XXH64_hash_t;
+
     // zdict.h
 
+    pub fn ZDICT_isError(code: size_t) -> c_uint;
+    pub fn ZDICT_getErrorName(code: size_t) -> *const c_char;
+
     pub fn ZDICT_trainFromBuffer(dictBuffer: *mut u8,
                                  dictBufferCapacity: size_t,
                                  samplesBuffer: *const u8,
-                                 sampleSizes: *const size_t, nbSamples: size_t)
+                                 sampleSizes: *const size_t, nbSamples: c_uint)
                                  -> size_t;
+
+    /// Same as `ZDICT_trainFromBuffer()`, with explicit parameters.
+    ///
+    /// Parameters left to 0 (`k`, `d`, `steps`...) are optimized, and the
+    /// chosen values written back into `parameters`.
+    pub fn ZDICT_optimizeTrainFromBuffer_fastCover(
+        dictBuffer: *mut u8, dictBufferCapacity: size_t,
+        samplesBuffer: *const u8, samplesSizes: *const size_t,
+        nbSamples: c_uint, parameters: *mut ZDICT_fastCover_params_t)
+        -> size_t;
+
+    /// Trains a dictionary with the COVER algorithm, optimizing `k`
+    /// and `d` when they are 0.
+    ///
+    /// The chosen parameters are written back into `parameters`.
+    pub fn ZDICT_optimizeTrainFromBuffer_cover(
+        dictBuffer: *mut u8, dictBufferCapacity: size_t,
+        samplesBuffer: *const u8, samplesSizes: *const size_t,
+        nbSamples: c_uint, parameters: *mut ZDICT_cover_params_t)
+        -> size_t;
+
+    /// Trains a dictionary with explicit `k` and `d` parameters.
+    pub fn ZDICT_trainFromBuffer_fastCover(
+        dictBuffer: *mut u8, dictBufferCapacity: size_t,
+        samplesBuffer: *const u8, samplesSizes: *const size_t,
+        nbSamples: c_uint, parameters: ZDICT_fastCover_params_t)
+        -> size_t;
+
+    /// Extracts dictID; returns 0 if error (not a valid dictionary).
+    pub fn ZDICT_getDictID(dictBuffer: *const u8, dictSize: size_t) -> c_uint;
 }
@@ -0,0 +1,125 @@
+//! Accounting of the memory allocated by zstd.
+//!
+//! Contexts and prepared dictionaries created while tracking is enabled
+//! allocate through the functions below, which keep count of the memory
+//! in use and refuse allocations beyond the limit.
+
+use libc::{self, c_void, size_t};
+
+use std::{io, ptr, usize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use ll;
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// Each allocation starts with its size, padded to keep the alignment.
+const HEADER_SIZE: usize = 16;
+
+/// Enables or disables tracking of the memory allocated by zstd.
+///
+/// Only contexts and prepared dictionaries created while tracking is
+/// enabled are accounted for, in [`memory_in_use`](fn.memory_in_use.html)
+/// and against the limit.
+pub fn track_memory(enabled: bool) {
+    TRACKING.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns the memory currently allocated by tracked zstd objects,
+/// in bytes.
+///
+/// This includes the memory allocated as objects are used, like the
+/// buffers of a context growing on its first compression.
+pub fn memory_in_use() -> usize {
+    IN_USE.load(Ordering::SeqCst)
+}
+
+/// Caps the memory tracked objects may allocate, in bytes.
+///
+/// Once the limit would be exceeded, allocations fail: constructors
+/// return an error (or panic, for those which cannot fail, like
+/// `stream::FrameIter::new`), and so do operations needing more memory.
+/// `None` removes the limit.
+///
+/// Setting a limit enables tracking.
+pub fn set_memory_limit(limit: Option<usize>) {
+    LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::SeqCst);
+    if limit.is_some() {
+        track_memory(true);
+    }
+}
+
+// Returns the allocator to use for new zstd objects.
+pub(crate) fn custom_mem() -> ll::ZSTD_customMem {
+    if !TRACKING.load(Ordering::SeqCst) {
+        return ll::ZSTD_defaultCMem;
+    }
+    ll::ZSTD_customMem {
+        customAlloc: Some(tracked_alloc),
+        customFree: Some(tracked_free),
+        opaque: ptr::null_mut(),
+    }
+}
+
+// Error returned when zstd couldn't allocate an object.
+pub(crate) fn allocation_error(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other,
+                   format!("could not allocate {}", what))
+}
+
+unsafe extern "C" fn tracked_alloc(_: *mut c_void, size: size_t)
+                                   -> *mut c_void {
+    let total = match size.checked_add(HEADER_SIZE) {
+        Some(total) => total,
+        None => return ptr::null_mut(),
+    };
+
+    if !reserve(&IN_USE, size, LIMIT.load(Ordering::SeqCst)) {
+        return ptr::null_mut();
+    }
+
+    let block = libc::malloc(total) as *mut u8;
+    if block.is_null() {
+        IN_USE.fetch_sub(size, Ordering::SeqCst);
+        return ptr::null_mut();
+    }
+    *(block as *mut usize) = size;
+    block.add(HEADER_SIZE) as *mut c_void
+}
+
+// Adds `size` to `in_use`, unless it would exceed `limit`.
+fn reserve(in_use: &AtomicUsize, size: usize, limit: usize) -> bool {
+    let mut current = in_use.load(Ordering::SeqCst);
+    loop {
+        if current.saturating_add(size) > limit {
+            return false;
+        }
+        match in_use.compare_exchange_weak(current,
+                                           current + size,
+                                           Ordering::SeqCst,
+                                           Ordering::SeqCst) {
+            Ok(_) => return true,
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+unsafe extern "C" fn tracked_free(_: *mut c_void, address: *mut c_void) {
+    if address.is_null() {
+        return;
+    }
+    let block = (address as *mut u8).sub(HEADER_SIZE);
+    IN_USE.fetch_sub(*(block as *const usize), Ordering::SeqCst);
+    libc::free(block as *mut c_void);
+}
+
+#[test]
+fn test_reserve() {
+    let in_use = AtomicUsize::new(0);
+    assert!(reserve(&in_use, 600, 1000));
+    assert!(!reserve(&in_use, 600, 1000));
+    assert!(reserve(&in_use, 400, 1000));
+    assert_eq!(in_use.load(Ordering::SeqCst), 1000);
+}
@@ -0,0 +1,513 @@
+//! Advanced compression parameters.
+//!
+//! These parameters can be set on the stream encoders
+//! to fine-tune how zstd compresses data.
+
+use ll;
+
+use std::{cmp, io, thread};
+
+/// Controls how literals (bytes not part of a match) are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralCompressionMode {
+    /// Let zstd decide, depending on the compression level.
+    Auto,
+    /// Always compress literals with Huffman coding.
+    ///
+    /// Best for ratio.
+    Huffman,
+    /// Always store literals uncompressed.
+    ///
+    /// Faster compression and decompression, at the cost of ratio.
+    Uncompressed,
+}
+
+/// Match-finding strategy, from the fastest to the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Single hash lookup.
+    Fast,
+    /// Double hash lookup.
+    DFast,
+    /// Takes the first match found in a hash chain.
+    Greedy,
+    /// Checks whether the next position gives a better match.
+    Lazy,
+    /// Checks the next two positions for a better match.
+    Lazy2,
+    /// Lazy matching over a binary tree.
+    BtLazy2,
+    /// Optimal parsing over a binary tree.
+    BtOpt,
+    /// Optimal parsing, with more exhaustive search.
+    BtUltra,
+    /// Like `BtUltra`, with a pre-pass to improve statistics.
+    BtUltra2,
+}
+
+impl Strategy {
+    fn from_raw(strategy: ll::ZSTD_strategy) -> Self {
+        match strategy {
+            ll::ZSTD_fast => Strategy::Fast,
+            ll::ZSTD_dfast => Strategy::DFast,
+            ll::ZSTD_greedy => Strategy::Greedy,
+            ll::ZSTD_lazy => Strategy::Lazy,
+            ll::ZSTD_lazy2 => Strategy::Lazy2,
+            ll::ZSTD_btlazy2 => Strategy::BtLazy2,
+            ll::ZSTD_btopt => Strategy::BtOpt,
+            ll::ZSTD_btultra => Strategy::BtUltra,
+            _ => Strategy::BtUltra2,
+        }
+    }
+
+    fn as_raw(&self) -> ll::ZSTD_strategy {
+        match *self {
+            Strategy::Fast => ll::ZSTD_fast,
+            Strategy::DFast => ll::ZSTD_dfast,
+            Strategy::Greedy => ll::ZSTD_greedy,
+            Strategy::Lazy => ll::ZSTD_lazy,
+            Strategy::Lazy2 => ll::ZSTD_lazy2,
+            Strategy::BtLazy2 => ll::ZSTD_btlazy2,
+            Strategy::BtOpt => ll::ZSTD_btopt,
+            Strategy::BtUltra => ll::ZSTD_btultra,
+            Strategy::BtUltra2 => ll::ZSTD_btultra2,
+        }
+    }
+}
+
+/// A single compression parameter.
+///
+/// See the zstd documentation for the meaning and valid range of each one.
+/// Parameters are sticky: they apply to all following frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CParameter {
+    /// Compression level. Resets the parameters below to the level defaults.
+    CompressionLevel(i32),
+    /// Log2 of the largest match distance.
+    WindowLog(u32),
+    /// Log2 of the size of the initial probe table.
+    HashLog(u32),
+    /// Log2 of the size of the fully searched segment.
+    ChainLog(u32),
+    /// Log2 of the number of searches.
+    SearchLog(u32),
+    /// Minimum size of searched matches.
+    MinMatch(u32),
+    /// Target match length; impact depends on the strategy.
+    TargetLength(u32),
+    /// Match-finding strategy.
+    Strategy(Strategy),
+    /// Enables long distance matching, for large inputs with a large window.
+    EnableLongDistanceMatching(bool),
+    /// Log2 of the long distance matching table size.
+    LdmHashLog(u32),
+    /// Minimum match size for long distance matching.
+    LdmMinMatch(u32),
+    /// Log2 of the long distance matching bucket size.
+    LdmBucketSizeLog(u32),
+    /// Log2 of the frequency of long distance matching table insertions.
+    LdmHashRateLog(u32),
+    /// Writes the content size in the frame header, when known.
+    ContentSizeFlag(bool),
+    /// Writes a checksum of the content at the end of the frame.
+    ChecksumFlag(bool),
+    /// Writes the dictionary ID in the frame header, when using one.
+    DictIdFlag(bool),
+    /// Number of worker threads. Requires the `zstdmt` feature.
+    NbWorkers(u32),
+    /// Size of a compression job, in bytes, when using workers.
+    JobSize(u32),
+    /// Size of the overlap between jobs, as a fraction of the window size.
+    OverlapLog(u32),
+    /// Any parameter, given by its raw zstd identifier.
+    ///
+    /// No validation is done on this side.
+    #[cfg(feature = "experimental")]
+    Raw(u32, i32),
+}
+
+impl CParameter {
+    pub(crate) fn as_raw(&self) -> (ll::ZSTD_cParameter, i32) {
+        use self::CParameter::*;
+        match *self {
+            CompressionLevel(level) => (ll::ZSTD_c_compressionLevel, level),
+            WindowLog(value) => (ll::ZSTD_c_windowLog, value as i32),
+            HashLog(value) => (ll::ZSTD_c_hashLog, value as i32),
+            ChainLog(value) => (ll::ZSTD_c_chainLog, value as i32),
+            SearchLog(value) => (ll::ZSTD_c_searchLog, value as i32),
+            MinMatch(value) => (ll::ZSTD_c_minMatch, value as i32),
+            TargetLength(value) => (ll::ZSTD_c_targetLength, value as i32),
+            Strategy(strategy) => (ll::ZSTD_c_strategy, strategy.as_raw()),
+            EnableLongDistanceMatching(flag) => {
+                (ll::ZSTD_c_enableLongDistanceMatching, flag as i32)
+            }
+            LdmHashLog(value) => (ll::ZSTD_c_ldmHashLog, value as i32),
+            LdmMinMatch(value) => (ll::ZSTD_c_ldmMinMatch, value as i32),
+            LdmBucketSizeLog(value) => {
+                (ll::ZSTD_c_ldmBucketSizeLog, value as i32)
+            }
+            LdmHashRateLog(value) => (ll::ZSTD_c_ldmHashRateLog, value as i32),
+            ContentSizeFlag(flag) => (ll::ZSTD_c_contentSizeFlag, flag as i32),
+            ChecksumFlag(flag) => (ll::ZSTD_c_checksumFlag, flag as i32),
+            DictIdFlag(flag) => (ll::ZSTD_c_dictIDFlag, flag as i32),
+            NbWorkers(value) => (ll::ZSTD_c_nbWorkers, value as i32),
+            JobSize(value) => (ll::ZSTD_c_jobSize, value as i32),
+            OverlapLog(value) => (ll::ZSTD_c_overlapLog, value as i32),
+            #[cfg(feature = "experimental")]
+            Raw(param, value) => (param as ll::ZSTD_cParameter, value),
+        }
+    }
+}
+
+/// A single decompression parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DParameter {
+    /// Log2 of the largest window size the decoder will accept.
+    ///
+    /// Frames requiring a larger window are refused.
+    WindowLogMax(u32),
+    /// Any parameter, given by its raw zstd identifier.
+    ///
+    /// No validation is done on this side.
+    #[cfg(feature = "experimental")]
+    Raw(u32, i32),
+}
+
+impl DParameter {
+    pub(crate) fn as_raw(&self) -> (ll::ZSTD_dParameter, i32) {
+        match *self {
+            DParameter::WindowLogMax(value) => {
+                (ll::ZSTD_d_windowLogMax, value as i32)
+            }
+            #[cfg(feature = "experimental")]
+            DParameter::Raw(param, value) => {
+                (param as ll::ZSTD_dParameter, value)
+            }
+        }
+    }
+}
+
+/// A version of the zstd library, to stay compatible with its decoder.
+///
+/// See `Encoder::max_format_compatibility`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ZstdVersion {
+    /// Major version.
+    pub major: u32,
+    /// Minor version.
+    pub minor: u32,
+    /// Release number.
+    pub patch: u32,
+}
+
+impl ZstdVersion {
+    /// The first version decoding the current frame format: v0.8.0.
+    ///
+    /// Older versions can only read legacy frames.
+    pub const FRAME_FORMAT: ZstdVersion = ZstdVersion {
+        major: 0,
+        minor: 8,
+        patch: 0,
+    };
+
+    /// Creates a new version number.
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        ZstdVersion {
+            major: major,
+            minor: minor,
+            patch: patch,
+        }
+    }
+
+    /// Returns the version of the zstd library linked in.
+    pub fn current() -> Self {
+        let number = unsafe { ll::ZSTD_versionNumber() };
+        ZstdVersion::new(number / 10000, number / 100 % 100, number % 100)
+    }
+}
+
+/// Largest window decoders accept by default, whatever their version.
+///
+/// Frames with a larger window need decoders configured with
+/// `DParameter::WindowLogMax`, like `zstd --long`.
+pub const DEFAULT_WINDOW_LOG_LIMIT: u32 = 27;
+
+/// What to reset in an encoder or decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetDirective {
+    /// Drops the current frame, keeping the parameters and dictionary.
+    ///
+    /// Use this to start over after an error.
+    SessionOnly,
+    /// Restores the default parameters, and drops the dictionary.
+    ///
+    /// This is only possible between frames.
+    Parameters,
+    /// Both: the context is as good as new.
+    SessionAndParameters,
+}
+
+impl ResetDirective {
+    pub(crate) fn as_raw(&self) -> ll::ZSTD_ResetDirective {
+        match *self {
+            ResetDirective::SessionOnly => ll::ZSTD_reset_session_only,
+            ResetDirective::Parameters => ll::ZSTD_reset_parameters,
+            ResetDirective::SessionAndParameters => {
+                ll::ZSTD_reset_session_and_parameters
+            }
+        }
+    }
+}
+
+/// Concrete compression parameters.
+///
+/// Each compression level is a shortcut for a set of parameters,
+/// which also depend on the size of the data to compress.
+/// Use `CompressionParams::for_level` to see the parameters zstd picks,
+/// and tweak them before giving them to an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionParams {
+    params: ll::ZSTD_compressionParameters,
+}
+
+impl CompressionParams {
+    /// Returns the parameters used for the given level.
+    ///
+    /// `src_size` is the expected size of the data to compress,
+    /// or 0 if unknown.
+    pub fn for_level(level: i32, src_size: u64) -> Self {
+        CompressionParams::for_level_with_dict(level, src_size, 0)
+    }
+
+    /// Returns the parameters used for the given level,
+    /// when using a dictionary of the given size.
+    pub fn for_level_with_dict(level: i32, src_size: u64, dict_size: usize)
+                               -> Self {
+        let params =
+            unsafe { ll::ZSTD_getCParams(level, src_size, dict_size) };
+        CompressionParams { params: params }
+    }
+
+    /// Checks that all parameters are within their authorized range.
+    pub fn check(&self) -> io::Result<()> {
+        try!(ll::parse_code(unsafe { ll::ZSTD_checkCParams(self.params) }));
+        Ok(())
+    }
+
+    /// Log2 of the largest match distance.
+    ///
+    /// Decoders need a buffer this large.
+    pub fn window_log(&self) -> u32 {
+        self.params.windowLog
+    }
+
+    /// Sets the log2 of the largest match distance.
+    pub fn set_window_log(&mut self, value: u32) {
+        self.params.windowLog = value;
+    }
+
+    /// Log2 of the size of the fully searched segment.
+    pub fn chain_log(&self) -> u32 {
+        self.params.chainLog
+    }
+
+    /// Sets the log2 of the size of the fully searched segment.
+    pub fn set_chain_log(&mut self, value: u32) {
+        self.params.chainLog = value;
+    }
+
+    /// Log2 of the size of the initial probe table.
+    pub fn hash_log(&self) -> u32 {
+        self.params.hashLog
+    }
+
+    /// Sets the log2 of the size of the initial probe table.
+    pub fn set_hash_log(&mut self, value: u32) {
+        self.params.hashLog = value;
+    }
+
+    /// Log2 of the number of searches.
+    pub fn search_log(&self) -> u32 {
+        self.params.searchLog
+    }
+
+    /// Sets the log2 of the number of searches.
+    pub fn set_search_log(&mut self, value: u32) {
+        self.params.searchLog = value;
+    }
+
+    /// Minimum size of searched matches.
+    pub fn min_match(&self) -> u32 {
+        self.params.minMatch
+    }
+
+    /// Sets the minimum size of searched matches.
+    pub fn set_min_match(&mut self, value: u32) {
+        self.params.minMatch = value;
+    }
+
+    /// Impact depends on the strategy.
+    ///
+    /// For `BtOpt` and stronger, it is the match length above which
+    /// the search stops.
+    pub fn target_length(&self) -> u32 {
+        self.params.targetLength
+    }
+
+    /// Sets the target length.
+    pub fn set_target_length(&mut self, value: u32) {
+        self.params.targetLength = value;
+    }
+
+    /// Match-finding strategy.
+    pub fn strategy(&self) -> Strategy {
+        Strategy::from_raw(self.params.strategy)
+    }
+
+    /// Sets the match-finding strategy.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.params.strategy = strategy.as_raw();
+    }
+
+    /// Returns the memory a streaming encoder using these parameters
+    /// will consume, in bytes.
+    pub fn encoder_memory(&self) -> usize {
+        unsafe { ll::ZSTD_estimateCStreamSize_usingCParams(self.params) }
+    }
+
+    /// Returns the memory a streaming decoder will consume
+    /// to decompress data produced with these parameters, in bytes.
+    pub fn decoder_memory(&self) -> usize {
+        decoder_memory(1usize << self.params.windowLog)
+    }
+
+    pub(crate) fn as_raw(&self) -> &ll::ZSTD_compressionParameters {
+        &self.params
+    }
+}
+
+/// Returns the memory a streaming encoder at the given level will
+/// consume, in bytes.
+///
+/// This is the largest use for any input size; use
+/// [`CompressionParams::encoder_memory`] for a tighter bound
+/// when the input size is known.
+///
+/// [`CompressionParams::encoder_memory`]: struct.CompressionParams.html#method.encoder_memory
+pub fn encoder_memory(level: i32) -> usize {
+    unsafe { ll::ZSTD_estimateCStreamSize(level) }
+}
+
+/// Returns the memory a streaming decoder will consume for frames with
+/// the given window size, in bytes.
+pub fn decoder_memory(window_size: usize) -> usize {
+    unsafe { ll::ZSTD_estimateDStreamSize(window_size) }
+}
+
+/// Returns the strongest compression level fitting the given memory budgets.
+///
+/// * `max_encoder_mem` is the memory a streaming encoder may use.
+/// * `max_decoder_mem` is the memory a streaming decoder may use
+///   to decompress the result.
+/// * `expected_src_size` is the size of the data to compress,
+///   or 0 if unknown.
+///
+/// If even level 1 doesn't fit, returns 1.
+pub fn choose_level(max_encoder_mem: usize, max_decoder_mem: usize,
+                    expected_src_size: u64)
+                    -> i32 {
+    let max_level = unsafe { ll::ZSTD_maxCLevel() };
+
+    for level in (1..max_level + 1).rev() {
+        let params = CompressionParams::for_level(level, expected_src_size);
+        let encoder_mem = params.encoder_memory();
+        let decoder_mem = params.decoder_memory();
+
+        if encoder_mem <= max_encoder_mem && decoder_mem <= max_decoder_mem {
+            return level;
+        }
+    }
+
+    1
+}
+
+/// Returns a sensible number of workers for multithreaded compression.
+///
+/// Each worker needs a few megabytes of input to be worth its overhead,
+/// so small inputs get few or no workers (`0` means single-threaded).
+/// The result never exceeds the parallelism available on this machine.
+///
+/// `expected_src_size` is the size of the data to compress,
+/// or 0 if unknown.
+pub fn choose_workers(expected_src_size: u64) -> u32 {
+    let parallelism = thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1);
+    workers_for(expected_src_size, parallelism)
+}
+
+// Minimum input per worker.
+const BYTES_PER_WORKER: u64 = 4 << 20;
+
+fn workers_for(expected_src_size: u64, parallelism: u32) -> u32 {
+    if parallelism <= 1 {
+        return 0;
+    }
+    if expected_src_size == 0 {
+        return parallelism;
+    }
+
+    let workers = expected_src_size / BYTES_PER_WORKER;
+    if workers <= 1 {
+        0
+    } else {
+        cmp::min(workers, parallelism as u64) as u32
+    }
+}
+
+#[test]
+fn test_choose_workers() {
+    assert_eq!(workers_for(2 << 20, 32), 0);
+    assert_eq!(workers_for(16 << 20, 32), 4);
+    assert_eq!(workers_for(1 << 40, 32), 32);
+    assert_eq!(workers_for(0, 8), 8);
+    assert_eq!(workers_for(1 << 40, 1), 0);
+}
+
+#[test]
+fn test_choose_level() {
+    use std::usize;
+
+    let max_level = unsafe { ll::ZSTD_maxCLevel() };
+    assert_eq!(choose_level(usize::MAX, usize::MAX, 0), max_level);
+    assert_eq!(choose_level(0, 0, 0), 1);
+
+    // Small inputs need small windows: strong levels become affordable.
+    let budget = 4 << 20;
+    assert!(choose_level(budget, budget, 1024) >=
+            choose_level(budget, budget, 0));
+}
+
+#[test]
+fn test_params() {
+    let mut params = CompressionParams::for_level(19, 0);
+    assert!(params.check().is_ok());
+
+    params.set_strategy(Strategy::Lazy);
+    assert_eq!(params.strategy(), Strategy::Lazy);
+
+    // Small inputs don't need a large window.
+    let small = CompressionParams::for_level(19, 1024);
+    assert!(small.window_log() < params.window_log());
+}
+
+#[test]
+fn test_memory_estimation() {
+    assert!(encoder_memory(19) > encoder_memory(1));
+    assert!(decoder_memory(1 << 20) > decoder_memory(1 << 10));
+
+    let params = CompressionParams::for_level(19, 1000);
+    assert!(params.encoder_memory() <= encoder_memory(19));
+    assert_eq!(params.decoder_memory(),
+               decoder_memory(1 << params.window_log()));
+}
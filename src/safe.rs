@@ -0,0 +1,360 @@
+//! Safe functions mirroring the zstd C API, one for one.
+//!
+//! This sits between the raw bindings of the [`sys`](../sys/index.html)
+//! module and the `Read`/`Write` adapters: contexts are owned and freed
+//! automatically, pointers are replaced with slices, and errors are
+//! returned as `io::Error`, but nothing else happens behind the scenes.
+//! There is no buffering: each method is a single call into zstd, and
+//! returns what zstd returned.
+//!
+//! Methods are named after the C functions they call, without the
+//! `ZSTD_` prefix and the context type: `ZSTD_CCtx_setParameter` is
+//! `CCtx::set_parameter`.
+
+use std::io;
+use std::marker::PhantomData;
+
+use context::{DecoderContext, EncoderContext};
+use ll;
+use params::{CParameter, DParameter, ResetDirective};
+use stream::raw::{InBuffer, OutBuffer};
+
+/// What `CCtx::compress_stream2` should do after consuming the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndDirective {
+    /// Just compress: zstd decides when to output data.
+    Continue,
+    /// Output everything compressed so far, ending the current block.
+    Flush,
+    /// Output everything, and end the frame.
+    End,
+}
+
+impl EndDirective {
+    fn as_raw(&self) -> ll::ZSTD_EndDirective {
+        match *self {
+            EndDirective::Continue => ll::ZSTD_e_continue,
+            EndDirective::Flush => ll::ZSTD_e_flush,
+            EndDirective::End => ll::ZSTD_e_end,
+        }
+    }
+}
+
+/// A compression context.
+///
+/// `'a` is the lifetime of a prefix referenced with `ref_prefix`.
+pub struct CCtx<'a> {
+    context: EncoderContext,
+    prefix: PhantomData<&'a [u8]>,
+}
+
+impl<'a> CCtx<'a> {
+    /// `ZSTD_createCCtx`: creates a new context.
+    pub fn create() -> io::Result<Self> {
+        Ok(CCtx {
+            context: try!(EncoderContext::new()),
+            prefix: PhantomData,
+        })
+    }
+
+    /// `ZSTD_compressCCtx`: compresses `src` into `dst` as one frame.
+    ///
+    /// Returns the compressed size.
+    pub fn compress(&mut self, dst: &mut [u8], src: &[u8], level: i32)
+                    -> io::Result<usize> {
+        ll::parse_code(unsafe {
+            ll::ZSTD_compressCCtx(self.context.c,
+                                  dst.as_mut_ptr(),
+                                  dst.len(),
+                                  src.as_ptr(),
+                                  src.len(),
+                                  level)
+        })
+    }
+
+    /// `ZSTD_compress2`: compresses `src` into `dst` as one frame, with
+    /// the parameters and dictionary set on the context.
+    ///
+    /// Returns the compressed size.
+    pub fn compress2(&mut self, dst: &mut [u8], src: &[u8])
+                     -> io::Result<usize> {
+        ll::parse_code(unsafe {
+            ll::ZSTD_compress2(self.context.c,
+                               dst.as_mut_ptr(),
+                               dst.len(),
+                               src.as_ptr(),
+                               src.len())
+        })
+    }
+
+    /// `ZSTD_compress_usingDict`: compresses with the given dictionary.
+    ///
+    /// Returns the compressed size.
+    pub fn compress_using_dict(&mut self, dst: &mut [u8], src: &[u8],
+                               dict: &[u8], level: i32)
+                               -> io::Result<usize> {
+        ll::parse_code(unsafe {
+            ll::ZSTD_compress_usingDict(self.context.c,
+                                        dst.as_mut_ptr(),
+                                        dst.len(),
+                                        src.as_ptr(),
+                                        src.len(),
+                                        dict.as_ptr(),
+                                        dict.len(),
+                                        level)
+        })
+    }
+
+    /// `ZSTD_compressStream2`: compresses some input into `output`.
+    ///
+    /// Returns a hint for the next call: with `Flush` and `End`, 0 means
+    /// everything was output.
+    pub fn compress_stream2(&mut self, output: &mut OutBuffer,
+                            input: &mut InBuffer, end_op: EndDirective)
+                            -> io::Result<usize> {
+        let mut in_buffer = ll::ZSTD_inBuffer::new(input.src);
+        in_buffer.pos = input.pos;
+        let mut out_buffer = ll::ZSTD_outBuffer::new(output.dst);
+        out_buffer.pos = output.pos;
+
+        let code = unsafe {
+            ll::ZSTD_compressStream2(self.context.c,
+                                     &mut out_buffer,
+                                     &mut in_buffer,
+                                     end_op.as_raw())
+        };
+        input.pos = in_buffer.pos;
+        output.pos = out_buffer.pos;
+        ll::parse_code(code)
+    }
+
+    /// `ZSTD_CCtx_setParameter`: sets a parameter for the next frames.
+    pub fn set_parameter(&mut self, parameter: CParameter)
+                         -> io::Result<()> {
+        self.context.set_cparameter(parameter)
+    }
+
+    /// `ZSTD_CCtx_setPledgedSrcSize`: announces the size of the next
+    /// frame.
+    pub fn set_pledged_src_size(&mut self, size: u64) -> io::Result<()> {
+        self.context.set_pledged_src_size(size)
+    }
+
+    /// `ZSTD_CCtx_loadDictionary`: copies a dictionary, used for all the
+    /// next frames.
+    pub fn load_dictionary(&mut self, dict: &[u8]) -> io::Result<()> {
+        self.context.load_dictionary(dict)
+    }
+
+    /// `ZSTD_CCtx_refPrefix`: uses `prefix` as a dictionary for the next
+    /// frame only.
+    pub fn ref_prefix(&mut self, prefix: &'a [u8]) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_refPrefix(self.context.c,
+                                    prefix.as_ptr(),
+                                    prefix.len())
+        }));
+        Ok(())
+    }
+
+    /// `ZSTD_CCtx_reset`: resets the session, the parameters, or both.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        self.context.reset(directive)
+    }
+
+    /// `ZSTD_sizeof_CCtx`: returns the memory used by the context.
+    pub fn sizeof(&self) -> usize {
+        unsafe { ll::ZSTD_sizeof_CCtx(self.context.c) }
+    }
+}
+
+/// A decompression context.
+///
+/// `'a` is the lifetime of a prefix referenced with `ref_prefix`.
+pub struct DCtx<'a> {
+    context: DecoderContext,
+    prefix: PhantomData<&'a [u8]>,
+}
+
+impl<'a> DCtx<'a> {
+    /// `ZSTD_createDCtx`: creates a new context.
+    pub fn create() -> io::Result<Self> {
+        Ok(DCtx {
+            context: try!(DecoderContext::new()),
+            prefix: PhantomData,
+        })
+    }
+
+    /// `ZSTD_decompressDCtx`: decompresses all the frames in `src`.
+    ///
+    /// Returns the decompressed size.
+    pub fn decompress(&mut self, dst: &mut [u8], src: &[u8])
+                      -> io::Result<usize> {
+        ll::parse_code(unsafe {
+            ll::ZSTD_decompressDCtx(self.context.c,
+                                    dst.as_mut_ptr(),
+                                    dst.len(),
+                                    src.as_ptr(),
+                                    src.len())
+        })
+    }
+
+    /// `ZSTD_decompress_usingDict`: decompresses with the given
+    /// dictionary.
+    ///
+    /// Returns the decompressed size.
+    pub fn decompress_using_dict(&mut self, dst: &mut [u8], src: &[u8],
+                                 dict: &[u8])
+                                 -> io::Result<usize> {
+        ll::parse_code(unsafe {
+            ll::ZSTD_decompress_usingDict(self.context.c,
+                                          dst.as_mut_ptr(),
+                                          dst.len(),
+                                          src.as_ptr(),
+                                          src.len(),
+                                          dict.as_ptr(),
+                                          dict.len())
+        })
+    }
+
+    /// `ZSTD_decompressStream`: decompresses some input into `output`.
+    ///
+    /// Returns 0 at the end of a frame, or a hint for the next input size.
+    pub fn decompress_stream(&mut self, output: &mut OutBuffer,
+                             input: &mut InBuffer)
+                             -> io::Result<usize> {
+        let mut in_buffer = ll::ZSTD_inBuffer::new(input.src);
+        in_buffer.pos = input.pos;
+        let mut out_buffer = ll::ZSTD_outBuffer::new(output.dst);
+        out_buffer.pos = output.pos;
+
+        let code = unsafe {
+            ll::ZSTD_decompressStream(self.context.c,
+                                      &mut out_buffer,
+                                      &mut in_buffer)
+        };
+        input.pos = in_buffer.pos;
+        output.pos = out_buffer.pos;
+        ll::parse_code(code)
+    }
+
+    /// `ZSTD_DCtx_setParameter`: sets a parameter for the next frames.
+    pub fn set_parameter(&mut self, parameter: DParameter)
+                         -> io::Result<()> {
+        self.context.set_dparameter(parameter)
+    }
+
+    /// `ZSTD_DCtx_loadDictionary`: copies a dictionary, used for all the
+    /// next frames.
+    pub fn load_dictionary(&mut self, dict: &[u8]) -> io::Result<()> {
+        self.context.load_dictionary(dict)
+    }
+
+    /// `ZSTD_DCtx_refPrefix`: uses `prefix` as a dictionary for the next
+    /// frame only.
+    pub fn ref_prefix(&mut self, prefix: &'a [u8]) -> io::Result<()> {
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_DCtx_refPrefix(self.context.c,
+                                    prefix.as_ptr(),
+                                    prefix.len())
+        }));
+        Ok(())
+    }
+
+    /// `ZSTD_DCtx_reset`: resets the session, the parameters, or both.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        self.context.reset(directive)
+    }
+
+    /// `ZSTD_sizeof_DCtx`: returns the memory used by the context.
+    pub fn sizeof(&self) -> usize {
+        unsafe { ll::ZSTD_sizeof_DCtx(self.context.c) }
+    }
+}
+
+/// `ZSTD_compressBound`: the largest compressed size for `src_size`
+/// bytes.
+pub fn compress_bound(src_size: usize) -> usize {
+    unsafe { ll::ZSTD_compressBound(src_size) }
+}
+
+/// `ZSTD_findFrameCompressedSize`: the size of the frame starting `src`.
+pub fn find_frame_compressed_size(src: &[u8]) -> io::Result<usize> {
+    ll::parse_code(unsafe {
+        ll::ZSTD_findFrameCompressedSize(src.as_ptr(), src.len())
+    })
+}
+
+/// `ZSTD_getFrameContentSize`: the decompressed size of the frame
+/// starting `src`, or `None` if its header doesn't say.
+pub fn get_frame_content_size(src: &[u8]) -> io::Result<Option<u64>> {
+    match unsafe { ll::ZSTD_getFrameContentSize(src.as_ptr(), src.len()) } {
+        ll::ZSTD_CONTENTSIZE_ERROR => {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                               "invalid frame header"))
+        }
+        ll::ZSTD_CONTENTSIZE_UNKNOWN => Ok(None),
+        size => Ok(Some(size)),
+    }
+}
+
+/// `ZSTD_getDictID_fromFrame`: the dictionary ID a frame needs, or 0.
+pub fn get_dict_id_from_frame(src: &[u8]) -> u32 {
+    unsafe { ll::ZSTD_getDictID_fromFrame(src.as_ptr(), src.len()) }
+}
+
+/// `ZSTD_getDictID_fromDict`: the ID of a dictionary, or 0 for raw
+/// content.
+pub fn get_dict_id_from_dict(dict: &[u8]) -> u32 {
+    unsafe { ll::ZSTD_getDictID_fromDict(dict.as_ptr(), dict.len()) }
+}
+
+/// `ZSTD_CStreamInSize`: recommended input size for streaming
+/// compression.
+pub fn cstream_in_size() -> usize {
+    unsafe { ll::ZSTD_CStreamInSize() }
+}
+
+/// `ZSTD_CStreamOutSize`: recommended output size for streaming
+/// compression.
+pub fn cstream_out_size() -> usize {
+    unsafe { ll::ZSTD_CStreamOutSize() }
+}
+
+/// `ZSTD_DStreamInSize`: recommended input size for streaming
+/// decompression.
+pub fn dstream_in_size() -> usize {
+    unsafe { ll::ZSTD_DStreamInSize() }
+}
+
+/// `ZSTD_DStreamOutSize`: recommended output size for streaming
+/// decompression.
+pub fn dstream_out_size() -> usize {
+    unsafe { ll::ZSTD_DStreamOutSize() }
+}
+
+#[test]
+fn test_safe() {
+    let text = b"one for one, one for one, one for one";
+    let mut compressed = vec![0u8; compress_bound(text.len())];
+
+    let mut cctx = CCtx::create().unwrap();
+    cctx.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    cctx.ref_prefix(b"one for one").unwrap();
+    let size = cctx.compress2(&mut compressed, text).unwrap();
+    compressed.truncate(size);
+    assert_eq!(find_frame_compressed_size(&compressed).unwrap(), size);
+    assert_eq!(get_frame_content_size(&compressed).unwrap(),
+               Some(text.len() as u64));
+
+    let mut decompressed = vec![0u8; text.len()];
+    let mut dctx = DCtx::create().unwrap();
+    dctx.ref_prefix(b"one for one").unwrap();
+    let mut output = OutBuffer::new(&mut decompressed);
+    let mut input = InBuffer::new(&compressed);
+    assert_eq!(dctx.decompress_stream(&mut output, &mut input).unwrap(), 0);
+    assert_eq!(output.as_slice(), &text[..]);
+
+    dctx.reset(ResetDirective::SessionAndParameters).unwrap();
+    assert!(dctx.sizeof() > 0);
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+// Amount of input to process between two level adjustments.
+const SAMPLE_SIZE: usize = 1 << 20;
+
+// Adjusts the compression level depending on where time is spent.
+//
+// If we spend more time waiting for the writer than compressing,
+// we have CPU to spare: raise the level.
+// If we spend a lot more time compressing than writing,
+// compression is the bottleneck: lower the level.
+pub struct AdaptiveLevel {
+    min: i32,
+    max: i32,
+    level: i32,
+    // time spent in zstd since the last adjustment
+    compressing: Duration,
+    // time spent in the writer since the last adjustment
+    writing: Duration,
+    // input consumed since the last adjustment
+    consumed: usize,
+}
+
+impl AdaptiveLevel {
+    pub fn new(min: i32, max: i32, level: i32) -> Self {
+        AdaptiveLevel {
+            min: min,
+            max: max,
+            level: clamp(level, min, max),
+            compressing: Duration::new(0, 0),
+            writing: Duration::new(0, 0),
+            consumed: 0,
+        }
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    // Record some work, and returns the new level if it needs to change.
+    pub fn record(&mut self, consumed: usize, compressing: Duration,
+                  writing: Duration)
+                  -> Option<i32> {
+        self.consumed += consumed;
+        self.compressing += compressing;
+        self.writing += writing;
+
+        if self.consumed < SAMPLE_SIZE {
+            return None;
+        }
+
+        let level = if self.writing > self.compressing {
+            self.level + 1
+        } else if self.writing * 2 < self.compressing {
+            self.level - 1
+        } else {
+            self.level
+        };
+
+        self.consumed = 0;
+        self.compressing = Duration::new(0, 0);
+        self.writing = Duration::new(0, 0);
+
+        let level = clamp(level, self.min, self.max);
+        if level == self.level {
+            None
+        } else {
+            self.level = level;
+            Some(level)
+        }
+    }
+}
+
+fn clamp(level: i32, min: i32, max: i32) -> i32 {
+    if level < min {
+        min
+    } else if level > max {
+        max
+    } else {
+        level
+    }
+}
+
+#[test]
+fn test_adapt_bounds() {
+    let slow = Duration::from_millis(10);
+    let fast = Duration::from_millis(1);
+
+    let mut adapt = AdaptiveLevel::new(1, 4, 3);
+    // Slow writer: raise the level, but not above the maximum.
+    assert_eq!(adapt.record(SAMPLE_SIZE, fast, slow), Some(4));
+    assert_eq!(adapt.record(SAMPLE_SIZE, fast, slow), None);
+    // Slow compression: lower the level.
+    assert_eq!(adapt.record(SAMPLE_SIZE, slow, fast), Some(3));
+    // Not enough data to decide.
+    assert_eq!(adapt.record(1, slow, fast), None);
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Read};
+
+/// A reader over a sequence of byte chunks.
+///
+/// This allows to feed discontiguous data (for instance messages received
+/// from a queue or a channel) to a `Decoder` without copying everything
+/// into a single buffer first.
+///
+/// Empty chunks are skipped, and the first error returned by the iterator
+/// is forwarded to the caller.
+pub struct ChunkReader<I> {
+    // source of chunks
+    chunks: I,
+    // chunk currently being read
+    current: Vec<u8>,
+    // we already read everything in the current chunk up to that point
+    offset: usize,
+}
+
+impl<I> ChunkReader<I>
+    where I: Iterator<Item = io::Result<Vec<u8>>>
+{
+    /// Creates a new reader over the given chunks.
+    pub fn new(chunks: I) -> Self {
+        ChunkReader {
+            chunks: chunks,
+            current: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Returns the inner iterator.
+    ///
+    /// Any data left in the current chunk is lost.
+    pub fn into_inner(self) -> I {
+        self.chunks
+    }
+}
+
+impl<I> Read for ChunkReader<I>
+    where I: Iterator<Item = io::Result<Vec<u8>>>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.current.len() {
+            match self.chunks.next() {
+                None => return Ok(0),
+                Some(chunk) => {
+                    self.current = try!(chunk);
+                    self.offset = 0;
+                }
+            }
+        }
+
+        let len = try!((&self.current[self.offset..]).read(buf));
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_chunks() {
+    use super::super::{Decoder, encode_all};
+
+    let text = b"The quick brown fox jumps over the lazy dog.";
+    let compressed = encode_all(text, 1).unwrap();
+
+    let chunks: Vec<io::Result<Vec<u8>>> = compressed.chunks(3)
+        .map(|c| Ok(c.to_vec()))
+        .collect();
+
+    let mut decoder = Decoder::from_chunks(chunks.into_iter()).unwrap();
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result).unwrap();
+
+    assert_eq!(&result[..], &text[..]);
+}
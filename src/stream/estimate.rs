@@ -0,0 +1,51 @@
+use std::io::{self, Read};
+
+use stream::write::Encoder;
+use verify::Counter;
+
+/// Sizes found by [`compressed_size`](fn.compressed_size.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Size of the input, in bytes.
+    pub uncompressed: u64,
+    /// Exact size the compressed output would have, in bytes.
+    pub compressed: u64,
+}
+
+impl SizeEstimate {
+    /// Returns the compression ratio: uncompressed over compressed size.
+    pub fn ratio(&self) -> f64 {
+        self.uncompressed as f64 / self.compressed as f64
+    }
+}
+
+/// Computes the size `reader` would have once compressed, without
+/// writing the output anywhere.
+///
+/// The data is really compressed, with the given level and dictionary,
+/// but the output is only counted, then dropped. This gives the exact
+/// size [`copy_encode`](../fn.copy_encode.html) would produce, to check
+/// whether the output fits somewhere before writing it.
+pub fn compressed_size<R: Read>(mut reader: R, level: i32,
+                                dictionary: Option<&[u8]>)
+                                -> io::Result<SizeEstimate> {
+    let dictionary = dictionary.unwrap_or(&[]);
+    let mut encoder =
+        try!(Encoder::with_dictionary(Counter(0), level, dictionary));
+    let uncompressed = try!(io::copy(&mut reader, &mut encoder));
+    let compressed = try!(encoder.finish()).0;
+    Ok(SizeEstimate {
+        uncompressed: uncompressed,
+        compressed: compressed,
+    })
+}
+
+#[test]
+fn test_compressed_size() {
+    let data: Vec<u8> = (0..100000u32).map(|i| (i % 97) as u8).collect();
+    let estimate = compressed_size(&data[..], 3, None).unwrap();
+    assert_eq!(estimate.uncompressed, data.len() as u64);
+    assert_eq!(estimate.compressed,
+               ::encode_all(&data[..], 3).unwrap().len() as u64);
+    assert!(estimate.ratio() > 1.0);
+}
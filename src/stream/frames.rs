@@ -0,0 +1,882 @@
+use std::{cmp, u64};
+use std::io::{self, Read};
+
+use ll;
+use context::DecoderContext;
+
+/// Information from a frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Size of the decompressed content, if recorded in the header.
+    ///
+    /// For skippable frames, this is the size of the skipped content.
+    pub content_size: Option<u64>,
+    /// Window size required to decompress the frame.
+    pub window_size: u64,
+    /// ID of the dictionary used to compress the frame, or 0.
+    ///
+    /// For skippable frames, this is the magic number variant (0 to 15).
+    pub dict_id: u32,
+    /// Whether the frame ends with a content checksum.
+    pub has_checksum: bool,
+    /// Whether this is a skippable frame, which holds no compressed data.
+    pub skippable: bool,
+    /// For frames in a legacy format, the format version (`5` for v0.5).
+    ///
+    /// Legacy headers are not parsed: the other fields are left empty.
+    pub legacy_version: Option<u32>,
+}
+
+impl FrameHeader {
+    // Header reported for legacy frames.
+    fn legacy(version: u32) -> Self {
+        FrameHeader {
+            content_size: None,
+            window_size: 0,
+            dict_id: 0,
+            has_checksum: false,
+            skippable: false,
+            legacy_version: Some(version),
+        }
+    }
+}
+
+/// Returns the format version of a legacy frame, or `None` if `data`
+/// doesn't start with a legacy frame.
+///
+/// Legacy frames come from zstd versions prior to v0.8 (`1` to `7`).
+/// They can be decompressed when the `legacy` feature is enabled.
+pub fn legacy_version(data: &[u8]) -> Option<u32> {
+    if data.len() < 4 {
+        return None;
+    }
+    let magic = (0..4).fold(0u32, |acc, i| acc | (data[i] as u32) << (8 * i));
+    match magic {
+        0xFD2FB51E => Some(1),
+        0xFD2FB522..=0xFD2FB527 => Some(magic - 0xFD2FB520),
+        _ => None,
+    }
+}
+
+impl From<ll::ZSTD_frameHeader> for FrameHeader {
+    fn from(header: ll::ZSTD_frameHeader) -> Self {
+        FrameHeader {
+            content_size: match header.frameContentSize {
+                ll::ZSTD_CONTENTSIZE_UNKNOWN => None,
+                size => Some(size),
+            },
+            window_size: header.windowSize,
+            dict_id: header.dictID,
+            has_checksum: header.checksumFlag != 0,
+            skippable: header.frameType == ll::ZSTD_skippableFrame,
+            legacy_version: None,
+        }
+    }
+}
+
+// Receives the variant and content of skippable frames.
+pub(crate) type SkippableCallback = Box<FnMut(u32, &[u8]) + Send>;
+
+// Hands the skippable frames preceding a regular frame to a callback.
+pub(crate) struct SkippableFrames {
+    callback: SkippableCallback,
+    // magic number and size of the current frame, as they come
+    header: Vec<u8>,
+    // content of the current skippable frame, and its size once known
+    content: Vec<u8>,
+    size: Option<usize>,
+    // whether a regular frame started
+    done: bool,
+}
+
+impl SkippableFrames {
+    pub fn new(callback: SkippableCallback) -> Self {
+        SkippableFrames {
+            callback: callback,
+            header: Vec::new(),
+            content: Vec::new(),
+            size: None,
+            done: false,
+        }
+    }
+
+    // Consumes the skippable frames at the start of `data`.
+    //
+    // Returns the number of bytes consumed. Once a regular frame starts,
+    // `regular_prefix` returns its first bytes, consumed already.
+    pub fn consume(&mut self, data: &[u8]) -> usize {
+        let mut pos = 0;
+        while !self.done && pos < data.len() {
+            match self.size {
+                Some(size) => {
+                    let wanted = size - self.content.len();
+                    let n = cmp::min(wanted, data.len() - pos);
+                    self.content.extend_from_slice(&data[pos..pos + n]);
+                    pos += n;
+                }
+                None => {
+                    self.header.push(data[pos]);
+                    pos += 1;
+                    if self.header.len() == 4 {
+                        let magic = read_u32_le(&self.header);
+                        self.done = magic & 0xFFFFFFF0 !=
+                                    SKIPPABLE_MAGIC_START;
+                    } else if self.header.len() == SKIPPABLE_HEADER_SIZE {
+                        let size = read_u32_le(&self.header[4..]);
+                        self.size = Some(size as usize);
+                    }
+                }
+            }
+
+            if self.size == Some(self.content.len()) {
+                let variant = read_u32_le(&self.header) -
+                              SKIPPABLE_MAGIC_START;
+                (self.callback)(variant, &self.content);
+                self.header.clear();
+                self.content.clear();
+                self.size = None;
+            }
+        }
+        pos
+    }
+
+    // Returns the first bytes of the regular frame, once it started.
+    pub fn regular_prefix(&self) -> Option<&[u8]> {
+        if self.done { Some(&self.header) } else { None }
+    }
+}
+
+// Feeds the first bytes of a frame to a decoder, when they were consumed
+// while looking for skippable frames.
+pub(crate) fn feed_prefix(context: &DecoderContext, prefix: &[u8])
+                          -> io::Result<()> {
+    // These are fewer bytes than any header: zstd takes them all,
+    // without producing anything.
+    let mut input = ll::ZSTD_inBuffer::new(prefix);
+    let mut output = ll::ZSTD_outBuffer::new(&mut []);
+    try!(ll::parse_code(unsafe {
+        ll::ZSTD_decompressStream(context.c, &mut output, &mut input)
+    }));
+    Ok(())
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+// Largest possible frame header, in bytes.
+const FRAME_HEADER_SIZE_MAX: usize = 18;
+
+// Collects the first bytes fed to a decoder, until they make a complete
+// frame header.
+#[derive(Default)]
+pub(crate) struct HeaderRecorder {
+    bytes: Vec<u8>,
+    header: Option<FrameHeader>,
+    // whether the bytes can't be parsed
+    invalid: bool,
+}
+
+impl HeaderRecorder {
+    // Records data consumed by the decoder.
+    pub fn record(&mut self, data: &[u8]) {
+        if self.header.is_some() || self.invalid || data.is_empty() {
+            return;
+        }
+        let wanted = FRAME_HEADER_SIZE_MAX - self.bytes.len();
+        self.bytes.extend_from_slice(&data[..cmp::min(wanted, data.len())]);
+
+        if self.bytes.len() < 4 {
+            return;
+        }
+        if let Some(version) = legacy_version(&self.bytes) {
+            self.header = Some(FrameHeader::legacy(version));
+            return;
+        }
+        let mut header = ll::ZSTD_frameHeader::default();
+        let code = unsafe {
+            ll::ZSTD_getFrameHeader(&mut header,
+                                    self.bytes.as_ptr(),
+                                    self.bytes.len())
+        };
+        match ll::parse_code(code) {
+            Ok(0) => self.header = Some(FrameHeader::from(header)),
+            Ok(_) => (),
+            Err(_) => self.invalid = true,
+        }
+    }
+
+    // Returns the header, once complete.
+    pub fn header(&self) -> Option<FrameHeader> {
+        self.header
+    }
+}
+
+// Magic number and size preceding the content of a skippable frame.
+const SKIPPABLE_HEADER_SIZE: usize = 8;
+// Magic number of the first skippable frame variant; there are 16.
+pub(crate) const SKIPPABLE_MAGIC_START: u32 = 0x184D2A50;
+// Size of a block header, in a zstd frame.
+pub(crate) const BLOCK_HEADER_SIZE: usize = 3;
+// Size of the content checksum, at the end of a frame.
+const CHECKSUM_SIZE: u64 = 4;
+
+/// How a block is encoded, in a zstd frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    /// Stored as is, when compression wouldn't help.
+    Raw,
+    /// A single byte, repeated.
+    Rle,
+    /// Compressed with entropy coding and matches.
+    Compressed,
+}
+
+/// A block decoded by a [`FrameIter`](struct.FrameIter.html), as
+/// reported to [`on_block`](struct.FrameIter.html#method.on_block).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// Offset of the block header in the input.
+    pub offset: u64,
+    /// How the block is encoded.
+    pub block_type: BlockType,
+    /// Size of the block in the input, without its 3-byte header.
+    pub compressed_size: u64,
+    /// Size of the decoded block.
+    pub decompressed_size: u64,
+    /// Whether this is the last block of its frame.
+    pub last: bool,
+}
+
+// Receives each decoded block.
+type BlockCallback = Box<FnMut(&BlockInfo) + Send>;
+
+// Where the decoding is in the blocks of a frame, to report them.
+struct BlockCursor {
+    // input position zstd must not read past, until the current block
+    // is reported
+    limit: u64,
+    // block being decoded, and its size decoded so far
+    block: Option<BlockInfo>,
+}
+
+// Parses a block header: whether it's the last block, its type, and its
+// size in the input.
+pub(crate) fn parse_block_header(bytes: &[u8])
+                                 -> io::Result<(bool, BlockType, u64)> {
+    let value = bytes[0] as u64 | (bytes[1] as u64) << 8 |
+                (bytes[2] as u64) << 16;
+    let (block_type, size) = match (value >> 1) & 3 {
+        0 => (BlockType::Raw, value >> 3),
+        // RLE block: a single byte is repeated.
+        1 => (BlockType::Rle, 1),
+        2 => (BlockType::Compressed, value >> 3),
+        _ => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "invalid block type"))
+        }
+    };
+    Ok((value & 1 == 1, block_type, size))
+}
+
+/// A frame decoded by a [`FrameIter`](struct.FrameIter.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Header of the frame.
+    pub header: FrameHeader,
+    /// Decompressed content of the frame.
+    ///
+    /// For skippable frames, this is the skipped content.
+    pub data: Vec<u8>,
+}
+
+/// The result of verifying a frame.
+#[derive(Debug)]
+pub struct FrameReport {
+    /// Offset of the frame in the input.
+    pub offset: u64,
+    /// Size of the frame, in bytes.
+    ///
+    /// For a corrupt frame, this is the size read until the error.
+    pub compressed_size: u64,
+    /// Size of the decompressed content (0 for skippable frames).
+    pub decompressed_size: u64,
+    /// Header of the frame, unless it couldn't be read.
+    pub header: Option<FrameHeader>,
+    /// Why the frame is corrupt, or `None` if it is valid.
+    pub error: Option<io::Error>,
+}
+
+/// The result of verifying compressed data, with
+/// [`verify`](fn.verify.html).
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// Every frame checked, in order.
+    ///
+    /// If the data is corrupt, the corrupt frame is the last one.
+    pub frames: Vec<FrameReport>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if every frame is valid.
+    pub fn is_ok(&self) -> bool {
+        self.frames.iter().all(|frame| frame.error.is_none())
+    }
+
+    /// Returns the offset of the first corrupt frame, if any.
+    pub fn corrupt_offset(&self) -> Option<u64> {
+        self.frames
+            .iter()
+            .find(|frame| frame.error.is_some())
+            .map(|frame| frame.offset)
+    }
+
+    /// Returns the total size of the decompressed data.
+    pub fn decompressed_size(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.decompressed_size).sum()
+    }
+}
+
+/// Checks compressed data, without keeping the decompressed output.
+///
+/// See [`FrameIter::verify`](struct.FrameIter.html#method.verify). To
+/// check data compressed with a dictionary, use
+/// [`FrameIter::with_dictionary`](struct.FrameIter.html#method.with_dictionary)
+/// instead.
+pub fn verify<R: Read>(reader: R) -> VerifyReport {
+    FrameIter::new(reader).verify()
+}
+
+/// An iterator decoding each frame of a multi-frame source separately.
+///
+/// This is useful when each frame is a logical record, like a batch of
+/// logs or events. Each frame is entirely decompressed in memory.
+///
+/// The iteration stops after the first error.
+pub struct FrameIter<R: Read> {
+    // input reader (compressed data)
+    reader: R,
+    // input buffer
+    buffer: Vec<u8>,
+    // we already read everything in the buffer up to that point
+    offset: usize,
+    // bytes dropped from the front of the buffer so far
+    drained: u64,
+    // decompression context
+    context: DecoderContext,
+    // whether we're done, after the end of the input or an error
+    finished: bool,
+    // notified of each block decoded, if set
+    on_block: Option<BlockCallback>,
+}
+
+impl<R: Read> FrameIter<R> {
+    /// Creates a new iterator over the frames from `reader`.
+    pub fn new(reader: R) -> Self {
+        FrameIter {
+            reader: reader,
+            buffer: Vec::new(),
+            offset: 0,
+            drained: 0,
+            context: DecoderContext::default(),
+            finished: false,
+            on_block: None,
+        }
+    }
+
+    /// Creates a new iterator, using an existing dictionary.
+    ///
+    /// The dictionary must be the same as the one used during compression.
+    pub fn with_dictionary(reader: R, dictionary: &[u8]) -> io::Result<Self> {
+        let iter = FrameIter::new(reader);
+        try!(iter.context.load_dictionary(dictionary));
+        Ok(iter)
+    }
+
+    /// Calls the given callback for each block decoded.
+    ///
+    /// The callback receives the type of the block, and its size before
+    /// and after decoding: enough for format analysis or compression
+    /// dashboards, without parsing the format by hand. Blocks are
+    /// reported by `next()` and `verify()`, not by `skip_frame()`, and
+    /// not for frames in a legacy format.
+    pub fn on_block<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(&BlockInfo)
+    {
+        self.on_block = Some(Box::new(f));
+    }
+
+    /// Returns the inner reader.
+    ///
+    /// Data already buffered from the reader is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the number of input bytes consumed so far.
+    ///
+    /// Between frames, this is the offset of the next frame in the input.
+    pub fn position(&self) -> u64 {
+        self.drained + self.offset as u64
+    }
+
+    /// Skips the next frame, without decompressing it.
+    ///
+    /// Only the block headers are read, so this is much cheaper than
+    /// decoding the frame, and the frame is never held in memory.
+    /// Returns the header of the skipped frame, or `None` at the end of
+    /// the input. Frames in a legacy format are decompressed, then
+    /// dropped.
+    ///
+    /// After an error, the iteration stops, as with `next()`.
+    pub fn skip_frame(&mut self) -> io::Result<Option<FrameHeader>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let result = self.skip();
+        match result {
+            Ok(Some(_)) => (),
+            _ => self.finished = true,
+        }
+        result
+    }
+
+    // Skips the next frame.
+    fn skip(&mut self) -> io::Result<Option<FrameHeader>> {
+        let header = match try!(self.read_header()) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if header.legacy_version.is_some() {
+            return self.read_frame().map(|frame| frame.map(|f| f.header));
+        }
+        if header.skippable {
+            let size = header.content_size.unwrap_or(0);
+            try!(self.discard(SKIPPABLE_HEADER_SIZE as u64 + size));
+            return Ok(Some(header));
+        }
+
+        let header_size = try!(self.header_size());
+        try!(self.discard(header_size));
+
+        loop {
+            let (last, _, size) =
+                try!(parse_block_header(try!(self.peek(BLOCK_HEADER_SIZE))));
+            try!(self.discard(BLOCK_HEADER_SIZE as u64 + size));
+            if last {
+                break;
+            }
+        }
+        if header.has_checksum {
+            try!(self.discard(CHECKSUM_SIZE));
+        }
+        Ok(Some(header))
+    }
+
+    // Returns the size of the frame header at the current position.
+    //
+    // `read_header` must have made sure the whole header is buffered.
+    fn header_size(&self) -> io::Result<u64> {
+        let mut raw = ll::ZSTD_frameHeader::default();
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_getFrameHeader(&mut raw,
+                                    self.buffer[self.offset..].as_ptr(),
+                                    self.buffer.len() - self.offset)
+        }));
+        Ok(raw.headerSize as u64)
+    }
+
+    // Returns the next `n` bytes, without consuming them.
+    fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        while self.buffer.len() - self.offset < n {
+            if try!(self.fill()) == 0 {
+                return Err(incomplete_frame());
+            }
+        }
+        Ok(&self.buffer[self.offset..self.offset + n])
+    }
+
+    // Consumes the next `n` bytes, without keeping them.
+    fn discard(&mut self, mut n: u64) -> io::Result<()> {
+        loop {
+            let available = (self.buffer.len() - self.offset) as u64;
+            if n <= available {
+                self.offset += n as usize;
+                return Ok(());
+            }
+            n -= available;
+            self.offset = self.buffer.len();
+            if try!(self.fill()) == 0 {
+                return Err(incomplete_frame());
+            }
+        }
+    }
+
+    // Reads more data, keeping what wasn't consumed yet.
+    //
+    // Returns the number of bytes added.
+    fn fill(&mut self) -> io::Result<usize> {
+        self.drained += self.offset as u64;
+        self.buffer.drain(..self.offset);
+        self.offset = 0;
+
+        let len = self.buffer.len();
+        let chunk_size = unsafe { ll::ZSTD_DStreamInSize() };
+        self.buffer.resize(len + chunk_size, 0);
+        let read = self.reader.read(&mut self.buffer[len..]);
+        self.buffer.truncate(len + *read.as_ref().unwrap_or(&0));
+        read
+    }
+
+    // Decodes the header of the next frame, without consuming it.
+    //
+    // Returns `None` at the end of the input.
+    fn read_header(&mut self) -> io::Result<Option<FrameHeader>> {
+        loop {
+            let mut header = ll::ZSTD_frameHeader::default();
+            let available = self.buffer.len() - self.offset;
+            let needed = if available == 0 {
+                1
+            } else if available < 4 {
+                // Not enough to tell whether this is a legacy frame.
+                4 - available
+            } else if let Some(version) =
+                legacy_version(&self.buffer[self.offset..]) {
+                return Ok(Some(FrameHeader::legacy(version)));
+            } else {
+                try!(ll::parse_code(unsafe {
+                    ll::ZSTD_getFrameHeader(&mut header,
+                                            self.buffer[self.offset..]
+                                                .as_ptr(),
+                                            available)
+                }))
+            };
+            if needed == 0 {
+                return Ok(Some(FrameHeader::from(header)));
+            }
+
+            if try!(self.fill()) == 0 {
+                if self.offset == self.buffer.len() {
+                    return Ok(None);
+                }
+                return Err(incomplete_frame());
+            }
+        }
+    }
+
+    // Decodes the next frame entirely.
+    fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let header = match try!(self.read_header()) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if header.skippable {
+            return self.read_skippable(header).map(Some);
+        }
+
+        let mut data = Vec::new();
+        try!(self.decode(&mut data, true));
+        Ok(Some(Frame {
+            header: header,
+            data: data,
+        }))
+    }
+
+    // Decompresses the frame at the current position.
+    //
+    // Unless `keep` is set, the output is dropped as it comes, and `data`
+    // is only used as a buffer. Returns the decompressed size.
+    fn decode(&mut self, data: &mut Vec<u8>, keep: bool) -> io::Result<u64> {
+        let out_size = unsafe { ll::ZSTD_DStreamOutSize() };
+        let mut size = 0;
+        // zstd may have more output for us even without new input.
+        let mut output_full = false;
+        // When reporting blocks, zstd is fed one block at a time, so the
+        // output can be attributed to each of them. (`position` is spelled
+        // out, as `&mut Self` is also an iterator.)
+        let mut cursor = if self.on_block.is_some() &&
+                            legacy_version(&self.buffer[self.offset..])
+                                .is_none() {
+            Some(BlockCursor {
+                limit: FrameIter::position(self) + try!(self.header_size()),
+                block: None,
+            })
+        } else {
+            None
+        };
+        loop {
+            if let Some(ref mut cursor) = cursor {
+                if FrameIter::position(self) == cursor.limit && !output_full {
+                    try!(self.next_block(cursor));
+                }
+            }
+            if self.offset == self.buffer.len() && !output_full &&
+               try!(self.fill()) == 0 {
+                return Err(incomplete_frame());
+            }
+
+            if !keep {
+                data.clear();
+            }
+            let len = data.len();
+            data.resize(len + out_size, 0);
+            let end = match cursor {
+                Some(ref cursor) => {
+                    let left = cursor.limit - FrameIter::position(self);
+                    cmp::min(self.buffer.len() as u64,
+                             self.offset as u64 + left) as usize
+                }
+                None => self.buffer.len(),
+            };
+            let mut output = ll::ZSTD_outBuffer::new(&mut data[len..]);
+            let mut input =
+                ll::ZSTD_inBuffer::new(&self.buffer[self.offset..end]);
+            let res = try!(ll::parse_code(unsafe {
+                ll::ZSTD_decompressStream(self.context.c,
+                                          &mut output,
+                                          &mut input)
+            }));
+            data.truncate(len + output.pos);
+            size += output.pos as u64;
+            self.offset += input.pos;
+            output_full = output.pos == output.size;
+            if let Some(ref mut cursor) = cursor {
+                if let Some(ref mut block) = cursor.block {
+                    block.decompressed_size += output.pos as u64;
+                }
+            }
+
+            if res == 0 {
+                // End-of-frame marker.
+                if let Some(ref mut cursor) = cursor {
+                    self.report_block(cursor);
+                }
+                return Ok(size);
+            }
+        }
+    }
+
+    // Reports the block just decoded, if any, and lets zstd read the
+    // next one.
+    fn next_block(&mut self, cursor: &mut BlockCursor) -> io::Result<()> {
+        if self.report_block(cursor) {
+            // Only the checksum is left.
+            cursor.limit = u64::MAX;
+            return Ok(());
+        }
+        let offset = FrameIter::position(self);
+        let (last, block_type, size) =
+            try!(parse_block_header(try!(self.peek(BLOCK_HEADER_SIZE))));
+        cursor.limit = offset + BLOCK_HEADER_SIZE as u64 + size;
+        cursor.block = Some(BlockInfo {
+            offset: offset,
+            block_type: block_type,
+            compressed_size: size,
+            decompressed_size: 0,
+            last: last,
+        });
+        Ok(())
+    }
+
+    // Hands the current block to the callback.
+    //
+    // Returns whether it was the last block of the frame.
+    fn report_block(&mut self, cursor: &mut BlockCursor) -> bool {
+        match (cursor.block.take(), self.on_block.as_mut()) {
+            (Some(block), Some(on_block)) => {
+                on_block(&block);
+                block.last
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks every frame, without keeping the decompressed data.
+    ///
+    /// This is the library equivalent of `zstd -t`: frames are decoded
+    /// one after the other, and their checksum and declared content size
+    /// are checked. Memory use doesn't depend on the size of the frames.
+    ///
+    /// The verification stops at the first corrupt frame.
+    pub fn verify(mut self) -> VerifyReport {
+        let mut report = VerifyReport { frames: Vec::new() };
+        let mut buffer = Vec::new();
+        while !self.finished {
+            let offset = self.position();
+            let result = self.verify_frame(&mut buffer);
+            let (header, decompressed_size, error) = match result {
+                Ok(Some((header, size))) => (Some(header), size, None),
+                Ok(None) => break,
+                Err((header, e)) => {
+                    self.finished = true;
+                    (header, 0, Some(e))
+                }
+            };
+            report.frames.push(FrameReport {
+                offset: offset,
+                compressed_size: self.position() - offset,
+                decompressed_size: decompressed_size,
+                header: header,
+                error: error,
+            });
+        }
+        report
+    }
+
+    // Decodes the next frame, returning its header and content size.
+    //
+    // On error, returns the header if it could be read.
+    fn verify_frame(&mut self, buffer: &mut Vec<u8>)
+                    -> Result<Option<(FrameHeader, u64)>,
+                              (Option<FrameHeader>, io::Error)> {
+        let header = match self.read_header() {
+            Ok(Some(header)) => header,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err((None, e)),
+        };
+        if header.skippable {
+            return match self.skip() {
+                Ok(_) => Ok(Some((header, 0))),
+                Err(e) => Err((Some(header), e)),
+            };
+        }
+
+        let size = match self.decode(buffer, false) {
+            Ok(size) => size,
+            Err(e) => return Err((Some(header), e)),
+        };
+        if header.content_size.map_or(false, |expected| expected != size) {
+            let error = io::Error::new(io::ErrorKind::InvalidData,
+                                       "content size mismatch");
+            return Err((Some(header), error));
+        }
+        Ok(Some((header, size)))
+    }
+
+    // Reads the content of a skippable frame, which zstd would discard.
+    fn read_skippable(&mut self, header: FrameHeader) -> io::Result<Frame> {
+        let size = header.content_size.unwrap_or(0) as usize;
+        while self.buffer.len() - self.offset < SKIPPABLE_HEADER_SIZE + size {
+            if try!(self.fill()) == 0 {
+                return Err(incomplete_frame());
+            }
+        }
+
+        let start = self.offset + SKIPPABLE_HEADER_SIZE;
+        let data = self.buffer[start..start + size].to_vec();
+        self.offset = start + size;
+        Ok(Frame {
+            header: header,
+            data: data,
+        })
+    }
+}
+
+impl<R: Read> Iterator for FrameIter<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        if self.finished {
+            return None;
+        }
+
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn incomplete_frame() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete frame")
+}
+
+#[test]
+fn test_frames() {
+    let mut input = ::encode_all(b"first", 1).unwrap();
+    input.extend_from_slice(&::encode_all(b"second", 1).unwrap());
+
+    let frames: Vec<Frame> =
+        FrameIter::new(&input[..]).map(|frame| frame.unwrap()).collect();
+
+    assert_eq!(frames.len(), 2);
+    assert_eq!(&frames[0].data, b"first");
+    assert_eq!(&frames[1].data, b"second");
+    assert!(!frames[1].header.skippable);
+}
+
+#[test]
+fn test_skip_frame() {
+    let mut input = ::encode_all(&vec![1u8; 500000][..], 1).unwrap();
+    // A skippable frame with 3 bytes of content.
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 3, 0, 0, 0]);
+    input.extend_from_slice(b"abc");
+    input.extend_from_slice(&::encode_all(b"wanted", 1).unwrap());
+
+    let mut frames = FrameIter::new(&input[..]);
+    let header = frames.skip_frame().unwrap().unwrap();
+    assert!(!header.skippable);
+    assert!(frames.skip_frame().unwrap().unwrap().skippable);
+    assert_eq!(&frames.next().unwrap().unwrap().data, b"wanted");
+    assert!(frames.skip_frame().unwrap().is_none());
+    assert!(frames.next().is_none());
+}
+
+#[test]
+fn test_verify() {
+    let text = vec![3u8; 300000];
+    let mut input = ::encode_all(&text[..], 1).unwrap();
+    let first_size = input.len() as u64;
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 3, 0, 0, 0]);
+    input.extend_from_slice(b"abc");
+    input.extend_from_slice(&::encode_all(b"second", 1).unwrap());
+
+    let report = verify(&input[..]);
+    assert!(report.is_ok());
+    assert_eq!(report.frames.len(), 3);
+    assert_eq!(report.frames[0].compressed_size, first_size);
+    assert_eq!(report.frames[1].offset, first_size);
+    assert_eq!(report.decompressed_size(), text.len() as u64 + 6);
+
+    // Truncated in the last frame.
+    input.pop();
+    let report = verify(&input[..]);
+    assert!(!report.is_ok());
+    assert_eq!(report.frames.len(), 3);
+    assert_eq!(report.corrupt_offset(), Some(first_size + 11));
+}
+
+#[test]
+fn test_on_block() {
+    use std::sync::{Arc, Mutex};
+
+    let text: Vec<u8> = (0..300000u32).map(|i| (i % 13) as u8).collect();
+    let mut input = ::encode_all(&text[..], 1).unwrap();
+    input.extend_from_slice(&::encode_all(&b"xyz"[..], 1).unwrap());
+
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let mut frames = FrameIter::new(&input[..]);
+    let seen = blocks.clone();
+    frames.on_block(move |block| seen.lock().unwrap().push(*block));
+    assert_eq!(frames.map(|frame| frame.unwrap().data.len()).sum::<usize>(),
+               text.len() + 3);
+
+    let blocks = blocks.lock().unwrap();
+    // 128KB blocks for the first frame, then a single raw block.
+    assert_eq!(blocks.len(), 4);
+    assert!(blocks[2].last);
+    let first: u64 = blocks[..3].iter().map(|b| b.decompressed_size).sum();
+    assert_eq!(first, text.len() as u64);
+    assert_eq!(blocks[0].block_type, BlockType::Compressed);
+    assert_eq!(blocks[3].block_type, BlockType::Raw);
+    assert_eq!(blocks[3].compressed_size, 3);
+    assert_eq!(blocks[3].decompressed_size, 3);
+}
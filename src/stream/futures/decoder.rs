@@ -0,0 +1,145 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::AsyncWrite;
+
+use ll;
+use context::DecoderContext;
+use stream::futures::poll_write_staged;
+
+/// A decoder that decompress and forward data to an async writer.
+///
+/// Anything written after the end of the frame is ignored.
+pub struct Decoder<W: AsyncWrite + Unpin> {
+    // output writer (decompressed data)
+    writer: W,
+    // output buffer
+    buffer: Vec<u8>,
+    // we already sent everything in the buffer up to that point
+    offset: usize,
+    // decompression context
+    context: DecoderContext,
+    // whether we reached the end of the frame
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin> Decoder<W> {
+    /// Creates a new decoder.
+    pub fn new(writer: W) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        Decoder::with_context(writer, context)
+    }
+
+    /// Creates a new decoder, using an existing dictionary.
+    ///
+    /// The dictionary must be the same as the one used during compression.
+    pub fn with_dictionary(writer: W, dictionary: &[u8]) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        try!(context.load_dictionary(dictionary));
+
+        Decoder::with_context(writer, context)
+    }
+
+    fn with_context(writer: W, context: DecoderContext) -> io::Result<Self> {
+        let buffer_size = unsafe { ll::ZSTD_DStreamOutSize() };
+
+        Ok(Decoder {
+            writer: writer,
+            buffer: Vec::with_capacity(buffer_size),
+            offset: 0,
+            context: context,
+            finished: false,
+        })
+    }
+
+    /// Return the inner writer.
+    ///
+    /// Only data already sent, for instance with `flush()`,
+    /// reached the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // Decompress some input into our output buffer.
+    //
+    // Everything in the buffer must already have been sent.
+    fn decompress(&mut self, input: &mut ll::ZSTD_inBuffer)
+                  -> io::Result<()> {
+        let buffer_size = self.buffer.capacity();
+        unsafe {
+            self.buffer.set_len(buffer_size);
+        }
+        let mut output = ll::ZSTD_outBuffer::new(&mut self.buffer);
+
+        let code = unsafe {
+            ll::ZSTD_decompressStream(self.context.c, &mut output, input)
+        };
+        unsafe {
+            self.buffer.set_len(output.pos);
+        }
+        self.offset = 0;
+        if try!(ll::parse_code(code)) == 0 {
+            // End-of-frame marker.
+            self.finished = true;
+        }
+        Ok(())
+    }
+
+    fn poll_staged(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        poll_write_staged(&mut self.writer,
+                          cx,
+                          &self.buffer,
+                          &mut self.offset)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Decoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        loop {
+            try_ready!(this.poll_staged(cx));
+            if this.finished {
+                return Poll::Ready(Ok(buf.len()));
+            }
+            if input.pos > 0 || buf.is_empty() {
+                // Output not sent yet goes first on the next call.
+                return Poll::Ready(Ok(input.pos));
+            }
+            if let Err(e) = this.decompress(&mut input) {
+                return Poll::Ready(Err(e));
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            try_ready!(this.poll_staged(cx));
+            if this.finished {
+                break;
+            }
+            // zstd may still have output for us.
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            if let Err(e) = this.decompress(&mut input) {
+                return Poll::Ready(Err(e));
+            }
+            if this.buffer.is_empty() {
+                break;
+            }
+        }
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        try_ready!(Pin::new(&mut *this).poll_flush(cx));
+        Pin::new(&mut this.writer).poll_close(cx)
+    }
+}
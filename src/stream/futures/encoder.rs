@@ -0,0 +1,257 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::AsyncWrite;
+
+use ll;
+use context::EncoderContext;
+use stream::futures::poll_write_staged;
+
+/// An encoder that compress and forward data to an async writer.
+///
+/// Don't forget to call `finish()`, or `close()`, before dropping it!
+/// To finish the stream on drop instead, see
+/// [`on_drop`](#method.on_drop).
+pub struct Encoder<W: AsyncWrite + Unpin> {
+    // output writer (compressed data)
+    writer: W,
+    // output buffer
+    buffer: Vec<u8>,
+    // we already sent everything in the buffer up to that point
+    offset: usize,
+    // compression context
+    context: EncoderContext,
+    // whether the end of the frame was written to the buffer
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<W> {
+    /// Creates a new encoder.
+    ///
+    /// `level`: compression level (1-21)
+    pub fn new(writer: W, level: i32) -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+
+        Encoder::with_context(writer, context)
+    }
+
+    /// Creates a new encoder, using an existing dictionary.
+    pub fn with_dictionary(writer: W, level: i32, dictionary: &[u8])
+                           -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        try!(context.load_dictionary(dictionary));
+
+        Encoder::with_context(writer, context)
+    }
+
+    fn with_context(writer: W, context: EncoderContext) -> io::Result<Self> {
+        let buffer_size = unsafe { ll::ZSTD_CStreamOutSize() };
+
+        Ok(Encoder {
+            writer: writer,
+            buffer: Vec::with_capacity(buffer_size),
+            offset: 0,
+            context: context,
+            finished: false,
+        })
+    }
+
+    /// Finishes the stream.
+    ///
+    /// The returned future writes the end of the frame, waiting for the
+    /// writer to accept it, then flushes the writer and returns it.
+    /// Unlike `close()`, this doesn't close the inner writer.
+    pub fn finish(self) -> Finish<W> {
+        Finish { encoder: Some(self) }
+    }
+
+    /// Returns an encoder that will finish the stream on drop.
+    ///
+    /// Since finishing may have to wait for the writer, it cannot happen
+    /// in `drop` itself: instead, `spawn` receives the future finishing
+    /// the stream, and should run it, for instance with
+    /// `async_std::task::spawn` or `smol::spawn`.
+    pub fn on_drop<F>(self, spawn: F) -> AutoFinishEncoder<W>
+        where F: 'static + Send + FnMut(Finish<W>)
+    {
+        AutoFinishEncoder {
+            encoder: Some(self),
+            spawn: Some(Box::new(spawn)),
+        }
+    }
+
+    // Compress some input into our output buffer.
+    //
+    // Everything in the buffer must already have been sent.
+    // Returns zstd's result code.
+    fn compress(&mut self, input: &mut ll::ZSTD_inBuffer,
+                directive: ll::ZSTD_EndDirective)
+                -> io::Result<usize> {
+        let buffer_size = self.buffer.capacity();
+        unsafe {
+            self.buffer.set_len(buffer_size);
+        }
+        let mut output = ll::ZSTD_outBuffer::new(&mut self.buffer);
+
+        let code = unsafe {
+            ll::ZSTD_compressStream2(self.context.c,
+                                     &mut output,
+                                     input,
+                                     directive)
+        };
+        unsafe {
+            self.buffer.set_len(output.pos);
+        }
+        self.offset = 0;
+        ll::parse_code(code)
+    }
+
+    fn poll_staged(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        poll_write_staged(&mut self.writer,
+                          cx,
+                          &self.buffer,
+                          &mut self.offset)
+    }
+
+    // Writes the end of the frame, and sends it to the writer.
+    fn poll_end(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            try_ready!(self.poll_staged(cx));
+            if self.finished {
+                return Poll::Ready(Ok(()));
+            }
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            let remaining = match self.compress(&mut input, ll::ZSTD_e_end) {
+                Ok(remaining) => remaining,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+            self.finished = remaining == 0;
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other,
+                                                  "stream is finished")));
+        }
+
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        loop {
+            try_ready!(this.poll_staged(cx));
+            if input.pos > 0 || buf.is_empty() {
+                // Output not sent yet goes first on the next call.
+                return Poll::Ready(Ok(input.pos));
+            }
+            if let Err(e) = this.compress(&mut input, ll::ZSTD_e_continue) {
+                return Poll::Ready(Err(e));
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut done = this.finished;
+        loop {
+            try_ready!(this.poll_staged(cx));
+            if done {
+                break;
+            }
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            match this.compress(&mut input, ll::ZSTD_e_flush) {
+                Ok(remaining) => done = remaining == 0,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        try_ready!(this.poll_end(cx));
+        Pin::new(&mut this.writer).poll_close(cx)
+    }
+}
+
+/// Future finishing a stream, returned by `Encoder::finish()`.
+///
+/// Resolves to the inner writer.
+pub struct Finish<W: AsyncWrite + Unpin> {
+    encoder: Option<Encoder<W>>,
+}
+
+impl<W: AsyncWrite + Unpin> Future for Finish<W> {
+    type Output = io::Result<W>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<W>> {
+        let this = self.get_mut();
+        {
+            let encoder = this.encoder
+                .as_mut()
+                .expect("Finish polled after completion");
+            try_ready!(encoder.poll_end(cx));
+            try_ready!(Pin::new(&mut encoder.writer).poll_flush(cx));
+        }
+        Poll::Ready(Ok(this.encoder.take().unwrap().writer))
+    }
+}
+
+/// A wrapper around an `Encoder<W>` that finishes the stream on drop.
+///
+/// Created by [`Encoder::on_drop`](struct.Encoder.html#method.on_drop).
+pub struct AutoFinishEncoder<W: AsyncWrite + Unpin> {
+    // We wrap this in an option to take it during drop.
+    encoder: Option<Encoder<W>>,
+    // TODO: make this a FnOnce once it works in a Box
+    spawn: Option<Box<FnMut(Finish<W>) + Send>>,
+}
+
+impl<W: AsyncWrite + Unpin> AutoFinishEncoder<W> {
+    /// Finishes the stream now, rather than on drop.
+    pub fn finish(mut self) -> Finish<W> {
+        self.encoder.take().unwrap().finish()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Drop for AutoFinishEncoder<W> {
+    fn drop(&mut self) {
+        if let (Some(encoder), Some(mut spawn)) = (self.encoder.take(),
+                                                   self.spawn.take()) {
+            // Nothing to do if the stream was already closed.
+            if !encoder.finished || encoder.offset < encoder.buffer.len() {
+                spawn(encoder.finish());
+            }
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AutoFinishEncoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        let encoder = self.get_mut().encoder.as_mut().unwrap();
+        Pin::new(encoder).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let encoder = self.get_mut().encoder.as_mut().unwrap();
+        Pin::new(encoder).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<io::Result<()>> {
+        let encoder = self.get_mut().encoder.as_mut().unwrap();
+        Pin::new(encoder).poll_close(cx)
+    }
+}
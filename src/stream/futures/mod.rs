@@ -0,0 +1,175 @@
+//! Implement the `futures-io` `AsyncWrite` trait for both compressing and
+//! decompressing.
+//!
+//! These adapters work with any runtime built on `futures-io`, like
+//! async-std or smol. They require the `futures` feature.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::AsyncWrite;
+
+// Returns early from a poll function unless `$e` is ready and successful.
+macro_rules! try_ready {
+    ($e:expr) => {
+        match $e {
+            Poll::Ready(Ok(value)) => value,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+mod encoder;
+mod decoder;
+
+pub use self::decoder::Decoder;
+pub use self::encoder::{AutoFinishEncoder, Encoder, Finish};
+
+// Sends everything in `buffer` after `offset` to the writer.
+//
+// On `Pending` or error, `offset` records what was already sent.
+fn poll_write_staged<W: AsyncWrite + Unpin>(writer: &mut W,
+                                            cx: &mut Context,
+                                            buffer: &[u8],
+                                            offset: &mut usize)
+                                            -> Poll<io::Result<()>> {
+    while *offset < buffer.len() {
+        let written = try_ready!(Pin::new(&mut *writer)
+            .poll_write(cx, &buffer[*offset..]));
+        if written == 0 {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::WriteZero,
+                                                  "failed to write data")));
+        }
+        *offset += written;
+    }
+    Poll::Ready(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::ptr;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    use futures_io::AsyncWrite;
+
+    use super::{Decoder, Encoder};
+
+    // A writer accepting a few bytes at a time, pushing back in between.
+    struct Slow {
+        data: Vec<u8>,
+        ready: bool,
+    }
+
+    impl AsyncWrite for Slow {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context,
+                      buf: &[u8])
+                      -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = ::std::cmp::min(buf.len(), 7);
+            self.data.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context)
+                      -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context)
+                      -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable =
+            RawWakerVTable::new(clone, noop, noop, noop);
+        unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+    }
+
+    // Polls the future until it completes.
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    // Writes all of `data`, like `AsyncWriteExt::write_all`.
+    fn write_all<W: AsyncWrite + Unpin>(writer: &mut W, mut data: &[u8]) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while !data.is_empty() {
+            match Pin::new(&mut *writer).poll_write(&mut cx, data) {
+                Poll::Ready(Ok(n)) => data = &data[n..],
+                Poll::Ready(Err(e)) => panic!("{}", e),
+                Poll::Pending => (),
+            }
+        }
+    }
+
+    fn close<W: AsyncWrite + Unpin>(writer: &mut W) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match Pin::new(&mut *writer).poll_close(&mut cx) {
+                Poll::Ready(result) => return result.unwrap(),
+                Poll::Pending => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_async_cycle() {
+        let text = b"The quick brown fox jumps over the lazy dog.";
+        let slow = Slow {
+            data: Vec::new(),
+            ready: false,
+        };
+
+        let mut encoder = Encoder::new(slow, 1).unwrap();
+        write_all(&mut encoder, text);
+        let compressed = block_on(encoder.finish()).unwrap().data;
+        assert_eq!(::decode_all(&compressed[..]).unwrap(), &text[..]);
+
+        let mut decoder = Decoder::new(Vec::new()).unwrap();
+        write_all(&mut decoder, &compressed);
+        close(&mut decoder);
+        assert_eq!(decoder.into_inner(), &text[..]);
+    }
+
+    #[test]
+    fn test_async_auto_finish() {
+        use std::sync::{Arc, Mutex};
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let output_clone = output.clone();
+
+        let mut encoder = Encoder::new(Vec::new(), 1)
+            .unwrap()
+            .on_drop(move |finish| {
+                *output_clone.lock().unwrap() = block_on(finish).unwrap();
+            });
+        write_all(&mut encoder, b"abc");
+        drop(encoder);
+
+        let compressed = output.lock().unwrap().clone();
+        assert_eq!(::decode_all(&compressed[..]).unwrap(), b"abc");
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, Read};
+
+use ll;
+use stream::read::Encoder;
+
+/// Compresses data from `reader`, returning the output as an iterator of
+/// chunks.
+///
+/// See [`EncodeIter`](struct.EncodeIter.html).
+pub fn encode_iter<R: Read>(reader: R, level: i32)
+                            -> io::Result<EncodeIter<R>> {
+    let encoder = try!(Encoder::new(reader, level));
+    Ok(EncodeIter {
+        encoder: encoder,
+        chunk_size: unsafe { ll::ZSTD_CStreamOutSize() },
+        finished: false,
+    })
+}
+
+/// An iterator over compressed chunks.
+///
+/// This is handy to feed compressed data to channels, RPC streams or
+/// multipart uploads, without going through a `Write`.
+///
+/// The iteration stops after the first error.
+pub struct EncodeIter<R: Read> {
+    // source of compressed data
+    encoder: Encoder<R>,
+    // size of the chunks we produce
+    chunk_size: usize,
+    // whether the last chunk was returned
+    finished: bool,
+}
+
+impl<R: Read> EncodeIter<R> {
+    /// Returns `true` once the last chunk was returned.
+    ///
+    /// Checking this right after getting a chunk tells whether
+    /// it was the final one.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.encoder.finish()
+    }
+}
+
+impl<R: Read> Iterator for EncodeIter<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.finished {
+            return None;
+        }
+
+        let mut chunk = vec![0; self.chunk_size];
+        match self.encoder.read(&mut chunk) {
+            Ok(len) => {
+                chunk.truncate(len);
+                self.finished = self.encoder.is_finished();
+                Some(Ok(chunk))
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_iter() {
+    let data = vec![42u8; 1000000];
+
+    let mut iter = encode_iter(&data[..], 1).unwrap();
+    let mut compressed = Vec::new();
+    while let Some(chunk) = iter.next() {
+        compressed.extend_from_slice(&chunk.unwrap());
+    }
+    assert!(iter.is_finished());
+
+    assert_eq!(::decode_all(&compressed).unwrap(), data);
+}
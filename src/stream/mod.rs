@@ -1,12 +1,113 @@
-pub mod encoder;
-pub mod decoder;
+//! Compress and decompress streams of data.
+//!
+//! The [`read`](read/index.html) module provides adapters pulling data from
+//! a `Read`, while the [`write`](write/index.html) module provides adapters
+//! pushing data to a `Write`. The [`raw`](raw/index.html) module lets
+//! the caller drive the operation with its own buffers. The
+//! [`seekable`](seekable/index.html) module gives random access to
+//! multi-frame archives, and the [`mux`](mux/index.html) module
+//! interleaves compressed channels over a single stream.
+
+pub mod mux;
+pub mod raw;
+pub mod read;
+pub mod seekable;
+pub mod write;
+#[cfg(feature = "futures")]
+pub mod futures;
+
+use std::error;
+use std::fmt;
+use std::io;
+
+mod adapt;
+mod chunks;
+mod estimate;
+mod frames;
+mod iter;
+mod progression;
+mod recompress;
+mod salvage;
+mod transcoder;
+mod transform;
+
+pub use self::chunks::ChunkReader;
+pub use self::estimate::{SizeEstimate, compressed_size};
+pub use self::frames::{BlockInfo, BlockType, Frame, FrameHeader, FrameIter,
+                       FrameReport, VerifyReport, legacy_version, verify};
+pub use self::iter::{EncodeIter, encode_iter};
+pub use self::progression::{FrameProgression, FrameStats};
+pub use self::recompress::{recompress, recompress_with_dictionary};
+pub use self::salvage::{Corruption, SalvageReport, salvage,
+                        salvage_with_dictionary};
+pub use self::transcoder::Transcoder;
+pub use self::transform::{FrameOpener, FrameSealer, FrameTransform};
+
+pub(crate) use self::frames::{BLOCK_HEADER_SIZE, SKIPPABLE_MAGIC_START,
+                              parse_block_header};
+
+/// What a decoder should do with data following the end of the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingData {
+    /// Silently ignore anything after the frame.
+    ///
+    /// This is the default.
+    Ignore,
+    /// Fail with an `InvalidData` error if anything follows the frame.
+    Error,
+    /// Keep whatever follows the frame, available from the decoder
+    /// once the end of the stream is reached.
+    Return,
+}
+
+/// Error when a decoder would produce more than its output limit.
+///
+/// It is returned inside an `InvalidData` `io::Error`; use
+/// `io::Error::get_ref` and `downcast_ref` to tell it apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimitExceeded {
+    /// The limit that was exceeded, in bytes.
+    pub limit: u64,
+}
+
+impl fmt::Display for OutputLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "decompressed data exceeds the limit of {} bytes",
+               self.limit)
+    }
+}
+
+impl error::Error for OutputLimitExceeded {}
+
+impl From<OutputLimitExceeded> for io::Error {
+    fn from(error: OutputLimitExceeded) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+// Window log used by the low-memory presets (`ZSTD_WINDOWLOG_MIN`).
+const SMALL_WINDOW_LOG: u32 = 10;
+// Buffer size used by the low-memory presets.
+const SMALL_BUFFER_SIZE: usize = 4096;
+
+// Error returned when data follows the frame, and shouldn't.
+fn trailing_data_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   "trailing data after the end of the frame")
+}
+
+// Error returned when a frame has no checksum, and should.
+fn missing_checksum_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   "frame has no content checksum")
+}
 
 
 #[test]
 fn test_end_of_frame() {
     use std::io::{Read, Write};
 
-    let mut enc = encoder::Encoder::new(Vec::new(), 1).unwrap();
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
     enc.write_all(b"foo").unwrap();
     let mut compressed = enc.finish().unwrap();
 
@@ -14,7 +115,7 @@ fn test_end_of_frame() {
     compressed.push(0);
 
     // Drain zstd stream until end-of-frame.
-    let mut dec = decoder::Decoder::new(&compressed[..]).unwrap();
+    let mut dec = read::Decoder::new(&compressed[..]).unwrap();
     let mut buf = Vec::new();
     dec.read_to_end(&mut buf).unwrap();
     assert_eq!(&buf, b"foo");
@@ -25,7 +126,7 @@ fn test_flush() {
     use std::io::Write;
 
     let buf = Vec::new();
-    let mut z = encoder::Encoder::new(buf, 19).unwrap();
+    let mut z = write::Encoder::new(buf, 19).unwrap();
 
     z.write_all(b"hello").unwrap();
 
@@ -36,3 +137,826 @@ fn test_flush() {
     let s = ::std::str::from_utf8(&s).unwrap();
     assert_eq!(s, "hello");
 }
+
+#[test]
+fn test_reverse_cycle() {
+    use std::io;
+
+    let text = b"Some text to compress with a read encoder, and to decompress \
+                 with a write decoder.";
+
+    let mut enc = read::Encoder::new(&text[..], 1).unwrap();
+    let mut dec = write::Decoder::new(Vec::new()).unwrap();
+    io::copy(&mut enc, &mut dec).unwrap();
+
+    assert_eq!(&dec.finish()[..], &text[..]);
+}
+
+#[test]
+fn test_small_input() {
+    use std::io::Write;
+
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    z.write_all(b"a small ").unwrap();
+    z.write_all(b"message").unwrap();
+    assert_eq!(z.progress().ingested, 15);
+    let compressed = z.finish().unwrap();
+
+    // Compressed in a single call: the header records the size.
+    let frame = FrameIter::new(&compressed[..]).next().unwrap().unwrap();
+    assert_eq!(frame.header.content_size, Some(15));
+    assert_eq!(&frame.data, b"a small message");
+
+    // Larger inputs are streamed.
+    let text = vec![b'x'; 100000];
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    z.write_all(&text[..10]).unwrap();
+    z.write_all(&text[10..]).unwrap();
+    let compressed = z.finish().unwrap();
+    let frame = FrameIter::new(&compressed[..]).next().unwrap().unwrap();
+    assert_eq!(frame.header.content_size, None);
+    assert_eq!(frame.data, text);
+}
+
+#[test]
+fn test_progress() {
+    use std::io::Write;
+
+    let mut z = write::Encoder::new(Vec::new(), 1).unwrap();
+    z.write_all(b"hello").unwrap();
+
+    let progress = z.progress();
+    assert_eq!(progress.ingested, 5);
+    assert!(progress.consumed <= progress.ingested);
+}
+
+#[test]
+fn test_observer() {
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let produced = Arc::new(AtomicUsize::new(0));
+
+    let mut z = write::Encoder::new(Vec::new(), 1).unwrap();
+    {
+        let consumed = consumed.clone();
+        let produced = produced.clone();
+        z.on_progress(move |c, p| {
+            consumed.fetch_add(c, Ordering::SeqCst);
+            produced.fetch_add(p, Ordering::SeqCst);
+        });
+    }
+    z.write_all(b"hello").unwrap();
+    let buf = z.finish().unwrap();
+
+    assert_eq!(consumed.load(Ordering::SeqCst), 5);
+    assert_eq!(produced.load(Ordering::SeqCst), buf.len());
+}
+
+#[test]
+fn test_literal_compression_mode() {
+    use params::LiteralCompressionMode;
+    use std::io::Write;
+
+    let text = b"Literals, literals everywhere, and not a match to find.";
+    for &mode in &[LiteralCompressionMode::Auto,
+                   LiteralCompressionMode::Huffman,
+                   LiteralCompressionMode::Uncompressed] {
+        let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+        z.set_literal_compression_mode(mode).unwrap();
+        z.write_all(text).unwrap();
+        let compressed = z.finish().unwrap();
+        assert_eq!(&::decode_all(&compressed[..]).unwrap()[..], &text[..]);
+    }
+}
+
+#[test]
+fn test_source_size_hint() {
+    use std::io::{Read, Write};
+
+    let text = b"A small input, hinted at.";
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    z.set_source_size_hint(text.len()).unwrap();
+    z.write_all(text).unwrap();
+    let compressed = z.finish().unwrap();
+    assert_eq!(&::decode_all(&compressed[..]).unwrap()[..], &text[..]);
+
+    // The hint may be wrong.
+    let mut z = read::Encoder::new(&text[..], 3).unwrap();
+    z.set_source_size_hint(1000).unwrap();
+    let mut compressed = Vec::new();
+    z.read_to_end(&mut compressed).unwrap();
+    assert_eq!(&::decode_all(&compressed[..]).unwrap()[..], &text[..]);
+
+    // Hints must fit in an `i32`.
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    assert_eq!(z.set_source_size_hint(1 << 31).unwrap_err().kind(),
+               io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_job_size_bounds() {
+    use params::CParameter;
+
+    // Sizes above 1GB don't wrap into negative values.
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    let job_size = CParameter::JobSize(u32::max_value());
+    assert_eq!(z.set_parameter(job_size).unwrap_err().kind(),
+               io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_deterministic() {
+    use std::io::Write;
+
+    let compress = || {
+        let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+        z.set_deterministic().unwrap();
+        z.write_all(b"Some reproducible artifact").unwrap();
+        z.finish().unwrap()
+    };
+    assert_eq!(compress(), compress());
+
+    let mut z = write::Encoder::new(Vec::new(), 3).unwrap();
+    z.set_deterministic().unwrap();
+    assert!(z.set_adaptive_level(1, 19).is_err());
+}
+
+#[test]
+fn test_frame_per_write() {
+    use std::io::Write;
+
+    let single = |message: &[u8]| {
+        let mut z = write::Encoder::new(Vec::new(), 1).unwrap();
+        z.write_message(message).unwrap();
+        z.finish().unwrap()
+    };
+    let first = single(b"first");
+    let second = single(b"second");
+
+    let mut z = write::Encoder::new(Vec::new(), 1).unwrap();
+    z.set_frame_per_write(true);
+    z.write_all(b"first").unwrap();
+    z.write_all(b"second").unwrap();
+    let buf = z.finish().unwrap();
+
+    // Each message is its own frame, and can be decoded on its own.
+    assert_eq!(buf, [&first[..], &second[..]].concat());
+    assert_eq!(super::decode_all(&second).unwrap(), b"second");
+}
+
+#[test]
+fn test_trailing_data() {
+    use std::io::Read;
+
+    let mut compressed = super::encode_all(b"foo", 1).unwrap();
+    compressed.extend_from_slice(b"bar");
+
+    let mut buf = Vec::new();
+    let mut dec = read::Decoder::new(&compressed[..]).unwrap();
+    dec.set_trailing_data(TrailingData::Return);
+    dec.read_to_end(&mut buf).unwrap();
+    assert_eq!(&buf, b"foo");
+    assert_eq!(dec.trailing_data(), b"bar");
+    assert_eq!(dec.compressed_bytes_consumed() as usize,
+               compressed.len() - 3);
+
+    let mut dec = read::Decoder::new(&compressed[..]).unwrap();
+    dec.set_trailing_data(TrailingData::Error);
+    assert!(dec.read_to_end(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn test_partial_writes() {
+    use std::io::Write;
+
+    // A writer only accepting a few bytes at a time.
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = ::std::cmp::min(buf.len(), 7);
+            self.0.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let data: Vec<u8> = (0..100000).map(|i| (i % 251) as u8).collect();
+    let mut enc = write::Encoder::new(Trickle(Vec::new()), 1).unwrap();
+    enc.set_partial_writes(true);
+    enc.write_all(&data).unwrap();
+    let compressed = enc.finish().unwrap().0;
+
+    assert_eq!(super::decode_all(&compressed[..]).unwrap(), data);
+}
+
+#[test]
+fn test_writer_failure() {
+    use std::io::Write;
+
+    // A writer failing once, after a few calls.
+    struct Flaky {
+        data: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == 3 {
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            let n = ::std::cmp::min(buf.len(), 1000);
+            self.data.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Hardly compressible data, so output is produced along the way.
+    let mut seed = 1u32;
+    let data: Vec<u8> = (0..1000000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+
+    let writer = Flaky {
+        data: Vec::new(),
+        calls: 0,
+    };
+    let mut enc = write::Encoder::new(writer, 1).unwrap();
+    let mut input = &data[..];
+    while !input.is_empty() {
+        if let Ok(n) = enc.write(input) {
+            input = &input[n..];
+        }
+    }
+    let writer = enc.finish().unwrap();
+
+    assert!(writer.calls > 3);
+    assert_eq!(super::decode_all(&writer.data[..]).unwrap(), data);
+}
+
+#[test]
+fn test_message_writer_failure() {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    // A writer failing on its second call.
+    struct Flaky {
+        data: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == 2 {
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let writer = Flaky {
+        data: Vec::new(),
+        calls: 0,
+    };
+    let frames = Arc::new(Mutex::new(0));
+    let counted = frames.clone();
+    let mut enc = write::Encoder::new(writer, 1).unwrap();
+    enc.on_frame(move |_| *counted.lock().unwrap() += 1);
+    enc.write_message(b"first").unwrap();
+    assert!(enc.write_message(b"second").is_err());
+    // The second frame is complete, and sent by `finish()`.
+    let writer = enc.finish().unwrap();
+
+    assert_eq!(*frames.lock().unwrap(), 2);
+    let decoded = ::bulk::Decompressor::new()
+        .decompress_multi(&writer.data)
+        .unwrap();
+    assert_eq!(&decoded[..], &b"firstsecond"[..]);
+}
+
+#[test]
+fn test_small_frame_writer_failure() {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    // A writer failing on its first call.
+    struct Flaky {
+        data: Vec<u8>,
+        failed: bool,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !self.failed {
+                self.failed = true;
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let writer = Flaky {
+        data: Vec::new(),
+        failed: false,
+    };
+    let frames = Arc::new(Mutex::new(0));
+    let counted = frames.clone();
+    let mut enc = write::Encoder::new(writer, 1).unwrap();
+    enc.on_frame(move |_| *counted.lock().unwrap() += 1);
+    // Compressed in a single call, then the writer fails.
+    enc.write_all(b"small frame").unwrap();
+    assert!(enc.flush_end().is_err());
+    let writer = enc.finish().unwrap();
+
+    // No empty frame after it.
+    assert_eq!(*frames.lock().unwrap(), 1);
+    assert_eq!(::safe::find_frame_compressed_size(&writer.data).unwrap(),
+               writer.data.len());
+    assert_eq!(&::decode_all(&writer.data[..]).unwrap()[..],
+               &b"small frame"[..]);
+}
+
+#[test]
+fn test_reader_failure() {
+    use std::io::Read;
+
+    // A reader failing on its first call.
+    struct Flaky<'a> {
+        data: &'a [u8],
+        failed: bool,
+    }
+
+    impl<'a> Read for Flaky<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.failed {
+                self.failed = true;
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    let data = b"data read after a failure";
+    let reader = Flaky {
+        data: &data[..],
+        failed: false,
+    };
+    let mut enc = read::Encoder::new(reader, 1).unwrap();
+    assert!(enc.read(&mut [0u8; 64]).is_err());
+    let mut compressed = Vec::new();
+    enc.read_to_end(&mut compressed).unwrap();
+    assert_eq!(::decode_all(&compressed[..]).unwrap(), &data[..]);
+}
+
+#[test]
+fn test_content_checksum() {
+    use std::io::{Read, Write};
+    use params::CParameter;
+    use xxhash::xxh64;
+
+    let data = b"some data to checksum";
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    enc.write_all(data).unwrap();
+    let compressed = enc.finish().unwrap();
+
+    let mut dec = read::Decoder::new(&compressed[..]).unwrap();
+    dec.track_content_checksum(true);
+    assert_eq!(dec.content_checksum(), None);
+    dec.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(dec.content_checksum(), Some(xxh64(data, 0) as u32));
+}
+
+#[test]
+fn test_frame_stats() {
+    use std::sync::{Arc, Mutex};
+    use xxhash::xxh64;
+
+    let stats = Arc::new(Mutex::new(Vec::new()));
+    let recorded = stats.clone();
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.on_frame(move |s| recorded.lock().unwrap().push(s));
+    enc.write_message(b"first").unwrap();
+    enc.write_message(b"second message").unwrap();
+    let compressed = enc.finish().unwrap();
+
+    let stats = stats.lock().unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[1].index, 1);
+    assert_eq!(stats[1].uncompressed_size, 14);
+    assert_eq!(stats[1].checksum, xxh64(b"second message", 0) as u32);
+    assert_eq!(stats[0].compressed_size + stats[1].compressed_size,
+               compressed.len() as u64);
+}
+
+#[test]
+fn test_low_memory() {
+    use std::io::{Read, Write};
+    use params;
+
+    let data = vec![7u8; 100000];
+    let mut enc = write::Encoder::small_window(Vec::new(), 3).unwrap();
+    enc.write_all(&data).unwrap();
+    let compressed = enc.finish().unwrap();
+
+    let mut decoded = Vec::new();
+    let mut dec = read::Decoder::low_memory(&compressed[..]).unwrap();
+    dec.read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, data);
+
+    let small = params::decoder_memory(1 << SMALL_WINDOW_LOG);
+    assert_eq!(read::Decoder::<&[u8]>::low_memory_requirement(),
+               small + SMALL_BUFFER_SIZE);
+    assert!(small < params::decoder_memory(1 << 20));
+    assert!(write::Encoder::<Vec<u8>>::small_window_requirement(3) > 0);
+}
+
+#[test]
+fn test_buffer_capacity() {
+    use std::cmp;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    let data = vec![3u8; 100000];
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.set_buffer_capacity(16);
+    assert!(enc.buffer_capacity() < 1024);
+    enc.write_all(&data).unwrap();
+    let compressed = enc.finish().unwrap();
+    assert_eq!(::decode_all(&compressed[..]).unwrap(), data);
+
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.set_shrink_policy(Some(256));
+    enc.write_message(b"tiny").unwrap();
+    assert!(enc.buffer_capacity() < 1024);
+    enc.write_message(&data).unwrap();
+    let compressed = enc.finish().unwrap();
+    let mut expected = b"tiny".to_vec();
+    expected.extend_from_slice(&data);
+    // Each message is its own frame.
+    let decoded = ::bulk::Decompressor::new()
+        .decompress_multi(&compressed)
+        .unwrap();
+    assert_eq!(decoded, expected);
+
+    // A large frame after a small one gets the whole buffer back.
+    let mut seed = 1u32;
+    let noise: Vec<u8> = (0..1000000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    let produced = Arc::new(Mutex::new(0));
+    let recorded = produced.clone();
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.set_shrink_policy(Some(1));
+    enc.on_progress(move |_, n| {
+        let mut produced = recorded.lock().unwrap();
+        *produced = cmp::max(*produced, n);
+    });
+    enc.write_message(b"").unwrap();
+    assert!(enc.buffer_capacity() < 1024);
+    enc.write_message(&noise).unwrap();
+    assert!(*produced.lock().unwrap() > 64 * 1024);
+    enc.finish().unwrap();
+}
+
+#[test]
+fn test_shared_prepared_dictionary() {
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use std::thread;
+    use dict::{DecoderDictionary, EncoderDictionary};
+
+    let dict = b"shared entry: shared entry: shared entry".to_vec();
+    let cdict = Arc::new(EncoderDictionary::new(&dict, 3).unwrap());
+    let ddict = Arc::new(DecoderDictionary::new(&dict).unwrap());
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let cdict = cdict.clone();
+            let ddict = ddict.clone();
+            thread::spawn(move || {
+                let text = format!("shared entry: {}", i).into_bytes();
+                let mut enc =
+                    write::Encoder::with_prepared_dictionary(Vec::new(),
+                                                             cdict)
+                        .unwrap();
+                enc.write_all(&text).unwrap();
+                let compressed = enc.finish().unwrap();
+
+                let mut dec =
+                    read::Decoder::with_prepared_dictionary(&compressed[..],
+                                                            ddict)
+                        .unwrap();
+                let mut decompressed = Vec::new();
+                dec.read_to_end(&mut decompressed).unwrap();
+                assert_eq!(decompressed, text);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn test_frame_info() {
+    use std::io::{Read, Write};
+    use params::CParameter;
+
+    let dict = b"a dictionary, a dictionary, a dictionary".to_vec();
+    let text = vec![5u8; 10000];
+    let mut enc = write::Encoder::with_dictionary(Vec::new(), 3, &dict)
+        .unwrap();
+    enc.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    enc.write_all(&text).unwrap();
+    let compressed = enc.finish().unwrap();
+
+    let mut dec = read::Decoder::with_dictionary(&compressed[..], &dict)
+        .unwrap();
+    assert_eq!(dec.frame_info(), None);
+    let mut first = [0u8];
+    dec.read_exact(&mut first).unwrap();
+    let info = dec.frame_info().unwrap();
+    assert!(info.has_checksum);
+    assert_eq!(info.dict_id, 0);
+    assert!(info.window_size > 0);
+
+    let mut dec = write::Decoder::new(Vec::new()).unwrap();
+    dec.write_all(&::encode_all(&text[..], 1).unwrap()[..8]).unwrap();
+    let info = dec.frame_info().unwrap();
+    assert!(!info.has_checksum);
+}
+
+#[test]
+fn test_require_checksum() {
+    use std::io::{Read, Write};
+    use params::CParameter;
+
+    let text = vec![9u8; 10000];
+    let unchecked = ::encode_all(&text[..], 1).unwrap();
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    enc.set_parameter(CParameter::ChecksumFlag(true)).unwrap();
+    enc.write_all(&text).unwrap();
+    let checked = enc.finish().unwrap();
+
+    let mut dec = read::Decoder::new(&unchecked[..]).unwrap();
+    dec.require_checksum(true);
+    let err = dec.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut dec = read::Decoder::new(&checked[..]).unwrap();
+    dec.require_checksum(true);
+    let mut decompressed = Vec::new();
+    dec.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, text);
+
+    let mut dec = write::Decoder::new(Vec::new()).unwrap();
+    dec.require_checksum(true);
+    assert!(dec.write_all(&unchecked).is_err());
+    assert!(dec.finish().is_empty());
+}
+
+#[test]
+fn test_output_limit() {
+    use std::io::{Read, Write};
+
+    let bomb = ::encode_all(&vec![0u8; 1000000][..], 19).unwrap();
+
+    let mut dec = read::Decoder::new(&bomb[..])
+        .unwrap()
+        .with_output_limit(1000);
+    let err = dec.read_to_end(&mut Vec::new()).unwrap_err();
+    let limit = err.get_ref()
+        .and_then(|e| e.downcast_ref::<OutputLimitExceeded>());
+    assert_eq!(limit, Some(&OutputLimitExceeded { limit: 1000 }));
+
+    let mut dec = write::Decoder::new(Vec::new())
+        .unwrap()
+        .with_output_limit(1000);
+    assert!(dec.write_all(&bomb).is_err());
+    assert!(dec.finish().len() <= 1000);
+
+    // Exactly at the limit is fine.
+    let mut dec = read::Decoder::new(&bomb[..])
+        .unwrap()
+        .with_output_limit(1000000);
+    let mut decompressed = Vec::new();
+    dec.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed.len(), 1000000);
+}
+
+#[test]
+fn test_skippable_callback() {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    // A skippable frame (variant 2) with a manifest, then the data.
+    let mut input = vec![0x52, 0x2A, 0x4D, 0x18, 8, 0, 0, 0];
+    input.extend_from_slice(b"manifest");
+    input.extend_from_slice(&::encode_all(&b"payload"[..], 1).unwrap());
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let record = seen.clone();
+    let mut dec = read::Decoder::new(&input[..]).unwrap();
+    dec.on_skippable_frame(move |variant, content| {
+        record.lock().unwrap().push((variant, content.to_vec()));
+    });
+    let mut decompressed = Vec::new();
+    dec.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, b"payload");
+    assert_eq!(*seen.lock().unwrap(), vec![(2, b"manifest".to_vec())]);
+    assert_eq!(dec.compressed_bytes_consumed(), input.len() as u64);
+
+    // One byte at a time, through a writer.
+    let record = seen.clone();
+    let mut dec = write::Decoder::new(Vec::new()).unwrap();
+    dec.on_skippable_frame(move |variant, content| {
+        record.lock().unwrap().push((variant, content.to_vec()));
+    });
+    for byte in input.chunks(1) {
+        dec.write_all(byte).unwrap();
+    }
+    assert_eq!(dec.finish(), b"payload");
+    assert_eq!(seen.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_format_compatibility() {
+    use params::{CParameter, ZstdVersion};
+
+    let mut enc = write::Encoder::new(Vec::new(), 19).unwrap();
+    enc.max_format_compatibility(ZstdVersion::new(1, 3, 0)).unwrap();
+    assert!(enc.set_parameter(CParameter::WindowLog(30)).is_err());
+    enc.set_parameter(CParameter::WindowLog(27)).unwrap();
+
+    // Settings made before are checked too.
+    let mut enc = write::Encoder::new(Vec::new(), 19).unwrap();
+    enc.set_parameter(CParameter::WindowLog(30)).unwrap();
+    assert!(enc.max_format_compatibility(ZstdVersion::new(1, 3, 0))
+        .is_err());
+
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    assert!(enc.max_format_compatibility(ZstdVersion::new(0, 7, 0))
+        .is_err());
+    assert!(ZstdVersion::current() >= ZstdVersion::FRAME_FORMAT);
+}
+
+#[test]
+fn test_write_vectored() {
+    use std::io::{IoSlice, Write};
+
+    let big = vec![b'v'; 50000];
+    let slices = [IoSlice::new(b"small, "), IoSlice::new(&big),
+                  IoSlice::new(b", end")];
+    let mut enc = write::Encoder::new(Vec::new(), 1).unwrap();
+    let mut total = 0;
+    while total < 50012 {
+        // Skip what was already taken, like `write_all_vectored` would.
+        let mut skip = total;
+        let rest: Vec<IoSlice> = slices.iter()
+            .filter_map(|slice| if skip >= slice.len() {
+                skip -= slice.len();
+                None
+            } else {
+                let slice = IoSlice::new(&slice[skip..]);
+                skip = 0;
+                Some(slice)
+            })
+            .collect();
+        total += enc.write_vectored(&rest).unwrap();
+    }
+    let compressed = enc.finish().unwrap();
+
+    let mut expected = b"small, ".to_vec();
+    expected.extend_from_slice(&big);
+    expected.extend_from_slice(b", end");
+    assert_eq!(::decode_all(&compressed[..]).unwrap(), expected);
+    // Everything went in the same frame.
+    assert_eq!(::safe::find_frame_compressed_size(&compressed).unwrap(),
+               compressed.len());
+}
+
+#[test]
+fn test_replace_writer() {
+    use std::io::{Read, Write};
+
+    let dict = b"rotated log line, rotated log line".to_vec();
+    let mut enc = write::Encoder::with_dictionary(Vec::new(), 3, &dict)
+        .unwrap();
+    enc.write_all(b"first file").unwrap();
+    let first = enc.replace_writer(Vec::new()).unwrap();
+    enc.write_all(b"second file").unwrap();
+    let second = enc.finish().unwrap();
+
+    for &(compressed, expected) in &[(&first, &b"first file"[..]),
+                                     (&second, &b"second file"[..])] {
+        let mut dec = read::Decoder::with_dictionary(&compressed[..], &dict)
+            .unwrap();
+        let mut decoded = Vec::new();
+        dec.read_to_end(&mut decoded).unwrap();
+        assert_eq!(&decoded[..], expected);
+    }
+}
+
+#[test]
+fn test_flush_decodable() {
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    // Lets the test look at the output while the encoder is alive.
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let text: Vec<u8> = (0..300000u32).map(|i| (i % 253) as u8).collect();
+    let mut enc = write::Encoder::new(Shared(output.clone()), 3).unwrap();
+    enc.write_all(&text).unwrap();
+    enc.flush().unwrap();
+
+    // Everything written so far decodes, before the frame ends.
+    let mut dec = write::Decoder::new(Vec::new()).unwrap();
+    dec.write_all(&output.borrow()).unwrap();
+    assert_eq!(dec.finish(), text);
+
+    enc.write_all(b"more").unwrap();
+    enc.flush_end().unwrap();
+    let mut expected = text.clone();
+    expected.extend_from_slice(b"more");
+    assert_eq!(::decode_all(&output.borrow()[..]).unwrap(), expected);
+}
+
+#[test]
+fn test_header_flags() {
+    use std::io::Read;
+
+    // A trained dictionary has an ID.
+    let samples: Vec<Vec<u8>> = (0..1000)
+        .map(|i| format!("{{\"id\": {}, \"message\": \"some message\"}}", i)
+            .into_bytes())
+        .collect();
+    let dict = ::dict::from_samples(&samples, 1024).unwrap();
+    let dict_id = ::dict::Dictionary::from_bytes(dict.clone()).unwrap().id();
+    assert!(dict_id != 0);
+
+    let header = |content_size: bool, dict_id: bool| {
+        let mut enc = write::Encoder::with_dictionary(Vec::new(), 3, &dict)
+            .unwrap();
+        enc.set_content_size_flag(content_size).unwrap();
+        enc.set_dict_id_flag(dict_id).unwrap();
+        enc.write_message(b"some message").unwrap();
+        let compressed = enc.finish().unwrap();
+
+        let mut dec = read::Decoder::with_dictionary(&compressed[..], &dict)
+            .unwrap();
+        let mut decoded = Vec::new();
+        dec.read_to_end(&mut decoded).unwrap();
+        assert_eq!(&decoded[..], b"some message");
+        (dec.frame_info().unwrap(), compressed.len())
+    };
+
+    let (full, full_size) = header(true, true);
+    assert_eq!(full.content_size, Some(12));
+    assert_eq!(full.dict_id, dict_id);
+    let (bare, bare_size) = header(false, false);
+    assert_eq!(bare.content_size, None);
+    assert_eq!(bare.dict_id, 0);
+    assert!(bare_size < full_size);
+}
@@ -0,0 +1,401 @@
+//! Several compressed channels interleaved over a single stream.
+//!
+//! Each channel is compressed with its own context and dictionary, as
+//! one long frame, so every message benefits from the previous ones of
+//! the same channel. This suits structured log shipping, with one
+//! channel per table or topic.
+//!
+//! The stream is a sequence of chunks, each made of the channel ID (4
+//! bytes), the size of the data (4 bytes), both little-endian, and some
+//! compressed data from that channel. A
+//! [`Multiplexer`](struct.Multiplexer.html) writes them, and a
+//! [`Demultiplexer`](struct.Demultiplexer.html) reads them back.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use ll;
+use stream::raw::{Decoder, Encoder, InBuffer, OutBuffer, Status};
+
+// Channel ID and data size, before each chunk.
+const CHUNK_HEADER_SIZE: usize = 8;
+// Data is sent once that much is staged, even in the middle of a
+// message.
+const MAX_CHUNK_SIZE: usize = 1 << 20;
+
+/// Identifies a channel of a [`Multiplexer`](struct.Multiplexer.html).
+pub type ChannelId = u32;
+
+/// Compresses several channels over a single writer.
+///
+/// Don't forget to call `finish()` before dropping it!
+///
+/// Once an operation failed, the stream is broken: the following ones
+/// return an error.
+pub struct Multiplexer<W: Write> {
+    writer: W,
+    channels: Vec<Encoder>,
+    // compressed data of the current chunk
+    staging: Vec<u8>,
+    // zstd's output, before it's staged
+    output: Vec<u8>,
+    // whether an operation failed, leaving a channel in an unknown state
+    failed: bool,
+}
+
+impl<W: Write> Multiplexer<W> {
+    /// Creates a multiplexer, without any channel yet.
+    pub fn new(writer: W) -> Self {
+        let out_size = unsafe { ll::ZSTD_CStreamOutSize() };
+        Multiplexer {
+            writer: writer,
+            channels: Vec::new(),
+            staging: Vec::new(),
+            output: vec![0u8; out_size],
+            failed: false,
+        }
+    }
+
+    /// Adds a channel compressed at the given level.
+    ///
+    /// Channels are numbered from 0, in the order they are added.
+    pub fn add_channel(&mut self, level: i32) -> io::Result<ChannelId> {
+        let encoder = try!(Encoder::new(level));
+        Ok(self.push_channel(encoder))
+    }
+
+    /// Adds a channel compressed with a dictionary.
+    ///
+    /// The demultiplexer needs the same dictionary for this channel.
+    pub fn add_channel_with_dictionary(&mut self, level: i32,
+                                       dictionary: &[u8])
+                                       -> io::Result<ChannelId> {
+        let encoder = try!(Encoder::with_dictionary(level, dictionary));
+        Ok(self.push_channel(encoder))
+    }
+
+    fn push_channel(&mut self, encoder: Encoder) -> ChannelId {
+        self.channels.push(encoder);
+        (self.channels.len() - 1) as ChannelId
+    }
+
+    /// Compresses `message` on the given channel, and sends it.
+    ///
+    /// The message is flushed: the other side can read it right away.
+    pub fn send(&mut self, channel: ChannelId, message: &[u8])
+                -> io::Result<()> {
+        try!(self.check_channel(channel));
+        try!(self.check_failed());
+        let result = self.compress_message(channel, message);
+        self.check_result(result)
+    }
+
+    /// Ends the frame of every channel, and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.check_failed());
+        for channel in 0..self.channels.len() {
+            try!(self.drain(channel as ChannelId, true));
+        }
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    fn compress_message(&mut self, channel: ChannelId, message: &[u8])
+                        -> io::Result<()> {
+        let mut input = InBuffer::new(message);
+        while input.pos < message.len() {
+            let status = {
+                let encoder = &mut self.channels[channel as usize];
+                let mut output = OutBuffer::new(&mut self.output);
+                let status =
+                    try!(encoder.poll_compress(&mut input, &mut output));
+                self.staging.extend_from_slice(output.as_slice());
+                status
+            };
+            if status == Status::NeedOutput ||
+               self.staging.len() >= MAX_CHUNK_SIZE {
+                try!(self.send_chunk(channel));
+            }
+        }
+        self.drain(channel, false)
+    }
+
+    // Breaks the stream on error.
+    //
+    // Data staged for a channel must not be sent as part of another one.
+    fn check_result(&mut self, result: io::Result<()>) -> io::Result<()> {
+        if result.is_err() {
+            self.failed = true;
+            self.staging.clear();
+        }
+        result
+    }
+
+    fn check_failed(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      "a previous operation failed"));
+        }
+        Ok(())
+    }
+
+    // Sends all the data compressed on a channel, flushing or ending its
+    // frame.
+    fn drain(&mut self, channel: ChannelId, end: bool) -> io::Result<()> {
+        loop {
+            let status = {
+                let encoder = &mut self.channels[channel as usize];
+                let mut output = OutBuffer::new(&mut self.output);
+                let status = if end {
+                    try!(encoder.poll_finish(&mut output))
+                } else {
+                    try!(encoder.poll_flush(&mut output))
+                };
+                self.staging.extend_from_slice(output.as_slice());
+                status
+            };
+            if status == Status::Done {
+                return self.send_chunk(channel);
+            }
+            if self.staging.len() >= MAX_CHUNK_SIZE {
+                try!(self.send_chunk(channel));
+            }
+        }
+    }
+
+    // Sends the staged data as a chunk of the given channel.
+    fn send_chunk(&mut self, channel: ChannelId) -> io::Result<()> {
+        if self.staging.is_empty() {
+            return Ok(());
+        }
+        let mut header = [0u8; CHUNK_HEADER_SIZE];
+        write_u32_le(&mut header[..4], channel);
+        write_u32_le(&mut header[4..], self.staging.len() as u32);
+        try!(self.writer.write_all(&header));
+        try!(self.writer.write_all(&self.staging));
+        self.staging.clear();
+        Ok(())
+    }
+
+    fn check_channel(&self, channel: ChannelId) -> io::Result<()> {
+        if channel as usize >= self.channels.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "unknown channel"));
+        }
+        Ok(())
+    }
+}
+
+// Decoder of a channel, and whether it's in the middle of a frame.
+struct Channel {
+    decoder: Decoder,
+    in_frame: bool,
+}
+
+/// Reads back the channels written by a
+/// [`Multiplexer`](struct.Multiplexer.html).
+///
+/// It iterates over the data of each chunk, decompressed, with its
+/// channel. Decoders are created as channels show up.
+pub struct Demultiplexer<R: Read> {
+    reader: R,
+    channels: HashMap<ChannelId, Channel>,
+    // dictionaries given for channels not seen yet
+    dictionaries: HashMap<ChannelId, Vec<u8>>,
+    // compressed data of the current chunk
+    chunk: Vec<u8>,
+    output: Vec<u8>,
+    // size of the largest chunk a multiplexer sends
+    max_chunk_size: usize,
+}
+
+impl<R: Read> Demultiplexer<R> {
+    /// Creates a demultiplexer reading chunks from `reader`.
+    pub fn new(reader: R) -> Self {
+        let out_size = unsafe { ll::ZSTD_DStreamOutSize() };
+        // Chunks are sent once they reach `MAX_CHUNK_SIZE`, which the
+        // last output of zstd may exceed.
+        let max_chunk_size = MAX_CHUNK_SIZE +
+                             unsafe { ll::ZSTD_CStreamOutSize() };
+        Demultiplexer {
+            reader: reader,
+            channels: HashMap::new(),
+            dictionaries: HashMap::new(),
+            chunk: Vec::new(),
+            output: vec![0u8; out_size],
+            max_chunk_size: max_chunk_size,
+        }
+    }
+
+    /// Sets the dictionary of a channel.
+    ///
+    /// This must be called before any data of that channel is read.
+    pub fn set_dictionary(&mut self, channel: ChannelId, dictionary: &[u8]) {
+        self.dictionaries.insert(channel, dictionary.to_vec());
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next chunk, and returns its channel and decompressed
+    /// data.
+    ///
+    /// Returns `None` at the end of the input. A chunk may hold part of a
+    /// message, or several of them: the data of each channel is a
+    /// stream.
+    pub fn next_chunk(&mut self) -> io::Result<Option<(ChannelId, Vec<u8>)>> {
+        let mut header = [0u8; CHUNK_HEADER_SIZE];
+        if !try!(read_header(&mut self.reader, &mut header)) {
+            if self.channels.values().any(|channel| channel.in_frame) {
+                return Err(incomplete("frame"));
+            }
+            return Ok(None);
+        }
+        let channel = read_u32_le(&header[..4]);
+        let size = read_u32_le(&header[4..]) as usize;
+        if size > self.max_chunk_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "chunk is too large"));
+        }
+        self.chunk.resize(size, 0);
+        try!(self.reader.read_exact(&mut self.chunk).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                incomplete("chunk")
+            } else {
+                e
+            }
+        }));
+
+        if !self.channels.contains_key(&channel) {
+            let decoder = match self.dictionaries.remove(&channel) {
+                Some(dict) => try!(Decoder::with_dictionary(&dict)),
+                None => try!(Decoder::new()),
+            };
+            self.channels.insert(channel,
+                                 Channel {
+                                     decoder: decoder,
+                                     in_frame: false,
+                                 });
+        }
+        let state = self.channels.get_mut(&channel).unwrap();
+
+        let mut data = Vec::new();
+        let mut input = InBuffer::new(&self.chunk);
+        loop {
+            let start = input.pos;
+            let status = {
+                let mut output = OutBuffer::new(&mut self.output);
+                let status = try!(state.decoder
+                    .poll_decompress(&mut input, &mut output));
+                data.extend_from_slice(output.as_slice());
+                status
+            };
+            state.in_frame = match status {
+                Status::Done => false,
+                _ => state.in_frame || input.pos > start,
+            };
+            if input.pos == self.chunk.len() && status != Status::NeedOutput {
+                break;
+            }
+        }
+        Ok(Some((channel, data)))
+    }
+}
+
+impl<R: Read> Iterator for Demultiplexer<R> {
+    type Item = io::Result<(ChannelId, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<io::Result<(ChannelId, Vec<u8>)>> {
+        match self.next_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// Reads a chunk header.
+//
+// Returns `false` at the end of the input, before the header.
+fn read_header<R: Read>(reader: &mut R, header: &mut [u8])
+                        -> io::Result<bool> {
+    let mut read = 0;
+    while read < header.len() {
+        match reader.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(incomplete("chunk header")),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+fn write_u32_le(bytes: &mut [u8], value: u32) {
+    for (i, byte) in bytes[..4].iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+}
+
+fn incomplete(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof,
+                   format!("incomplete {}", what))
+}
+
+#[test]
+fn test_mux() {
+    let dict = b"table=users table=users table=users".to_vec();
+    let mut mux = Multiplexer::new(Vec::new());
+    let users = mux.add_channel_with_dictionary(3, &dict).unwrap();
+    let orders = mux.add_channel(1).unwrap();
+    mux.send(users, b"table=users id=1\n").unwrap();
+    mux.send(orders, b"order 1\n").unwrap();
+    mux.send(users, b"table=users id=2\n").unwrap();
+    assert!(mux.send(7, b"nowhere").is_err());
+    let stream = mux.finish().unwrap();
+
+    let mut demux = Demultiplexer::new(&stream[..]);
+    demux.set_dictionary(users, &dict);
+    let mut received: HashMap<ChannelId, Vec<u8>> = HashMap::new();
+    for chunk in demux {
+        let (channel, data) = chunk.unwrap();
+        received.entry(channel).or_insert_with(Vec::new).extend(data);
+    }
+    assert_eq!(&received[&users][..],
+               &b"table=users id=1\ntable=users id=2\n"[..]);
+    assert_eq!(&received[&orders][..], &b"order 1\n"[..]);
+
+    // Cut in the middle of a chunk.
+    let mut demux = Demultiplexer::new(&stream[..stream.len() - 2]);
+    demux.set_dictionary(users, &dict);
+    assert!(demux.any(|chunk| chunk.is_err()));
+
+    // A chunk size no multiplexer sends.
+    let mut corrupt = stream.clone();
+    write_u32_le(&mut corrupt[4..8], u32::max_value());
+    let mut demux = Demultiplexer::new(&corrupt[..]);
+    assert_eq!(demux.next_chunk().unwrap_err().kind(),
+               io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_mux_failure() {
+    // Room for the first chunk only.
+    let mut output = [0u8; 64];
+    let mut mux = Multiplexer::new(&mut output[..]);
+    let first = mux.add_channel(1).unwrap();
+    let second = mux.add_channel(1).unwrap();
+    mux.send(first, b"fits").unwrap();
+    let noise: Vec<u8> = (0..1000u32).map(|i| (i * i % 251) as u8).collect();
+    assert!(mux.send(first, &noise).is_err());
+    // The data of the first channel isn't sent as part of the second one.
+    assert!(mux.send(second, b"x").is_err());
+    assert!(mux.finish().is_err());
+}
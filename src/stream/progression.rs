@@ -0,0 +1,55 @@
+use ll;
+
+// Callback notified each time data flows through a stream adapter,
+// with the number of bytes consumed and produced.
+pub type Observer = Box<FnMut(usize, usize) + Send>;
+
+/// Progress of the frame currently being compressed.
+///
+/// All counters are reset at the start of each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameProgression {
+    /// Number of input bytes read and buffered by zstd.
+    pub ingested: u64,
+    /// Number of input bytes actually compressed.
+    ///
+    /// The difference with `ingested` is buffered inside the context.
+    pub consumed: u64,
+    /// Number of compressed bytes generated.
+    pub produced: u64,
+    /// Number of compressed bytes flushed out of the context.
+    pub flushed: u64,
+    /// Latest started job (multithreading only).
+    pub current_job: u32,
+    /// Number of workers compressing at probe time (multithreading only).
+    pub active_workers: u32,
+}
+
+impl From<ll::ZSTD_frameProgression> for FrameProgression {
+    fn from(progression: ll::ZSTD_frameProgression) -> Self {
+        FrameProgression {
+            ingested: progression.ingested,
+            consumed: progression.consumed,
+            produced: progression.produced,
+            flushed: progression.flushed,
+            current_job: progression.currentJobID,
+            active_workers: progression.nbActiveWorkers,
+        }
+    }
+}
+
+/// Statistics about a completed frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Index of the frame in the stream, starting at 0.
+    pub index: u64,
+    /// Size of the data compressed in this frame.
+    pub uncompressed_size: u64,
+    /// Size of the compressed frame.
+    pub compressed_size: u64,
+    /// Content checksum of the frame.
+    ///
+    /// This is the lower 32 bits of the XXH64 hash of the uncompressed
+    /// data, as stored in frames with a checksum.
+    pub checksum: u32,
+}
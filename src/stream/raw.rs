@@ -0,0 +1,294 @@
+//! Resumable compression and decompression over caller-provided buffers.
+//!
+//! Unlike the `Read` and `Write` adapters, these never block nor own any
+//! I/O: each call makes as much progress as the buffers allow, and reports
+//! what it needs to continue. This lets event loops (like mio) drive
+//! compression from readiness events alone.
+
+use std::io;
+
+use ll;
+use context::{DecoderContext, EncoderContext};
+use params::ResetDirective;
+
+/// Input for a raw operation.
+///
+/// `pos` is updated as data is consumed.
+pub struct InBuffer<'a> {
+    /// Data to process.
+    pub src: &'a [u8],
+    /// Data before this point was already consumed.
+    pub pos: usize,
+}
+
+impl<'a> InBuffer<'a> {
+    /// Wraps the given slice, starting at the beginning.
+    pub fn new(src: &'a [u8]) -> Self {
+        InBuffer { src: src, pos: 0 }
+    }
+
+    /// Returns the data not consumed yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.src[self.pos..]
+    }
+}
+
+/// Output for a raw operation.
+///
+/// `pos` is updated as data is produced.
+pub struct OutBuffer<'a> {
+    /// Where to write the output.
+    pub dst: &'a mut [u8],
+    /// Data before this point was already produced.
+    pub pos: usize,
+}
+
+impl<'a> OutBuffer<'a> {
+    /// Wraps the given slice, starting at the beginning.
+    pub fn new(dst: &'a mut [u8]) -> Self {
+        OutBuffer { dst: dst, pos: 0 }
+    }
+
+    /// Returns the data produced so far.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.dst[..self.pos]
+    }
+}
+
+/// What a raw operation needs to continue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All the input was consumed: call again when more is available.
+    NeedInput,
+    /// The output buffer is full: drain it, then call again.
+    NeedOutput,
+    /// The operation is complete.
+    ///
+    /// For `poll_flush`, all data given so far was output; for
+    /// `poll_finish` and `poll_decompress`, the frame is complete.
+    Done,
+}
+
+/// A resumable encoder.
+pub struct Encoder {
+    context: EncoderContext,
+}
+
+impl Encoder {
+    /// Creates a new encoder.
+    ///
+    /// `level`: compression level (1-21)
+    pub fn new(level: i32) -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        Ok(Encoder { context: context })
+    }
+
+    /// Creates a new encoder, using an existing dictionary.
+    pub fn with_dictionary(level: i32, dictionary: &[u8]) -> io::Result<Self> {
+        let encoder = try!(Encoder::new(level));
+        try!(encoder.context.load_dictionary(dictionary));
+        Ok(encoder)
+    }
+
+    /// Compresses some input into `output`.
+    ///
+    /// Returns `NeedInput` once all the input was consumed,
+    /// or `NeedOutput` if `output` is full first.
+    /// zstd may keep some data internally: see `poll_flush`.
+    pub fn poll_compress(&mut self, input: &mut InBuffer,
+                         output: &mut OutBuffer)
+                         -> io::Result<Status> {
+        try!(self.run(input, output, ll::ZSTD_e_continue));
+        if input.pos == input.src.len() {
+            Ok(Status::NeedInput)
+        } else {
+            Ok(Status::NeedOutput)
+        }
+    }
+
+    /// Outputs all the data compressed so far.
+    ///
+    /// Call it until it returns `Done`, draining `output` in between.
+    pub fn poll_flush(&mut self, output: &mut OutBuffer)
+                      -> io::Result<Status> {
+        let remaining =
+            try!(self.run(&mut InBuffer::new(&[]), output, ll::ZSTD_e_flush));
+        Ok(if remaining == 0 { Status::Done } else { Status::NeedOutput })
+    }
+
+    /// Completes the frame.
+    ///
+    /// Call it until it returns `Done`, draining `output` in between.
+    /// Compressing more data afterwards starts a new frame.
+    pub fn poll_finish(&mut self, output: &mut OutBuffer)
+                       -> io::Result<Status> {
+        let remaining =
+            try!(self.run(&mut InBuffer::new(&[]), output, ll::ZSTD_e_end));
+        Ok(if remaining == 0 { Status::Done } else { Status::NeedOutput })
+    }
+
+    /// Resets the current frame, the parameters, or both.
+    ///
+    /// Resetting the session drops the frame in progress, so the next
+    /// call starts a new one; the level and dictionary are kept.
+    /// Resetting the parameters also drops the level (back to the
+    /// default) and the dictionary, and is only possible between frames.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        self.context.reset(directive)
+    }
+
+    fn run(&mut self, input: &mut InBuffer, output: &mut OutBuffer,
+           directive: ll::ZSTD_EndDirective)
+           -> io::Result<usize> {
+        let mut in_buffer = ll::ZSTD_inBuffer::new(input.src);
+        in_buffer.pos = input.pos;
+        let mut out_buffer = ll::ZSTD_outBuffer::new(output.dst);
+        out_buffer.pos = output.pos;
+
+        let code = unsafe {
+            ll::ZSTD_compressStream2(self.context.c,
+                                     &mut out_buffer,
+                                     &mut in_buffer,
+                                     directive)
+        };
+        input.pos = in_buffer.pos;
+        output.pos = out_buffer.pos;
+        ll::parse_code(code)
+    }
+}
+
+/// A resumable decoder.
+pub struct Decoder {
+    context: DecoderContext,
+}
+
+impl Decoder {
+    /// Creates a new decoder.
+    pub fn new() -> io::Result<Self> {
+        Ok(Decoder { context: try!(DecoderContext::new()) })
+    }
+
+    /// Creates a new decoder, using an existing dictionary.
+    ///
+    /// The dictionary must be the same as the one used during compression.
+    pub fn with_dictionary(dictionary: &[u8]) -> io::Result<Self> {
+        let decoder = try!(Decoder::new());
+        try!(decoder.context.load_dictionary(dictionary));
+        Ok(decoder)
+    }
+
+    /// Resets the current frame, the parameters, or both.
+    ///
+    /// Resetting the session drops the frame in progress, like after an
+    /// error, keeping the dictionary. Resetting the parameters also drops
+    /// the dictionary, and is only possible between frames.
+    pub fn reset(&mut self, directive: ResetDirective) -> io::Result<()> {
+        self.context.reset(directive)
+    }
+
+    /// Decompresses some input into `output`.
+    ///
+    /// Returns `Done` at the end of a frame: the input after it is left
+    /// unconsumed, and the next call starts a new frame.
+    /// Otherwise returns `NeedOutput` if `output` is full,
+    /// or `NeedInput` if zstd needs more input.
+    pub fn poll_decompress(&mut self, input: &mut InBuffer,
+                           output: &mut OutBuffer)
+                           -> io::Result<Status> {
+        let mut in_buffer = ll::ZSTD_inBuffer::new(input.src);
+        in_buffer.pos = input.pos;
+        let mut out_buffer = ll::ZSTD_outBuffer::new(output.dst);
+        out_buffer.pos = output.pos;
+
+        let code = unsafe {
+            ll::ZSTD_decompressStream(self.context.c,
+                                      &mut out_buffer,
+                                      &mut in_buffer)
+        };
+        input.pos = in_buffer.pos;
+        output.pos = out_buffer.pos;
+
+        if try!(ll::parse_code(code)) == 0 {
+            Ok(Status::Done)
+        } else if output.pos == output.dst.len() {
+            // zstd may have more output for us.
+            Ok(Status::NeedOutput)
+        } else {
+            Ok(Status::NeedInput)
+        }
+    }
+}
+
+#[test]
+fn test_raw() {
+    let text = vec![42u8; 10000];
+    let mut encoder = Encoder::new(1).unwrap();
+    let mut compressed = Vec::new();
+    let mut chunk = [0u8; 16];
+
+    let mut input = InBuffer::new(&text);
+    loop {
+        let mut output = OutBuffer::new(&mut chunk);
+        let status = encoder.poll_compress(&mut input, &mut output).unwrap();
+        compressed.extend_from_slice(output.as_slice());
+        if status == Status::NeedInput {
+            break;
+        }
+    }
+    loop {
+        let mut output = OutBuffer::new(&mut chunk);
+        let status = encoder.poll_finish(&mut output).unwrap();
+        compressed.extend_from_slice(output.as_slice());
+        if status == Status::Done {
+            break;
+        }
+    }
+
+    let mut decoder = Decoder::new().unwrap();
+    let mut decompressed = Vec::new();
+    // Feed the input one byte at a time, like slow readiness events.
+    let mut status = Status::NeedInput;
+    for i in 0..compressed.len() {
+        let mut input = InBuffer::new(&compressed[i..i + 1]);
+        while input.pos < 1 || status == Status::NeedOutput {
+            let mut output = OutBuffer::new(&mut chunk);
+            status = decoder.poll_decompress(&mut input, &mut output)
+                .unwrap();
+            decompressed.extend_from_slice(output.as_slice());
+        }
+    }
+    assert_eq!(status, Status::Done);
+    assert_eq!(decompressed, text);
+}
+
+#[test]
+fn test_reset() {
+    let mut encoder = Encoder::new(1).unwrap();
+    let mut buffer = vec![0u8; 1000];
+
+    // Abandon a first frame halfway.
+    let mut output = OutBuffer::new(&mut buffer);
+    encoder.poll_compress(&mut InBuffer::new(b"dropped"), &mut output)
+        .unwrap();
+    encoder.reset(ResetDirective::SessionOnly).unwrap();
+
+    let mut output = OutBuffer::new(&mut buffer);
+    encoder.poll_compress(&mut InBuffer::new(b"kept"), &mut output)
+        .unwrap();
+    assert_eq!(encoder.poll_finish(&mut output).unwrap(), Status::Done);
+    let len = output.pos;
+    let compressed = buffer[..len].to_vec();
+
+    // A decoder stuck on garbage can start over.
+    let mut decoder = Decoder::new().unwrap();
+    let mut output = OutBuffer::new(&mut buffer);
+    let mut garbage = InBuffer::new(b"not zstd");
+    assert!(decoder.poll_decompress(&mut garbage, &mut output).is_err());
+    decoder.reset(ResetDirective::SessionOnly).unwrap();
+
+    let mut input = InBuffer::new(&compressed);
+    let status = decoder.poll_decompress(&mut input, &mut output).unwrap();
+    assert_eq!(status, Status::Done);
+    assert_eq!(output.as_slice(), b"kept");
+}
@@ -0,0 +1,649 @@
+use std::{cmp, mem, usize};
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use ll;
+use stream::ChunkReader;
+use context::DecoderContext;
+use dict::DecoderDictionary;
+use params::DParameter;
+use stream::{FrameHeader, OutputLimitExceeded, SMALL_BUFFER_SIZE,
+             SMALL_WINDOW_LOG, TrailingData, missing_checksum_error,
+             trailing_data_error};
+use stream::frames::{self, HeaderRecorder, SkippableFrames};
+use stream::progression::Observer;
+use xxhash::Xxh64;
+
+// Size of the reads made before the frame header is known, or once the
+// output vector is full.
+const PROBE_SIZE: usize = 32;
+
+/// A decoder that decompress input data from another `Read`.
+///
+/// This allows to read a stream of compressed data
+/// (good for files or heavy network stream).
+///
+/// The decoder stops at the end of the frame; what happens to data
+/// following it is decided by [`set_trailing_data`].
+///
+/// [`set_trailing_data`]: #method.set_trailing_data
+pub struct Decoder<R: Read> {
+    // input reader (compressed data)
+    reader: R,
+    // input buffer
+    buffer: Vec<u8>,
+    // we already read everything in the buffer up to that point
+    offset: usize,
+    // decompression context
+    context: DecoderContext,
+    // compressed bytes actually fed to zstd so far
+    consumed: u64,
+    // compressed bytes read from the reader so far
+    fetched: u64,
+    // parses the frame header as it goes through
+    header: HeaderRecorder,
+    // reject frames without a checksum
+    require_checksum: bool,
+    // decompressed bytes produced so far, and how many are allowed
+    produced: u64,
+    output_limit: Option<u64>,
+    // notified as data goes through
+    observer: Option<Observer>,
+    // receives skippable frames, until the regular frame starts
+    skippable: Option<SkippableFrames>,
+    // whether we reached the end of the frame
+    finished: bool,
+    // hashes the decompressed data, if enabled
+    hasher: Option<Xxh64>,
+    // what to do with data after the frame
+    trailing_policy: TrailingData,
+    // data found after the frame, with `TrailingData::Return`
+    trailing: Vec<u8>,
+    // reader error held back while returning the data before it
+    pending_error: Option<io::Error>,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a new decoder.
+    pub fn new(reader: R) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        Decoder::with_context(reader, context)
+    }
+
+    /// Creates a new decoder, using an existing dictionary.
+    ///
+    /// The dictionary must be the same as the one used during compression.
+    pub fn with_dictionary(reader: R, dictionary: &[u8]) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        try!(context.load_dictionary(dictionary));
+
+        Decoder::with_context(reader, context)
+    }
+
+    /// Creates a new decoder, referencing a prepared dictionary.
+    ///
+    /// The dictionary is shared rather than copied, so many decoders can
+    /// use it at once, from any thread.
+    pub fn with_prepared_dictionary(reader: R,
+                                    dictionary: Arc<DecoderDictionary>)
+                                    -> io::Result<Self> {
+        let mut context = try!(DecoderContext::new());
+
+        try!(context.ref_dictionary(dictionary));
+
+        Decoder::with_context(reader, context)
+    }
+
+    /// Creates a new decoder using as little memory as possible.
+    ///
+    /// This only accepts frames compressed with the smallest window
+    /// (1KB), for instance by [`Encoder::small_window`]; other frames
+    /// result in an error. The decoder then needs
+    /// [`low_memory_requirement()`](#method.low_memory_requirement) bytes.
+    ///
+    /// [`Encoder::small_window`]: ../write/struct.Encoder.html#method.small_window
+    pub fn low_memory(reader: R) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+        let window_log_max = DParameter::WindowLogMax(SMALL_WINDOW_LOG);
+        try!(context.set_dparameter(window_log_max));
+
+        Decoder::with_buffer_size(reader, context, SMALL_BUFFER_SIZE)
+    }
+
+    /// Returns the memory used by a decoder created with `low_memory()`,
+    /// in bytes.
+    pub fn low_memory_requirement() -> usize {
+        let window_size = 1 << SMALL_WINDOW_LOG;
+        let context_size =
+            unsafe { ll::ZSTD_estimateDStreamSize(window_size) };
+        context_size + SMALL_BUFFER_SIZE
+    }
+
+    fn with_context(reader: R, context: DecoderContext) -> io::Result<Self> {
+        let buffer_size = unsafe { ll::ZSTD_DStreamInSize() };
+
+        Decoder::with_buffer_size(reader, context, buffer_size)
+    }
+
+    fn with_buffer_size(reader: R, context: DecoderContext,
+                        buffer_size: usize)
+                        -> io::Result<Self> {
+        Ok(Decoder {
+            reader: reader,
+            buffer: Vec::with_capacity(buffer_size),
+            offset: 0,
+            context: context,
+            consumed: 0,
+            fetched: 0,
+            header: HeaderRecorder::default(),
+            require_checksum: false,
+            produced: 0,
+            output_limit: None,
+            observer: None,
+            skippable: None,
+            finished: false,
+            hasher: None,
+            trailing_policy: TrailingData::Ignore,
+            trailing: Vec::new(),
+            pending_error: None,
+        })
+    }
+
+    /// Sets what to do with data following the end of the frame.
+    pub fn set_trailing_data(&mut self, policy: TrailingData) {
+        self.trailing_policy = policy;
+    }
+
+    /// Returns the data found after the end of the frame.
+    ///
+    /// This is only filled with `TrailingData::Return`,
+    /// once the end of the stream is reached (`read` returned 0).
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    /// Computes the content checksum of the decompressed data.
+    ///
+    /// Once enabled, [`content_checksum`](#method.content_checksum) returns
+    /// the checksum of the frame, whether or not the frame included one.
+    /// This must be enabled before any data is decompressed.
+    pub fn track_content_checksum(&mut self, track: bool) {
+        self.hasher = if track { Some(Xxh64::default()) } else { None };
+    }
+
+    /// Returns the content checksum of the frame.
+    ///
+    /// This is the lower 32 bits of the XXH64 hash of the decompressed
+    /// data: the same value zstd stores, and verifies, in frames with a
+    /// checksum. It is only available once the end of the frame is
+    /// reached, and when `track_content_checksum(true)` was called.
+    pub fn content_checksum(&self) -> Option<u32> {
+        match self.hasher {
+            Some(ref hasher) if self.finished => Some(hasher.digest() as u32),
+            _ => None,
+        }
+    }
+
+    /// Rejects frames without a content checksum.
+    ///
+    /// When enabled, decompression fails as soon as the frame header shows
+    /// the frame carries no checksum, before any data is returned. Frames
+    /// with a checksum are always verified.
+    pub fn require_checksum(&mut self, require: bool) {
+        self.require_checksum = require;
+    }
+
+    /// Fails once more than `limit` bytes would be decompressed.
+    ///
+    /// This guards against decompression bombs: the error is an
+    /// [`OutputLimitExceeded`](../struct.OutputLimitExceeded.html), and
+    /// the data beyond the limit is never handed over. Unlike `take()`,
+    /// this can't silently truncate the output.
+    pub fn with_output_limit(mut self, limit: u64) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Sets a single decompression parameter.
+    pub fn set_parameter(&mut self, parameter: DParameter) -> io::Result<()> {
+        self.context.set_dparameter(parameter)
+    }
+
+    /// Calls the given callback each time data flows through the decoder.
+    ///
+    /// The callback receives the number of compressed bytes consumed
+    /// and the number of decompressed bytes produced since the last call.
+    pub fn on_progress<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(usize, usize)
+    {
+        self.observer = Some(Box::new(f));
+    }
+
+    /// Calls the given callback for each skippable frame before the frame.
+    ///
+    /// Skippable frames hold metadata zstd ignores, like seek tables or
+    /// manifests. The callback receives the magic number variant (0 to
+    /// 15) and the content of each of them, instead of discarding them.
+    /// This must be set before any data is decompressed.
+    pub fn on_skippable_frame<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(u32, &[u8])
+    {
+        self.skippable = Some(SkippableFrames::new(Box::new(f)));
+    }
+
+    /// Returns the number of compressed bytes consumed so far.
+    ///
+    /// The decoder reads ahead from the inner reader, but this only counts
+    /// bytes that were actually part of the frame. Once the end of the
+    /// frame is reached, this is the exact size of the frame, which tells
+    /// where the following data begins in the original stream.
+    pub fn compressed_bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Returns the number of compressed bytes read from the inner reader
+    /// so far.
+    ///
+    /// This includes data read ahead, not decompressed yet: it's where a
+    /// new reader should resume, with
+    /// [`replace_reader`](#method.replace_reader).
+    pub fn compressed_bytes_read(&self) -> u64 {
+        self.fetched
+    }
+
+    /// Replaces the inner reader, keeping the decompression state.
+    ///
+    /// When the source fails in the middle of a frame, for instance a
+    /// dropped connection, reconnect at
+    /// [`compressed_bytes_read()`](#method.compressed_bytes_read) and give
+    /// the new reader here: decompression continues the same frame.
+    /// Data already decompressed when the source failed was returned
+    /// before the error.
+    ///
+    /// Returns the previous reader. An error it returned, and which
+    /// wasn't reported yet, is dropped.
+    pub fn replace_reader(&mut self, reader: R) -> R {
+        self.pending_error = None;
+        mem::replace(&mut self.reader, reader)
+    }
+
+    /// Returns the header of the frame being decompressed.
+    ///
+    /// This is `None` until the decoder has seen the complete header,
+    /// which takes a few bytes of compressed data. It tells the content
+    /// size (when recorded), the window size, the dictionary ID and
+    /// whether the frame has a checksum: enough to size the output, or to
+    /// check the frame expects the right dictionary.
+    pub fn frame_info(&self) -> Option<FrameHeader> {
+        self.header.header()
+    }
+
+    /// Recommendation for the size of the output buffer.
+    pub fn recommended_output_size() -> usize {
+        unsafe { ll::ZSTD_DStreamOutSize() }
+    }
+
+    /// Return the inner `Read`.
+    ///
+    /// Calling `finish()` is not *required* after reading a stream -
+    /// just use it if you need to get the `Read` back.
+    pub fn finish(self) -> R {
+        self.reader
+    }
+
+    // Hands the skippable frames at the start of the buffer to the callback.
+    //
+    // Returns the number of bytes consumed.
+    fn consume_skippable(&mut self) -> io::Result<usize> {
+        let (consumed, prefix) = match self.skippable {
+            Some(ref mut skippable) => {
+                let consumed = skippable.consume(&self.buffer[self.offset..]);
+                (consumed, skippable.regular_prefix().map(|p| p.to_vec()))
+            }
+            None => return Ok(0),
+        };
+        self.offset += consumed;
+        self.consumed += consumed as u64;
+        if let Some(prefix) = prefix {
+            self.skippable = None;
+            try!(frames::feed_prefix(&self.context, &prefix));
+            self.header.record(&prefix);
+        }
+        Ok(consumed)
+    }
+
+    // Counts produced bytes, failing if the limit is exceeded.
+    fn check_output(&mut self, produced: usize) -> io::Result<()> {
+        self.produced += produced as u64;
+        match self.output_limit {
+            Some(limit) if self.produced > limit => {
+                Err(OutputLimitExceeded { limit: limit }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if the frame header is known, and not acceptable.
+    fn check_header(&self) -> io::Result<()> {
+        match self.header.header() {
+            Some(header) if self.require_checksum && !header.has_checksum => {
+                Err(missing_checksum_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Tell the observer, if any, about the data that just went through.
+    fn notify(&mut self, consumed: usize, produced: usize) {
+        if consumed == 0 && produced == 0 {
+            return;
+        }
+        if let Some(ref mut observer) = self.observer {
+            observer(consumed, produced);
+        }
+    }
+
+    // Reserves room in `buf` for the rest of the frame, once the header
+    // tells its size.
+    //
+    // Returns whether the header was known.
+    fn reserve_content(&self, buf: &mut Vec<u8>, produced: u64) -> bool {
+        let header = match self.header.header() {
+            Some(header) => header,
+            None => return false,
+        };
+        if let Some(size) = header.content_size {
+            let size = cmp::min(size, self.output_limit.unwrap_or(size));
+            let left = cmp::min(size.saturating_sub(produced),
+                                usize::MAX as u64);
+            // The size comes from the input: if it's absurd, just grow
+            // the vector as usual.
+            let _ = buf.try_reserve_exact(left as usize);
+        }
+        true
+    }
+
+    // Handles whatever follows the frame, according to the policy.
+    fn read_trailing(&mut self) -> io::Result<usize> {
+        match self.trailing_policy {
+            TrailingData::Ignore => (),
+            TrailingData::Error => {
+                let mut probe = [0u8];
+                if self.offset < self.buffer.len() ||
+                   try!(self.reader.read(&mut probe)) > 0 {
+                    return Err(trailing_data_error());
+                }
+            }
+            TrailingData::Return => {
+                self.trailing.extend_from_slice(&self.buffer[self.offset..]);
+                self.offset = self.buffer.len();
+                try!(self.reader.read_to_end(&mut self.trailing));
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl<I> Decoder<ChunkReader<I>>
+    where I: Iterator<Item = io::Result<Vec<u8>>>
+{
+    /// Creates a new decoder, reading compressed data from a sequence of
+    /// chunks.
+    ///
+    /// See [`ChunkReader`](../struct.ChunkReader.html).
+    pub fn from_chunks(chunks: I) -> io::Result<Self> {
+        Decoder::new(ChunkReader::new(chunks))
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+
+        if self.finished {
+            return self.read_trailing();
+        }
+
+        let mut written = 0;
+        let mut eof = false;
+        while written != buf.len() {
+
+            if self.offset == self.buffer.len() {
+                // We need moar data!
+                // Make a nice clean buffer
+                let buffer_size = self.buffer.capacity();
+                unsafe {
+                    self.buffer.set_len(buffer_size);
+                }
+
+                // And FILL IT!
+                self.offset = 0;
+                let result = self.reader.read(&mut self.buffer);
+                let read = *result.as_ref().unwrap_or(&0);
+                unsafe {
+                    self.buffer.set_len(read);
+                }
+                match result {
+                    Ok(_) => (),
+                    // Hand over what was decompressed already; the error
+                    // is returned by the next call.
+                    Err(e) if written > 0 => {
+                        self.pending_error = Some(e);
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+                self.fetched += read as u64;
+                eof = read == 0;
+            }
+
+            if self.skippable.is_some() {
+                try!(self.consume_skippable());
+                if self.offset == self.buffer.len() && !eof {
+                    continue;
+                }
+            }
+
+            let mut output = ll::ZSTD_outBuffer::new(&mut buf[written..]);
+            let mut input =
+                ll::ZSTD_inBuffer::new(&self.buffer[self.offset..]);
+
+            let res = unsafe {
+                let code = ll::ZSTD_decompressStream(self.context.c,
+                                                     &mut output,
+                                                     &mut input);
+                try!(ll::parse_code(code))
+            };
+
+            if let Some(ref mut hasher) = self.hasher {
+                hasher.update(&buf[written..written + output.pos]);
+            }
+            written += output.pos;
+            self.header
+                .record(&self.buffer[self.offset..self.offset + input.pos]);
+            try!(self.check_header());
+            try!(self.check_output(output.pos));
+            self.offset += input.pos;
+            self.consumed += input.pos as u64;
+            self.notify(input.pos, output.pos);
+            if res == 0 {
+                // End-of-frame marker.
+                self.finished = true;
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Reads the whole frame, allocating the output only once when the
+    /// frame header declares the content size.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        let mut reserved = false;
+        let mut probe = [0u8; PROBE_SIZE];
+        loop {
+            if !reserved {
+                reserved = self.reserve_content(buf,
+                                                (buf.len() - start) as u64);
+            }
+            let result = if buf.len() == buf.capacity() {
+                // An exact reservation leaves no room to see the end:
+                // check for more data before growing the vector.
+                let result = self.read(&mut probe);
+                if let Ok(n) = result {
+                    buf.extend_from_slice(&probe[..n]);
+                }
+                result
+            } else {
+                let len = buf.len();
+                let capacity = buf.capacity();
+                buf.resize(capacity, 0);
+                let result = self.read(&mut buf[len..]);
+                buf.truncate(len + *result.as_ref().unwrap_or(&0));
+                result
+            };
+            match result {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buf.len() - start)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_read_small() {
+        use std::io::{self, Read};
+        use super::Decoder;
+
+        const EXPECTED_DECODED_LENGTH: usize = 18;
+        let mut output = [0u8; EXPECTED_DECODED_LENGTH];
+        let input = &[
+            0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x68, 0x7d, 0x00,
+            0x00, 0x48, 0x05, 0x54, 0x45, 0x53, 0x54, 0x30,
+            0x00, 0x10, 0x00, 0x01, 0x00, 0x05, 0x38, 0x02,
+        ];
+
+        let r = io::Cursor::new(input);
+        let mut r = Decoder::new(r).unwrap();
+
+        assert!(r.read(&mut output[..1]).unwrap() == 1);
+        assert!(r.read(&mut output[1..]).unwrap() > 0);
+    }
+
+    #[test]
+    fn test_replace_reader() {
+        use std::io::{self, Read};
+        use super::Decoder;
+
+        // Fails after `limit` bytes, like a dropped connection.
+        struct Flaky<'a> {
+            data: &'a [u8],
+            limit: usize,
+        }
+
+        impl<'a> Read for Flaky<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.limit == 0 {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionReset,
+                                              "connection dropped"));
+                }
+                let len = ::std::cmp::min(buf.len(), self.limit);
+                let read = try!(self.data.read(&mut buf[..len]));
+                self.limit -= read;
+                Ok(read)
+            }
+        }
+
+        let data: Vec<u8> = (0..200000u32).map(|i| (i % 251) as u8).collect();
+        let compressed = ::encode_all(&data[..], 1).unwrap();
+        let source = Flaky {
+            data: &compressed,
+            limit: compressed.len() / 2,
+        };
+        let mut decoder = Decoder::new(source).unwrap();
+        let mut output = Vec::new();
+        assert!(decoder.read_to_end(&mut output).is_err());
+        assert!(output.len() < data.len());
+
+        // Reconnect where the previous source stopped.
+        let resume = decoder.compressed_bytes_read() as usize;
+        assert_eq!(resume, compressed.len() / 2);
+        decoder.replace_reader(Flaky {
+            data: &compressed[resume..],
+            limit: usize::max_value(),
+        });
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_reader_error_after_data() {
+        use std::io::{self, Read};
+        use super::Decoder;
+
+        // Fails once, on the second call.
+        struct Flaky<'a> {
+            data: &'a [u8],
+            calls: usize,
+        }
+
+        impl<'a> Read for Flaky<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.calls += 1;
+                match self.calls {
+                    1 => {
+                        let len = self.data.len() - 10;
+                        self.data.read(&mut buf[..len])
+                    }
+                    2 => Err(io::Error::new(io::ErrorKind::TimedOut,
+                                            "timed out")),
+                    _ => self.data.read(buf),
+                }
+            }
+        }
+
+        let data: Vec<u8> = (0..200000u32).map(|i| (i % 251) as u8).collect();
+        let compressed = ::encode_all(&data[..], 1).unwrap();
+        let source = Flaky {
+            data: &compressed,
+            calls: 0,
+        };
+        let mut decoder = Decoder::new(source).unwrap();
+        let mut output = vec![0u8; data.len() + 1];
+        let read = decoder.read(&mut output).unwrap();
+        assert!(read > 0);
+        // The error isn't lost.
+        assert_eq!(decoder.read(&mut output[read..]).unwrap_err().kind(),
+                   io::ErrorKind::TimedOut);
+        let mut rest = Vec::new();
+        decoder.read_to_end(&mut rest).unwrap();
+        output.truncate(read);
+        output.extend_from_slice(&rest);
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_read_to_end_reserves_content_size() {
+        use std::io::Read;
+        use super::Decoder;
+
+        let data: Vec<u8> = (0..100000u32).map(|i| (i % 91) as u8).collect();
+        // Bulk compression records the content size in the header.
+        let compressed = ::bulk::compress(&data, 1).unwrap();
+
+        let mut output = Vec::new();
+        let mut decoder = Decoder::new(&compressed[..]).unwrap();
+        assert_eq!(decoder.read_to_end(&mut output).unwrap(), data.len());
+        assert_eq!(output, data);
+        assert_eq!(output.capacity(), data.len());
+    }
+}
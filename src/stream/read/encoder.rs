@@ -0,0 +1,365 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use ll;
+#[cfg(feature = "experimental")]
+use bulk::SequenceProducer;
+use context::EncoderContext;
+use dict::EncoderDictionary;
+use params::{CParameter, CompressionParams, LiteralCompressionMode,
+             ZstdVersion};
+use stream::FrameProgression;
+use stream::progression::Observer;
+
+/// An encoder that compress input data from another `Read`.
+///
+/// Reading from this encoder yields the compressed version
+/// of the data read from the inner reader.
+pub struct Encoder<R: Read> {
+    // input reader (uncompressed data)
+    reader: R,
+    // input buffer
+    buffer: Vec<u8>,
+    // we already read everything in the buffer up to that point
+    offset: usize,
+    // compression context
+    context: EncoderContext,
+    // where we are in the stream
+    state: EncoderState,
+    // notified as data goes through
+    observer: Option<Observer>,
+}
+
+#[derive(PartialEq)]
+enum EncoderState {
+    // Still reading from the inner reader
+    Reading,
+    // The inner reader is exhausted, the epilogue still needs to be written
+    Ending,
+    // Everything was written
+    Finished,
+}
+
+impl<R: Read> Encoder<R> {
+    /// Creates a new encoder.
+    ///
+    /// `level`: compression level (1-21)
+    pub fn new(reader: R, level: i32) -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        // Initialize the stream
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+
+        Encoder::with_context(reader, context)
+    }
+
+    /// Creates a new encoder, using an existing dictionary.
+    ///
+    /// (Provides better compression ratio for small files,
+    /// but requires the dictionary to be present during decompression.)
+    pub fn with_dictionary(reader: R, level: i32, dictionary: &[u8])
+                           -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        // Initialize the stream with an existing dictionary
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        try!(context.load_dictionary(dictionary));
+
+        Encoder::with_context(reader, context)
+    }
+
+    /// Creates a new encoder, referencing a prepared dictionary.
+    ///
+    /// The dictionary is shared rather than copied, so many encoders can
+    /// use it at once, from any thread. Data is compressed at the level
+    /// the dictionary was prepared for.
+    pub fn with_prepared_dictionary(reader: R,
+                                    dictionary: Arc<EncoderDictionary>)
+                                    -> io::Result<Self> {
+        let mut context = try!(EncoderContext::new());
+
+        try!(context.ref_dictionary(dictionary));
+
+        Encoder::with_context(reader, context)
+    }
+
+    fn with_context(reader: R, context: EncoderContext) -> io::Result<Self> {
+        // This is the input buffer size,
+        // for uncompressed data we read from the reader.
+        let buffer_size = unsafe { ll::ZSTD_CStreamInSize() };
+
+        Ok(Encoder {
+            reader: reader,
+            buffer: Vec::with_capacity(buffer_size),
+            offset: 0,
+            context: context,
+            state: EncoderState::Reading,
+            observer: None,
+        })
+    }
+
+    /// Calls the given callback each time data flows through the encoder.
+    ///
+    /// The callback receives the number of uncompressed bytes consumed
+    /// and the number of compressed bytes produced since the last call.
+    pub fn on_progress<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(usize, usize)
+    {
+        self.observer = Some(Box::new(f));
+    }
+
+    /// Guarantees a byte-identical output for identical input.
+    ///
+    /// With this mode, compressing the same data with the same level,
+    /// parameters and dictionary always produces the same output,
+    /// with the same version of the zstd library.
+    ///
+    /// This disables multithreading (whose output depends on the number of
+    /// workers), and makes options depending on timing or on the
+    /// environment return an error.
+    pub fn set_deterministic(&mut self) -> io::Result<()> {
+        self.context.set_deterministic()
+    }
+
+    /// Makes sure the frames stay readable by older or constrained
+    /// decoders.
+    ///
+    /// Settings those decoders couldn't handle then return an error when
+    /// configured, rather than producing frames that fail to decode
+    /// later: windows larger than
+    /// [`DEFAULT_WINDOW_LOG_LIMIT`](../../params/constant.DEFAULT_WINDOW_LOG_LIMIT.html),
+    /// which decoders refuse by default, and raw parameters. Versions
+    /// older than v0.8 can't read the current frame format at all, and
+    /// are refused.
+    pub fn max_format_compatibility(&mut self, version: ZstdVersion)
+                                    -> io::Result<()> {
+        self.context.set_max_format_compatibility(version)
+    }
+
+    /// Uses an external match-finder, like a hardware accelerator.
+    ///
+    /// The producer finds the sequences of each block, and zstd does the
+    /// entropy coding. With `fallback`, blocks the producer can't handle
+    /// are compressed by zstd alone; otherwise, compression fails.
+    ///
+    /// Not compatible with multithreading, nor with long distance
+    /// matching. See
+    /// [`SequenceProducer`](../../bulk/trait.SequenceProducer.html).
+    #[cfg(feature = "experimental")]
+    pub fn set_sequence_producer<P>(&mut self, producer: P, fallback: bool)
+                                    -> io::Result<()>
+        where P: SequenceProducer + 'static
+    {
+        self.context.set_sequence_producer(Box::new(producer), fallback)
+    }
+
+    /// Gives a hint about the expected size of the input.
+    ///
+    /// zstd will pick parameters tuned for this size, which helps when
+    /// compressing small inputs whose exact size isn't known in advance.
+    /// Unlike a pledged size, the actual input may differ from the hint.
+    /// Hints above `i32::MAX` return an `InvalidInput` error.
+    pub fn set_source_size_hint(&mut self, size: usize) -> io::Result<()> {
+        self.context.set_source_size_hint(size)
+    }
+
+    /// Sets a single compression parameter.
+    ///
+    /// This gives access to any parameter supported by zstd,
+    /// including those without a dedicated method.
+    pub fn set_parameter(&mut self, parameter: CParameter) -> io::Result<()> {
+        self.context.set_cparameter(parameter)
+    }
+
+    /// Sets all the compression parameters at once.
+    ///
+    /// These take precedence over the compression level.
+    /// See [`CompressionParams`](../../params/struct.CompressionParams.html).
+    pub fn set_compression_params(&mut self, params: &CompressionParams)
+                                  -> io::Result<()> {
+        self.context.set_compression_params(params)
+    }
+
+    /// Sets how literals are compressed.
+    ///
+    /// By default, zstd decides depending on the compression level.
+    pub fn set_literal_compression_mode(&mut self,
+                                        mode: LiteralCompressionMode)
+                                        -> io::Result<()> {
+        self.context.set_literal_compression_mode(mode)
+    }
+
+    /// Sets whether frame headers record the content size, when known.
+    ///
+    /// This is on by default. Turning it off saves up to 8 bytes per
+    /// frame, but decoders can no longer size their output up front.
+    pub fn set_content_size_flag(&mut self, flag: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_contentSizeFlag, flag as i32)
+    }
+
+    /// Sets whether frame headers record the dictionary ID.
+    ///
+    /// This is on by default. Turning it off hides which dictionary was
+    /// used, and saves up to 4 bytes per frame; decoders then can't check
+    /// they were given the right dictionary.
+    pub fn set_dict_id_flag(&mut self, flag: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_dictIDFlag, flag as i32)
+    }
+
+    /// Enables multithreaded compression, using `n_workers` threads.
+    ///
+    /// `0` disables multithreading (the default).
+    /// This must be called before any data is compressed.
+    #[cfg(feature = "zstdmt")]
+    pub fn multithread(&mut self, n_workers: u32) -> io::Result<()> {
+        if n_workers > 0 {
+            try!(self.context.check_not_deterministic("multithreading"));
+        }
+        self.context.set_parameter(ll::ZSTD_c_nbWorkers, n_workers as i32)
+    }
+
+    /// Enables multithreading with a number of workers suited to the input.
+    ///
+    /// `expected_src_size` is the size of the data to compress, or 0 if
+    /// unknown. See [`choose_workers`](../../params/fn.choose_workers.html).
+    /// In deterministic mode, this keeps compression single-threaded.
+    ///
+    /// Returns the number of workers chosen.
+    #[cfg(feature = "zstdmt")]
+    pub fn auto_workers(&mut self, expected_src_size: u64) -> io::Result<u32> {
+        let n_workers = if self.context.deterministic {
+            0
+        } else {
+            ::params::choose_workers(expected_src_size)
+        };
+        try!(self.multithread(n_workers));
+        Ok(n_workers)
+    }
+
+    /// Enables rsyncable mode.
+    ///
+    /// The compressed output will contain synchronization points,
+    /// so that changes in the input only affect nearby compressed data,
+    /// making tools like `rsync` effective on the compressed files.
+    /// This slightly reduces the compression ratio.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_rsyncable(&mut self, rsyncable: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_rsyncable, rsyncable as i32)
+    }
+
+    /// Sets the size of each compression job, in bytes.
+    ///
+    /// Each worker compresses one job at a time: larger jobs improve the
+    /// compression ratio, smaller jobs improve parallelism.
+    /// `0` lets zstd pick a size depending on the compression parameters.
+    /// Sizes above 1GB (512MB on 32-bit platforms) return an
+    /// `InvalidInput` error.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_job_size(&mut self, size: u32) -> io::Result<()> {
+        self.context.set_cparameter(CParameter::JobSize(size))
+    }
+
+    /// Sets how much data from the previous job is reloaded by each job,
+    /// as a fraction of the window size.
+    ///
+    /// From `1` (no overlap) to `9` (the whole window); `0` is the default.
+    /// More overlap improves the compression ratio, but slows down
+    /// compression.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_overlap_log(&mut self, overlap_log: u32) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_overlapLog, overlap_log as i32)
+    }
+
+    /// Returns the progress of the current frame.
+    pub fn progress(&self) -> FrameProgression {
+        self.context.progression()
+    }
+
+    /// Return the inner `Read`.
+    ///
+    /// If the stream was not read until the end,
+    /// the compressed output will be incomplete.
+    pub fn finish(self) -> R {
+        self.reader
+    }
+
+    // Whether the whole frame was produced.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.state == EncoderState::Finished
+    }
+
+    // Tell the observer, if any, about the data that just went through.
+    fn notify(&mut self, consumed: usize, produced: usize) {
+        if consumed == 0 && produced == 0 {
+            return;
+        }
+        if let Some(ref mut observer) = self.observer {
+            observer(consumed, produced);
+        }
+    }
+}
+
+impl<R: Read> Read for Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written != buf.len() {
+            if self.state == EncoderState::Finished {
+                break;
+            }
+
+            if self.state == EncoderState::Reading &&
+               self.offset == self.buffer.len() {
+                // We need moar data!
+                let buffer_size = self.buffer.capacity();
+                unsafe {
+                    self.buffer.set_len(buffer_size);
+                }
+
+                self.offset = 0;
+                let result = self.reader.read(&mut self.buffer);
+                // Don't leave bytes the reader didn't fill in the buffer.
+                let read = *result.as_ref().unwrap_or(&0);
+                unsafe {
+                    self.buffer.set_len(read);
+                }
+                try!(result);
+
+                if read == 0 {
+                    self.state = EncoderState::Ending;
+                }
+            }
+
+            let mut output = ll::ZSTD_outBuffer::new(&mut buf[written..]);
+            let mut input =
+                ll::ZSTD_inBuffer::new(&self.buffer[self.offset..]);
+            let directive = match self.state {
+                EncoderState::Reading => ll::ZSTD_e_continue,
+                _ => ll::ZSTD_e_end,
+            };
+
+            let remaining = try!(ll::parse_code(unsafe {
+                ll::ZSTD_compressStream2(self.context.c,
+                                         &mut output,
+                                         &mut input,
+                                         directive)
+            }));
+            self.offset += input.pos;
+
+            if self.state == EncoderState::Ending && remaining == 0 {
+                self.state = EncoderState::Finished;
+            }
+            written += output.pos;
+            self.notify(input.pos, output.pos);
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,7 @@
+//! Implement pull-based `Read` trait for both compressing and decompressing.
+
+mod encoder;
+mod decoder;
+
+pub use self::decoder::Decoder;
+pub use self::encoder::Encoder;
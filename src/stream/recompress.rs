@@ -0,0 +1,88 @@
+use std::io::{self, Read, Write};
+
+use bulk::Compressor;
+use params::CParameter;
+use stream::FrameIter;
+use stream::frames::SKIPPABLE_MAGIC_START;
+
+/// Recompresses a zstd stream with a new level and dictionary.
+///
+/// Each frame is decompressed and compressed again on its own, so frame
+/// boundaries are kept, along with the content checksums. Skippable
+/// frames are copied unchanged. Legacy frames are upgraded to the
+/// current format.
+///
+/// Each frame is entirely held in memory. Returns the writer.
+pub fn recompress<R: Read, W: Write>(reader: R, writer: W, level: i32,
+                                     dictionary: Option<&[u8]>)
+                                     -> io::Result<W> {
+    recompress_frames(FrameIter::new(reader), writer, level, dictionary)
+}
+
+/// Recompresses a zstd stream compressed with `old_dictionary`.
+///
+/// See [`recompress`](fn.recompress.html).
+pub fn recompress_with_dictionary<R: Read, W: Write>
+    (reader: R, writer: W, old_dictionary: &[u8], level: i32,
+     dictionary: Option<&[u8]>)
+     -> io::Result<W> {
+    let frames = try!(FrameIter::with_dictionary(reader, old_dictionary));
+    recompress_frames(frames, writer, level, dictionary)
+}
+
+fn recompress_frames<R: Read, W: Write>(frames: FrameIter<R>,
+                                        mut writer: W, level: i32,
+                                        dictionary: Option<&[u8]>)
+                                        -> io::Result<W> {
+    let mut compressor = Compressor::with_dict(dictionary.unwrap_or(&[]));
+
+    for frame in frames {
+        let frame = try!(frame);
+        let header = frame.header;
+
+        if header.skippable {
+            let magic = SKIPPABLE_MAGIC_START + header.dict_id;
+            try!(write_u32_le(&mut writer, magic));
+            try!(write_u32_le(&mut writer, frame.data.len() as u32));
+            try!(writer.write_all(&frame.data));
+            continue;
+        }
+
+        let checksum = CParameter::ChecksumFlag(header.has_checksum);
+        try!(compressor.set_parameter(checksum));
+        let compressed = try!(compressor.compress(&frame.data, level));
+        try!(writer.write_all(&compressed));
+    }
+
+    Ok(writer)
+}
+
+fn write_u32_le<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    let bytes = [value as u8,
+                 (value >> 8) as u8,
+                 (value >> 16) as u8,
+                 (value >> 24) as u8];
+    writer.write_all(&bytes)
+}
+
+#[test]
+fn test_recompress() {
+    use stream::Frame;
+
+    let mut input = ::encode_all(b"first frame", 1).unwrap();
+    // A skippable frame with 3 bytes of content.
+    input.extend_from_slice(&[0x53, 0x2A, 0x4D, 0x18, 3, 0, 0, 0]);
+    input.extend_from_slice(b"abc");
+    input.extend_from_slice(&::encode_all(b"second frame", 1).unwrap());
+
+    let output = recompress(&input[..], Vec::new(), 19, None).unwrap();
+
+    let frames: Vec<Frame> =
+        FrameIter::new(&output[..]).map(|frame| frame.unwrap()).collect();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(&frames[0].data, b"first frame");
+    assert!(frames[1].header.skippable);
+    assert_eq!(frames[1].header.dict_id, 3);
+    assert_eq!(&frames[1].data, b"abc");
+    assert_eq!(&frames[2].data, b"second frame");
+}
@@ -0,0 +1,211 @@
+use std::io::{self, Read, Write};
+
+use context::DecoderContext;
+use ll;
+use params::ResetDirective;
+use stream::frames::SKIPPABLE_MAGIC_START;
+
+// Magic number starting every (non-legacy) zstd frame.
+const FRAME_MAGIC: u32 = 0xFD2FB528;
+// Magic number and size preceding the content of a skippable frame.
+const SKIPPABLE_HEADER_SIZE: usize = 8;
+
+/// A corrupt part of a stream, found by [`salvage`](fn.salvage.html).
+#[derive(Debug)]
+pub struct Corruption {
+    /// Offset of the corrupt frame in the input.
+    pub frame_offset: u64,
+    /// Offset in the input where decoding stopped.
+    ///
+    /// zstd buffers some input, so the corruption may be a little before.
+    pub offset: u64,
+    /// The error returned by the decoder.
+    pub error: io::Error,
+    /// Offset of the next frame found after the corruption, where
+    /// recovery resumed, or `None` if there was none.
+    pub resumed_at: Option<u64>,
+}
+
+/// What [`salvage`](fn.salvage.html) could recover.
+#[derive(Debug)]
+pub struct SalvageReport {
+    /// Number of bytes of decompressed data written.
+    pub recovered: u64,
+    /// Number of frames decoded without error.
+    pub frames: usize,
+    /// Corrupt parts of the input, in order.
+    pub corruptions: Vec<Corruption>,
+}
+
+impl SalvageReport {
+    /// Returns `true` if the input had no corruption.
+    pub fn is_ok(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+/// Decompresses as much as possible of a corrupt stream.
+///
+/// Decompressed data is written to `writer` as it comes, including the
+/// part of a corrupt frame decoded before the error. After a corruption,
+/// the input is searched for the next frame magic number, and decoding
+/// resumes from there. Data found this way could still be garbage: this
+/// is a best effort.
+///
+/// The whole input is held in memory, to look for frames after a
+/// corruption. Only errors from the reader or the writer are returned;
+/// corruptions are listed in the report.
+pub fn salvage<R: Read, W: Write>(reader: R, writer: W)
+                                  -> io::Result<SalvageReport> {
+    salvage_frames(try!(DecoderContext::new()), reader, writer)
+}
+
+/// Salvages a stream compressed with a dictionary.
+///
+/// See [`salvage`](fn.salvage.html).
+pub fn salvage_with_dictionary<R: Read, W: Write>
+    (reader: R, writer: W, dictionary: &[u8])
+     -> io::Result<SalvageReport> {
+    let context = try!(DecoderContext::new());
+    try!(context.load_dictionary(dictionary));
+    salvage_frames(context, reader, writer)
+}
+
+fn salvage_frames<R: Read, W: Write>(mut context: DecoderContext,
+                                     mut reader: R, mut writer: W)
+                                     -> io::Result<SalvageReport> {
+    let mut input = Vec::new();
+    try!(reader.read_to_end(&mut input));
+
+    let mut report = SalvageReport {
+        recovered: 0,
+        frames: 0,
+        corruptions: Vec::new(),
+    };
+    let mut output = vec![0u8; unsafe { ll::ZSTD_DStreamOutSize() }];
+    let mut position = 0;
+    while position < input.len() {
+        let result = try!(decode_frame(&context,
+                                       &input[position..],
+                                       &mut output,
+                                       &mut writer,
+                                       &mut report.recovered));
+        match result {
+            Ok(size) => {
+                report.frames += 1;
+                position += size;
+            }
+            Err((consumed, error)) => {
+                try!(context.reset(ResetDirective::SessionOnly));
+                let next = find_frame(&input, position + 1);
+                report.corruptions.push(Corruption {
+                    frame_offset: position as u64,
+                    offset: (position + consumed) as u64,
+                    error: error,
+                    resumed_at: next.map(|next| next as u64),
+                });
+                position = next.unwrap_or(input.len());
+            }
+        }
+    }
+    try!(writer.flush());
+    Ok(report)
+}
+
+// Decodes the frame at the start of `input`, writing its content.
+//
+// Returns the size of the frame, or how much was consumed before the
+// corruption, and the corruption. Only I/O errors from the writer are
+// returned as errors.
+fn decode_frame<W: Write>(context: &DecoderContext, input: &[u8],
+                          output: &mut [u8], writer: &mut W,
+                          recovered: &mut u64)
+                          -> io::Result<Result<usize, (usize, io::Error)>> {
+    if input.len() >= SKIPPABLE_HEADER_SIZE &&
+       read_u32_le(input) & 0xFFFFFFF0 == SKIPPABLE_MAGIC_START {
+        let size = SKIPPABLE_HEADER_SIZE + read_u32_le(&input[4..]) as usize;
+        if size > input.len() {
+            return Ok(Err((0, incomplete_frame())));
+        }
+        return Ok(Ok(size));
+    }
+
+    let mut consumed = 0;
+    loop {
+        let mut in_buffer = ll::ZSTD_inBuffer::new(&input[consumed..]);
+        let mut out_buffer = ll::ZSTD_outBuffer::new(output);
+        let code = unsafe {
+            ll::ZSTD_decompressStream(context.c,
+                                      &mut out_buffer,
+                                      &mut in_buffer)
+        };
+        let res = match ll::parse_code(code) {
+            Ok(res) => res,
+            Err(e) => return Ok(Err((consumed, e))),
+        };
+        let written = out_buffer.pos;
+        try!(writer.write_all(&output[..written]));
+        *recovered += written as u64;
+        consumed += in_buffer.pos;
+
+        if res == 0 {
+            // End-of-frame marker.
+            return Ok(Ok(consumed));
+        }
+        // zstd may have more output for us even without new input.
+        if consumed == input.len() && written < output.len() {
+            return Ok(Err((consumed, incomplete_frame())));
+        }
+    }
+}
+
+// Returns the offset of the next frame magic number, from `start`.
+fn find_frame(input: &[u8], start: usize) -> Option<usize> {
+    if start >= input.len() {
+        return None;
+    }
+    input[start..]
+        .windows(4)
+        .position(|bytes| {
+            let magic = read_u32_le(bytes);
+            magic == FRAME_MAGIC ||
+            magic & 0xFFFFFFF0 == SKIPPABLE_MAGIC_START
+        })
+        .map(|i| start + i)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+fn incomplete_frame() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete frame")
+}
+
+#[test]
+fn test_salvage() {
+    let first = ::encode_all(&b"first"[..], 1).unwrap();
+    let third = ::encode_all(&b"third"[..], 1).unwrap();
+    // A frame header, followed by a block of the reserved type.
+    let mut input = first.clone();
+    input.extend_from_slice(&third[..6]);
+    input.extend_from_slice(&[0x07, 0, 0]);
+    input.extend_from_slice(&third);
+
+    let mut output = Vec::new();
+    let report = salvage(&input[..], &mut output).unwrap();
+    assert_eq!(output, b"firstthird");
+    assert_eq!(report.frames, 2);
+    assert_eq!(report.recovered, 10);
+    assert_eq!(report.corruptions.len(), 1);
+    let corruption = &report.corruptions[0];
+    assert_eq!(corruption.frame_offset, first.len() as u64);
+    assert_eq!(corruption.resumed_at, Some(first.len() as u64 + 9));
+
+    // Truncated: everything before the end is recovered.
+    let mut output = Vec::new();
+    let report = salvage(&input[..input.len() - 2], &mut output).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.frames, 1);
+    assert_eq!(report.corruptions[1].resumed_at, None);
+}
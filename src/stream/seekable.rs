@@ -0,0 +1,341 @@
+//! Random access to multi-frame archives, through a seek table.
+//!
+//! A seek table lists the compressed and decompressed size of each frame.
+//! With it, reading from any position only requires decompressing the
+//! frame containing it. The table uses zstd's seekable format: it is
+//! stored in a skippable frame, so it can be appended to the archive
+//! (decoders ignore it), or kept in a separate index file.
+//!
+//! [`SeekTable::build`](struct.SeekTable.html#method.build) retrofits
+//! a table onto existing archives, like those produced by the `zstd`
+//! command-line tool with `--frame-size`.
+
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use bulk::Decompressor;
+use stream::FrameIter;
+use stream::frames::SKIPPABLE_MAGIC_START;
+
+// The seek table is in this skippable frame variant.
+const SEEK_TABLE_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xE;
+// Last bytes of a seek table.
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+// Frame count, descriptor and magic number.
+const FOOTER_SIZE: usize = 9;
+// Skippable frame magic number and size.
+const FRAME_HEADER_SIZE: usize = 8;
+// Compressed and decompressed sizes.
+const ENTRY_SIZE: usize = 8;
+// In the descriptor, whether entries have a checksum.
+const CHECKSUM_FLAG: u8 = 0x80;
+
+// Location of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    compressed_offset: u64,
+    decompressed_offset: u64,
+    compressed_size: u32,
+    decompressed_size: u32,
+}
+
+/// The location of each frame in an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SeekTable {
+    entries: Vec<Entry>,
+}
+
+impl SeekTable {
+    /// Builds the seek table of an existing archive.
+    ///
+    /// Each frame is decompressed, one at a time, to learn its size.
+    /// Skippable frames are listed too, as frames without content.
+    pub fn build<R: Read>(reader: R) -> io::Result<Self> {
+        let mut table = SeekTable::default();
+        let mut frames = FrameIter::new(reader);
+        loop {
+            let start = frames.position();
+            let frame = match frames.next() {
+                Some(frame) => try!(frame),
+                None => break,
+            };
+            let compressed_size = frames.position() - start;
+            let decompressed_size = if frame.header.skippable {
+                0
+            } else {
+                frame.data.len() as u64
+            };
+            try!(table.push(compressed_size, decompressed_size));
+        }
+        Ok(table)
+    }
+
+    /// Reads a seek table from the end of `reader`.
+    ///
+    /// This works both for a separate index file, and for an archive with
+    /// its seek table appended.
+    pub fn read_from<R: Read + Seek>(mut reader: R) -> io::Result<Self> {
+        let mut footer = [0u8; FOOTER_SIZE];
+        try!(reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64))));
+        try!(reader.read_exact(&mut footer));
+        if read_u32_le(&footer[5..]) != SEEKABLE_MAGIC {
+            return Err(invalid_table());
+        }
+        let count = read_u32_le(&footer) as usize;
+        let entry_size = if footer[4] & CHECKSUM_FLAG != 0 {
+            ENTRY_SIZE + 4
+        } else {
+            ENTRY_SIZE
+        };
+
+        let table_size = count * entry_size + FOOTER_SIZE;
+        let start = -((FRAME_HEADER_SIZE + table_size) as i64);
+        try!(reader.seek(SeekFrom::End(start)));
+        let mut data = vec![0u8; FRAME_HEADER_SIZE + table_size];
+        try!(reader.read_exact(&mut data));
+        if read_u32_le(&data) != SEEK_TABLE_MAGIC ||
+           read_u32_le(&data[4..]) as usize != table_size {
+            return Err(invalid_table());
+        }
+
+        let mut table = SeekTable::default();
+        for entry in data[FRAME_HEADER_SIZE..]
+            .chunks(entry_size)
+            .take(count) {
+            try!(table.push(read_u32_le(entry) as u64,
+                            read_u32_le(&entry[4..]) as u64));
+        }
+        Ok(table)
+    }
+
+    /// Writes the seek table, as a skippable frame.
+    ///
+    /// Append it to the archive, or keep it in a separate file.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let table_size = self.entries.len() * ENTRY_SIZE + FOOTER_SIZE;
+        let mut data = Vec::with_capacity(FRAME_HEADER_SIZE + table_size);
+        write_u32_le(&mut data, SEEK_TABLE_MAGIC);
+        write_u32_le(&mut data, table_size as u32);
+        for entry in &self.entries {
+            write_u32_le(&mut data, entry.compressed_size);
+            write_u32_le(&mut data, entry.decompressed_size);
+        }
+        write_u32_le(&mut data, self.entries.len() as u32);
+        data.push(0);
+        write_u32_le(&mut data, SEEKABLE_MAGIC);
+        writer.write_all(&data)
+    }
+
+    /// Returns the number of frames.
+    pub fn num_frames(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the total size of the frames, in bytes.
+    pub fn compressed_size(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(0, |e| e.compressed_offset + e.compressed_size as u64)
+    }
+
+    /// Returns the total size of the decompressed data, in bytes.
+    pub fn decompressed_size(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(0, |e| e.decompressed_offset + e.decompressed_size as u64)
+    }
+
+    fn push(&mut self, compressed_size: u64, decompressed_size: u64)
+            -> io::Result<()> {
+        if compressed_size > u32::max_value() as u64 ||
+           decompressed_size > u32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "frame too large for a seek table"));
+        }
+        let entry = Entry {
+            compressed_offset: self.compressed_size(),
+            decompressed_offset: self.decompressed_size(),
+            compressed_size: compressed_size as u32,
+            decompressed_size: decompressed_size as u32,
+        };
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // Returns the index of the frame holding the given decompressed byte.
+    fn find(&self, position: u64) -> Option<usize> {
+        if position >= self.decompressed_size() {
+            return None;
+        }
+        // The last frame starting at or before the position, with content.
+        let index = match self.entries
+            .binary_search_by_key(&position, |e| e.decompressed_offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        self.entries[index..]
+            .iter()
+            .position(|e| e.decompressed_size > 0)
+            .map(|i| index + i)
+    }
+}
+
+/// A reader with random access to an archive, using its seek table.
+///
+/// It implements `Read` and `Seek` over the decompressed data. Only the
+/// frame containing the current position is decompressed, and kept in
+/// memory.
+pub struct SeekableReader<R: Read + Seek> {
+    reader: R,
+    table: SeekTable,
+    // position in the decompressed data
+    position: u64,
+    decompressor: Decompressor<'static>,
+    // the last frame decompressed, and its index
+    frame: Vec<u8>,
+    frame_index: Option<usize>,
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    /// Creates a new reader, using the given seek table.
+    ///
+    /// The archive must start at the beginning of `reader`.
+    pub fn new(reader: R, table: SeekTable) -> Self {
+        SeekableReader {
+            reader: reader,
+            table: table,
+            position: 0,
+            decompressor: Decompressor::new(),
+            frame: Vec::new(),
+            frame_index: None,
+        }
+    }
+
+    /// Creates a new reader, for an archive with its seek table appended.
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let table = try!(SeekTable::read_from(&mut reader));
+        Ok(SeekableReader::new(reader, table))
+    }
+
+    /// Returns the seek table.
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Decompresses the given frame, unless it's already there.
+    fn load_frame(&mut self, index: usize) -> io::Result<()> {
+        if self.frame_index == Some(index) {
+            return Ok(());
+        }
+        let entry = self.table.entries[index];
+        try!(self.reader.seek(SeekFrom::Start(entry.compressed_offset)));
+        let mut compressed = vec![0u8; entry.compressed_size as usize];
+        try!(self.reader.read_exact(&mut compressed));
+
+        self.frame_index = None;
+        self.frame = try!(self.decompressor
+            .decompress(&compressed, entry.decompressed_size as usize));
+        if self.frame.len() != entry.decompressed_size as usize {
+            return Err(invalid_table());
+        }
+        self.frame_index = Some(index);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let index = match self.table.find(self.position) {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+        try!(self.load_frame(index));
+
+        let entry = self.table.entries[index];
+        let start = (self.position - entry.decompressed_offset) as usize;
+        let len = cmp::min(buf.len(), self.frame.len() - start);
+        buf[..len].copy_from_slice(&self.frame[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => {
+                self.table.decompressed_size() as i64 + offset
+            }
+        };
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "seek before the start"));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+fn invalid_table() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid seek table")
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+fn write_u32_le(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&[value as u8,
+                             (value >> 8) as u8,
+                             (value >> 16) as u8,
+                             (value >> 24) as u8]);
+}
+
+#[test]
+fn test_seek_table() {
+    use std::io::Cursor;
+
+    // Like `zstd --frame-size`: independent frames, one after the other.
+    let text: Vec<u8> = (0..30000u32).map(|i| (i % 253) as u8).collect();
+    let mut archive = Vec::new();
+    for chunk in text.chunks(4096) {
+        archive.extend_from_slice(&::encode_all(chunk, 3).unwrap());
+    }
+
+    let table = SeekTable::build(&archive[..]).unwrap();
+    assert_eq!(table.num_frames(), 8);
+    assert_eq!(table.compressed_size(), archive.len() as u64);
+    assert_eq!(table.decompressed_size(), text.len() as u64);
+
+    // In a separate index.
+    let mut index = Vec::new();
+    table.write_to(&mut index).unwrap();
+    assert_eq!(SeekTable::read_from(Cursor::new(&index)).unwrap(), table);
+
+    // Appended to the archive, which still decodes normally.
+    table.write_to(&mut archive).unwrap();
+    let decoded: Vec<u8> = FrameIter::new(&archive[..])
+        .map(|frame| frame.unwrap())
+        .filter(|frame| !frame.header.skippable)
+        .flat_map(|frame| frame.data)
+        .collect();
+    assert_eq!(decoded, text);
+
+    let mut reader = SeekableReader::open(Cursor::new(&archive)).unwrap();
+    let mut buffer = vec![0u8; 5000];
+    reader.seek(SeekFrom::Start(10000)).unwrap();
+    reader.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer[..], &text[10000..15000]);
+
+    reader.seek(SeekFrom::End(-100)).unwrap();
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(&rest[..], &text[text.len() - 100..]);
+}
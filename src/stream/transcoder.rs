@@ -0,0 +1,239 @@
+use std::io::{self, Read, Write};
+
+use ll;
+use stream::raw::{Decoder, Encoder, InBuffer, OutBuffer, Status};
+
+/// A compressed duplex link over a stream, like a socket.
+///
+/// Data read from the transcoder is decompressed from the stream, and
+/// data written to it is compressed to the stream, each direction with
+/// its own context and dictionary. This is the usual shape of a
+/// compressed RPC link.
+///
+/// Outbound data is one long frame: `flush()` sends everything written so
+/// far, so call it after each message for the peer to decode it without
+/// waiting. [`finish_frame`](#method.finish_frame) ends the frame, before
+/// closing the link. Inbound data can be any sequence of frames, flushed
+/// or not; a read returns as soon as some data was decompressed.
+///
+/// After an error from the stream, the link is in an unknown state.
+pub struct Transcoder<S: Read + Write> {
+    stream: S,
+    encoder: Encoder,
+    decoder: Decoder,
+    // compressed data read from the stream, not decoded yet
+    inbound: Vec<u8>,
+    offset: usize,
+    // whether zstd has decompressed data left to hand over
+    output_pending: bool,
+    // whether the decoder is in the middle of a frame
+    in_frame: bool,
+    // compressed data on its way to the stream
+    outbound: Vec<u8>,
+    // whether data was written since the last flush
+    unflushed: bool,
+}
+
+impl<S: Read + Write> Transcoder<S> {
+    /// Creates a new transcoder, compressing outbound data at `level`.
+    pub fn new(stream: S, level: i32) -> io::Result<Self> {
+        let encoder = try!(Encoder::new(level));
+        let decoder = try!(Decoder::new());
+        Ok(Transcoder::with_codecs(stream, encoder, decoder))
+    }
+
+    /// Creates a new transcoder, using a dictionary for each direction.
+    ///
+    /// Outbound data is compressed with `outbound_dictionary`, and
+    /// inbound data must have been compressed with `inbound_dictionary`.
+    pub fn with_dictionaries(stream: S, level: i32,
+                             outbound_dictionary: &[u8],
+                             inbound_dictionary: &[u8])
+                             -> io::Result<Self> {
+        let encoder =
+            try!(Encoder::with_dictionary(level, outbound_dictionary));
+        let decoder = try!(Decoder::with_dictionary(inbound_dictionary));
+        Ok(Transcoder::with_codecs(stream, encoder, decoder))
+    }
+
+    fn with_codecs(stream: S, encoder: Encoder, decoder: Decoder) -> Self {
+        let in_size = unsafe { ll::ZSTD_DStreamInSize() };
+        let out_size = unsafe { ll::ZSTD_CStreamOutSize() };
+        Transcoder {
+            stream: stream,
+            encoder: encoder,
+            decoder: decoder,
+            inbound: Vec::with_capacity(in_size),
+            offset: 0,
+            output_pending: false,
+            in_frame: false,
+            outbound: vec![0u8; out_size],
+            unflushed: false,
+        }
+    }
+
+    /// Returns a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the inner stream.
+    ///
+    /// Reading or writing directly would corrupt the link.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Returns the inner stream.
+    ///
+    /// Inbound data already read from the stream is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Ends the outbound frame, and sends it.
+    ///
+    /// Writing more data afterwards starts a new frame.
+    pub fn finish_frame(&mut self) -> io::Result<()> {
+        loop {
+            let status = {
+                let mut output = OutBuffer::new(&mut self.outbound);
+                let status = try!(self.encoder.poll_finish(&mut output));
+                try!(self.stream.write_all(output.as_slice()));
+                status
+            };
+            if status == Status::Done {
+                break;
+            }
+        }
+        self.unflushed = false;
+        self.stream.flush()
+    }
+
+    // Reads more compressed data from the stream.
+    //
+    // Returns the number of bytes read.
+    fn fill(&mut self) -> io::Result<usize> {
+        let capacity = self.inbound.capacity();
+        self.inbound.resize(capacity, 0);
+        self.offset = 0;
+        let read = self.stream.read(&mut self.inbound);
+        self.inbound.truncate(*read.as_ref().unwrap_or(&0));
+        read
+    }
+}
+
+impl<S: Read + Write> Read for Transcoder<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.offset == self.inbound.len() && !self.output_pending &&
+               try!(self.fill()) == 0 {
+                if self.in_frame {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "incomplete frame"));
+                }
+                return Ok(0);
+            }
+
+            let mut input = InBuffer::new(&self.inbound);
+            input.pos = self.offset;
+            let mut output = OutBuffer::new(buf);
+            let status = try!(self.decoder
+                .poll_decompress(&mut input, &mut output));
+            self.in_frame = match status {
+                Status::Done => false,
+                _ => self.in_frame || input.pos > self.offset,
+            };
+            self.offset = input.pos;
+            self.output_pending = status == Status::NeedOutput;
+            if output.pos > 0 {
+                return Ok(output.pos);
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Write for Transcoder<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut input = InBuffer::new(buf);
+        while input.pos < buf.len() {
+            let mut output = OutBuffer::new(&mut self.outbound);
+            try!(self.encoder.poll_compress(&mut input, &mut output));
+            try!(self.stream.write_all(output.as_slice()));
+        }
+        self.unflushed = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while self.unflushed {
+            let mut output = OutBuffer::new(&mut self.outbound);
+            let status = try!(self.encoder.poll_flush(&mut output));
+            try!(self.stream.write_all(output.as_slice()));
+            self.unflushed = status != Status::Done;
+        }
+        self.stream.flush()
+    }
+}
+
+#[test]
+fn test_transcoder() {
+    use std::io::Cursor;
+
+    // One end of a link: reads from `inbound`, writes to `outbound`.
+    struct Link {
+        inbound: Cursor<Vec<u8>>,
+        outbound: Vec<u8>,
+    }
+
+    impl Read for Link {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inbound.read(buf)
+        }
+    }
+
+    impl Write for Link {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outbound.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut requests = ::encode_all(&b"first request"[..], 1).unwrap();
+    requests.extend_from_slice(&::encode_all(&b", second"[..], 1).unwrap());
+    let link = Link {
+        inbound: Cursor::new(requests),
+        outbound: Vec::new(),
+    };
+    let mut transcoder = Transcoder::new(link, 3).unwrap();
+
+    let mut received = String::new();
+    transcoder.read_to_string(&mut received).unwrap();
+    assert_eq!(received, "first request, second");
+
+    // A flushed message can be decoded right away.
+    transcoder.write_all(b"reply").unwrap();
+    transcoder.flush().unwrap();
+    let mut peer = ::stream::write::Decoder::new(Vec::new()).unwrap();
+    peer.write_all(&transcoder.get_ref().outbound).unwrap();
+    assert_eq!(&peer.finish()[..], b"reply");
+
+    transcoder.finish_frame().unwrap();
+    let link = transcoder.into_inner();
+    assert_eq!(&::decode_all(&link.outbound).unwrap()[..], b"reply");
+
+    // The stream ends in the middle of a frame.
+    let request = ::encode_all(&b"truncated"[..], 1).unwrap();
+    let link = Link {
+        inbound: Cursor::new(request[..request.len() - 3].to_vec()),
+        outbound: Vec::new(),
+    };
+    let mut transcoder = Transcoder::new(link, 3).unwrap();
+    assert!(transcoder.read_to_end(&mut Vec::new()).is_err());
+}
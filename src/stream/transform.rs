@@ -0,0 +1,348 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use ll;
+
+// Size of the record header: the size of the sealed frame.
+const RECORD_HEADER_SIZE: usize = 4;
+
+/// A transformation applied to each complete compressed frame.
+///
+/// This is the place for encryption or authentication, which must come
+/// after compression. A [`FrameSealer`](struct.FrameSealer.html) calls
+/// `seal` on each frame written to it, and a
+/// [`FrameOpener`](struct.FrameOpener.html) calls `open` to get it back.
+pub trait FrameTransform {
+    /// Transforms a complete frame, appending the result to `output`.
+    fn seal(&mut self, frame: &[u8], output: &mut Vec<u8>)
+            -> io::Result<()>;
+
+    /// Reverts `seal`, appending the original frame to `output`.
+    fn open(&mut self, sealed: &[u8], output: &mut Vec<u8>)
+            -> io::Result<()>;
+}
+
+/// A writer sealing each compressed frame before sending it.
+///
+/// Put it between an [`Encoder`](write/struct.Encoder.html) and the
+/// actual writer: it collects the compressed data until a frame is
+/// complete, transforms it, and sends it as a record made of its size (4
+/// bytes, little-endian) and the sealed frame. Skippable frames are
+/// sealed too.
+///
+/// Data only reaches the writer a frame at a time, so flushing the
+/// encoder doesn't send anything until the frame ends; use frames as
+/// the unit of delivery, for instance with `write_message()`.
+///
+/// If the writer fails, sealed data it didn't accept is kept and sent
+/// first on the next call.
+pub struct FrameSealer<W: Write, T: FrameTransform> {
+    writer: W,
+    transform: T,
+    // compressed data of the frame in progress
+    pending: Vec<u8>,
+    // sealed records, sent up to `offset`
+    sealed: Vec<u8>,
+    offset: usize,
+}
+
+impl<W: Write, T: FrameTransform> FrameSealer<W, T> {
+    /// Creates a new sealer, sending records to `writer`.
+    pub fn new(writer: W, transform: T) -> Self {
+        FrameSealer {
+            writer: writer,
+            transform: transform,
+            pending: Vec::new(),
+            sealed: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Sends the last records, and returns the writer and the transform.
+    ///
+    /// Fails if the data written ends in the middle of a frame.
+    pub fn finish(mut self) -> io::Result<(W, T)> {
+        try!(self.seal_frames());
+        if !self.pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "incomplete frame"));
+        }
+        try!(self.writer.flush());
+        Ok((self.writer, self.transform))
+    }
+
+    // Seals every complete frame, and sends them.
+    fn seal_frames(&mut self) -> io::Result<()> {
+        let mut consumed = 0;
+        let mut result = Ok(());
+        loop {
+            let size = match frame_size(&self.pending[consumed..]) {
+                Ok(Some(size)) => size,
+                Ok(None) => break,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            let frame = &self.pending[consumed..consumed + size];
+            if let Err(e) = seal_record(&mut self.transform,
+                                        frame,
+                                        &mut self.sealed) {
+                result = Err(e);
+                break;
+            }
+            consumed += size;
+        }
+        self.pending.drain(..consumed);
+        try!(result);
+        self.write_staged()
+    }
+
+    // Sends the sealed records to the writer.
+    //
+    // On error, the data not yet sent is kept for the next attempt.
+    fn write_staged(&mut self) -> io::Result<()> {
+        while self.offset < self.sealed.len() {
+            match self.writer.write(&self.sealed[self.offset..]) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero,
+                                              "failed to write sealed \
+                                               data"))
+                }
+                Ok(n) => self.offset += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        self.sealed.clear();
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write, T: FrameTransform> Write for FrameSealer<W, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Leftovers from a failed write go first.
+        try!(self.write_staged());
+        self.pending.extend_from_slice(buf);
+        // The data is ours now: on error, it's sent on the next call.
+        let _ = self.seal_frames();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.seal_frames());
+        self.writer.flush()
+    }
+}
+
+/// A reader opening the records written by a
+/// [`FrameSealer`](struct.FrameSealer.html).
+///
+/// It returns the original compressed frames, one after the other. A
+/// [`Decoder`](read/struct.Decoder.html) stops after the first frame, so
+/// use [`decompress_multi`](../bulk/struct.Decompressor.html) to get all
+/// the data back.
+pub struct FrameOpener<R: Read, T: FrameTransform> {
+    reader: R,
+    transform: T,
+    // sealed record being read
+    record: Vec<u8>,
+    // opened frame, returned up to `offset`
+    frame: Vec<u8>,
+    offset: usize,
+    // larger records are refused
+    max_record_size: usize,
+}
+
+impl<R: Read, T: FrameTransform> FrameOpener<R, T> {
+    /// Creates a new opener, reading records from `reader`.
+    pub fn new(reader: R, transform: T) -> Self {
+        FrameOpener {
+            reader: reader,
+            transform: transform,
+            record: Vec::new(),
+            frame: Vec::new(),
+            offset: 0,
+            max_record_size: usize::max_value(),
+        }
+    }
+
+    /// Sets the size of the largest sealed frame accepted, in bytes.
+    ///
+    /// Larger records return an `InvalidData` error. By default, any
+    /// record is accepted: memory is only allocated as its data is
+    /// actually read, whatever size its header claims.
+    pub fn set_max_record_size(&mut self, size: usize) {
+        self.max_record_size = size;
+    }
+
+    /// Returns the inner reader and the transform.
+    pub fn into_inner(self) -> (R, T) {
+        (self.reader, self.transform)
+    }
+
+    // Reads and opens the next record.
+    //
+    // Returns `false` at the end of the input.
+    fn next_record(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        let mut read = 0;
+        while read < header.len() {
+            match self.reader.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(incomplete()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let size = header.iter()
+            .rev()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        if size > self.max_record_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "record is too large"));
+        }
+        self.record.clear();
+        let read = try!((&mut self.reader)
+            .take(size as u64)
+            .read_to_end(&mut self.record));
+        if read < size {
+            return Err(incomplete());
+        }
+        self.frame.clear();
+        self.offset = 0;
+        try!(self.transform.open(&self.record, &mut self.frame));
+        Ok(true)
+    }
+}
+
+impl<R: Read, T: FrameTransform> Read for FrameOpener<R, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.frame.len() {
+            if !try!(self.next_record()) {
+                return Ok(0);
+            }
+        }
+        let len = cmp::min(buf.len(), self.frame.len() - self.offset);
+        buf[..len].copy_from_slice(&self.frame[self.offset..][..len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+// Returns the size of the frame starting `data`, if it's all there.
+fn frame_size(data: &[u8]) -> io::Result<Option<usize>> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let code =
+        unsafe { ll::ZSTD_findFrameCompressedSize(data.as_ptr(), data.len()) };
+    if unsafe { ll::ZSTD_isError(code) } != 0 &&
+       unsafe { ll::ZSTD_getErrorCode(code) } ==
+       ll::ZSTD_error_srcSize_wrong {
+        return Ok(None);
+    }
+    ll::parse_code(code).map(Some)
+}
+
+// Seals a frame, and appends it to `records` with its header.
+fn seal_record<T: FrameTransform>(transform: &mut T, frame: &[u8],
+                                  records: &mut Vec<u8>)
+                                  -> io::Result<()> {
+    let start = records.len();
+    records.extend_from_slice(&[0u8; RECORD_HEADER_SIZE]);
+    if let Err(e) = transform.seal(frame, records) {
+        records.truncate(start);
+        return Err(e);
+    }
+    let size = records.len() - start - RECORD_HEADER_SIZE;
+    if size > u32::max_value() as usize {
+        records.truncate(start);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "sealed frame is too large"));
+    }
+    for (i, byte) in records[start..start + RECORD_HEADER_SIZE]
+        .iter_mut()
+        .enumerate() {
+        *byte = (size >> (8 * i)) as u8;
+    }
+    Ok(())
+}
+
+fn incomplete() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete record")
+}
+
+#[test]
+fn test_frame_transform() {
+    use bulk::Decompressor;
+    use stream::write::Encoder;
+
+    // Xors each frame with a key stream that depends on its index.
+    struct Scramble {
+        frames: u8,
+    }
+
+    impl Scramble {
+        fn apply(&mut self, data: &[u8], output: &mut Vec<u8>) {
+            let key = self.frames.wrapping_mul(31).wrapping_add(7);
+            output.extend(data.iter().map(|&b| b ^ key));
+            self.frames = self.frames.wrapping_add(1);
+        }
+    }
+
+    impl FrameTransform for Scramble {
+        fn seal(&mut self, frame: &[u8], output: &mut Vec<u8>)
+                -> io::Result<()> {
+            self.apply(frame, output);
+            Ok(())
+        }
+
+        fn open(&mut self, sealed: &[u8], output: &mut Vec<u8>)
+                -> io::Result<()> {
+            self.apply(sealed, output);
+            Ok(())
+        }
+    }
+
+    let sealer = FrameSealer::new(Vec::new(), Scramble { frames: 0 });
+    let mut encoder = Encoder::new(sealer, 3).unwrap();
+    encoder.write_message(b"first message, ").unwrap();
+    encoder.write_all(&vec![b'z'; 100000]).unwrap();
+    let (sealed, _) = encoder.finish().unwrap().finish().unwrap();
+    assert!(::decode_all(&sealed[RECORD_HEADER_SIZE..]).is_err());
+
+    // The opener returns the original frames, one after the other.
+    let mut opener = FrameOpener::new(&sealed[..], Scramble { frames: 0 });
+    let mut frames = Vec::new();
+    opener.read_to_end(&mut frames).unwrap();
+    let decoded = Decompressor::new().decompress_multi(&frames).unwrap();
+    let mut expected = b"first message, ".to_vec();
+    expected.extend_from_slice(&vec![b'z'; 100000]);
+    assert_eq!(decoded, expected);
+
+    // A header claiming more than what follows.
+    let mut corrupt = sealed[..RECORD_HEADER_SIZE + 10].to_vec();
+    corrupt[..RECORD_HEADER_SIZE].copy_from_slice(&[0xff; 4]);
+    let mut opener = FrameOpener::new(&corrupt[..], Scramble { frames: 0 });
+    assert_eq!(opener.read(&mut [0u8; 16]).unwrap_err().kind(),
+               io::ErrorKind::UnexpectedEof);
+    let mut opener = FrameOpener::new(&corrupt[..], Scramble { frames: 0 });
+    opener.set_max_record_size(1 << 20);
+    assert_eq!(opener.read(&mut [0u8; 16]).unwrap_err().kind(),
+               io::ErrorKind::InvalidData);
+
+    // Only complete frames go through.
+    let mut sealer = FrameSealer::new(Vec::new(), Scramble { frames: 0 });
+    let frame = ::encode_all(&b"cut short"[..], 1).unwrap();
+    sealer.write_all(&frame[..frame.len() - 2]).unwrap();
+    assert!(sealer.get_ref().is_empty());
+    assert!(sealer.finish().is_err());
+}
@@ -0,0 +1,340 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use ll;
+use context::DecoderContext;
+use dict::DecoderDictionary;
+use params::DParameter;
+use stream::{FrameHeader, OutputLimitExceeded, TrailingData,
+             missing_checksum_error, trailing_data_error};
+use stream::frames::{self, HeaderRecorder, SkippableFrames};
+use stream::progression::Observer;
+use xxhash::Xxh64;
+
+/// A decoder that decompress and forward data to another writer.
+///
+/// Compressed data written to this decoder is decompressed
+/// and sent to the inner writer.
+///
+/// By default, anything written after the end of the frame is ignored.
+/// See [`set_trailing_data`](#method.set_trailing_data).
+pub struct Decoder<W: Write> {
+    // output writer (decompressed data)
+    writer: W,
+    // output buffer
+    buffer: Vec<u8>,
+    // decompression context
+    context: DecoderContext,
+    // compressed bytes actually fed to zstd so far
+    consumed: u64,
+    // parses the frame header as it goes through
+    header: HeaderRecorder,
+    // reject frames without a checksum
+    require_checksum: bool,
+    // decompressed bytes produced so far, and how many are allowed
+    produced: u64,
+    output_limit: Option<u64>,
+    // whether we reached the end of the frame
+    finished: bool,
+    // notified as data goes through
+    observer: Option<Observer>,
+    // receives skippable frames, until the regular frame starts
+    skippable: Option<SkippableFrames>,
+    // hashes the decompressed data, if enabled
+    hasher: Option<Xxh64>,
+    // what to do with data after the frame
+    trailing_policy: TrailingData,
+    // data found after the frame, with `TrailingData::Return`
+    trailing: Vec<u8>,
+}
+
+impl<W: Write> Decoder<W> {
+    /// Creates a new decoder.
+    pub fn new(writer: W) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        Decoder::with_context(writer, context)
+    }
+
+    /// Creates a new decoder, using an existing dictionary.
+    ///
+    /// The dictionary must be the same as the one used during compression.
+    pub fn with_dictionary(writer: W, dictionary: &[u8]) -> io::Result<Self> {
+        let context = try!(DecoderContext::new());
+
+        try!(context.load_dictionary(dictionary));
+
+        Decoder::with_context(writer, context)
+    }
+
+    /// Creates a new decoder, referencing a prepared dictionary.
+    ///
+    /// The dictionary is shared rather than copied, so many decoders can
+    /// use it at once, from any thread.
+    pub fn with_prepared_dictionary(writer: W,
+                                    dictionary: Arc<DecoderDictionary>)
+                                    -> io::Result<Self> {
+        let mut context = try!(DecoderContext::new());
+
+        try!(context.ref_dictionary(dictionary));
+
+        Decoder::with_context(writer, context)
+    }
+
+    fn with_context(writer: W, context: DecoderContext) -> io::Result<Self> {
+        // This is the output buffer size,
+        // for decompressed data we get from zstd.
+        let buffer_size = unsafe { ll::ZSTD_DStreamOutSize() };
+
+        Ok(Decoder {
+            writer: writer,
+            buffer: Vec::with_capacity(buffer_size),
+            context: context,
+            consumed: 0,
+            header: HeaderRecorder::default(),
+            require_checksum: false,
+            produced: 0,
+            output_limit: None,
+            finished: false,
+            observer: None,
+            skippable: None,
+            hasher: None,
+            trailing_policy: TrailingData::Ignore,
+            trailing: Vec::new(),
+        })
+    }
+
+    /// Sets what to do with data written after the end of the frame.
+    pub fn set_trailing_data(&mut self, policy: TrailingData) {
+        self.trailing_policy = policy;
+    }
+
+    /// Returns the data written after the end of the frame.
+    ///
+    /// This is only filled with `TrailingData::Return`.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    /// Returns the number of compressed bytes consumed so far.
+    ///
+    /// Once the end of the frame is reached, this is the exact size of the
+    /// frame; anything written after it is not counted.
+    pub fn compressed_bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Returns the header of the frame being decompressed.
+    ///
+    /// This is `None` until the decoder has seen the complete header,
+    /// which takes a few bytes of compressed data. It tells the content
+    /// size (when recorded), the window size, the dictionary ID and
+    /// whether the frame has a checksum: enough to size the output, or to
+    /// check the frame expects the right dictionary.
+    pub fn frame_info(&self) -> Option<FrameHeader> {
+        self.header.header()
+    }
+
+    /// Computes the content checksum of the decompressed data.
+    ///
+    /// Once enabled, [`content_checksum`](#method.content_checksum) returns
+    /// the checksum of the frame, whether or not the frame included one.
+    /// This must be enabled before any data is decompressed.
+    pub fn track_content_checksum(&mut self, track: bool) {
+        self.hasher = if track { Some(Xxh64::default()) } else { None };
+    }
+
+    /// Returns the content checksum of the frame.
+    ///
+    /// This is the lower 32 bits of the XXH64 hash of the decompressed
+    /// data: the same value zstd stores, and verifies, in frames with a
+    /// checksum. It is only available once the end of the frame is
+    /// reached, and when `track_content_checksum(true)` was called.
+    pub fn content_checksum(&self) -> Option<u32> {
+        match self.hasher {
+            Some(ref hasher) if self.finished => Some(hasher.digest() as u32),
+            _ => None,
+        }
+    }
+
+    /// Rejects frames without a content checksum.
+    ///
+    /// When enabled, decompression fails as soon as the frame header shows
+    /// the frame carries no checksum, before any data is returned. Frames
+    /// with a checksum are always verified.
+    pub fn require_checksum(&mut self, require: bool) {
+        self.require_checksum = require;
+    }
+
+    /// Fails once more than `limit` bytes would be decompressed.
+    ///
+    /// This guards against decompression bombs: the error is an
+    /// [`OutputLimitExceeded`](../struct.OutputLimitExceeded.html), and
+    /// the data beyond the limit is never handed over. Unlike `take()`,
+    /// this can't silently truncate the output.
+    pub fn with_output_limit(mut self, limit: u64) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Sets a single decompression parameter.
+    pub fn set_parameter(&mut self, parameter: DParameter) -> io::Result<()> {
+        self.context.set_dparameter(parameter)
+    }
+
+    /// Calls the given callback each time data flows through the decoder.
+    ///
+    /// The callback receives the number of compressed bytes consumed
+    /// and the number of decompressed bytes produced since the last call.
+    pub fn on_progress<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(usize, usize)
+    {
+        self.observer = Some(Box::new(f));
+    }
+
+    /// Calls the given callback for each skippable frame before the frame.
+    ///
+    /// Skippable frames hold metadata zstd ignores, like seek tables or
+    /// manifests. The callback receives the magic number variant (0 to
+    /// 15) and the content of each of them, instead of discarding them.
+    /// This must be set before any data is decompressed.
+    pub fn on_skippable_frame<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(u32, &[u8])
+    {
+        self.skippable = Some(SkippableFrames::new(Box::new(f)));
+    }
+
+    /// Return the inner `Write`.
+    ///
+    /// All decompressed data has already been sent to the writer.
+    pub fn finish(self) -> W {
+        self.writer
+    }
+
+    // Decompresses data from the regular frame.
+    //
+    // Returns the number of bytes consumed.
+    fn decompress(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        loop {
+            let start = input.pos;
+            let buffer_size = self.buffer.capacity();
+            unsafe {
+                self.buffer.set_len(buffer_size);
+            }
+            let mut output = ll::ZSTD_outBuffer::new(&mut self.buffer);
+
+            let res = unsafe {
+                let code = ll::ZSTD_decompressStream(self.context.c,
+                                                     &mut output,
+                                                     &mut input);
+                self.buffer.set_len(output.pos);
+                try!(ll::parse_code(code))
+            };
+            self.consumed += (input.pos - start) as u64;
+            self.header.record(&buf[start..input.pos]);
+            try!(self.check_header());
+            try!(self.check_output(output.pos));
+            if let Some(ref mut hasher) = self.hasher {
+                hasher.update(&self.buffer);
+            }
+            try!(self.writer.write_all(&self.buffer));
+            self.notify(input.pos - start, output.pos);
+
+            if res == 0 {
+                // End-of-frame marker.
+                self.finished = true;
+                try!(self.write_trailing(&buf[input.pos..]));
+                return Ok(buf.len());
+            }
+
+            // Keep going while we have input,
+            // or while zstd may still have output for us.
+            if input.pos == input.size && output.pos < output.size {
+                break;
+            }
+        }
+        Ok(input.pos)
+    }
+
+    // Hands the skippable frames at the start of `data` to the callback.
+    //
+    // Returns the number of bytes consumed.
+    fn consume_skippable(&mut self, data: &[u8]) -> io::Result<usize> {
+        let (consumed, prefix) = match self.skippable {
+            Some(ref mut skippable) => {
+                let consumed = skippable.consume(data);
+                (consumed, skippable.regular_prefix().map(|p| p.to_vec()))
+            }
+            None => return Ok(0),
+        };
+        self.consumed += consumed as u64;
+        if let Some(prefix) = prefix {
+            self.skippable = None;
+            try!(frames::feed_prefix(&self.context, &prefix));
+            self.header.record(&prefix);
+        }
+        Ok(consumed)
+    }
+
+    // Counts produced bytes, failing if the limit is exceeded.
+    fn check_output(&mut self, produced: usize) -> io::Result<()> {
+        self.produced += produced as u64;
+        match self.output_limit {
+            Some(limit) if self.produced > limit => {
+                Err(OutputLimitExceeded { limit: limit }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if the frame header is known, and not acceptable.
+    fn check_header(&self) -> io::Result<()> {
+        match self.header.header() {
+            Some(header) if self.require_checksum && !header.has_checksum => {
+                Err(missing_checksum_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Tell the observer, if any, about the data that just went through.
+    fn notify(&mut self, consumed: usize, produced: usize) {
+        if consumed == 0 && produced == 0 {
+            return;
+        }
+        if let Some(ref mut observer) = self.observer {
+            observer(consumed, produced);
+        }
+    }
+
+    // Handles data written after the frame, according to the policy.
+    fn write_trailing(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self.trailing_policy {
+            TrailingData::Ignore => (),
+            TrailingData::Error if buf.is_empty() => (),
+            TrailingData::Error => return Err(trailing_data_error()),
+            TrailingData::Return => self.trailing.extend_from_slice(buf),
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            try!(self.write_trailing(buf));
+            return Ok(buf.len());
+        }
+
+        let skipped = try!(self.consume_skippable(buf));
+        if skipped > 0 && skipped == buf.len() {
+            return Ok(skipped);
+        }
+        self.decompress(&buf[skipped..]).map(|written| skipped + written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
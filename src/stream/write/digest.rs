@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+
+use stream::write::Encoder;
+use xxhash::Xxh64;
+
+/// A hash function for [`DigestEncoder`](struct.DigestEncoder.html).
+///
+/// Implement it to use other checksums, like CRC32.
+pub trait Digest {
+    /// The result of the hash.
+    type Output;
+
+    /// Feeds more data to the hash.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the hash of the data seen so far.
+    fn digest(&self) -> Self::Output;
+}
+
+impl Digest for Xxh64 {
+    type Output = u64;
+
+    fn update(&mut self, data: &[u8]) {
+        Xxh64::update(self, data);
+    }
+
+    fn digest(&self) -> u64 {
+        Xxh64::digest(self)
+    }
+}
+
+/// Sizes and digests of the data that went through a `DigestEncoder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestReport<T> {
+    /// Number of uncompressed bytes written to the encoder.
+    pub uncompressed_size: u64,
+    /// Digest of the uncompressed data.
+    pub uncompressed_digest: T,
+    /// Number of compressed bytes sent to the writer.
+    pub compressed_size: u64,
+    /// Digest of the compressed data.
+    pub compressed_digest: T,
+}
+
+// Hashes the data the inner writer accepted.
+struct HashingWriter<W: Write, D: Digest> {
+    writer: W,
+    digest: D,
+    size: u64,
+}
+
+impl<W: Write, D: Digest> Write for HashingWriter<W, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.writer.write(buf));
+        self.digest.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// An encoder also hashing its uncompressed input and compressed output.
+///
+/// This gives what a manifest needs in a single pass over the data.
+/// Don't forget to call `finish()` before dropping it!
+pub struct DigestEncoder<W: Write, D: Digest> {
+    encoder: Encoder<HashingWriter<W, D>>,
+    digest: D,
+    size: u64,
+}
+
+impl<W: Write, D: Digest> DigestEncoder<W, D> {
+    /// Creates a new encoder.
+    ///
+    /// `uncompressed` hashes the data written to this encoder,
+    /// while `compressed` hashes the data sent to `writer`.
+    pub fn new(writer: W, level: i32, uncompressed: D, compressed: D)
+               -> io::Result<Self> {
+        let writer = HashingWriter {
+            writer: writer,
+            digest: compressed,
+            size: 0,
+        };
+        Ok(DigestEncoder {
+            encoder: try!(Encoder::new(writer, level)),
+            digest: uncompressed,
+            size: 0,
+        })
+    }
+
+    /// Finishes the stream.
+    ///
+    /// Returns the inner writer, with the sizes and digests of the data.
+    pub fn finish(self) -> io::Result<(W, DigestReport<D::Output>)> {
+        let writer = try!(self.encoder.finish());
+        let report = DigestReport {
+            uncompressed_size: self.size,
+            uncompressed_digest: self.digest.digest(),
+            compressed_size: writer.size,
+            compressed_digest: writer.digest.digest(),
+        };
+        Ok((writer.writer, report))
+    }
+}
+
+impl<W: Write, D: Digest> Write for DigestEncoder<W, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.encoder.write(buf));
+        self.digest.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+#[test]
+fn test_digest_encoder() {
+    use xxhash::xxh64;
+
+    let text = b"Hash me on the way in, and on the way out.";
+    let mut encoder = DigestEncoder::new(Vec::new(),
+                                         3,
+                                         Xxh64::default(),
+                                         Xxh64::default())
+        .unwrap();
+    encoder.write_all(text).unwrap();
+    let (compressed, report) = encoder.finish().unwrap();
+
+    assert_eq!(report.uncompressed_size, text.len() as u64);
+    assert_eq!(report.uncompressed_digest, xxh64(text, 0));
+    assert_eq!(report.compressed_size, compressed.len() as u64);
+    assert_eq!(report.compressed_digest, xxh64(&compressed, 0));
+}
@@ -0,0 +1,952 @@
+use std::{cmp, mem};
+use std::io::{self, IoSlice, Write};
+use std::sync::Arc;
+use std::time::Instant;
+
+use ll;
+#[cfg(feature = "experimental")]
+use bulk::SequenceProducer;
+use context::EncoderContext;
+use dict::EncoderDictionary;
+use params::{CParameter, CompressionParams, LiteralCompressionMode,
+             ZstdVersion};
+use stream::{FrameProgression, FrameStats, SMALL_BUFFER_SIZE,
+             SMALL_WINDOW_LOG};
+use stream::adapt::AdaptiveLevel;
+use stream::progression::Observer;
+use xxhash::Xxh64;
+
+// Frames whose whole input is at most this size are compressed in a
+// single call when they end, rather than streamed.
+const SMALL_INPUT_SIZE: usize = 16 * 1024;
+
+/// An encoder that compress and forward data to another writer.
+///
+/// This allows to compress a stream of data
+/// (good for files or heavy network stream).
+///
+/// Don't forget to call `finish()` before dropping it!
+///
+/// If the inner writer fails, compressed data it didn't accept is kept
+/// and sent first on the next call, so the operation can be retried.
+///
+/// Frames of up to 16KB are held until they end, then compressed in a
+/// single call rather than streamed; their header records the content
+/// size.
+///
+/// Note: The zstd library has its own internal input buffer (~128kb).
+pub struct Encoder<W: Write> {
+    // output writer (compressed data)
+    writer: W,
+    // output buffer
+    buffer: Vec<u8>,
+    // capacity of the buffer while compressing
+    capacity: usize,
+    // we already sent everything in the buffer up to that point
+    offset: usize,
+
+    // compression context
+    context: EncoderContext,
+    // notified as data goes through
+    observer: Option<Observer>,
+    // adjusts the compression level, if enabled
+    adapt: Option<AdaptiveLevel>,
+    // whether some data was written since the last frame ended
+    frame_pending: bool,
+    // number of frames completed so far
+    frames: u64,
+    // whether each write is compressed as its own frame
+    frame_per_write: bool,
+    // whether `write` may return before all output was sent
+    partial_writes: bool,
+    // notified each time a frame is completed
+    frame_observer: Option<Box<FnMut(FrameStats) + Send>>,
+    // hashes the input of the current frame, if anyone wants stats
+    frame_hasher: Option<Xxh64>,
+    // bytes consumed and produced in the current frame
+    frame_in: u64,
+    frame_out: u64,
+    // if set, the buffer shrinks between frames, but not below this
+    shrink_min: Option<usize>,
+    // largest output staged at once during the current frame
+    watermark: usize,
+    // input of the current frame not given to zstd yet, while it's small
+    deferred: Vec<u8>,
+}
+
+/// A wrapper around an `Encoder<W>` that finishes the stream on drop.
+pub struct AutoFinishEncoder<W: Write> {
+    // We wrap this in an option to take it during drop.
+    encoder: Option<Encoder<W>>,
+    // TODO: make this a FnOnce once it works in a Box
+    on_finish: Option<Box<FnMut(io::Result<W>) + Send>>,
+}
+
+impl<W: Write> AutoFinishEncoder<W> {
+    fn new<F>(encoder: Encoder<W>, on_finish: F) -> Self
+        where F: 'static + Send + FnMut(io::Result<W>)
+    {
+        AutoFinishEncoder {
+            encoder: Some(encoder),
+            on_finish: Some(Box::new(on_finish)),
+        }
+    }
+}
+
+impl<W: Write> Drop for AutoFinishEncoder<W> {
+    fn drop(&mut self) {
+        let result = self.encoder.take().unwrap().finish();
+        if let Some(mut on_finish) = self.on_finish.take() {
+            on_finish(result);
+        }
+    }
+}
+
+impl<W: Write> Write for AutoFinishEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.as_mut().unwrap().write(buf)
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates a new encoder.
+    ///
+    /// `level`: compression level (1-21)
+    pub fn new(writer: W, level: i32) -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        // Initialize the stream
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+
+        Encoder::with_context(writer, context)
+    }
+
+    /// Creates a new encoder, using an existing dictionary.
+    ///
+    /// (Provides better compression ratio for small files,
+    /// but requires the dictionary to be present during decompression.)
+    pub fn with_dictionary(writer: W, level: i32, dictionary: &[u8])
+                           -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+
+        // Initialize the stream with an existing dictionary
+        try!(context.set_parameter(ll::ZSTD_c_compressionLevel, level));
+        try!(context.load_dictionary(dictionary));
+
+        Encoder::with_context(writer, context)
+    }
+
+    /// Creates a new encoder, referencing a prepared dictionary.
+    ///
+    /// The dictionary is shared rather than copied, so many encoders can
+    /// use it at once, from any thread. Data is compressed at the level
+    /// the dictionary was prepared for.
+    pub fn with_prepared_dictionary(writer: W,
+                                    dictionary: Arc<EncoderDictionary>)
+                                    -> io::Result<Self> {
+        let mut context = try!(EncoderContext::new());
+
+        try!(context.ref_dictionary(dictionary));
+
+        Encoder::with_context(writer, context)
+    }
+
+    /// Returns an encoder that will finish the stream on drop.
+    ///
+    /// # Panic
+    ///
+    /// Panics if an error happens when finishing the stream.
+    pub fn auto_finish(self) -> AutoFinishEncoder<W> {
+        self.on_finish(|result| {
+            result.unwrap();
+        })
+    }
+
+    /// Returns an encoder that will finish the stream on drop.
+    ///
+    /// Calls the given callback with the result from `finish()`.
+    pub fn on_finish<F>(self, f: F) -> AutoFinishEncoder<W>
+        where F: 'static + Send + FnMut(io::Result<W>)
+    {
+        AutoFinishEncoder::new(self, f)
+    }
+
+    /// Creates a new encoder using the smallest window (1KB),
+    /// and as little memory as possible.
+    ///
+    /// The compressed data can be decompressed by
+    /// [`Decoder::low_memory`], which needs much less memory than a
+    /// regular decoder.
+    /// The encoder needs
+    /// [`small_window_requirement(level)`](#method.small_window_requirement)
+    /// bytes. The compression ratio is lower than with a regular encoder.
+    ///
+    /// [`Decoder::low_memory`]: ../read/struct.Decoder.html#method.low_memory
+    pub fn small_window(writer: W, level: i32) -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+        try!(context.set_compression_params(&small_window_params(level)));
+
+        Encoder::with_buffer_size(writer, context, SMALL_BUFFER_SIZE)
+    }
+
+    /// Returns the memory used by an encoder created with `small_window()`
+    /// at the given level, in bytes.
+    pub fn small_window_requirement(level: i32) -> usize {
+        let params = small_window_params(level);
+        let context_size = unsafe {
+            ll::ZSTD_estimateCStreamSize_usingCParams(*params.as_raw())
+        };
+        context_size + SMALL_BUFFER_SIZE
+    }
+
+    fn with_context(writer: W, context: EncoderContext) -> io::Result<Self> {
+        // This is the output buffer size,
+        // for compressed data we get from zstd.
+        let buffer_size = unsafe { ll::ZSTD_CStreamOutSize() };
+
+        Encoder::with_buffer_size(writer, context, buffer_size)
+    }
+
+    fn with_buffer_size(writer: W, context: EncoderContext,
+                        buffer_size: usize)
+                        -> io::Result<Self> {
+        Ok(Encoder {
+            writer: writer,
+            buffer: Vec::with_capacity(buffer_size),
+            capacity: buffer_size,
+            offset: 0,
+            context: context,
+            observer: None,
+            adapt: None,
+            frame_pending: false,
+            frames: 0,
+            frame_per_write: false,
+            partial_writes: false,
+            frame_observer: None,
+            frame_hasher: None,
+            frame_in: 0,
+            frame_out: 0,
+            shrink_min: None,
+            watermark: 0,
+            deferred: Vec::new(),
+        })
+    }
+
+    /// Calls the given callback each time data flows through the encoder.
+    ///
+    /// The callback receives the number of uncompressed bytes consumed
+    /// and the number of compressed bytes produced since the last call.
+    pub fn on_progress<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(usize, usize)
+    {
+        self.observer = Some(Box::new(f));
+    }
+
+    /// Calls the given callback each time a frame is completed.
+    ///
+    /// This happens with `write_message()`, with
+    /// [`set_frame_per_write`](#method.set_frame_per_write),
+    /// and when `finish()` completes the last frame.
+    /// The callback receives statistics about the frame, which helps
+    /// building an index of the frames without re-reading the output.
+    ///
+    /// This must be called before any data is compressed.
+    pub fn on_frame<F>(&mut self, f: F)
+        where F: 'static + Send + FnMut(FrameStats)
+    {
+        self.frame_observer = Some(Box::new(f));
+        self.frame_hasher = Some(Xxh64::default());
+    }
+
+    /// Automatically adjusts the compression level between `min` and `max`,
+    /// depending on how fast the inner writer accepts data.
+    ///
+    /// When the writer is slow (for instance a congested network), the level
+    /// is raised since we have time to spare; when compression becomes the
+    /// bottleneck, the level is lowered.
+    ///
+    /// The new level takes effect at the next job when using multiple
+    /// workers, and at the next frame otherwise.
+    pub fn set_adaptive_level(&mut self, min: i32, max: i32)
+                              -> io::Result<()> {
+        try!(self.context.check_not_deterministic("adaptive level"));
+        let level =
+            try!(self.context.get_parameter(ll::ZSTD_c_compressionLevel));
+        let adapt = AdaptiveLevel::new(min, max, level);
+        try!(self.context
+            .set_parameter(ll::ZSTD_c_compressionLevel, adapt.level()));
+        self.adapt = Some(adapt);
+        Ok(())
+    }
+
+    /// Guarantees a byte-identical output for identical input.
+    ///
+    /// With this mode, compressing the same data with the same level,
+    /// parameters and dictionary always produces the same output,
+    /// with the same version of the zstd library.
+    ///
+    /// This disables multithreading (whose output depends on the number of
+    /// workers), and makes options depending on timing or on the
+    /// environment return an error.
+    pub fn set_deterministic(&mut self) -> io::Result<()> {
+        if self.adapt.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "adaptive level is not deterministic"));
+        }
+        self.context.set_deterministic()
+    }
+
+    /// Makes sure the frames stay readable by older or constrained
+    /// decoders.
+    ///
+    /// Settings those decoders couldn't handle then return an error when
+    /// configured, rather than producing frames that fail to decode
+    /// later: windows larger than
+    /// [`DEFAULT_WINDOW_LOG_LIMIT`](../../params/constant.DEFAULT_WINDOW_LOG_LIMIT.html),
+    /// which decoders refuse by default, and raw parameters. Versions
+    /// older than v0.8 can't read the current frame format at all, and
+    /// are refused.
+    pub fn max_format_compatibility(&mut self, version: ZstdVersion)
+                                    -> io::Result<()> {
+        self.context.set_max_format_compatibility(version)
+    }
+
+    /// Uses an external match-finder, like a hardware accelerator.
+    ///
+    /// The producer finds the sequences of each block, and zstd does the
+    /// entropy coding. With `fallback`, blocks the producer can't handle
+    /// are compressed by zstd alone; otherwise, compression fails.
+    ///
+    /// Not compatible with multithreading, nor with long distance
+    /// matching. See
+    /// [`SequenceProducer`](../../bulk/trait.SequenceProducer.html).
+    #[cfg(feature = "experimental")]
+    pub fn set_sequence_producer<P>(&mut self, producer: P, fallback: bool)
+                                    -> io::Result<()>
+        where P: SequenceProducer + 'static
+    {
+        self.context.set_sequence_producer(Box::new(producer), fallback)
+    }
+
+    /// Gives a hint about the expected size of the input.
+    ///
+    /// zstd will pick parameters tuned for this size, which helps when
+    /// compressing small inputs whose exact size isn't known in advance.
+    /// Unlike a pledged size, the actual input may differ from the hint.
+    /// Hints above `i32::MAX` return an `InvalidInput` error.
+    pub fn set_source_size_hint(&mut self, size: usize) -> io::Result<()> {
+        self.context.set_source_size_hint(size)
+    }
+
+    /// Sets a single compression parameter.
+    ///
+    /// This gives access to any parameter supported by zstd,
+    /// including those without a dedicated method.
+    pub fn set_parameter(&mut self, parameter: CParameter) -> io::Result<()> {
+        self.context.set_cparameter(parameter)
+    }
+
+    /// Sets all the compression parameters at once.
+    ///
+    /// These take precedence over the compression level.
+    /// See [`CompressionParams`](../../params/struct.CompressionParams.html).
+    pub fn set_compression_params(&mut self, params: &CompressionParams)
+                                  -> io::Result<()> {
+        self.context.set_compression_params(params)
+    }
+
+    /// Sets how literals are compressed.
+    ///
+    /// By default, zstd decides depending on the compression level.
+    pub fn set_literal_compression_mode(&mut self,
+                                        mode: LiteralCompressionMode)
+                                        -> io::Result<()> {
+        self.context.set_literal_compression_mode(mode)
+    }
+
+    /// Sets whether frame headers record the content size, when known.
+    ///
+    /// This is on by default. Turning it off saves up to 8 bytes per
+    /// frame, but decoders can no longer size their output up front.
+    pub fn set_content_size_flag(&mut self, flag: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_contentSizeFlag, flag as i32)
+    }
+
+    /// Sets whether frame headers record the dictionary ID.
+    ///
+    /// This is on by default. Turning it off hides which dictionary was
+    /// used, and saves up to 4 bytes per frame; decoders then can't check
+    /// they were given the right dictionary.
+    pub fn set_dict_id_flag(&mut self, flag: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_dictIDFlag, flag as i32)
+    }
+
+    /// Enables multithreaded compression, using `n_workers` threads.
+    ///
+    /// `0` disables multithreading (the default).
+    /// This must be called before any data is compressed.
+    #[cfg(feature = "zstdmt")]
+    pub fn multithread(&mut self, n_workers: u32) -> io::Result<()> {
+        if n_workers > 0 {
+            try!(self.context.check_not_deterministic("multithreading"));
+        }
+        self.context.set_parameter(ll::ZSTD_c_nbWorkers, n_workers as i32)
+    }
+
+    /// Enables multithreading with a number of workers suited to the input.
+    ///
+    /// `expected_src_size` is the size of the data to compress, or 0 if
+    /// unknown. See [`choose_workers`](../../params/fn.choose_workers.html).
+    /// In deterministic mode, this keeps compression single-threaded.
+    ///
+    /// Returns the number of workers chosen.
+    #[cfg(feature = "zstdmt")]
+    pub fn auto_workers(&mut self, expected_src_size: u64) -> io::Result<u32> {
+        let n_workers = if self.context.deterministic {
+            0
+        } else {
+            ::params::choose_workers(expected_src_size)
+        };
+        try!(self.multithread(n_workers));
+        Ok(n_workers)
+    }
+
+    /// Enables rsyncable mode.
+    ///
+    /// The compressed output will contain synchronization points,
+    /// so that changes in the input only affect nearby compressed data,
+    /// making tools like `rsync` effective on the compressed files.
+    /// This slightly reduces the compression ratio.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_rsyncable(&mut self, rsyncable: bool) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_rsyncable, rsyncable as i32)
+    }
+
+    /// Sets the size of each compression job, in bytes.
+    ///
+    /// Each worker compresses one job at a time: larger jobs improve the
+    /// compression ratio, smaller jobs improve parallelism.
+    /// `0` lets zstd pick a size depending on the compression parameters.
+    /// Sizes above 1GB (512MB on 32-bit platforms) return an
+    /// `InvalidInput` error.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_job_size(&mut self, size: u32) -> io::Result<()> {
+        self.context.set_cparameter(CParameter::JobSize(size))
+    }
+
+    /// Sets how much data from the previous job is reloaded by each job,
+    /// as a fraction of the window size.
+    ///
+    /// From `1` (no overlap) to `9` (the whole window); `0` is the default.
+    /// More overlap improves the compression ratio, but slows down
+    /// compression.
+    ///
+    /// This only has an effect when using multiple workers
+    /// (see `multithread()`).
+    #[cfg(feature = "zstdmt")]
+    pub fn set_overlap_log(&mut self, overlap_log: u32) -> io::Result<()> {
+        self.context.set_parameter(ll::ZSTD_c_overlapLog, overlap_log as i32)
+    }
+
+    /// Makes each call to `write()` produce a complete frame.
+    ///
+    /// See [`write_message()`](#method.write_message).
+    pub fn set_frame_per_write(&mut self, frame_per_write: bool) {
+        self.frame_per_write = frame_per_write;
+    }
+
+    /// Lets `write()` return before all compressed output was sent.
+    ///
+    /// By default, `write()` only returns once the inner writer accepted
+    /// all the compressed data. With partial writes, it returns as soon as
+    /// the writer stops accepting data (a short write or a `WouldBlock`
+    /// error), with the number of input bytes consumed so far.
+    /// Compressed data not yet sent is kept, and sent first on the next
+    /// call. This suits non-blocking writers and cooperative schedulers.
+    ///
+    /// `flush()` and `finish()` still send everything.
+    pub fn set_partial_writes(&mut self, partial_writes: bool) {
+        self.partial_writes = partial_writes;
+    }
+
+    /// Returns the capacity of the output buffer, in bytes.
+    ///
+    /// Compressed data is staged there before being sent to the writer.
+    pub fn buffer_capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Changes the capacity of the output buffer.
+    ///
+    /// The default is `ZSTD_CStreamOutSize()`, about 128kB. A smaller
+    /// buffer saves memory for encoders handling tiny payloads, at the
+    /// cost of more calls to the inner writer.
+    /// Data not yet sent is kept, even if it exceeds `capacity`.
+    pub fn set_buffer_capacity(&mut self, capacity: usize) {
+        let staged = &self.buffer[self.offset..];
+        let capacity = cmp::max(capacity, 1);
+        let mut buffer = Vec::with_capacity(cmp::max(capacity, staged.len()));
+        buffer.extend_from_slice(staged);
+        self.buffer = buffer;
+        self.capacity = capacity;
+        self.offset = 0;
+    }
+
+    /// Shrinks the output buffer between frames.
+    ///
+    /// When a frame is completed, the buffer is shrunk to the largest
+    /// output staged at once during that frame, but not below
+    /// `min_capacity`. This reclaims memory from long-lived encoders
+    /// which stay idle between small frames. The buffer grows back to
+    /// its full capacity when compression resumes. `None` disables
+    /// shrinking (the default).
+    pub fn set_shrink_policy(&mut self, min_capacity: Option<usize>) {
+        self.shrink_min = min_capacity.map(|min| cmp::max(min, 1));
+    }
+
+    /// Compresses `message` as its own frame, and sends it to the writer.
+    ///
+    /// The frame can be decompressed independently of the other ones,
+    /// and its header records the size of the message.
+    /// The dictionary and parameters are kept for the following frames.
+    ///
+    /// Any data written before with `write()` is first completed
+    /// as a separate frame.
+    pub fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.frame_pending {
+            try!(self.end_frame());
+        }
+
+        try!(self.context.set_pledged_src_size(message.len() as u64));
+        self.hash_input(message);
+        self.frame_pending = true;
+        let mut input = ll::ZSTD_inBuffer::new(message);
+        while try!(self.run(&mut input, ll::ZSTD_e_end)) != 0 {}
+        Ok(())
+    }
+
+    /// Ends the current frame, sends it, and flushes the writer.
+    ///
+    /// Unlike `flush()`, which keeps the frame open, this completes it:
+    /// the output so far is a complete stream, which any decoder accepts.
+    /// The next writes start a new frame.
+    pub fn flush_end(&mut self) -> io::Result<()> {
+        if self.frame_pending {
+            try!(self.end_frame());
+        }
+        try!(self.write_staged());
+        self.writer.flush()
+    }
+
+    /// Returns the progress of the current frame.
+    ///
+    /// When using multiple threads, the compressed output may lag
+    /// significantly behind the input.
+    pub fn progress(&self) -> FrameProgression {
+        let mut progression = self.context.progression();
+        progression.ingested += self.deferred.len() as u64;
+        progression
+    }
+
+    /// Finishes the stream. You *need* to call this after writing your stuff.
+    ///
+    /// This returns the inner writer in case you need it.
+    pub fn finish(mut self) -> io::Result<W> {
+
+        // Closes the stream, and write the end out.
+        // If we never wrote anything, this writes an empty frame.
+        if self.frame_pending || self.frames == 0 {
+            try!(self.end_frame());
+        }
+        // The frame may have ended earlier, but not been sent.
+        try!(self.write_staged());
+
+        // Return the writer, because why not
+        Ok(self.writer)
+    }
+
+    /// Completes the current frame, and switches to another writer.
+    ///
+    /// The context, with its dictionary and parameters, is kept for the
+    /// following frames, which go to `writer`. This suits log rotation.
+    /// Like with `finish()`, if nothing was written to the previous
+    /// writer, it receives an empty frame. Frame indexes, as given to
+    /// [`on_frame`](#method.on_frame), start again from 0.
+    ///
+    /// Returns the previous writer, flushed.
+    pub fn replace_writer(&mut self, writer: W) -> io::Result<W> {
+        if self.frame_pending || self.frames == 0 {
+            try!(self.end_frame());
+        }
+        try!(self.write_staged());
+        try!(self.writer.flush());
+        self.frames = 0;
+        Ok(mem::replace(&mut self.writer, writer))
+    }
+
+    /// Return a recommendation for the size of data to write at once.
+    pub fn recommended_input_size() -> usize {
+        unsafe { ll::ZSTD_CStreamInSize() }
+    }
+
+    // Completes the current frame.
+    fn end_frame(&mut self) -> io::Result<()> {
+        if self.deferred.is_empty() {
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            while try!(self.run(&mut input, ll::ZSTD_e_end)) != 0 {}
+        } else {
+            try!(self.compress_deferred());
+        }
+        Ok(())
+    }
+
+    // Compresses the deferred input as a whole frame.
+    //
+    // When the output fits in our buffer, this is a single call to zstd,
+    // rather than the streaming sequence.
+    fn compress_deferred(&mut self) -> io::Result<()> {
+        try!(self.write_staged());
+        self.restore_buffer();
+        let data = mem::replace(&mut self.deferred, Vec::new());
+        let bound = unsafe { ll::ZSTD_compressBound(data.len()) };
+        let result = if bound <= self.buffer.capacity() {
+            self.compress_whole(&data)
+        } else {
+            let mut input = ll::ZSTD_inBuffer::new(&data);
+            loop {
+                match self.run(&mut input, ll::ZSTD_e_end) {
+                    Ok(0) => break Ok(()),
+                    Ok(_) => (),
+                    Err(e) => break Err(e),
+                }
+            }
+        };
+        // Keep the allocation for the next frame.
+        self.deferred = data;
+        self.deferred.clear();
+        result
+    }
+
+    // Compresses `data` as a frame into our buffer, and sends it.
+    //
+    // The buffer must be empty, and large enough for the whole frame.
+    fn compress_whole(&mut self, data: &[u8]) -> io::Result<()> {
+        let capacity = self.buffer.capacity();
+        let code = unsafe {
+            ll::ZSTD_compress2(self.context.c,
+                               self.buffer.as_mut_ptr(),
+                               capacity,
+                               data.as_ptr(),
+                               data.len())
+        };
+        let written = try!(ll::parse_code(code));
+        unsafe {
+            self.buffer.set_len(written);
+        }
+        self.offset = 0;
+        self.frame_in += data.len() as u64;
+        self.frame_out += written as u64;
+        self.watermark = cmp::max(self.watermark, written);
+        self.notify(data.len(), written);
+        // The frame is complete, even if it isn't sent yet.
+        self.frame_pending = false;
+        self.frame_done();
+        self.write_staged()
+    }
+
+    // Hands the deferred input to zstd, to continue the frame as a
+    // stream.
+    fn spill_deferred(&mut self) -> io::Result<()> {
+        if self.deferred.is_empty() {
+            return Ok(());
+        }
+        let data = mem::replace(&mut self.deferred, Vec::new());
+        let mut input = ll::ZSTD_inBuffer::new(&data);
+        // Once zstd took the input, the output is kept on error, and sent
+        // on the next call.
+        let mut result = Ok(());
+        while input.pos != input.size {
+            if let Err(e) = self.run(&mut input, ll::ZSTD_e_continue) {
+                result = Err(e);
+                break;
+            }
+        }
+        self.deferred = data;
+        self.deferred.drain(..input.pos);
+        result
+    }
+
+    // Wraps up statistics for the frame that just ended.
+    fn frame_done(&mut self) {
+        if let Some(ref mut observer) = self.frame_observer {
+            let hasher = self.frame_hasher.as_mut().unwrap();
+            observer(FrameStats {
+                index: self.frames,
+                uncompressed_size: self.frame_in,
+                compressed_size: self.frame_out,
+                checksum: hasher.digest() as u32,
+            });
+            hasher.reset(0);
+        }
+        self.frames += 1;
+        self.frame_in = 0;
+        self.frame_out = 0;
+        if let Some(min) = self.shrink_min {
+            self.buffer.shrink_to(cmp::max(self.watermark, min));
+        }
+        self.watermark = 0;
+    }
+
+    // Accounts for input added to the current frame.
+    fn hash_input(&mut self, data: &[u8]) {
+        if let Some(ref mut hasher) = self.frame_hasher {
+            hasher.update(data);
+        }
+    }
+
+    // Compress some input into our output buffer, and send it to the writer.
+    //
+    // Returns zstd's result code.
+    fn run(&mut self, input: &mut ll::ZSTD_inBuffer,
+           directive: ll::ZSTD_EndDirective)
+           -> io::Result<usize> {
+        // Leftovers from a partial write go first.
+        try!(self.write_staged());
+
+        let start = input.pos;
+        let compress_start = Instant::now();
+        let remaining = try!(self.compress(input, directive));
+
+        let write_start = Instant::now();
+        try!(self.write_staged());
+
+        if let Some(ref mut adapt) = self.adapt {
+            let compressing = write_start.duration_since(compress_start);
+            let writing = write_start.elapsed();
+            if let Some(level) =
+                   adapt.record(input.pos - start, compressing, writing) {
+                try!(self.context
+                    .set_parameter(ll::ZSTD_c_compressionLevel, level));
+            }
+        }
+
+        Ok(remaining)
+    }
+
+    // Compress some input into our output buffer.
+    //
+    // Everything in the buffer must already have been sent.
+    // Returns zstd's result code.
+    fn compress(&mut self, input: &mut ll::ZSTD_inBuffer,
+                directive: ll::ZSTD_EndDirective)
+                -> io::Result<usize> {
+        self.restore_buffer();
+        let buffer_size = self.buffer.capacity();
+        unsafe {
+            self.buffer.set_len(buffer_size);
+        }
+        let mut output = ll::ZSTD_outBuffer::new(&mut self.buffer);
+        let start = input.pos;
+
+        let code = unsafe {
+            ll::ZSTD_compressStream2(self.context.c,
+                                     &mut output,
+                                     input,
+                                     directive)
+        };
+        unsafe {
+            self.buffer.set_len(output.pos);
+        }
+        self.offset = 0;
+        let remaining = try!(ll::parse_code(code));
+        self.frame_in += (input.pos - start) as u64;
+        self.frame_out += output.pos as u64;
+        self.watermark = cmp::max(self.watermark, output.pos);
+        self.notify(input.pos - start, output.pos);
+        if directive == ll::ZSTD_e_end && remaining == 0 {
+            // The frame is complete, even if it isn't sent yet.
+            self.frame_pending = false;
+            self.frame_done();
+        }
+
+        Ok(remaining)
+    }
+
+    // Empties the buffer, and gets it back to its full capacity after
+    // a shrink.
+    //
+    // Everything in the buffer must already have been sent.
+    fn restore_buffer(&mut self) {
+        self.buffer.clear();
+        self.offset = 0;
+        self.buffer.reserve(self.capacity);
+    }
+
+    // Sends everything left in the buffer to the writer.
+    //
+    // On error, the data not yet sent is kept for the next attempt.
+    fn write_staged(&mut self) -> io::Result<()> {
+        loop {
+            match self.write_staged_partial() {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Sends as much of the buffer as the writer accepts in a single call.
+    //
+    // Returns `true` once everything was sent.
+    fn write_staged_partial(&mut self) -> io::Result<bool> {
+        if self.offset < self.buffer.len() {
+            let written = try!(self.writer.write(&self.buffer[self.offset..]));
+            if written == 0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero,
+                                          "failed to write compressed data"));
+            }
+            self.offset += written;
+        }
+        Ok(self.offset == self.buffer.len())
+    }
+
+    // Compresses as much of `buf` as the writer lets us.
+    fn write_partial(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.spill_deferred());
+        self.frame_pending = true;
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        loop {
+            match self.write_staged_partial() {
+                Ok(true) => (),
+                // The writer is pushing back: report what we took so far.
+                Ok(false) if input.pos > 0 => break,
+                Ok(false) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock &&
+                              input.pos > 0 => break,
+                Err(e) => return Err(e),
+            }
+            if input.pos == input.size {
+                break;
+            }
+            let _ = try!(self.compress(&mut input, ll::ZSTD_e_continue));
+        }
+        self.hash_input(&buf[..input.pos]);
+        Ok(input.pos)
+    }
+
+    // Tell the observer, if any, about the data that just went through.
+    fn notify(&mut self, consumed: usize, produced: usize) {
+        if consumed == 0 && produced == 0 {
+            return;
+        }
+        if let Some(ref mut observer) = self.observer {
+            observer(consumed, produced);
+        }
+    }
+}
+
+// Parameters used by `Encoder::small_window`.
+fn small_window_params(level: i32) -> CompressionParams {
+    // Parameters for tiny inputs are tuned for a tiny window.
+    let mut params = CompressionParams::for_level(level,
+                                                  1 << SMALL_WINDOW_LOG);
+    params.set_window_log(SMALL_WINDOW_LOG);
+    params
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.frame_per_write {
+            try!(self.write_message(buf));
+            return Ok(buf.len());
+        }
+        if self.partial_writes {
+            return self.write_partial(buf);
+        }
+
+        // Small frames are kept for a single call to zstd at the end, as
+        // long as nothing was streamed yet.
+        if self.frame_in == 0 &&
+           self.deferred.len() + buf.len() <= SMALL_INPUT_SIZE {
+            self.frame_pending = true;
+            self.deferred.extend_from_slice(buf);
+            self.hash_input(buf);
+            return Ok(buf.len());
+        }
+        try!(self.spill_deferred());
+
+        self.frame_pending = true;
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        while input.pos != input.size {
+            // Do we care about the hint?
+            match self.run(&mut input, ll::ZSTD_e_continue) {
+                Ok(_) => (),
+                // The writer failed, but some input was already compressed:
+                // report it. The output is kept and sent on the next call.
+                Err(_) if input.pos > 0 &&
+                          self.offset < self.buffer.len() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.hash_input(&buf[..input.pos]);
+        Ok(input.pos)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        if self.frame_per_write || self.partial_writes {
+            // One slice at a time, like the default implementation.
+            return match bufs.iter().find(|buf| !buf.is_empty()) {
+                Some(buf) => self.write(buf),
+                None => Ok(0),
+            };
+        }
+
+        // Each slice continues the same frame, without copying them
+        // together first.
+        let mut written = 0;
+        for buf in bufs {
+            match self.write(buf) {
+                Ok(n) => {
+                    written += n;
+                    if n < buf.len() {
+                        break;
+                    }
+                }
+                // Report the slices already taken.
+                Err(_) if written > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(written)
+    }
+
+    /// Sends everything written so far, and flushes the writer.
+    ///
+    /// Afterwards, a peer can decompress all the data written so far,
+    /// without waiting for more: zstd is flushed until nothing is left in
+    /// the context. The frame goes on with the next writes; see
+    /// [`flush_end()`](#method.flush_end) to end it instead.
+    fn flush(&mut self) -> io::Result<()> {
+        // Don't start a new frame just to flush it.
+        if self.frame_pending {
+            try!(self.spill_deferred());
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            while try!(self.run(&mut input, ll::ZSTD_e_flush)) != 0 {}
+        } else {
+            try!(self.write_staged());
+        }
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,17 @@
+//! Implement push-based `Write` trait for both compressing and decompressing.
+
+mod encoder;
+mod decoder;
+mod digest;
+mod resumable;
+mod scratch;
+mod sized;
+mod tee;
+
+pub use self::decoder::Decoder;
+pub use self::digest::{Digest, DigestEncoder, DigestReport};
+pub use self::encoder::{AutoFinishEncoder, Encoder};
+pub use self::resumable::{Checkpoint, ResumableEncoder};
+pub use self::scratch::ScratchEncoder;
+pub use self::sized::SizedEncoder;
+pub use self::tee::Tee;
@@ -0,0 +1,223 @@
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use bulk::Compressor;
+
+/// A frame boundary, recorded by a
+/// [`ResumableEncoder`](struct.ResumableEncoder.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Checkpoint {
+    /// Input consumed up to this boundary.
+    pub uncompressed: u64,
+    /// Output written up to this boundary.
+    pub compressed: u64,
+}
+
+/// An encoder that can resume after a crash, like for resumable uploads.
+///
+/// The input is cut in frames of a fixed size, each compressed on its own.
+/// A [`Checkpoint`](struct.Checkpoint.html) is recorded after each frame.
+/// After a crash, compression restarts from the last complete frame:
+/// keep the output up to that checkpoint, and feed the input from there.
+///
+/// Compression is deterministic for a given zstd version, level and frame
+/// size, so the output is the same as without the crash.
+///
+/// Don't forget to call `finish()` before dropping it!
+pub struct ResumableEncoder<W: Write> {
+    writer: W,
+    compressor: Compressor<'static>,
+    level: i32,
+    frame_size: usize,
+    // input of the current frame
+    buffer: Vec<u8>,
+    checkpoints: Vec<Checkpoint>,
+    // where we started, when resuming
+    start: Checkpoint,
+}
+
+impl<W: Write> ResumableEncoder<W> {
+    /// Creates a new encoder, cutting frames every `frame_size` bytes of
+    /// input.
+    ///
+    /// # Panics
+    ///
+    /// If `frame_size` is 0.
+    pub fn new(writer: W, level: i32, frame_size: usize) -> Self {
+        ResumableEncoder::resume_at(writer,
+                                    level,
+                                    frame_size,
+                                    Checkpoint::default())
+    }
+
+    /// Creates an encoder resuming from a known checkpoint.
+    ///
+    /// `writer` must continue the output right after
+    /// `checkpoint.compressed` bytes, and the following input must start
+    /// at `checkpoint.uncompressed`. `level` and `frame_size` must be the
+    /// same as for the interrupted encoder.
+    ///
+    /// # Panics
+    ///
+    /// If `frame_size` is 0.
+    pub fn resume_at(writer: W, level: i32, frame_size: usize,
+                     checkpoint: Checkpoint)
+                     -> Self {
+        assert!(frame_size > 0, "frame size must not be 0");
+        ResumableEncoder {
+            writer: writer,
+            compressor: Compressor::new(),
+            level: level,
+            frame_size: frame_size,
+            buffer: Vec::with_capacity(frame_size),
+            checkpoints: Vec::new(),
+            start: checkpoint,
+        }
+    }
+
+    /// Creates an encoder resuming after a crash, from the length of the
+    /// output that made it.
+    ///
+    /// The original input is compressed again, without writing anything,
+    /// to find the last frame completed within `output_len` bytes. The
+    /// input is then left at that checkpoint, ready to be fed to the
+    /// returned encoder, and `writer` must continue the output from
+    /// [`last_checkpoint().compressed`](#method.last_checkpoint): any
+    /// output after it is an incomplete frame, to be dropped.
+    ///
+    /// # Panics
+    ///
+    /// If `frame_size` is 0.
+    pub fn resume<R: Read + Seek>(input: &mut R, writer: W, level: i32,
+                                  frame_size: usize, output_len: u64)
+                                  -> io::Result<Self> {
+        let mut encoder = ResumableEncoder::new(writer, level, frame_size);
+        try!(input.seek(SeekFrom::Start(0)));
+        let mut chunk = vec![0u8; frame_size];
+        loop {
+            let read = try!(read_full(input, &mut chunk));
+            if read == 0 {
+                break;
+            }
+            let compressed = try!(encoder.compressor
+                .compress(&chunk[..read], level));
+            let last = encoder.last_checkpoint();
+            let checkpoint = Checkpoint {
+                uncompressed: last.uncompressed + read as u64,
+                compressed: last.compressed + compressed.len() as u64,
+            };
+            if checkpoint.compressed > output_len {
+                break;
+            }
+            encoder.checkpoints.push(checkpoint);
+        }
+        let uncompressed = encoder.last_checkpoint().uncompressed;
+        try!(input.seek(SeekFrom::Start(uncompressed)));
+        Ok(encoder)
+    }
+
+    /// Returns the checkpoints recorded so far, one per frame.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Returns the last checkpoint, where compression would resume.
+    pub fn last_checkpoint(&self) -> Checkpoint {
+        self.checkpoints.last().cloned().unwrap_or(self.start)
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Compresses the last frame, and returns the inner writer.
+    ///
+    /// If no data was written at all, an empty frame is written, so the
+    /// output is always valid.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() ||
+           self.last_checkpoint() == Checkpoint::default() {
+            try!(self.write_frame());
+        }
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    // Compresses the buffered input as a frame.
+    fn write_frame(&mut self) -> io::Result<()> {
+        let compressed = try!(self.compressor
+            .compress(&self.buffer, self.level));
+        try!(self.writer.write_all(&compressed));
+        let last = self.last_checkpoint();
+        self.checkpoints.push(Checkpoint {
+            uncompressed: last.uncompressed + self.buffer.len() as u64,
+            compressed: last.compressed + compressed.len() as u64,
+        });
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ResumableEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full frame is only compressed with the next data, so that
+        // errors are reported before taking it.
+        if self.buffer.len() == self.frame_size {
+            try!(self.write_frame());
+        }
+        let len = cmp::min(buf.len(), self.frame_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.len() == self.frame_size {
+            try!(self.write_frame());
+        }
+        self.writer.flush()
+    }
+}
+
+// Reads until `buf` is full or the input ends.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+#[test]
+fn test_resumable_encoder() {
+    use std::io::Cursor;
+
+    let input: Vec<u8> = (0..50000u32).map(|i| (i % 251) as u8).collect();
+    let mut encoder = ResumableEncoder::new(Vec::new(), 3, 8192);
+    encoder.write_all(&input).unwrap();
+    encoder.flush().unwrap();
+    assert_eq!(encoder.checkpoints().len(), 6);
+    let complete = encoder.finish().unwrap();
+
+    // The upload stopped in the middle of a frame.
+    let mut source = Cursor::new(&input);
+    let crash = (complete.len() / 2) as u64;
+    let mut encoder =
+        ResumableEncoder::resume(&mut source, Vec::new(), 3, 8192, crash)
+            .unwrap();
+    let checkpoint = encoder.last_checkpoint();
+    assert!(checkpoint.compressed <= crash);
+    assert_eq!(source.position(), checkpoint.uncompressed);
+    assert_eq!(checkpoint.uncompressed % 8192, 0);
+
+    io::copy(&mut source, &mut encoder).unwrap();
+    let rest = encoder.finish().unwrap();
+    let mut resumed = complete[..checkpoint.compressed as usize].to_vec();
+    resumed.extend_from_slice(&rest);
+    assert_eq!(resumed, complete);
+}
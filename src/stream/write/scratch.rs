@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+use std::marker::PhantomData;
+
+use ll;
+#[cfg(feature = "experimental")]
+use bulk::workspace::StaticCompressor;
+use context::EncoderContext;
+
+/// An encoder staging its output in a caller-provided buffer.
+///
+/// Unlike [`Encoder`](struct.Encoder.html), it allocates nothing itself:
+/// the output goes through `buffer`, which can live on the stack or in a
+/// static. With the `experimental` feature,
+/// [`with_workspace`](#method.with_workspace) also puts the compression
+/// context in caller-provided memory, so no allocation happens at all.
+///
+/// Any buffer size works; smaller ones only mean more calls to the
+/// writer. If the writer fails, compressed data it didn't accept is kept
+/// and sent first on the next call.
+///
+/// Don't forget to call `finish()` before dropping it!
+pub struct ScratchEncoder<'a, W: Write> {
+    writer: W,
+    buffer: &'a mut [u8],
+    // staged output, not sent yet
+    offset: usize,
+    len: usize,
+    c: ll::ZSTDCompressionContext,
+    // owns `c`, unless it lives in a workspace
+    _context: Option<EncoderContext>,
+    workspace: PhantomData<&'a mut [u8]>,
+    // whether some data was written since the last frame ended
+    frame_pending: bool,
+}
+
+impl<'a, W: Write> ScratchEncoder<'a, W> {
+    /// Creates a new encoder, staging its output in `buffer`.
+    ///
+    /// Fails if `buffer` is empty.
+    pub fn new(writer: W, level: i32, buffer: &'a mut [u8])
+               -> io::Result<Self> {
+        let context = try!(EncoderContext::new());
+        let c = context.c;
+        ScratchEncoder::with_context(writer, level, buffer, c, Some(context))
+    }
+
+    /// Creates a new encoder whose context lives in `workspace`.
+    ///
+    /// The workspace must be 8-bytes aligned, and at least
+    /// [`workspace_size(level)`](#method.workspace_size) bytes.
+    #[cfg(feature = "experimental")]
+    pub fn with_workspace(writer: W, level: i32, buffer: &'a mut [u8],
+                          workspace: &'a mut [u8])
+                          -> io::Result<Self> {
+        let c = try!(StaticCompressor::new(workspace)).c;
+        ScratchEncoder::with_context(writer, level, buffer, c, None)
+    }
+
+    /// Returns the workspace size needed to stream at the given level.
+    #[cfg(feature = "experimental")]
+    pub fn workspace_size(level: i32) -> usize {
+        unsafe { ll::ZSTD_estimateCStreamSize(level) }
+    }
+
+    fn with_context(writer: W, level: i32, buffer: &'a mut [u8],
+                    c: ll::ZSTDCompressionContext,
+                    context: Option<EncoderContext>)
+                    -> io::Result<Self> {
+        if buffer.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "scratch buffer is empty"));
+        }
+        try!(ll::parse_code(unsafe {
+            ll::ZSTD_CCtx_setParameter(c, ll::ZSTD_c_compressionLevel, level)
+        }));
+        Ok(ScratchEncoder {
+            writer: writer,
+            buffer: buffer,
+            offset: 0,
+            len: 0,
+            c: c,
+            _context: context,
+            workspace: PhantomData,
+            frame_pending: false,
+        })
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Finishes the stream, and returns the inner writer.
+    ///
+    /// If nothing was written, this writes an empty frame.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut input = ll::ZSTD_inBuffer::new(&[]);
+        while try!(self.run(&mut input, ll::ZSTD_e_end)) != 0 {}
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    // Sends the staged output, then compresses some input into the buffer
+    // and sends that too.
+    //
+    // Returns zstd's result code.
+    fn run(&mut self, input: &mut ll::ZSTD_inBuffer,
+           directive: ll::ZSTD_EndDirective)
+           -> io::Result<usize> {
+        try!(self.write_staged());
+        let mut output = ll::ZSTD_outBuffer::new(self.buffer);
+        let remaining = try!(ll::parse_code(unsafe {
+            ll::ZSTD_compressStream2(self.c, &mut output, input, directive)
+        }));
+        self.offset = 0;
+        self.len = output.pos;
+        try!(self.write_staged());
+        Ok(remaining)
+    }
+
+    // Sends everything staged to the writer.
+    //
+    // On error, the data not yet sent is kept for the next attempt.
+    fn write_staged(&mut self) -> io::Result<()> {
+        while self.offset < self.len {
+            match self.writer.write(&self.buffer[self.offset..self.len]) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero,
+                                              "failed to write compressed \
+                                               data"))
+                }
+                Ok(n) => self.offset += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Write for ScratchEncoder<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.frame_pending = true;
+        let mut input = ll::ZSTD_inBuffer::new(buf);
+        while input.pos != input.size {
+            match self.run(&mut input, ll::ZSTD_e_continue) {
+                Ok(_) => (),
+                // Report the input already taken; the output is kept.
+                Err(_) if input.pos > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(input.pos)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.frame_pending {
+            let mut input = ll::ZSTD_inBuffer::new(&[]);
+            while try!(self.run(&mut input, ll::ZSTD_e_flush)) != 0 {}
+        }
+        self.writer.flush()
+    }
+}
+
+// Like other contexts, it can be used from any thread, one at a time.
+unsafe impl<'a, W: Write + Send> Send for ScratchEncoder<'a, W> {}
+
+#[test]
+fn test_scratch_encoder() {
+    let text = vec![b'y'; 100000];
+    let mut buffer = [0u8; 64];
+    let mut encoder = ScratchEncoder::new(Vec::new(), 3, &mut buffer)
+        .unwrap();
+    encoder.write_all(&text).unwrap();
+    encoder.flush().unwrap();
+    encoder.write_all(b"end").unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut expected = text.clone();
+    expected.extend_from_slice(b"end");
+    assert_eq!(::decode_all(&compressed).unwrap(), expected);
+
+    assert!(ScratchEncoder::new(Vec::new(), 3, &mut []).is_err());
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_scratch_workspace() {
+    use std::slice;
+
+    let size = ScratchEncoder::<Vec<u8>>::workspace_size(1);
+    let mut aligned = vec![0u64; (size + 7) / 8];
+    let workspace = unsafe {
+        slice::from_raw_parts_mut(aligned.as_mut_ptr() as *mut u8,
+                                  aligned.len() * 8)
+    };
+    let mut buffer = [0u8; 256];
+    let mut encoder =
+        ScratchEncoder::with_workspace(Vec::new(), 1, &mut buffer, workspace)
+            .unwrap();
+    encoder.write_all(b"no allocation in here").unwrap();
+    let compressed = encoder.finish().unwrap();
+    assert_eq!(&::decode_all(&compressed).unwrap()[..],
+               &b"no allocation in here"[..]);
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use stream::write::Encoder;
+
+// Frame header descriptor flags.
+const SINGLE_SEGMENT_FLAG: u8 = 0x20;
+const CONTENT_SIZE_MASK: u8 = 0xC0;
+
+// Rewrites the frame header so it has room for an 8-byte content size,
+// and remembers where that size goes.
+struct HeaderWriter<W: Write + Seek> {
+    writer: W,
+    // header received so far, until it's complete
+    header: Vec<u8>,
+    // offset of the content size field in `writer`, once written
+    size_offset: Option<u64>,
+    // whether the header was sent
+    done: bool,
+}
+
+impl<W: Write + Seek> HeaderWriter<W> {
+    // Returns the complete header length, if known yet.
+    fn header_len(&self) -> Option<usize> {
+        // Magic number, frame header descriptor, window descriptor.
+        if self.header.len() < 6 {
+            return None;
+        }
+        let dict_id_len = match self.header[4] & 0x03 {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            _ => 4,
+        };
+        Some(6 + dict_id_len)
+    }
+
+    fn send_header(&mut self) -> io::Result<()> {
+        let descriptor = self.header[4];
+        if descriptor & (CONTENT_SIZE_MASK | SINGLE_SEGMENT_FLAG) != 0 {
+            // zstd already knew the size (for an empty frame).
+            try!(self.writer.write_all(&self.header));
+        } else {
+            self.header[4] |= CONTENT_SIZE_MASK;
+            try!(self.writer.write_all(&self.header));
+            let offset = try!(self.writer.seek(SeekFrom::Current(0)));
+            // Placeholder, filled in by `finish`.
+            try!(self.writer.write_all(&[0u8; 8]));
+            self.size_offset = Some(offset);
+        }
+        self.done = true;
+        Ok(())
+    }
+
+    fn finish(mut self, size: u64) -> io::Result<W> {
+        if let Some(offset) = self.size_offset {
+            let end = try!(self.writer.seek(SeekFrom::Current(0)));
+            try!(self.writer.seek(SeekFrom::Start(offset)));
+            let bytes: Vec<u8> = (0..8).map(|i| (size >> (8 * i)) as u8)
+                .collect();
+            try!(self.writer.write_all(&bytes));
+            try!(self.writer.seek(SeekFrom::Start(end)));
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> Write for HeaderWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.done {
+            return self.writer.write(buf);
+        }
+
+        // Take the header byte by byte: it's short, and we must not
+        // swallow any of the data after it.
+        for (i, &byte) in buf.iter().enumerate() {
+            if self.header_len() == Some(self.header.len()) {
+                try!(self.send_header());
+                return Ok(i);
+            }
+            self.header.push(byte);
+        }
+        if self.header_len() == Some(self.header.len()) {
+            try!(self.send_header());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// An encoder recording the content size in the frame header.
+///
+/// Usually, the content size is only written in the header if it's known
+/// up front. This encoder instead reserves room for it, and fills it in
+/// on `finish()`, by seeking back in the writer. Decoders can then use it
+/// to allocate their output, or to report progress.
+///
+/// It writes a single frame. Don't forget to call `finish()` before
+/// dropping it!
+pub struct SizedEncoder<W: Write + Seek> {
+    encoder: Encoder<HeaderWriter<W>>,
+    size: u64,
+}
+
+impl<W: Write + Seek> SizedEncoder<W> {
+    /// Creates a new encoder.
+    ///
+    /// The frame starts at the current position of `writer`.
+    pub fn new(writer: W, level: i32) -> io::Result<Self> {
+        let writer = HeaderWriter {
+            writer: writer,
+            header: Vec::new(),
+            size_offset: None,
+            done: false,
+        };
+        Ok(SizedEncoder {
+            encoder: try!(Encoder::new(writer, level)),
+            size: 0,
+        })
+    }
+
+    /// Finishes the frame, and writes the content size in its header.
+    ///
+    /// The writer is left at the end of the frame.
+    pub fn finish(self) -> io::Result<W> {
+        let writer = try!(self.encoder.finish());
+        writer.finish(self.size)
+    }
+}
+
+impl<W: Write + Seek> Write for SizedEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.encoder.write(buf));
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+#[test]
+fn test_sized_encoder() {
+    use std::io::Cursor;
+    use stream::FrameIter;
+
+    let text = vec![7u8; 100000];
+    let mut encoder = SizedEncoder::new(Cursor::new(Vec::new()), 3)
+        .unwrap();
+    for chunk in text.chunks(1000) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let compressed = encoder.finish().unwrap().into_inner();
+
+    let frames: Vec<_> = FrameIter::new(&compressed[..])
+        .map(|frame| frame.unwrap())
+        .collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].header.content_size, Some(text.len() as u64));
+    assert_eq!(frames[0].data, text);
+
+    // An empty frame already has its size.
+    let encoder = SizedEncoder::new(Cursor::new(Vec::new()), 3).unwrap();
+    let compressed = encoder.finish().unwrap().into_inner();
+    assert_eq!(::decode_all(&compressed[..]).unwrap(), b"");
+}
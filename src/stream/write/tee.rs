@@ -0,0 +1,131 @@
+use std::cmp;
+use std::io::{self, Write};
+
+/// A writer sending the same data to multiple sinks.
+///
+/// Wrap it in an [`Encoder`](struct.Encoder.html) to compress once and
+/// send the result to several places, like a local file and an upload:
+///
+/// ```ignore
+/// let sinks: Vec<Box<Write>> = vec![Box::new(file), Box::new(upload)];
+/// let mut encoder = Encoder::new(Tee::new(sinks), 3)?;
+/// ```
+///
+/// Sinks may accept different amounts of data in each call: `write()`
+/// reports what all of them accepted, and remembers how far ahead each
+/// sink is. Like the `Write` contract requires, the caller must write
+/// the rest of the data again; each sink then skips what it already has.
+/// `write_all()` and the encoders do this.
+///
+/// The first error from a sink is returned; later calls retry that sink.
+pub struct Tee<W: Write> {
+    sinks: Vec<W>,
+    // bytes of the upcoming data each sink already accepted
+    ahead: Vec<usize>,
+}
+
+impl<W: Write> Tee<W> {
+    /// Creates a new tee writing to the given sinks.
+    pub fn new(sinks: Vec<W>) -> Self {
+        let ahead = vec![0; sinks.len()];
+        Tee {
+            sinks: sinks,
+            ahead: ahead,
+        }
+    }
+
+    /// Returns the sinks.
+    pub fn get_ref(&self) -> &[W] {
+        &self.sinks
+    }
+
+    /// Flushes all the sinks, and returns them.
+    ///
+    /// Each sink is flushed even if another one fails;
+    /// the first error is returned.
+    pub fn finish(mut self) -> io::Result<Vec<W>> {
+        try!(self.flush());
+        Ok(self.sinks)
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut error = None;
+        for (sink, ahead) in self.sinks.iter_mut().zip(&mut self.ahead) {
+            if *ahead >= buf.len() {
+                continue;
+            }
+            match sink.write(&buf[*ahead..]) {
+                Ok(written) => *ahead += written,
+                Err(e) => {
+                    if error.is_none() {
+                        error = Some(e);
+                    }
+                }
+            }
+        }
+
+        let written = self.ahead.iter().cloned().min().unwrap_or(buf.len());
+        let written = cmp::min(written, buf.len());
+        if written == 0 {
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+        for ahead in &mut self.ahead {
+            *ahead -= written;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            let flushed = sink.flush();
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn test_tee() {
+    use stream::write::Encoder;
+
+    // Accepts at most `limit` bytes per call.
+    struct Slow {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Slow {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = cmp::min(buf.len(), self.limit);
+            self.data.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let text = vec![5u8; 100000];
+    let sinks = vec![Slow {
+                         data: Vec::new(),
+                         limit: 3,
+                     },
+                     Slow {
+                         data: Vec::new(),
+                         limit: 1000,
+                     }];
+    let mut encoder = Encoder::new(Tee::new(sinks), 1).unwrap();
+    encoder.write_all(&text).unwrap();
+    let sinks = encoder.finish().unwrap().finish().unwrap();
+
+    assert_eq!(sinks[0].data, sinks[1].data);
+    assert_eq!(::decode_all(&sinks[0].data[..]).unwrap(), text);
+}
@@ -0,0 +1,165 @@
+//! Raw bindings to the zstd C library.
+//!
+//! The rest of this crate is built on these declarations. They are
+//! exposed for the few cases the safe API doesn't cover, without having
+//! to fork the crate or link zstd a second time. Everything here is
+//! `unsafe` to call, and follows the C documentation in `zstd.h`:
+//! sizes returned by most functions must be checked with `ZSTD_isError`.
+//!
+//! Contexts created here are unrelated to the ones owned by this crate's
+//! types, and must be freed by the caller. C integer types come from the
+//! `libc` crate.
+//!
+//! The stable C API is always available. The experimental part of
+//! `zstd.h` (behind `ZSTD_STATIC_LINKING_ONLY`), which can change between
+//! zstd versions, requires the `experimental` feature, like
+//! [`CParameter::Raw`](../params/enum.CParameter.html).
+
+// Types and constants
+pub use ll::{ErrorCode, ZSTDCompressionContext, ZSTDCompressionDictionary,
+             ZSTDDecompressionContext, ZSTDDecompressionDictionary,
+             ZSTD_CONTENTSIZE_ERROR, ZSTD_CONTENTSIZE_UNKNOWN,
+             ZSTD_EndDirective, ZSTD_ErrorCode, ZSTD_ResetDirective,
+             ZSTD_bounds, ZSTD_cParameter, ZSTD_dParameter, ZSTD_inBuffer,
+             ZSTD_outBuffer, ZSTD_strategy};
+pub use ll::{ZSTD_btlazy2, ZSTD_btopt, ZSTD_btultra, ZSTD_btultra2,
+             ZSTD_dfast, ZSTD_fast, ZSTD_greedy, ZSTD_lazy, ZSTD_lazy2};
+pub use ll::{ZSTD_e_continue, ZSTD_e_end, ZSTD_e_flush};
+pub use ll::{ZSTD_reset_parameters, ZSTD_reset_session_and_parameters,
+             ZSTD_reset_session_only};
+pub use ll::{ZSTD_c_chainLog, ZSTD_c_checksumFlag, ZSTD_c_compressionLevel,
+             ZSTD_c_contentSizeFlag, ZSTD_c_dictIDFlag,
+             ZSTD_c_enableLongDistanceMatching, ZSTD_c_hashLog,
+             ZSTD_c_jobSize, ZSTD_c_ldmBucketSizeLog, ZSTD_c_ldmHashLog,
+             ZSTD_c_ldmHashRateLog, ZSTD_c_ldmMinMatch, ZSTD_c_minMatch,
+             ZSTD_c_nbWorkers, ZSTD_c_overlapLog, ZSTD_c_searchLog,
+             ZSTD_c_strategy, ZSTD_c_targetLength, ZSTD_c_windowLog,
+             ZSTD_d_windowLogMax};
+pub use ll::{ZSTD_error_GENERIC, ZSTD_error_checksum_wrong,
+             ZSTD_error_corruption_detected,
+             ZSTD_error_dictionaryCreation_failed,
+             ZSTD_error_dictionary_corrupted, ZSTD_error_dictionary_wrong,
+             ZSTD_error_dstBuffer_null, ZSTD_error_dstSize_tooSmall,
+             ZSTD_error_frameParameter_unsupported,
+             ZSTD_error_frameParameter_windowTooLarge,
+             ZSTD_error_init_missing, ZSTD_error_memory_allocation,
+             ZSTD_error_no_error, ZSTD_error_parameter_outOfBound,
+             ZSTD_error_parameter_unsupported, ZSTD_error_prefix_unknown,
+             ZSTD_error_srcSize_wrong, ZSTD_error_stage_wrong,
+             ZSTD_error_version_unsupported, ZSTD_error_workSpace_tooSmall};
+
+// Functions
+pub use ll::{ZSTD_getErrorCode, ZSTD_getErrorName, ZSTD_getErrorString,
+             ZSTD_isError, ZSTD_versionNumber, ZSTD_versionString};
+pub use ll::{ZSTD_compress, ZSTD_compressBound, ZSTD_decompress,
+             ZSTD_findFrameCompressedSize, ZSTD_getFrameContentSize};
+pub use ll::{ZSTD_defaultCLevel, ZSTD_maxCLevel, ZSTD_minCLevel};
+pub use ll::{ZSTD_compressCCtx, ZSTD_createCCtx, ZSTD_createDCtx,
+             ZSTD_decompressDCtx, ZSTD_freeCCtx, ZSTD_freeDCtx,
+             ZSTD_sizeof_CCtx, ZSTD_sizeof_DCtx};
+pub use ll::{ZSTD_CCtx_loadDictionary, ZSTD_CCtx_refCDict,
+             ZSTD_CCtx_refPrefix, ZSTD_CCtx_reset, ZSTD_CCtx_setParameter,
+             ZSTD_CCtx_setPledgedSrcSize, ZSTD_DCtx_loadDictionary,
+             ZSTD_DCtx_refDDict, ZSTD_DCtx_refPrefix, ZSTD_DCtx_reset,
+             ZSTD_DCtx_setParameter, ZSTD_cParam_getBounds,
+             ZSTD_compress2, ZSTD_dParam_getBounds};
+pub use ll::{ZSTD_CStreamInSize, ZSTD_CStreamOutSize, ZSTD_DStreamInSize,
+             ZSTD_DStreamOutSize, ZSTD_compressStream2,
+             ZSTD_decompressStream};
+pub use ll::{ZSTD_compress_usingCDict, ZSTD_compress_usingDict,
+             ZSTD_createCDict, ZSTD_createDDict,
+             ZSTD_decompress_usingDDict, ZSTD_decompress_usingDict,
+             ZSTD_freeCDict, ZSTD_freeDDict, ZSTD_getDictID_fromDDict,
+             ZSTD_getDictID_fromDict, ZSTD_getDictID_fromFrame,
+             ZSTD_sizeof_CDict, ZSTD_sizeof_DDict};
+pub use ll::{ZDICT_getDictID, ZDICT_getErrorName, ZDICT_isError,
+             ZDICT_trainFromBuffer};
+
+// Experimental API
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTDCompressionParameters, ZSTD_Sequence, ZSTD_allocFunction,
+             ZSTD_compressionParameters, ZSTD_customMem,
+             ZSTD_defaultCMem, ZSTD_dictContentType_e,
+             ZSTD_dictLoadMethod_e, ZSTD_frameHeader,
+             ZSTD_frameProgression, ZSTD_frameType_e, ZSTD_freeFunction,
+             ZSTD_paramSwitch_e};
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTD_dct_auto, ZSTD_dct_fullDict, ZSTD_dct_rawContent,
+             ZSTD_dlm_byCopy, ZSTD_dlm_byRef, ZSTD_frame, ZSTD_ps_auto,
+             ZSTD_ps_disable, ZSTD_ps_enable, ZSTD_skippableFrame};
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTD_c_blockDelimiters, ZSTD_c_deterministicRefPrefix,
+             ZSTD_c_enableDedicatedDictSearch,
+             ZSTD_c_enableSeqProducerFallback,
+             ZSTD_c_literalCompressionMode, ZSTD_c_rsyncable,
+             ZSTD_c_srcSizeHint, ZSTD_c_validateSequences};
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTD_sequenceFormat_e, ZSTD_sf_explicitBlockDelimiters,
+             ZSTD_sf_noBlockDelimiters};
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTD_SEQUENCE_PRODUCER_ERROR, ZSTD_sequenceProducer_F};
+#[cfg(feature = "experimental")]
+pub use ll::{ZSTD_CCtxParams_init, ZSTD_CCtxParams_setParameter,
+             ZSTD_CCtx_getParameter, ZSTD_checkCParams,
+             ZSTD_createCCtxParams, ZSTD_createCCtx_advanced,
+             ZSTD_createCDict_advanced2, ZSTD_createDCtx_advanced,
+             ZSTD_createDDict_advanced, ZSTD_decompressBound,
+             ZSTD_estimateCCtxSize, ZSTD_estimateCStreamSize,
+             ZSTD_estimateCStreamSize_usingCParams, ZSTD_estimateDCtxSize,
+             ZSTD_compressSequences, ZSTD_estimateDStreamSize,
+             ZSTD_freeCCtxParams,
+             ZSTD_generateSequences, ZSTD_getCParams, ZSTD_getFrameHeader,
+             ZSTD_getFrameProgression, ZSTD_initStaticCCtx,
+             ZSTD_initStaticDCtx, ZSTD_registerSequenceProducer};
+#[cfg(feature = "experimental")]
+pub use ll::{ZDICT_cover_params_t, ZDICT_fastCover_params_t,
+             ZDICT_optimizeTrainFromBuffer_cover,
+             ZDICT_optimizeTrainFromBuffer_fastCover, ZDICT_params_t,
+             ZDICT_trainFromBuffer_fastCover};
+
+#[test]
+fn test_sys() {
+    let text = b"raw calls, raw calls, raw calls";
+    unsafe {
+        assert!(ZSTD_versionNumber() >= 10500);
+
+        let mut compressed = vec![0u8; ZSTD_compressBound(text.len())];
+        let size = ZSTD_compress(compressed.as_mut_ptr(),
+                                 compressed.len(),
+                                 text.as_ptr(),
+                                 text.len(),
+                                 ZSTD_defaultCLevel());
+        assert_eq!(ZSTD_isError(size), 0);
+        assert_eq!(ZSTD_getFrameContentSize(compressed.as_ptr(), size),
+                   text.len() as u64);
+
+        let mut decompressed = vec![0u8; text.len()];
+        let size = ZSTD_decompress(decompressed.as_mut_ptr(),
+                                   decompressed.len(),
+                                   compressed.as_ptr(),
+                                   size);
+        assert_eq!(size, text.len());
+        assert_eq!(&decompressed[..], &text[..]);
+
+        let bounds = ZSTD_cParam_getBounds(ZSTD_c_compressionLevel);
+        assert_eq!(ZSTD_isError(bounds.error), 0);
+        assert_eq!(bounds.upperBound, ZSTD_maxCLevel());
+    }
+}
+
+#[cfg(feature = "experimental")]
+#[test]
+fn test_experimental_parameters() {
+    let parameters = [ZSTD_c_rsyncable,
+                      ZSTD_c_literalCompressionMode,
+                      ZSTD_c_srcSizeHint,
+                      ZSTD_c_enableDedicatedDictSearch,
+                      ZSTD_c_blockDelimiters,
+                      ZSTD_c_validateSequences,
+                      ZSTD_c_deterministicRefPrefix,
+                      ZSTD_c_enableSeqProducerFallback];
+    for &parameter in &parameters {
+        let bounds = unsafe { ZSTD_cParam_getBounds(parameter) };
+        assert_eq!(unsafe { ZSTD_isError(bounds.error) }, 0);
+    }
+}
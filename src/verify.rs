@@ -0,0 +1,97 @@
+//! Helpers checking that compressed data decompresses back to its input.
+
+use stream::write::Decoder;
+use xxhash::Xxh64;
+
+use std::io::{self, Read, Write};
+
+/// Reads from a reader, remembering the size and hash of what went through.
+pub struct HashingReader<R: Read> {
+    reader: R,
+    hasher: Xxh64,
+    size: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(reader: R) -> Self {
+        HashingReader {
+            reader: reader,
+            hasher: Xxh64::default(),
+            size: 0,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the checksum zstd would store for the data read so far.
+    pub fn checksum(&self) -> u32 {
+        self.hasher.digest() as u32
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.reader.read(buf));
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+/// Counts the bytes written to it, and drops them.
+pub struct Counter(pub u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Forwards compressed data to a writer, decompressing it on the way.
+pub struct Verifier<W: Write> {
+    writer: W,
+    decoder: Decoder<Counter>,
+}
+
+impl<W: Write> Verifier<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut decoder = try!(Decoder::new(Counter(0)));
+        decoder.track_content_checksum(true);
+        Ok(Verifier {
+            writer: writer,
+            decoder: decoder,
+        })
+    }
+
+    /// Checks the decompressed data matched the given size and checksum.
+    ///
+    /// Returns the inner writer on success.
+    pub fn check(self, size: u64, checksum: u32) -> io::Result<W> {
+        let actual_checksum = self.decoder.content_checksum();
+        let actual_size = self.decoder.finish().0;
+        if actual_checksum != Some(checksum) || actual_size != size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "compressed data failed verification"));
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for Verifier<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.writer.write(buf));
+        try!(self.decoder.write_all(&buf[..written]));
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,97 @@
+//! Fast non-cryptographic hashing with XXH64.
+//!
+//! This uses the copy of xxHash bundled with zstd, which is also used
+//! for frame content checksums: the checksum stored in a frame is
+//! the lower 32 bits of the XXH64 hash (with a seed of 0)
+//! of the decompressed content.
+
+use ll;
+
+use std::io;
+
+/// Computes the XXH64 hash of `data`.
+pub fn xxh64(data: &[u8], seed: u64) -> u64 {
+    unsafe { ll::ZSTD_XXH64(data.as_ptr(), data.len(), seed) }
+}
+
+/// A streaming XXH64 hasher.
+///
+/// Feed it data with `update()` (or through `Write`),
+/// then get the hash with `digest()`.
+pub struct Xxh64 {
+    state: ll::XXH64_state,
+}
+
+impl Xxh64 {
+    /// Creates a new hasher with the given seed.
+    pub fn new(seed: u64) -> Self {
+        let state = unsafe { ll::ZSTD_XXH64_createState() };
+        assert!(!state.is_null(), "could not allocate a XXH64 state");
+        let mut hasher = Xxh64 { state: state };
+        hasher.reset(seed);
+        hasher
+    }
+
+    /// Starts over with a new seed, forgetting any data seen so far.
+    pub fn reset(&mut self, seed: u64) {
+        let code = unsafe { ll::ZSTD_XXH64_reset(self.state, seed) };
+        assert_eq!(code, ll::XXH_OK);
+    }
+
+    /// Feeds more data to the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        let code = unsafe {
+            ll::ZSTD_XXH64_update(self.state, data.as_ptr(), data.len())
+        };
+        assert_eq!(code, ll::XXH_OK);
+    }
+
+    /// Returns the hash of the data seen so far.
+    ///
+    /// More data can still be added afterward.
+    pub fn digest(&self) -> u64 {
+        unsafe { ll::ZSTD_XXH64_digest(self.state) }
+    }
+}
+
+impl Default for Xxh64 {
+    /// Creates a hasher with a seed of 0, like zstd checksums.
+    fn default() -> Self {
+        Xxh64::new(0)
+    }
+}
+
+// The state is only accessed through `&mut self` or read through `&self`,
+// so it can be moved to another thread.
+unsafe impl Send for Xxh64 {}
+
+impl Drop for Xxh64 {
+    fn drop(&mut self) {
+        unsafe {
+            ll::ZSTD_XXH64_freeState(self.state);
+        }
+    }
+}
+
+impl io::Write for Xxh64 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_xxh64() {
+    // Reference value from the xxHash test suite.
+    assert_eq!(xxh64(b"", 0), 0xef46db3751d8e999);
+
+    let data = b"The quick brown fox jumps over the lazy dog";
+    let mut hasher = Xxh64::new(42);
+    hasher.update(&data[..10]);
+    hasher.update(&data[10..]);
+    assert_eq!(hasher.digest(), xxh64(data, 42));
+}
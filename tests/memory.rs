@@ -0,0 +1,40 @@
+//! Memory tracking is global to the process: these tests run on their
+//! own, so they don't change the allocations of the other tests.
+
+extern crate zstd;
+
+use std::io::Write;
+
+// Restores the defaults, even if the test fails.
+struct Reset;
+
+impl Drop for Reset {
+    fn drop(&mut self) {
+        zstd::set_memory_limit(None);
+        zstd::track_memory(false);
+    }
+}
+
+// A single test, so nothing else allocates while it runs.
+#[test]
+fn test_memory() {
+    let _reset = Reset;
+
+    zstd::track_memory(true);
+    let before = zstd::memory_in_use();
+    let mut encoder = zstd::Encoder::new(Vec::new(), 3).unwrap();
+    encoder.write_all(b"tracked").unwrap();
+    encoder.flush().unwrap();
+    assert!(zstd::memory_in_use() > before);
+    encoder.finish().unwrap();
+    assert_eq!(zstd::memory_in_use(), before);
+
+    // No room left for a context.
+    zstd::set_memory_limit(Some(before));
+    assert!(zstd::Encoder::new(Vec::new(), 3).is_err());
+    assert!(zstd::Decoder::new(&b""[..]).is_err());
+    assert_eq!(zstd::memory_in_use(), before);
+
+    zstd::set_memory_limit(None);
+    assert!(zstd::Encoder::new(Vec::new(), 3).is_ok());
+}